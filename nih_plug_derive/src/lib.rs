@@ -10,7 +10,25 @@ pub fn derive_enum(input: TokenStream) -> TokenStream {
 }
 
 /// Derive the `Params` trait for your plugin's parameters struct. See the `Plugin` trait.
-#[proc_macro_derive(Params, attributes(id, persist, nested))]
+///
+/// Parameter IDs need to be unique. Using the same ID for two fields in the same struct is a
+/// compile error:
+///
+/// ```compile_fail
+/// use nih_plug::prelude::*;
+///
+/// #[derive(Params)]
+/// struct DuplicateParams {
+///     #[id = "gain"]
+///     pub gain: FloatParam,
+///     #[id = "gain"]
+///     pub output_gain: FloatParam,
+/// }
+/// ```
+///
+/// IDs coming from `#[nested]` parameter objects can only be checked when the plugin instance is
+/// created, so the plugin wrappers will panic with a list of the duplicate IDs instead.
+#[proc_macro_derive(Params, attributes(id, persist, group, nested, state_version))]
 pub fn derive_params(input: TokenStream) -> TokenStream {
     params::derive_params(input)
 }
@@ -23,6 +23,42 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
         }
     };
 
+    // The struct can optionally declare a version for its state using `#[state_version = 2]`
+    let mut state_version: Option<u32> = None;
+    for attr in &ast.attrs {
+        if attr.path.is_ident("state_version") {
+            match attr.parse_meta() {
+                Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                    lit: syn::Lit::Int(n),
+                    ..
+                })) if state_version.is_none() => match n.base10_parse() {
+                    Ok(n) => state_version = Some(n),
+                    Err(err) => return err.to_compile_error().into(),
+                },
+                Ok(syn::Meta::NameValue(_)) => {
+                    return syn::Error::new(attr.span(), "Duplicate state_version attribute")
+                        .to_compile_error()
+                        .into();
+                }
+                _ => {
+                    return syn::Error::new(
+                        attr.span(),
+                        "The state_version attribute should be a key-value pair with an integer argument: #[state_version = 2]",
+                    )
+                    .to_compile_error()
+                    .into()
+                }
+            }
+        }
+    }
+    let state_version_tokens = state_version.map(|state_version| {
+        quote! {
+            fn state_version(&self) -> u32 {
+                #state_version
+            }
+        }
+    });
+
     // We only care about fields with `id`, `persist`, and `nested` attributes. For the `id` fields
     // we'll build a mapping function that creates a hashmap containing pointers to those
     // parameters. For the `persist` function we'll create functions that serialize and deserialize
@@ -32,12 +68,12 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
     let mut param_mapping_self_tokens = Vec::new();
     let mut field_serialize_tokens = Vec::new();
     let mut field_deserialize_tokens = Vec::new();
-    let mut nested_params_field_idents: Vec<syn::Ident> = Vec::new();
-    let mut nested_params_group_names: Vec<String> = Vec::new();
+    let mut nested_params: Vec<NestedParams> = Vec::new();
 
-    // We'll also enforce that there are no duplicate keys at compile time
-    // TODO: This doesn't work for nested fields since we don't know anything about the fields on
-    //       the nested structs
+    // We'll also enforce that there are no duplicate keys at compile time. Parameter IDs and persist
+    // keys are stored separately, but using the same key for both would be very confusing.
+    // NOTE: This doesn't work for nested fields since we don't know anything about the fields on
+    //       the nested structs. The wrappers check for duplicate IDs at runtime to catch those.
     let mut param_ids = HashSet::new();
     let mut persist_ids = HashSet::new();
     for field in fields.named {
@@ -49,8 +85,11 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
         // These two attributes are mutually exclusive
         let mut id_attr: Option<String> = None;
         let mut persist_attr: Option<String> = None;
-        // And the `#[nested = "..."]` attribute contains a group name we should use
-        let mut nested_attr: Option<String> = None;
+        // Parameters can optionally be put in a group using `#[group = "..."]`
+        let mut group_attr: Option<String> = None;
+        // And the `#[nested = "..."]` or `#[nested(...)]` attribute describes how a nested
+        // `Params` object should be included
+        let mut nested_attr: Option<NestedParams> = None;
         for attr in &field.attrs {
             if attr.path.is_ident("id") {
                 match attr.parse_meta() {
@@ -98,41 +137,59 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                         .into()
                     }
                 };
-            } else if attr.path.is_ident("nested") {
+            } else if attr.path.is_ident("group") {
                 match attr.parse_meta() {
                     Ok(syn::Meta::NameValue(syn::MetaNameValue {
                         lit: syn::Lit::Str(s),
                         ..
                     })) => {
-                        let s = s.value();
-                        if s.is_empty() {
-                            return syn::Error::new(attr.span(), "Group names cannot be empty")
-                                .to_compile_error()
-                                .into();
-                        } else if s.contains('/') {
-                            return syn::Error::new(attr.span(), "Group names may not contain slashes")
+                        if group_attr.is_some() {
+                            return syn::Error::new(attr.span(), "Duplicate group attribute")
                                 .to_compile_error()
                                 .into();
-                        } else if nested_attr.is_some() {
-                            return syn::Error::new(attr.span(), "Duplicate nested attribute")
-                                .to_compile_error()
-                                .into();
-                        } else {
-                            nested_attr = Some(s);
+                        } else if s.value().split('/').any(str::is_empty) {
+                            return syn::Error::new(
+                                s.span(),
+                                "Group names cannot be empty, and nested group names should be separated by a single slash: #[group = \"Filter/Envelope\"]",
+                            )
+                            .to_compile_error()
+                            .into();
                         }
+
+                        group_attr = Some(s.value());
                     }
                     _ => {
                         return syn::Error::new(
                             attr.span(),
-                            "The nested attribute should be a key-value pair with a string argument: #[nested = \"Group Name\"]",
+                            "The group attribute should be a key-value pair with a string argument: #[group = \"Filter\"]",
                         )
                         .to_compile_error()
                         .into()
                     }
                 };
+            } else if attr.path.is_ident("nested") {
+                if nested_attr.is_some() {
+                    return syn::Error::new(attr.span(), "Duplicate nested attribute")
+                        .to_compile_error()
+                        .into();
+                }
+
+                match parse_nested_attr(field_name, attr) {
+                    Ok(nested) => nested_attr = Some(nested),
+                    Err(err) => return err.to_compile_error().into(),
+                }
             }
         }
 
+        if group_attr.is_some() && id_attr.is_none() {
+            return syn::Error::new(
+                field.span(),
+                "The group attribute can only be used on parameters with an id attribute. Use #[nested(group = \"...\")] for nested parameter objects.",
+            )
+            .to_compile_error()
+            .into();
+        }
+
         match (id_attr, persist_attr) {
             (Some(param_id), None) => {
                 if !param_ids.insert(param_id.clone()) {
@@ -142,14 +199,23 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                     )
                     .to_compile_error()
                     .into();
+                } else if persist_ids.contains(&param_id) {
+                    return syn::Error::new(
+                        field.span(),
+                        "A persisted field with the same key as this parameter's ID already exists",
+                    )
+                    .to_compile_error()
+                    .into();
                 }
 
                 // These are pairs of `(parameter_id, param_ptr, param_group)`. The specific
                 // parameter types know how to convert themselves into the correct ParamPtr variant.
-                // Top-level parameters have no group, and we'll prefix the group name specified in
-                // the `#[nested = "..."]` attribute to fields coming from nested groups
+                // Top-level parameters are in the root group unless they have a `#[group = "..."]`
+                // attribute, and we'll prefix the group name specified in the `#[nested = "..."]`
+                // attribute to fields coming from nested groups
+                let group = group_attr.unwrap_or_default();
                 param_mapping_self_tokens.push(
-                    quote! { (String::from(#param_id), self.#field_name.as_ptr(), String::new()) },
+                    quote! { (String::from(#param_id), self.#field_name.as_ptr(), String::from(#group)) },
                 );
             }
             (None, Some(persist_key)) => {
@@ -160,6 +226,13 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                     )
                     .to_compile_error()
                     .into();
+                } else if param_ids.contains(&persist_key) {
+                    return syn::Error::new(
+                        field.span(),
+                        "A parameter with the same ID as this persisted field's key already exists",
+                    )
+                    .to_compile_error()
+                    .into();
                 }
 
                 // We don't know anything about the field types, but because we can generate this
@@ -213,13 +286,15 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
             (None, None) => (),
         }
 
-        if let Some(nested_group_name) = nested_attr {
-            nested_params_field_idents.push(field_name.clone());
-            // FIXME: Generate the insertion code here
-            nested_params_group_names.push(nested_group_name);
+        if let Some(nested) = nested_attr {
+            nested_params.push(nested);
         }
     }
 
+    let nested_param_mapping_tokens = nested_params.iter().map(NestedParams::param_map_tokens);
+    let nested_serialize_tokens = nested_params.iter().map(NestedParams::serialize_tokens);
+    let nested_deserialize_tokens = nested_params.iter().map(NestedParams::deserialize_tokens);
+
     quote! {
         unsafe impl #impl_generics Params for #struct_name #ty_generics #where_clause {
             fn param_map(&self) -> Vec<(String, nih_plug::prelude::ParamPtr, String)> {
@@ -227,30 +302,7 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                 use ::nih_plug::param::Param;
 
                 let mut param_map = vec![#(#param_mapping_self_tokens),*];
-
-                let nested_params_fields: &[&dyn Params] = &[#(&self.#nested_params_field_idents),*];
-                let nested_params_groups: &[String] = &[#(String::from(#nested_params_group_names)),*];
-                for (nested_params, group_name) in
-                    nested_params_fields.into_iter().zip(nested_params_groups)
-                {
-                    let nested_param_map = nested_params.param_map();
-                    let prefixed_nested_param_map =
-                        nested_param_map
-                            .into_iter()
-                            .map(|(param_id, param_ptr, nested_group_name)| {
-                                (
-                                    param_id,
-                                    param_ptr,
-                                    if nested_group_name.is_empty() {
-                                        group_name.to_string()
-                                    } else {
-                                        format!("{}/{}", group_name, nested_group_name)
-                                    }
-                                )
-                            });
-
-                    param_map.extend(prefixed_nested_param_map);
-                }
+                #(#nested_param_mapping_tokens)*
 
                 param_map
             }
@@ -258,11 +310,7 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
             fn serialize_fields(&self) -> ::std::collections::HashMap<String, String> {
                 let mut serialized = ::std::collections::HashMap::new();
                 #(#field_serialize_tokens)*
-
-                let nested_params_fields: &[&dyn Params] = &[#(&self.#nested_params_field_idents),*];
-                for nested_params in nested_params_fields {
-                    serialized.extend(nested_params.serialize_fields());
-                }
+                #(#nested_serialize_tokens)*
 
                 serialized
             }
@@ -279,12 +327,245 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                 //        parameter structs. An easy fix would be to use
                 //        https://doc.rust-lang.org/std/collections/struct.HashMap.html#method.drain_filter
                 //        once that gets stabilized.
-                let nested_params_fields: &[&dyn Params] = &[#(&self.#nested_params_field_idents),*];
-                for nested_params in nested_params_fields {
-                    nested_params.deserialize_fields(serialized);
-                }
+                #(#nested_deserialize_tokens)*
             }
+
+            #state_version_tokens
         }
     }
     .into()
 }
+
+/// A field containing nested `Params` object(s), marked with either `#[nested = "Group Name"]` or
+/// `#[nested(...)]`.
+struct NestedParams {
+    /// The name of the field containing the nested `Params` object(s).
+    field_name: syn::Ident,
+    /// The group name the nested parameters should be put in. If this is not set, then the ID
+    /// prefix is used as the group name instead. If neither are set, then the nested parameters end
+    /// up in the same group as this object's own parameters.
+    group: Option<String>,
+    /// A prefix that's added to the parameter IDs and persist keys of the nested object, separated
+    /// by an underscore.
+    id_prefix: Option<String>,
+    /// Whether the field is an array or a `Vec` of `Params` objects. In that case the parameter IDs
+    /// and persist keys get a 1-indexed `_1`, `_2`, etc. suffix, and the group names get a ` 1`,
+    /// ` 2`, etc. suffix.
+    array: bool,
+}
+
+impl NestedParams {
+    /// The group name for the nested parameters, see [`group`][Self::group].
+    fn group(&self) -> Option<&String> {
+        self.group.as_ref().or(self.id_prefix.as_ref())
+    }
+
+    /// The tokens that add the nested parameters to the `param_map` vector.
+    fn param_map_tokens(&self) -> impl quote::ToTokens {
+        let field_name = &self.field_name;
+        let prefix_id = match &self.id_prefix {
+            Some(id_prefix) => quote! { format!("{}_{}", #id_prefix, param_id) },
+            None => quote! { param_id },
+        };
+
+        if self.array {
+            let group = match self.group() {
+                Some(group) => quote! { Some(format!("{} {}", #group, nested_idx + 1)) },
+                None => quote! { None },
+            };
+
+            quote! {
+                for (nested_idx, nested_params) in self.#field_name.iter().enumerate() {
+                    let group: Option<String> = #group;
+                    param_map.extend(nested_params.param_map().into_iter().map(
+                        |(param_id, param_ptr, nested_group_name)| {
+                            let param_id = format!("{}_{}", param_id, nested_idx + 1);
+                            (
+                                #prefix_id,
+                                param_ptr,
+                                ::nih_plug::param::internals::nest_group_name(
+                                    group.as_deref(),
+                                    nested_group_name,
+                                ),
+                            )
+                        },
+                    ));
+                }
+            }
+        } else {
+            let group = match self.group() {
+                Some(group) => quote! { Some(#group) },
+                None => quote! { None },
+            };
+
+            quote! {
+                param_map.extend(self.#field_name.param_map().into_iter().map(
+                    |(param_id, param_ptr, nested_group_name)| {
+                        (
+                            #prefix_id,
+                            param_ptr,
+                            ::nih_plug::param::internals::nest_group_name(#group, nested_group_name),
+                        )
+                    },
+                ));
+            }
+        }
+    }
+
+    /// The tokens that add the nested object's persisted fields to the `serialized` hash map.
+    fn serialize_tokens(&self) -> impl quote::ToTokens {
+        let field_name = &self.field_name;
+        let prefix_key = match &self.id_prefix {
+            Some(id_prefix) => quote! { format!("{}_{}", #id_prefix, key) },
+            None => quote! { key },
+        };
+
+        if self.array {
+            quote! {
+                for (nested_idx, nested_params) in self.#field_name.iter().enumerate() {
+                    for (key, value) in nested_params.serialize_fields() {
+                        let key = format!("{}_{}", key, nested_idx + 1);
+                        serialized.insert(#prefix_key, value);
+                    }
+                }
+            }
+        } else {
+            quote! {
+                for (key, value) in self.#field_name.serialize_fields() {
+                    serialized.insert(#prefix_key, value);
+                }
+            }
+        }
+    }
+
+    /// The tokens that restore the nested object's persisted fields from the `serialized` hash
+    /// map. This reverses the transformations from [`serialize_tokens()`][Self::serialize_tokens()].
+    fn deserialize_tokens(&self) -> impl quote::ToTokens {
+        let field_name = &self.field_name;
+        let strip_prefix = match &self.id_prefix {
+            Some(id_prefix) => quote! {
+                let serialized: ::std::collections::HashMap<String, String> = serialized
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        key.strip_prefix(#id_prefix)
+                            .and_then(|key| key.strip_prefix('_'))
+                            .map(|key| (key.to_string(), value.clone()))
+                    })
+                    .collect();
+                let serialized = &serialized;
+            },
+            None => quote! {},
+        };
+
+        if self.array {
+            quote! {
+                {
+                    #strip_prefix
+                    for (nested_idx, nested_params) in self.#field_name.iter().enumerate() {
+                        let suffix = format!("_{}", nested_idx + 1);
+                        let nested_serialized: ::std::collections::HashMap<String, String> =
+                            serialized
+                                .iter()
+                                .filter_map(|(key, value)| {
+                                    key.strip_suffix(&suffix)
+                                        .map(|key| (key.to_string(), value.clone()))
+                                })
+                                .collect();
+                        nested_params.deserialize_fields(&nested_serialized);
+                    }
+                }
+            }
+        } else {
+            quote! {
+                {
+                    #strip_prefix
+                    self.#field_name.deserialize_fields(serialized);
+                }
+            }
+        }
+    }
+}
+
+/// Parse a `#[nested = "Group Name"]` or `#[nested(array, id_prefix = "foo", group = "Group
+/// Name")]` attribute. All of the options in the second form are optional.
+fn parse_nested_attr(field_name: &syn::Ident, attr: &syn::Attribute) -> syn::Result<NestedParams> {
+    let mut nested = NestedParams {
+        field_name: field_name.clone(),
+        group: None,
+        id_prefix: None,
+        array: false,
+    };
+
+    let validate_group = |s: &syn::LitStr| {
+        let group = s.value();
+        if group.is_empty() {
+            Err(syn::Error::new(s.span(), "Group names cannot be empty"))
+        } else if group.contains('/') {
+            Err(syn::Error::new(
+                s.span(),
+                "Group names may not contain slashes",
+            ))
+        } else {
+            Ok(group)
+        }
+    };
+
+    match attr.parse_meta() {
+        Ok(syn::Meta::Path(_)) => (),
+        Ok(syn::Meta::NameValue(syn::MetaNameValue {
+            lit: syn::Lit::Str(s),
+            ..
+        })) => nested.group = Some(validate_group(&s)?),
+        Ok(syn::Meta::List(list)) => {
+            for item in list.nested {
+                match item {
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("array") => {
+                        if nested.array {
+                            return Err(syn::Error::new(path.span(), "Duplicate array option"));
+                        }
+
+                        nested.array = true;
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Str(s),
+                        ..
+                    })) if path.is_ident("group") => {
+                        if nested.group.is_some() {
+                            return Err(syn::Error::new(path.span(), "Duplicate group option"));
+                        }
+
+                        nested.group = Some(validate_group(&s)?);
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Str(s),
+                        ..
+                    })) if path.is_ident("id_prefix") => {
+                        if nested.id_prefix.is_some() {
+                            return Err(syn::Error::new(path.span(), "Duplicate id_prefix option"));
+                        } else if s.value().is_empty() {
+                            return Err(syn::Error::new(s.span(), "ID prefixes cannot be empty"));
+                        }
+
+                        nested.id_prefix = Some(s.value());
+                    }
+                    item => {
+                        return Err(syn::Error::new(
+                            item.span(),
+                            "Expected one of `array`, `id_prefix = \"...\"`, or `group = \"...\"`",
+                        ))
+                    }
+                }
+            }
+        }
+        _ => {
+            return Err(syn::Error::new(
+                attr.span(),
+                "The nested attribute should either be a key-value pair with a group name, or a list of options: #[nested = \"Group Name\"] or #[nested(array, id_prefix = \"foo\", group = \"Group Name\")]",
+            ))
+        }
+    }
+
+    Ok(nested)
+}
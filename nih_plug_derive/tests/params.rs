@@ -0,0 +1,351 @@
+use nih_plug::prelude::*;
+use std::collections::HashSet;
+use std::sync::{Mutex, RwLock};
+
+#[derive(Params)]
+struct InnerParams {
+    #[id = "one"]
+    pub one: BoolParam,
+    #[id = "two"]
+    pub two: FloatParam,
+
+    #[persist = "state"]
+    pub state: Mutex<u32>,
+}
+
+impl Default for InnerParams {
+    fn default() -> Self {
+        InnerParams {
+            one: BoolParam::new("one", true),
+            two: FloatParam::new("two", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            state: Mutex::new(0),
+        }
+    }
+}
+
+#[derive(Params)]
+struct MiddleParams {
+    #[id = "three"]
+    pub three: IntParam,
+
+    #[nested = "Inner"]
+    pub inner: InnerParams,
+}
+
+#[derive(Params)]
+struct GroupedParams {
+    #[id = "four"]
+    pub four: BoolParam,
+
+    #[nested = "Middle"]
+    pub middle: MiddleParams,
+}
+
+#[derive(Params, Default)]
+struct PrefixedParams {
+    #[nested(id_prefix = "foo", group = "Foo")]
+    pub foo: InnerParams,
+    #[nested(id_prefix = "bar")]
+    pub bar: InnerParams,
+}
+
+#[derive(Params, Default)]
+struct PrefixGroupParams {
+    #[nested(id_prefix = "filter1")]
+    pub filter1: MiddleParams,
+    #[nested(id_prefix = "filter2")]
+    pub filter2: MiddleParams,
+    #[nested(array, id_prefix = "osc")]
+    pub oscs: [InnerParams; 2],
+}
+
+#[derive(Params)]
+struct FieldGroupParams {
+    #[id = "gain"]
+    pub gain: FloatParam,
+    #[id = "cutoff"]
+    #[group = "Filter"]
+    pub cutoff: FloatParam,
+    #[id = "attack"]
+    #[group = "Filter/Envelope"]
+    pub attack: FloatParam,
+}
+
+#[derive(Params, Default)]
+struct NestedFieldGroupParams {
+    #[nested = "Voice"]
+    pub voice: FieldGroupParams,
+    #[nested(id_prefix = "lfo")]
+    pub lfo: FieldGroupParams,
+}
+
+#[derive(Params, Default)]
+struct ArrayParams {
+    #[nested(array, group = "Voice")]
+    pub voices: [InnerParams; 3],
+}
+
+#[derive(Params, Default)]
+struct PrefixedArrayParams {
+    #[nested(array, id_prefix = "band", group = "Band")]
+    pub bands: [InnerParams; 3],
+}
+
+impl Default for MiddleParams {
+    fn default() -> Self {
+        MiddleParams {
+            three: IntParam::new("three", 0, IntRange::Linear { min: 0, max: 3 }),
+            inner: InnerParams::default(),
+        }
+    }
+}
+
+impl Default for GroupedParams {
+    fn default() -> Self {
+        GroupedParams {
+            four: BoolParam::new("four", false),
+            middle: MiddleParams::default(),
+        }
+    }
+}
+
+impl Default for FieldGroupParams {
+    fn default() -> Self {
+        FieldGroupParams {
+            gain: FloatParam::new("gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            cutoff: FloatParam::new("cutoff", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            attack: FloatParam::new("attack", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+        }
+    }
+}
+
+#[derive(Params, Default)]
+struct WavetableParams {
+    #[persist = "wavetable"]
+    pub wavetable: RwLock<Vec<f32>>,
+}
+
+/// The `(param_id, group)` pairs from a `Params` object's parameter map.
+fn ids_and_groups(params: &impl Params) -> Vec<(String, String)> {
+    params
+        .param_map()
+        .into_iter()
+        .map(|(param_id, _, group)| (param_id, group))
+        .collect()
+}
+
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(param_id, group)| (param_id.to_string(), group.to_string()))
+        .collect()
+}
+
+#[test]
+fn nested_groups() {
+    let params = GroupedParams::default();
+
+    assert_eq!(
+        ids_and_groups(&params),
+        pairs(&[
+            ("four", ""),
+            ("three", "Middle"),
+            ("one", "Middle/Inner"),
+            ("two", "Middle/Inner"),
+        ])
+    );
+}
+
+#[test]
+fn field_groups() {
+    let params = FieldGroupParams::default();
+
+    assert_eq!(
+        ids_and_groups(&params),
+        pairs(&[
+            ("gain", ""),
+            ("cutoff", "Filter"),
+            ("attack", "Filter/Envelope"),
+        ])
+    );
+}
+
+#[test]
+fn nested_field_groups() {
+    let params = NestedFieldGroupParams::default();
+
+    assert_eq!(
+        ids_and_groups(&params),
+        pairs(&[
+            ("gain", "Voice"),
+            ("cutoff", "Voice/Filter"),
+            ("attack", "Voice/Filter/Envelope"),
+            ("lfo_gain", "lfo"),
+            ("lfo_cutoff", "lfo/Filter"),
+            ("lfo_attack", "lfo/Filter/Envelope"),
+        ])
+    );
+}
+
+#[test]
+fn nested_id_prefix() {
+    let params = PrefixedParams::default();
+
+    assert_eq!(
+        ids_and_groups(&params),
+        pairs(&[
+            ("foo_one", "Foo"),
+            ("foo_two", "Foo"),
+            ("bar_one", "bar"),
+            ("bar_two", "bar"),
+        ])
+    );
+}
+
+#[test]
+fn nested_id_prefix_as_group() {
+    let params = PrefixGroupParams::default();
+
+    // Without an explicit group the prefix is used as the group name, and nested groups are still
+    // added below it
+    assert_eq!(
+        ids_and_groups(&params),
+        pairs(&[
+            ("filter1_three", "filter1"),
+            ("filter1_one", "filter1/Inner"),
+            ("filter1_two", "filter1/Inner"),
+            ("filter2_three", "filter2"),
+            ("filter2_one", "filter2/Inner"),
+            ("filter2_two", "filter2/Inner"),
+            ("osc_one_1", "osc 1"),
+            ("osc_two_1", "osc 1"),
+            ("osc_one_2", "osc 2"),
+            ("osc_two_2", "osc 2"),
+        ])
+    );
+}
+
+#[test]
+fn nested_id_prefix_has_no_collisions() {
+    let params = PrefixedParams::default();
+
+    let param_ids: HashSet<String> = params
+        .param_map()
+        .into_iter()
+        .map(|(param_id, _, _)| param_id)
+        .collect();
+    let persist_keys: HashSet<String> = params.serialize_fields().into_keys().collect();
+    assert_eq!(param_ids.len(), 4);
+    assert_eq!(persist_keys.len(), 2);
+    assert!(param_ids.is_disjoint(&persist_keys));
+
+    // Both instances should still point to their own parameters
+    let param_map = params.param_map();
+    assert_eq!(param_map[0].1, params.foo.one.as_ptr());
+    assert_eq!(param_map[2].1, params.bar.one.as_ptr());
+}
+
+#[test]
+fn nested_array() {
+    let params = ArrayParams::default();
+
+    assert_eq!(
+        ids_and_groups(&params),
+        pairs(&[
+            ("one_1", "Voice 1"),
+            ("two_1", "Voice 1"),
+            ("one_2", "Voice 2"),
+            ("two_2", "Voice 2"),
+            ("one_3", "Voice 3"),
+            ("two_3", "Voice 3"),
+        ])
+    );
+
+    // The pointers should point to the parameters of the individual array elements
+    let param_map = params.param_map();
+    assert_eq!(param_map[2].1, params.voices[1].one.as_ptr());
+    assert_eq!(param_map[5].1, params.voices[2].two.as_ptr());
+}
+
+#[test]
+fn nested_prefixed_array() {
+    let params = PrefixedArrayParams::default();
+
+    // The index suffix is added before the prefix, so every element's IDs are unique
+    assert_eq!(
+        ids_and_groups(&params),
+        pairs(&[
+            ("band_one_1", "Band 1"),
+            ("band_two_1", "Band 1"),
+            ("band_one_2", "Band 2"),
+            ("band_two_2", "Band 2"),
+            ("band_one_3", "Band 3"),
+            ("band_two_3", "Band 3"),
+        ])
+    );
+
+    let serialized = params.serialize_fields();
+    let mut keys: Vec<&str> = serialized.keys().map(String::as_str).collect();
+    keys.sort_unstable();
+    assert_eq!(keys, ["band_state_1", "band_state_2", "band_state_3"]);
+}
+
+#[test]
+fn nested_persisted_fields() {
+    let params = PrefixedParams::default();
+    *params.foo.state.lock().unwrap() = 1;
+    *params.bar.state.lock().unwrap() = 2;
+
+    let serialized = params.serialize_fields();
+    let mut keys: Vec<&str> = serialized.keys().map(String::as_str).collect();
+    keys.sort_unstable();
+    assert_eq!(keys, ["bar_state", "foo_state"]);
+
+    let restored = PrefixedParams::default();
+    restored.deserialize_fields(&serialized);
+    assert_eq!(*restored.foo.state.lock().unwrap(), 1);
+    assert_eq!(*restored.bar.state.lock().unwrap(), 2);
+}
+
+#[test]
+fn nested_array_persisted_fields() {
+    let params = ArrayParams::default();
+    for (idx, voice) in params.voices.iter().enumerate() {
+        *voice.state.lock().unwrap() = idx as u32 * 10;
+    }
+
+    let serialized = params.serialize_fields();
+    let mut keys: Vec<&str> = serialized.keys().map(String::as_str).collect();
+    keys.sort_unstable();
+    assert_eq!(keys, ["state_1", "state_2", "state_3"]);
+
+    let restored = ArrayParams::default();
+    restored.deserialize_fields(&serialized);
+    for (idx, voice) in restored.voices.iter().enumerate() {
+        assert_eq!(*voice.state.lock().unwrap(), idx as u32 * 10);
+    }
+}
+
+#[test]
+fn persisted_vec() {
+    let params = WavetableParams::default();
+    *params.wavetable.write().unwrap() = vec![0.0, 0.25, -0.5, 1.0];
+
+    let serialized = params.serialize_fields();
+    assert_eq!(serialized.len(), 1);
+    assert!(serialized.contains_key("wavetable"));
+
+    let restored = WavetableParams::default();
+    restored.deserialize_fields(&serialized);
+    assert_eq!(*restored.wavetable.read().unwrap(), [0.0, 0.25, -0.5, 1.0]);
+}
+
+#[derive(Params, Default)]
+#[state_version = 2]
+struct VersionedParams {}
+
+#[test]
+fn state_version() {
+    assert_eq!(VersionedParams::default().state_version(), 2);
+    assert_eq!(InnerParams::default().state_version(), 0);
+}
@@ -104,7 +104,7 @@ where
         &self,
         parent: ParentWindowHandle,
         context: Arc<dyn GuiContext>,
-    ) -> Box<dyn std::any::Any + Send + Sync> {
+    ) -> Option<Box<dyn std::any::Any + Send + Sync>> {
         let update = self.update.clone();
         let state = self.user_state.clone();
 
@@ -155,10 +155,10 @@ where
         .expect("We provided an OpenGL config, did we not?");
 
         self.egui_state.open.store(true, Ordering::Release);
-        Box::new(EguiEditorHandle {
+        Some(Box::new(EguiEditorHandle {
             egui_state: self.egui_state.clone(),
             window,
-        })
+        }))
     }
 
     fn size(&self) -> (u32, u32) {
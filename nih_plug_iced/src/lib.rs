@@ -280,7 +280,7 @@ impl<E: IcedEditor> Editor for IcedEditorWrapper<E> {
         &self,
         parent: ParentWindowHandle,
         context: Arc<dyn GuiContext>,
-    ) -> Box<dyn std::any::Any + Send + Sync> {
+    ) -> Option<Box<dyn std::any::Any + Send + Sync>> {
         let (unscaled_width, unscaled_height) = self.iced_state.size();
         let scaling_factor = self.scaling_factor.load();
 
@@ -337,10 +337,10 @@ impl<E: IcedEditor> Editor for IcedEditorWrapper<E> {
         );
 
         self.iced_state.open.store(true, Ordering::Release);
-        Box::new(IcedEditorHandle {
+        Some(Box::new(IcedEditorHandle {
             iced_state: self.iced_state.clone(),
             window,
-        })
+        }))
     }
 
     fn size(&self) -> (u32, u32) {
@@ -154,7 +154,7 @@ impl Editor for ViziaEditor {
         &self,
         parent: ParentWindowHandle,
         context: Arc<dyn GuiContext>,
-    ) -> Box<dyn std::any::Any + Send + Sync> {
+    ) -> Option<Box<dyn std::any::Any + Send + Sync>> {
         let app = self.app.clone();
         let vizia_state = self.vizia_state.clone();
         let apply_theming = self.apply_theming;
@@ -210,10 +210,10 @@ impl Editor for ViziaEditor {
         .open_parented(&parent);
 
         self.vizia_state.open.store(true, Ordering::Release);
-        Box::new(ViziaEditorHandle {
+        Some(Box::new(ViziaEditorHandle {
             vizia_state: self.vizia_state.clone(),
             window,
-        })
+        }))
     }
 
     fn size(&self) -> (u32, u32) {
@@ -2,10 +2,12 @@
 
 use std::marker::PhantomData;
 
+use crate::midi::NoteEvent;
+
 mod blocks;
 mod samples;
 
-pub use blocks::{Block, BlockChannelsIter, BlocksIter};
+pub use blocks::{Block, BlockChannelsIter, BlocksIter, EventBlocksIter};
 pub use samples::{ChannelSamples, ChannelSamplesIter, SamplesIter};
 
 /// The audio buffers used during processing. This contains the output audio output buffers with the
@@ -63,7 +65,17 @@ impl<'a> Buffer<'a> {
         &self.output_slices
     }
 
-    /// Iterate over the samples, returning a channel iterator for each sample.
+    /// Iterate over the samples, returning a channel iterator for each sample. This works for any
+    /// number of channels. The sample for a channel can also be accessed directly by indexing into
+    /// the [`ChannelSamples`]:
+    ///
+    /// ```ignore
+    /// for mut channel_samples in buffer.iter_samples() {
+    ///     for channel_idx in 0..channel_samples.len() {
+    ///         channel_samples[channel_idx] *= gain;
+    ///     }
+    /// }
+    /// ```
     #[inline]
     pub fn iter_samples<'slice>(&'slice mut self) -> SamplesIter<'slice, 'a> {
         SamplesIter {
@@ -83,10 +95,8 @@ impl<'a> Buffer<'a> {
     /// SIMD.
     ///
     /// The parameter smoothers can also produce smoothed values for an entire block using
-    /// [`Smoother::next_block()`][crate::prelude::Smoother::next_block()]. Before using this, you
-    /// will need to call
-    /// [`Plugin::initialize_block_smoothers()`][crate::prelude::Plugin::initialize_block_smoothers()]
-    /// with the same `max_block_size` in your initialization function first.
+    /// [`Smoother::next_block()`][crate::prelude::Smoother::next_block()]. The slice passed to that
+    /// function needs to be able to hold at least `max_block_size` values.
     ///
     /// You can use this to obtain block-slices from a buffer so you can pass them to a library:
     ///
@@ -103,6 +113,8 @@ impl<'a> Buffer<'a> {
     /// ````
     #[inline]
     pub fn iter_blocks<'slice>(&'slice mut self, max_block_size: usize) -> BlocksIter<'slice, 'a> {
+        nih_debug_assert_ne!(max_block_size, 0);
+
         BlocksIter {
             buffers: self.output_slices.as_mut_slice(),
             max_block_size,
@@ -111,6 +123,76 @@ impl<'a> Buffer<'a> {
         }
     }
 
+    /// Split the buffer at the timing of every event in `events`. This yields blocks together with
+    /// the events that should be handled right before processing that block. Every event's timing
+    /// is equal to the start of the block it is yielded with, so the blocks cover the entire buffer
+    /// without any gaps or overlap and events with the same timing are yielded together. If the
+    /// first event does not occur at the start of the buffer, then the first block is yielded
+    /// with an empty slice of events. This makes it easy to handle note events with sample
+    /// accurate timing:
+    ///
+    /// ```ignore
+    /// for (mut block, events) in buffer.split_at_events(&note_events) {
+    ///     for event in events {
+    ///         self.handle_event(event);
+    ///     }
+    ///
+    ///     for channel_samples in block.iter_samples() {
+    ///         // Do something cool with the samples
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// The events need to be sorted by their timing, which is already the case for events you get
+    /// from [`ProcessContext::next_event()`][crate::prelude::ProcessContext::next_event()]. Events
+    /// with a timing past the end of the buffer are yielded with the last block.
+    #[inline]
+    pub fn split_at_events<'slice, 'events>(
+        &'slice mut self,
+        events: &'events [NoteEvent],
+    ) -> EventBlocksIter<'slice, 'a, 'events> {
+        EventBlocksIter {
+            buffers: self.output_slices.as_mut_slice(),
+            events,
+            current_block_start: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Multiply every sample in the buffer by `gain`.
+    pub fn apply_gain(&mut self, gain: f32) {
+        for channel in self.output_slices.iter_mut() {
+            for sample in channel.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
+
+    /// Set every sample in the buffer to zero.
+    pub fn clear(&mut self) {
+        for channel in self.output_slices.iter_mut() {
+            channel.fill(0.0);
+        }
+    }
+
+    /// Add the samples from `other` to this buffer after multiplying them by `mix`. Both buffers
+    /// need to have the same number of channels and samples. If they don't, then only the
+    /// overlapping part will be mixed in.
+    pub fn add_from(&mut self, other: &Buffer, mix: f32) {
+        nih_debug_assert_eq!(self.channels(), other.channels());
+        nih_debug_assert_eq!(self.len(), other.len());
+
+        for (channel, other_channel) in self
+            .output_slices
+            .iter_mut()
+            .zip(other.output_slices.iter())
+        {
+            for (sample, other_sample) in channel.iter_mut().zip(other_channel.iter()) {
+                *sample += other_sample * mix;
+            }
+        }
+    }
+
     /// Access the raw output slice vector. This needs to be resized to match the number of output
     /// channels during the plugin's initialization. Then during audio processing, these slices
     /// should be updated to point to the plugin's audio buffers.
@@ -190,4 +272,190 @@ mod miri {
             assert_eq!(real_buffers[0][i], 0.0);
         }
     }
+
+    #[test]
+    fn gain_and_clear() {
+        let mut real_buffers = vec![vec![1.0; 64]; 2];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.with_raw_vec(|output_slices| {
+                let (first_channel, other_channels) = real_buffers.split_at_mut(1);
+                *output_slices = vec![&mut first_channel[0], &mut other_channels[0]];
+            })
+        };
+
+        buffer.apply_gain(0.5);
+        assert!(buffer
+            .as_slice_immutable()
+            .iter()
+            .all(|channel| channel.iter().all(|sample| *sample == 0.5)));
+
+        buffer.clear();
+        assert!(buffer
+            .as_slice_immutable()
+            .iter()
+            .all(|channel| channel.iter().all(|sample| *sample == 0.0)));
+    }
+
+    #[test]
+    fn add_from_mono() {
+        let mut real_buffer = vec![1.0; 64];
+        let mut other_real_buffer = vec![2.0; 64];
+        let mut buffer = Buffer::default();
+        let mut other_buffer = Buffer::default();
+        unsafe {
+            buffer.with_raw_vec(|output_slices| *output_slices = vec![&mut real_buffer]);
+            other_buffer
+                .with_raw_vec(|output_slices| *output_slices = vec![&mut other_real_buffer]);
+        };
+
+        buffer.add_from(&other_buffer, 0.25);
+        assert!(buffer.as_slice_immutable()[0]
+            .iter()
+            .all(|sample| *sample == 1.5));
+        assert!(other_buffer.as_slice_immutable()[0]
+            .iter()
+            .all(|sample| *sample == 2.0));
+    }
+
+    #[test]
+    fn add_from_stereo() {
+        let mut real_buffers = [vec![1.0; 64], vec![-1.0; 64]];
+        let mut other_real_buffers = [vec![2.0; 64], vec![4.0; 64]];
+        let mut buffer = Buffer::default();
+        let mut other_buffer = Buffer::default();
+        unsafe {
+            buffer.with_raw_vec(|output_slices| {
+                let (first_channel, other_channels) = real_buffers.split_at_mut(1);
+                *output_slices = vec![&mut first_channel[0], &mut other_channels[0]];
+            });
+            other_buffer.with_raw_vec(|output_slices| {
+                let (first_channel, other_channels) = other_real_buffers.split_at_mut(1);
+                *output_slices = vec![&mut first_channel[0], &mut other_channels[0]];
+            });
+        };
+
+        buffer.add_from(&other_buffer, 0.5);
+        assert!(real_buffers[0].iter().all(|sample| *sample == 2.0));
+        assert!(real_buffers[1].iter().all(|sample| *sample == 1.0));
+    }
+
+    #[test]
+    fn blocks_cover_buffer() {
+        let mut real_buffers = vec![vec![0.0; 100]; 2];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.with_raw_vec(|output_slices| {
+                let (first_channel, other_channels) = real_buffers.split_at_mut(1);
+                *output_slices = vec![&mut first_channel[0], &mut other_channels[0]];
+            })
+        };
+
+        // 100 samples results in three full blocks and a final partial block
+        let blocks = buffer.iter_blocks(32);
+        assert_eq!(blocks.len(), 4);
+
+        let mut num_samples = 0;
+        for (block_offset, block) in blocks {
+            assert_eq!(block_offset, num_samples);
+            assert_eq!(block.len(), 32.min(100 - num_samples));
+
+            for (channel_idx, channel) in block.into_iter().enumerate() {
+                for (sample_idx, sample) in channel.iter_mut().enumerate() {
+                    *sample = (channel_idx * 1000 + block_offset + sample_idx) as f32;
+                }
+            }
+
+            num_samples += 32.min(100 - num_samples);
+        }
+        assert_eq!(num_samples, 100);
+
+        // Concatenating all blocks should result in the entire buffer, with every sample written
+        // exactly once
+        for (channel_idx, channel) in real_buffers.iter().enumerate() {
+            for (sample_idx, sample) in channel.iter().enumerate() {
+                assert_eq!(*sample, (channel_idx * 1000 + sample_idx) as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_without_channels() {
+        let mut buffer = Buffer::default();
+        let mut blocks = buffer.iter_blocks(32);
+        assert_eq!(blocks.len(), 0);
+        assert!(blocks.next().is_none());
+    }
+
+    #[test]
+    fn split_at_events() {
+        let mut real_buffers = vec![vec![0.0; 128]; 2];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.with_raw_vec(|output_slices| {
+                let (first_channel, other_channels) = real_buffers.split_at_mut(1);
+                *output_slices = vec![&mut first_channel[0], &mut other_channels[0]];
+            })
+        };
+
+        let note_on = |timing| NoteEvent::NoteOn {
+            timing,
+            voice_id: None,
+            channel: 0,
+            note: 60,
+            velocity: 1.0,
+        };
+        let events = [note_on(0), note_on(64), note_on(64)];
+
+        let blocks: Vec<_> = buffer
+            .split_at_events(&events)
+            .map(|(block, block_events)| (block.len(), block_events.len()))
+            .collect();
+        assert_eq!(blocks, [(64, 1), (64, 2)]);
+
+        // Without an event at the start of the buffer, the first block does not have any events
+        let blocks: Vec<_> = buffer
+            .split_at_events(&events[1..])
+            .map(|(block, block_events)| (block.len(), block_events.len()))
+            .collect();
+        assert_eq!(blocks, [(64, 0), (64, 2)]);
+
+        // And events past the end of the buffer are not lost
+        let blocks: Vec<_> = buffer
+            .split_at_events(&[note_on(200)])
+            .map(|(block, block_events)| (block.len(), block_events.len()))
+            .collect();
+        assert_eq!(blocks, [(127, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn samples_for_any_channel_count() {
+        for num_channels in [0, 1, 8] {
+            let mut real_buffers = vec![vec![0.0; 16]; num_channels];
+            let mut buffer = Buffer::default();
+            unsafe {
+                buffer.with_raw_vec(|output_slices| {
+                    *output_slices = real_buffers
+                        .iter_mut()
+                        .map(|channel| channel.as_mut_slice())
+                        .collect();
+                })
+            };
+
+            let samples = buffer.iter_samples();
+            assert_eq!(samples.len(), if num_channels == 0 { 0 } else { 16 });
+            for (sample_idx, mut channel_samples) in samples.enumerate() {
+                assert_eq!(channel_samples.len(), num_channels);
+                for channel_idx in 0..channel_samples.len() {
+                    channel_samples[channel_idx] = (channel_idx * 1000 + sample_idx) as f32;
+                }
+            }
+
+            for (channel_idx, channel) in real_buffers.iter().enumerate() {
+                for (sample_idx, sample) in channel.iter().enumerate() {
+                    assert_eq!(*sample, (channel_idx * 1000 + sample_idx) as f32);
+                }
+            }
+        }
+    }
 }
@@ -6,6 +6,7 @@ use std::marker::PhantomData;
 use std::simd::{LaneCount, Simd, SupportedLaneCount};
 
 use super::SamplesIter;
+use crate::midi::NoteEvent;
 
 /// An iterator over all samples in the buffer, slicing over the sample-dimension with a maximum
 /// size of `max_block_size`. See [`Buffer::iter_blocks()`][super::Buffer::iter_blocks()]. Yields
@@ -18,6 +19,18 @@ pub struct BlocksIter<'slice, 'sample: 'slice> {
     pub(super) _marker: PhantomData<&'slice mut [&'sample mut [f32]]>,
 }
 
+/// An iterator over all samples in the buffer, splitting the buffer at the timings of a list of
+/// note events. See [`Buffer::split_at_events()`][super::Buffer::split_at_events()]. Yields both
+/// the block and the events that should be handled at the start of that block.
+pub struct EventBlocksIter<'slice, 'sample: 'slice, 'events> {
+    /// The raw output buffers.
+    pub(super) buffers: *mut [&'sample mut [f32]],
+    /// The events that have not yet been yielded, sorted by timing.
+    pub(super) events: &'events [NoteEvent],
+    pub(super) current_block_start: usize,
+    pub(super) _marker: PhantomData<&'slice mut [&'sample mut [f32]]>,
+}
+
 /// A block yielded by [`BlocksIter`]. Can be iterated over once or multiple times, and also
 /// supports direct access to the block's samples if needed.
 pub struct Block<'slice, 'sample: 'slice> {
@@ -45,7 +58,7 @@ impl<'slice, 'sample> Iterator for BlocksIter<'slice, 'sample> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        let buffer_len = unsafe { (*self.buffers)[0].len() };
+        let buffer_len = self.buffer_len();
         if self.current_block_start < buffer_len {
             let current_block_start = self.current_block_start;
             let current_block_end =
@@ -67,13 +80,65 @@ impl<'slice, 'sample> Iterator for BlocksIter<'slice, 'sample> {
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let remaining = ((unsafe { (*self.buffers)[0].len() } - self.current_block_start) as f32
-            / self.max_block_size as f32)
-            .ceil() as usize;
+        let remaining = self
+            .buffer_len()
+            .saturating_sub(self.current_block_start)
+            .div_ceil(self.max_block_size);
         (remaining, Some(remaining))
     }
 }
 
+impl<'slice, 'sample, 'events> Iterator for EventBlocksIter<'slice, 'sample, 'events> {
+    type Item = (Block<'slice, 'sample>, &'events [NoteEvent]);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let buffer_len = buffer_len(self.buffers);
+        if self.current_block_start >= buffer_len {
+            return None;
+        }
+
+        // Events that are out of bounds are handled at the start of the last block so they don't
+        // get lost
+        let event_timing = |event: &NoteEvent| (event.timing() as usize).min(buffer_len - 1);
+        let num_block_events = self
+            .events
+            .iter()
+            .take_while(|event| event_timing(event) <= self.current_block_start)
+            .count();
+        let (block_events, remaining_events) = self.events.split_at(num_block_events);
+
+        let current_block_start = self.current_block_start;
+        let current_block_end = remaining_events.first().map_or(buffer_len, event_timing);
+        let block = Block {
+            buffers: self.buffers,
+            current_block_start,
+            current_block_end,
+            _marker: self._marker,
+        };
+
+        self.events = remaining_events;
+        self.current_block_start = current_block_end;
+
+        Some((block, block_events))
+    }
+}
+
+impl BlocksIter<'_, '_> {
+    /// The total number of samples in the buffer.
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        buffer_len(self.buffers)
+    }
+}
+
+/// The total number of samples in the buffer. Buffers for plugins without any audio outputs don't
+/// have any channels, so this can't just look at the first channel.
+#[inline]
+fn buffer_len(buffers: *mut [&mut [f32]]) -> usize {
+    unsafe { (*buffers).first().map_or(0, |channel| channel.len()) }
+}
+
 impl<'slice, 'sample> IntoIterator for Block<'slice, 'sample> {
     type Item = &'sample mut [f32];
     type IntoIter = BlockChannelsIter<'slice, 'sample>;
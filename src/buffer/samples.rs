@@ -1,6 +1,7 @@
 //! Per-sample per-channel iterators.
 
 use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
 
 #[cfg(feature = "simd")]
 use std::simd::{LaneCount, Simd, SupportedLaneCount};
@@ -61,7 +62,8 @@ impl<'slice, 'sample> Iterator for SamplesIter<'slice, 'sample> {
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let remaining = unsafe { (*self.buffers)[0].len() } - self.current_sample;
+        // This also works for buffers without any channels
+        let remaining = self.samples_end - self.current_sample;
         (remaining, Some(remaining))
     }
 }
@@ -111,6 +113,26 @@ impl<'slice, 'sample> Iterator for ChannelSamplesIter<'slice, 'sample> {
     }
 }
 
+impl Index<usize> for ChannelSamples<'_, '_> {
+    type Output = f32;
+
+    /// Access the sample for a channel by index. Panics if `channel_index` is out of bounds.
+    #[inline]
+    fn index(&self, channel_index: usize) -> &Self::Output {
+        // SAFETY: The sample bound has already been checked
+        unsafe { (*self.buffers)[channel_index].get_unchecked(self.current_sample) }
+    }
+}
+
+impl IndexMut<usize> for ChannelSamples<'_, '_> {
+    /// Access the sample for a channel by index. Panics if `channel_index` is out of bounds.
+    #[inline]
+    fn index_mut(&mut self, channel_index: usize) -> &mut Self::Output {
+        // SAFETY: The sample bound has already been checked
+        unsafe { (*self.buffers)[channel_index].get_unchecked_mut(self.current_sample) }
+    }
+}
+
 impl ExactSizeIterator for SamplesIter<'_, '_> {}
 impl ExactSizeIterator for ChannelSamplesIter<'_, '_> {}
 
@@ -5,6 +5,7 @@ use std::fmt::Display;
 use crate::midi::NoteEvent;
 use crate::param::internals::ParamPtr;
 use crate::param::Param;
+use crate::plugin::ProcessMode;
 use crate::wrapper::state::PluginState;
 
 /// Callbacks the plugin can make while it is being initialized. This is passed to the plugin during
@@ -20,6 +21,10 @@ pub trait InitContext {
 
     /// Update the current latency of the plugin. If the plugin is currently processing audio, then
     /// this may cause audio playback to be restarted.
+    ///
+    /// Plugins with a fixed amount of latency should call this from
+    /// [`Plugin::initialize()`][crate::prelude::Plugin::initialize()] so the host knows about the
+    /// latency before it starts processing audio.
     fn set_latency_samples(&self, samples: u32);
 }
 
@@ -27,6 +32,10 @@ pub trait InitContext {
 /// is how a plugin sends and receives note events, gets transport information, and accesses
 /// sidechain inputs and auxiliary outputs. This is passed to the plugin during as part of
 /// [`Plugin::process()`][crate::plugin::Plugin::process()].
+///
+/// Messages sent by the editor through a
+/// [`gui_to_dsp_channel()`][crate::util::gui_to_dsp_channel()] can be received from the
+/// [`DspReceiver`][crate::util::DspReceiver] stored on the plugin.
 //
 // # Safety
 //
@@ -36,9 +45,26 @@ pub trait ProcessContext {
     /// Get the current plugin API.
     fn plugin_api(&self) -> PluginApi;
 
+    /// A number uniquely identifying this instance of the plugin within the current process. This
+    /// is assigned when the plugin instance is created and it stays the same for the instance's
+    /// entire lifetime, which makes it useful for telling instances apart in log messages or for
+    /// keying resources shared between instances. These IDs are not stable across runs. The same
+    /// ID is returned by [`GuiContext::plugin_instance_id()`].
+    fn plugin_instance_id(&self) -> u64;
+
     /// Get information about the current transport position and status.
     fn transport(&self) -> &Transport;
 
+    /// Get the current processing mode, for instance to switch to a higher quality algorithm while
+    /// the host is rendering offline. This is [`ProcessMode::Realtime`] when the host hasn't
+    /// specified a mode. The same value is also passed to
+    /// [`Plugin::initialize()`][crate::prelude::Plugin::initialize()] as part of the
+    /// [`BufferConfig`][crate::prelude::BufferConfig], but not every host reinitializes the plugin
+    /// when the mode changes. Defaults to [`ProcessMode::Realtime`].
+    fn process_mode(&self) -> ProcessMode {
+        ProcessMode::Realtime
+    }
+
     /// Returns the next note event, if there is one. Use [`NoteEvent::timing()`] to get the event's
     /// timing within the buffer. Only available when
     /// [`Plugin::MIDI_INPUT`][crate::prelude::Plugin::MIDI_INPUT] is set.
@@ -74,12 +100,22 @@ pub trait ProcessContext {
     fn next_event(&mut self) -> Option<NoteEvent>;
 
     /// Send an event to the host. Only available when
-    /// [`Plugin::MIDI_OUTPUT`][crate::prelude::Plugin::MIDI_INPUT] is set. Will not do anything
-    /// otherwise.
+    /// [`Plugin::MIDI_OUTPUT`][crate::prelude::Plugin::MIDI_OUTPUT] is set. Will not do anything
+    /// otherwise. The events are sent to the host after the current block has been processed, in
+    /// order of their timing. The event's timing should be within the current block. Events with
+    /// timings past the end of the block will be moved to the block's last sample.
+    ///
+    /// [`NoteEvent::VoiceTerminated`] is the exception to the `MIDI_OUTPUT` requirement. That event
+    /// only requires [`Plugin::MIDI_INPUT`][crate::prelude::Plugin::MIDI_INPUT] to be set, see its
+    /// documentation for more information.
     fn send_event(&mut self, event: NoteEvent);
 
     /// Update the current latency of the plugin. If the plugin is currently processing audio, then
     /// this may cause audio playback to be restarted.
+    ///
+    /// This is realtime safe and it can be called every processing cycle. The host is only
+    /// notified when the latency actually changes, and that notification happens asynchronously
+    /// on the main thread.
     fn set_latency_samples(&self, samples: u32);
 
     // TODO: Add this, this works similar to [GuiContext::set_parameter] but it adds the parameter
@@ -91,6 +127,12 @@ pub trait ProcessContext {
 /// Callbacks the plugin can make when the user interacts with its GUI such as updating parameter
 /// values. This is passed to the plugin during [`Editor::spawn()`][crate::prelude::Editor::spawn()]. All of
 /// these functions assume they're being called from the main GUI thread.
+///
+/// Use [`ParamSetter::get_parameter()`] to read the parameter values that should be displayed in
+/// the editor.
+///
+/// Non-parameter state can be sent from the editor to the audio thread using a
+/// [`gui_to_dsp_channel()`][crate::util::gui_to_dsp_channel()].
 //
 // # Safety
 //
@@ -101,14 +143,41 @@ pub trait GuiContext: Send + Sync + 'static {
     /// about screen.
     fn plugin_api(&self) -> PluginApi;
 
+    /// A number uniquely identifying this instance of the plugin within the current process. See
+    /// [`ProcessContext::plugin_instance_id()`].
+    fn plugin_instance_id(&self) -> u64;
+
     /// Ask the host to resize the editor window to the size specified by
     /// [`Editor::size()`][crate::prelude::Editor::size()]. This will return false if the host
     /// somehow didn't like this and rejected the resize, in which case the window should revert to
     /// its old size. You should only actually resize your embedded window once this returns `true`.
+    /// Change the value returned from `Editor::size()` first, and then call this function to let
+    /// the host know about the new size, for instance after showing or hiding a panel.
+    ///
+    /// The VST3 wrapper can only report the host's answer when this is called from the host's GUI
+    /// thread. In any other case the resize request is dispatched to that thread and this function
+    /// optimistically returns `true`.
     ///
     /// TODO: Host->Plugin resizing has not been implemented yet
     fn request_resize(&self) -> bool;
 
+    /// The DPI scaling factor the host or the standalone wrapper has set for the editor through
+    /// [`Editor::set_scale_factor()`][crate::prelude::Editor::set_scale_factor()], or 1.0 if no
+    /// factor has been set. This is always 1.0 on macOS since the OS handles the scaling there.
+    /// The plugin's editor APIs deal in logical pixels, so this is only needed when the editor
+    /// needs to draw at the physical resolution, or when it needs to convert physical window
+    /// sizes back to logical sizes. Defaults to 1.0.
+    fn scale_factor(&self) -> f32 {
+        1.0
+    }
+
+    /// Whether the host is currently reading or writing automation for the plugin's parameters,
+    /// if the host reports this. Use [`ParamSetter::automation_state()`] instead. Defaults to
+    /// [`AutomationState::Unknown`].
+    fn automation_state(&self) -> AutomationState {
+        AutomationState::Unknown
+    }
+
     /// Inform the host a parameter will be automated. Create a [`ParamSetter`] and use
     /// [`ParamSetter::begin_set_parameter()`] instead for a safe, user friendly API.
     ///
@@ -137,8 +206,27 @@ pub trait GuiContext: Send + Sync + 'static {
     /// mostly marked as unsafe for API reasons.
     unsafe fn raw_end_set_parameter(&self, param: ParamPtr);
 
+    /// Look up a parameter by the ID it has in the plugin's
+    /// [`Params::param_map()`][crate::param::internals::Params::param_map()]. Returns `None` if the
+    /// plugin doesn't have a parameter with that ID. Use [`ParamSetter::set_parameter_by_id()`] and
+    /// [`ParamSetter::get_parameter_by_id()`] instead. The default implementation doesn't know
+    /// about any parameters and always returns `None`.
+    fn raw_param_by_id(&self, _id: &str) -> Option<ParamPtr> {
+        None
+    }
+
+    /// Start grouping parameter changes into a single gesture. Use
+    /// [`ParamSetter::begin_gesture()`] instead. Does nothing by default, in which case every
+    /// parameter change is its own gesture.
+    fn raw_begin_gesture(&self) {}
+
+    /// Finish the gesture started by [`raw_begin_gesture()`][Self::raw_begin_gesture()]. Use
+    /// [`ParamSetter::end_gesture()`] instead. Does nothing by default.
+    fn raw_end_gesture(&self) {}
+
     /// Serialize the plugin's current state to a serde-serializable object. Useful for implementing
-    /// preset handling within a plugin's GUI.
+    /// preset handling or undo snapshots within a plugin's GUI. This contains the unmodulated values
+    /// for all parameters as well as all of the `Params` object's persisted fields.
     fn get_state(&self) -> PluginState;
 
     /// Restore the state from a previously serialized state object. This will block the GUI thread
@@ -205,7 +293,8 @@ pub struct Transport {
 
 /// A convenience helper for setting parameter values. Any changes made here will be broadcasted to
 /// the host and reflected in the plugin's [`Params`][crate::param::internals::Params] object. These
-/// functions should only be called from the main thread.
+/// functions should only be called from the main thread. This also works for parameters marked as
+/// [`READ_ONLY`][crate::param::ParamFlags::READ_ONLY], which the host itself cannot change.
 pub struct ParamSetter<'a> {
     pub raw_context: &'a dyn GuiContext,
 }
@@ -229,6 +318,23 @@ impl Display for PluginApi {
     }
 }
 
+/// The host's automation mode as reported through [`GuiContext::automation_state()`]. This applies
+/// to all of the plugin's parameters. Right now only the VST3 wrapper knows about the host's
+/// automation mode, and only if the host implements `IAutomationState`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutomationState {
+    /// The host doesn't report its automation mode.
+    Unknown,
+    /// Automation is neither read nor written.
+    Off,
+    /// The host is playing back automation.
+    Read,
+    /// The host is recording automation.
+    Write,
+    /// The host is playing back automation while also recording changes to it.
+    ReadWrite,
+}
+
 // TODO: These conversions have not really been tested yet, there might be an error in there somewhere
 impl Transport {
     /// Initialize the transport struct without any information.
@@ -469,4 +575,251 @@ impl<'a> ParamSetter<'a> {
     pub fn end_set_parameter<P: Param>(&self, param: &P) {
         unsafe { self.raw_context.raw_end_set_parameter(param.as_ptr()) };
     }
+
+    /// Reset a parameter to its default value, for instance when the user double clicks on a knob.
+    /// Unlike [`set_parameter()`][Self::set_parameter()], this sends a complete automation gesture
+    /// to the host so the reset gets recorded properly. This works the same way for every
+    /// parameter type.
+    pub fn set_parameter_to_default<P: Param>(&self, param: &P) {
+        let ptr = param.as_ptr();
+        let normalized = param.default_normalized_value();
+        unsafe {
+            self.raw_context.raw_begin_set_parameter(ptr);
+            self.raw_context
+                .raw_set_parameter_normalized(ptr, normalized);
+            self.raw_context.raw_end_set_parameter(ptr);
+        }
+    }
+
+    /// Set the parameter with the ID `id` to an already normalized value. This is useful when you
+    /// only know a parameter's ID at runtime, for instance when implementing MIDI learn. The ID is
+    /// the same one used in the plugin's
+    /// [`Params::param_map()`][crate::param::internals::Params::param_map()]. Unlike
+    /// [`set_parameter_normalized()`][Self::set_parameter_normalized()], this sends a complete
+    /// automation gesture to the host. Wrap multiple calls in
+    /// [`begin_gesture()`][Self::begin_gesture()] and [`end_gesture()`][Self::end_gesture()] if
+    /// they should be treated as a single change.
+    ///
+    /// Returns `false` if the plugin doesn't have a parameter with that ID.
+    pub fn set_parameter_by_id(&self, id: &str, normalized: f32) -> bool {
+        match self.raw_context.raw_param_by_id(id) {
+            Some(param) => {
+                unsafe {
+                    self.raw_context.raw_begin_set_parameter(param);
+                    self.raw_context
+                        .raw_set_parameter_normalized(param, normalized);
+                    self.raw_context.raw_end_set_parameter(param);
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the unmodulated normalized value of the parameter with the ID `id`. See
+    /// [`set_parameter_by_id()`][Self::set_parameter_by_id()]. Returns `None` if the plugin doesn't
+    /// have a parameter with that ID.
+    pub fn get_parameter_by_id(&self, id: &str) -> Option<f32> {
+        self.raw_context
+            .raw_param_by_id(id)
+            .map(|param| unsafe { param.unmodulated_normalized_value() })
+    }
+
+    /// Get a parameter's current plain value for displaying it in the editor. This is the
+    /// parameter's unmodulated value, which is the most recent value set by either the host or the
+    /// editor. It does not include any modulation applied by the host, and it is not the per-sample
+    /// value from the parameter's [`Smoother`][crate::prelude::Smoother] that should be used for
+    /// DSP. Reading a smoother from the GUI thread would interfere with the audio thread.
+    pub fn get_parameter<P: Param>(&self, param: &P) -> P::Plain {
+        param.unmodulated_plain_value()
+    }
+
+    /// The same as [`get_parameter()`][Self::get_parameter()], but returns the parameter's
+    /// normalized value instead. This is useful for drawing knobs and sliders.
+    pub fn get_parameter_normalized<P: Param>(&self, param: &P) -> f32 {
+        param.unmodulated_normalized_value()
+    }
+
+    /// Get a parameter's normalized value with the host's monophonic modulation applied to it. This
+    /// can be compared to [`get_parameter_normalized()`][Self::get_parameter_normalized()] to draw
+    /// a ring around a knob showing where the host's modulation is currently pushing the parameter.
+    /// Only CLAP hosts can modulate parameters this way, so for other plugin APIs this will always
+    /// be the same as the unmodulated value.
+    ///
+    /// The modulation is applied on the audio thread and the editor is not notified when it
+    /// changes, so this should be polled when redrawing the editor. Polyphonic modulation is not
+    /// included since that is different for every voice.
+    pub fn get_parameter_modulated_normalized<P: Param>(&self, param: &P) -> f32 {
+        param.normalized_value()
+    }
+
+    /// Start a gesture that changes multiple parameters at once, for instance when dragging an XY
+    /// pad. Every parameter changed with [`set_parameter()`][Self::set_parameter()] or
+    /// [`set_parameter_normalized()`][Self::set_parameter_normalized()] until
+    /// [`end_gesture()`][Self::end_gesture()] is called becomes part of this gesture, so the host
+    /// can treat all of those changes as a single undo step. The
+    /// [`begin_set_parameter()`][Self::begin_set_parameter()] and
+    /// [`end_set_parameter()`][Self::end_set_parameter()] calls for those parameters are optional
+    /// during the gesture, and they will be handled when the gesture ends.
+    ///
+    /// Gestures cannot be nested. Every call to this function needs to be followed by exactly one
+    /// call to [`end_gesture()`][Self::end_gesture()].
+    pub fn begin_gesture(&self) {
+        self.raw_context.raw_begin_gesture();
+    }
+
+    /// Finish the gesture started by [`begin_gesture()`][Self::begin_gesture()]. This informs the
+    /// host that all of the parameters that were changed during the gesture are no longer being
+    /// automated.
+    pub fn end_gesture(&self) {
+        self.raw_context.raw_end_gesture();
+    }
+
+    /// Whether the host is currently reading or writing automation, for instance to show whether
+    /// a control will be overridden by automation. Returns [`AutomationState::Unknown`] if the
+    /// host does not report this.
+    pub fn automation_state(&self) -> AutomationState {
+        self.raw_context.automation_state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::param::range::FloatRange;
+    use crate::param::{FloatParam, ParamMut};
+    use crate::wrapper::state::ParamValue;
+
+    /// Records the parameter calls made through a [`ParamSetter`].
+    struct RecordingContext {
+        param: FloatParam,
+        calls: Mutex<Vec<&'static str>>,
+    }
+
+    impl RecordingContext {
+        /// A context for a single gain parameter with a default value of 0.25.
+        fn new() -> Self {
+            Self {
+                param: FloatParam::new("Gain", 0.25, FloatRange::Linear { min: 0.0, max: 1.0 }),
+                calls: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl GuiContext for RecordingContext {
+        fn plugin_api(&self) -> PluginApi {
+            PluginApi::Standalone
+        }
+
+        fn plugin_instance_id(&self) -> u64 {
+            0
+        }
+
+        fn request_resize(&self) -> bool {
+            false
+        }
+
+        fn automation_state(&self) -> AutomationState {
+            AutomationState::Read
+        }
+
+        unsafe fn raw_begin_set_parameter(&self, _param: ParamPtr) {
+            self.calls.lock().unwrap().push("begin");
+        }
+
+        unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
+            self.calls.lock().unwrap().push("set");
+            param.set_normalized_value(normalized);
+        }
+
+        unsafe fn raw_end_set_parameter(&self, _param: ParamPtr) {
+            self.calls.lock().unwrap().push("end");
+        }
+
+        fn raw_param_by_id(&self, id: &str) -> Option<ParamPtr> {
+            (id == "gain").then(|| self.param.as_ptr())
+        }
+
+        fn get_state(&self) -> PluginState {
+            PluginState {
+                version: 0,
+                params: [(
+                    String::from("gain"),
+                    ParamValue::F32(self.param.unmodulated_plain_value()),
+                )]
+                .into(),
+                fields: HashMap::new(),
+            }
+        }
+
+        fn set_state(&self, state: PluginState) {
+            if let Some(ParamValue::F32(gain)) = state.params.get("gain") {
+                unsafe {
+                    self.param
+                        .as_ptr()
+                        .set_normalized_value(self.param.preview_normalized(*gain))
+                };
+            }
+        }
+    }
+
+    #[test]
+    fn modulated_parameter_values() {
+        let mut context = RecordingContext::new();
+        context.param.modulate_value(0.5);
+
+        let setter = ParamSetter::new(&context);
+        assert_eq!(setter.get_parameter_normalized(&context.param), 0.25);
+        assert_eq!(
+            setter.get_parameter_modulated_normalized(&context.param),
+            0.75
+        );
+    }
+
+    #[test]
+    fn reset_to_default() {
+        let context = RecordingContext::new();
+        let setter = ParamSetter::new(&context);
+
+        assert!(setter.set_parameter_by_id("gain", 0.5));
+        assert_eq!(setter.get_parameter(&context.param), 0.5);
+
+        setter.set_parameter_to_default(&context.param);
+        assert_eq!(
+            setter.get_parameter(&context.param),
+            context.param.default_plain_value()
+        );
+        assert_eq!(
+            *context.calls.lock().unwrap(),
+            ["begin", "set", "end", "begin", "set", "end"]
+        );
+    }
+
+    #[test]
+    fn parameters_by_id() {
+        let context = RecordingContext::new();
+        let setter = ParamSetter::new(&context);
+
+        assert_eq!(setter.get_parameter_by_id("gain"), Some(0.25));
+        assert_eq!(setter.get_parameter_by_id("gian"), None);
+
+        assert_eq!(setter.get_parameter(&context.param), 0.25);
+        assert_eq!(setter.get_parameter_normalized(&context.param), 0.25);
+
+        assert!(setter.set_parameter_by_id("gain", 0.5));
+        assert!(!setter.set_parameter_by_id("gian", 0.5));
+        assert_eq!(*context.calls.lock().unwrap(), ["begin", "set", "end"]);
+    }
+
+    #[test]
+    fn automation_state() {
+        let context = RecordingContext::new();
+        let setter = ParamSetter::new(&context);
+
+        assert_eq!(setter.automation_state(), AutomationState::Read);
+    }
 }
@@ -13,6 +13,11 @@
 ///   `OutputDebugString()`.
 /// - A file path, in which case the output gets appended to the end of that file which will be
 ///   created if necessary.
+///
+/// Messages can be filtered by setting the `NIH_LOG_LEVEL` environment variable to `error`, `warn`,
+/// `info`, `debug`, `trace`, or `off`. Messages below that level are skipped before their arguments
+/// are formatted. `nih_log!()` logs at the info level, and [`nih_warn!()`], [`nih_error!()`],
+/// [`nih_trace!()`], and [`nih_dbg!()`] can be used to log at the other levels.
 #[macro_export]
 macro_rules! nih_log {
     ($($args:tt)*) => (
@@ -21,6 +26,16 @@ macro_rules! nih_log {
 }
 pub use nih_log;
 
+/// Similar to `nih_log!()`, but logged at the warning level. Used for recoverable problems the user
+/// should know about.
+#[macro_export]
+macro_rules! nih_warn {
+    ($($args:tt)*) => (
+        $crate::log::warn!($($args)*)
+    );
+}
+pub use nih_warn;
+
 /// Similar to `nih_log!()`, more scream-y. Used for printing fatal errors.
 #[macro_export]
 macro_rules! nih_error {
@@ -63,71 +78,127 @@ macro_rules! nih_dbg {
 pub use nih_dbg;
 
 /// A `debug_assert!()` analogue that prints the error with line number information instead of
-/// panicking.
+/// panicking. Panicking inside of a plugin would take down the entire host, and unwinding across
+/// the FFI boundary is undefined behavior.
 ///
-/// TODO: Detect if we're running under a debugger, and trigger a break if we are
+/// Like all of the `nih_debug_assert*!()` macros, the condition is only evaluated in debug builds.
+/// In release builds it still needs to compile, but it is never evaluated and the entire check is
+/// optimized out. Failed assertions are logged at the debug level. If the `debug_break` feature is
+/// enabled, then they also break into the debugger.
 #[macro_export]
 macro_rules! nih_debug_assert {
+    // The condition is bound to a variable first, since negating a comparison directly would
+    // trigger Clippy lints at the call site
     ($cond:expr $(,)?) => (
-        if cfg!(debug_assertions) && !$cond {
-            $crate::util::permit_alloc(|| $crate::log::debug!(concat!("Debug assertion failed: ", stringify!($cond))));
+        if cfg!(debug_assertions) {
+            let cond: bool = $cond;
+            if !cond {
+                $crate::nih_debug_assert_failure!(stringify!($cond));
+            }
         }
     );
     ($cond:expr, $format:expr $(, $($args:tt)*)?) => (
-        if cfg!(debug_assertions) && !$cond {
-            $crate::util::permit_alloc(|| $crate::log::debug!(concat!("Debug assertion failed: ", stringify!($cond), ", ", $format), $($($args)*)?));
+        if cfg!(debug_assertions) {
+            let cond: bool = $cond;
+            if !cond {
+                $crate::nih_debug_assert_failure!(concat!(stringify!($cond), ", ", $format) $(, $($args)*)?);
+            }
         }
     );
 }
 pub use nih_debug_assert;
 
 /// An unconditional debug assertion failure, for if the condition has already been checked
-/// elsewhere.
+/// elsewhere. See [`nih_debug_assert!()`] for more information.
 #[macro_export]
 macro_rules! nih_debug_assert_failure {
     () => (
         if cfg!(debug_assertions) {
             $crate::util::permit_alloc(|| $crate::log::debug!("Debug assertion failed"));
+            $crate::debug::debug_break();
         }
     );
     ($format:expr $(, $($args:tt)*)?) => (
         if cfg!(debug_assertions) {
             $crate::util::permit_alloc(|| $crate::log::debug!(concat!("Debug assertion failed: ", $format), $($($args)*)?));
+            $crate::debug::debug_break();
         }
     );
 }
 pub use nih_debug_assert_failure;
 
 /// A `debug_assert_eq!()` analogue that prints the error with line number information instead of
-/// panicking.
+/// panicking. See [`nih_debug_assert!()`] for more information.
 #[macro_export]
 macro_rules! nih_debug_assert_eq {
     ($left:expr, $right:expr $(,)?) => (
         if cfg!(debug_assertions) && $left != $right {
-            $crate::util::permit_alloc(|| $crate::log::debug!(concat!("Debug assertion failed: ", stringify!($left), " != ", stringify!($right))));
+            $crate::nih_debug_assert_failure!(concat!(stringify!($left), " != ", stringify!($right)));
         }
     );
     ($left:expr, $right:expr, $format:expr $(, $($args:tt)*)?) => (
         if cfg!(debug_assertions) && $left != $right  {
-            $crate::util::permit_alloc(|| $crate::log::debug!(concat!("Debug assertion failed: ", stringify!($left), " != ", stringify!($right), ", ", $format), $($($args)*)?));
+            $crate::nih_debug_assert_failure!(concat!(stringify!($left), " != ", stringify!($right), ", ", $format) $(, $($args)*)?);
         }
     );
 }
 pub use nih_debug_assert_eq;
 
 /// A `debug_assert_ne!()` analogue that prints the error with line number information instead of
-/// panicking.
+/// panicking. See [`nih_debug_assert!()`] for more information.
 #[macro_export]
 macro_rules! nih_debug_assert_ne {
     ($left:expr, $right:expr $(,)?) => (
         if cfg!(debug_assertions) && $left == $right {
-            $crate::util::permit_alloc(|| $crate::log::debug!(concat!("Debug assertion failed: ", stringify!($left), " == ", stringify!($right))));
+            $crate::nih_debug_assert_failure!(concat!(stringify!($left), " == ", stringify!($right)));
         }
     );
     ($left:expr, $right:expr, $format:expr $(, $($args:tt)*)?) => (
         if cfg!(debug_assertions) && $left == $right  {
-            $crate::util::permit_alloc(|| $crate::log::debug!(concat!("Debug assertion failed: ", stringify!($left), " == ", stringify!($right), ", ", $format), $($($args)*)?));
+            $crate::nih_debug_assert_failure!(concat!(stringify!($left), " == ", stringify!($right), ", ", $format) $(, $($args)*)?);
         }
     );
 }
 pub use nih_debug_assert_ne;
+
+/// Called by the `nih_debug_assert*!()` macros when an assertion fails. With the `debug_break`
+/// feature enabled this triggers a breakpoint, and it does nothing otherwise.
+#[doc(hidden)]
+#[inline]
+pub fn debug_break() {
+    #[cfg(feature = "debug_break")]
+    #[allow(unused_unsafe)]
+    unsafe {
+        std::intrinsics::breakpoint()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn assertions_only_evaluated_in_debug_builds() {
+        let mut evaluated = false;
+        let mut condition = || {
+            evaluated = true;
+            false
+        };
+        // Failing assertions must never panic
+        nih_debug_assert!(condition());
+
+        assert_eq!(evaluated, cfg!(debug_assertions));
+    }
+
+    #[test]
+    fn assertion_arguments_only_evaluated_in_debug_builds() {
+        let mut evaluated = 0;
+        let mut operand = || {
+            evaluated += 1;
+            1
+        };
+        nih_debug_assert_eq!(operand(), 2, "with {}", "arguments");
+        nih_debug_assert_ne!(operand(), 1);
+        nih_debug_assert_failure!("unconditional");
+
+        assert_eq!(evaluated, if cfg!(debug_assertions) { 2 } else { 0 });
+    }
+}
@@ -0,0 +1,277 @@
+//! A thin [baseview](https://github.com/RustAudio/baseview) based [`Editor`] for plugging in
+//! your own GUI or drawing library. This takes care of opening the window inside of the host's
+//! parent window, of keeping track of the window's size and scale factor, and of giving your
+//! callback a [`ParamSetter`] to interact with the plugin's parameters. Only enabled with the
+//! `editor` feature.
+//!
+//! The callback receives every window event from baseview, as well as an [`EditorEvent::Frame`]
+//! event once per frame that can be used to redraw the GUI. For instance, a single knob that
+//! controls a gain parameter by dragging the mouse up and down could look like this:
+//!
+//! ```no_run
+//! use nih_plug::editor::baseview::{Event, EventStatus, MouseEvent};
+//! use nih_plug::editor::{create_baseview_editor, EditorEvent, EditorState};
+//! use nih_plug::prelude::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Params)]
+//! struct GainParams {
+//!     #[id = "gain"]
+//!     pub gain: FloatParam,
+//! }
+//!
+//! #[derive(Default)]
+//! struct KnobDrag {
+//!     dragging: bool,
+//!     last_y: f64,
+//! }
+//!
+//! fn create_editor(params: Arc<GainParams>) -> Option<Box<dyn Editor>> {
+//!     let editor_state = EditorState::from_size(100, 100);
+//!     create_baseview_editor(
+//!         editor_state,
+//!         KnobDrag::default(),
+//!         move |_window, event, setter, drag| {
+//!             match event {
+//!                 EditorEvent::Frame => {
+//!                     // Draw the knob at `params.gain.unmodulated_normalized_value()` here using
+//!                     // your drawing library of choice
+//!                 }
+//!                 EditorEvent::Window(Event::Mouse(MouseEvent::ButtonPressed { .. })) => {
+//!                     setter.begin_set_parameter(&params.gain);
+//!                     drag.dragging = true;
+//!                 }
+//!                 EditorEvent::Window(Event::Mouse(MouseEvent::CursorMoved { position, .. })) => {
+//!                     if drag.dragging {
+//!                         let delta = (drag.last_y - position.y) as f32 / 200.0;
+//!                         let value = params.gain.unmodulated_normalized_value() + delta;
+//!                         setter.set_parameter_normalized(&params.gain, value.clamp(0.0, 1.0));
+//!                     }
+//!                     drag.last_y = position.y;
+//!                 }
+//!                 EditorEvent::Window(Event::Mouse(MouseEvent::ButtonReleased { .. })) => {
+//!                     setter.end_set_parameter(&params.gain);
+//!                     drag.dragging = false;
+//!                 }
+//!                 _ => return EventStatus::Ignored,
+//!             }
+//!
+//!             EventStatus::Captured
+//!         },
+//!     )
+//! }
+//! ```
+
+// This is re-exported so the editor's callback can use the exact same baseview version
+pub use baseview;
+
+use baseview::{
+    Event, EventStatus, Size, Window, WindowEvent, WindowHandle, WindowHandler, WindowOpenOptions,
+    WindowScalePolicy,
+};
+use crossbeam::atomic::AtomicCell;
+use parking_lot::RwLock;
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::context::{GuiContext, ParamSetter};
+use crate::plugin::{Editor, ParentWindowHandle};
+
+/// An event passed to the callback of an editor created with [`create_baseview_editor()`].
+pub enum EditorEvent<'a> {
+    /// Sent once per frame. The GUI should be redrawn in response to this event.
+    Frame,
+    /// Any other event baseview sent to the window, like mouse and keyboard events. Resize events
+    /// are also passed through after the [`EditorState`]'s size has been updated.
+    Window(&'a Event),
+}
+
+/// The type of the callback passed to [`create_baseview_editor()`].
+type EditorCallback<T> =
+    dyn Fn(&mut Window, EditorEvent, &ParamSetter, &mut T) -> EventStatus + 'static + Send + Sync;
+
+/// Create an [`Editor`] instance that opens a baseview window and passes all of that window's
+/// events to `callback`. The user state parameter can be used to keep track of GUI-only state,
+/// like the mouse position while dragging a knob. The [`EditorState`] contains the window's
+/// initial size, and this is kept in sync whenever the window gets resized. If you want this size
+/// to be persisted when restoring a plugin instance, then you can store it in a
+/// `#[persist = "key"]` field on your parameters struct.
+///
+/// The callback's return value tells baseview whether the event was handled. The return value is
+/// ignored for [`EditorEvent::Frame`] events.
+pub fn create_baseview_editor<T, F>(
+    editor_state: Arc<EditorState>,
+    user_state: T,
+    callback: F,
+) -> Option<Box<dyn Editor>>
+where
+    T: 'static + Send + Sync,
+    F: Fn(&mut Window, EditorEvent, &ParamSetter, &mut T) -> EventStatus + 'static + Send + Sync,
+{
+    Some(Box::new(BaseviewEditor {
+        editor_state,
+        user_state: Arc::new(RwLock::new(user_state)),
+        callback: Arc::new(callback),
+
+        // We can't get the size of the window when baseview does its own scaling, so if the host
+        // does not set a scale factor on Windows or Linux we'll use a factor of 1
+        #[cfg(target_os = "macos")]
+        scaling_factor: AtomicCell::new(None),
+        #[cfg(not(target_os = "macos"))]
+        scaling_factor: AtomicCell::new(Some(1.0)),
+    }))
+}
+
+/// State for an editor created with [`create_baseview_editor()`], shared between the editor and
+/// the plugin.
+pub struct EditorState {
+    size: AtomicCell<(u32, u32)>,
+    open: AtomicBool,
+}
+
+impl EditorState {
+    /// Initialize the editor's state. The window size is in logical pixels, so before it is
+    /// multiplied by the DPI scaling factor.
+    pub fn from_size(width: u32, height: u32) -> Arc<EditorState> {
+        Arc::new(EditorState {
+            size: AtomicCell::new((width, height)),
+            open: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns a `(width, height)` pair for the current size of the window in logical pixels.
+    pub fn size(&self) -> (u32, u32) {
+        self.size.load()
+    }
+
+    /// Whether the editor is currently open.
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Acquire)
+    }
+}
+
+/// The [`Editor`] implementation returned from [`create_baseview_editor()`].
+struct BaseviewEditor<T> {
+    editor_state: Arc<EditorState>,
+    /// The user's state. This is kept in between editor openings.
+    user_state: Arc<RwLock<T>>,
+    callback: Arc<EditorCallback<T>>,
+
+    /// The scaling factor reported by the host, if any. On macOS this will never be set and we
+    /// should use the system scaling factor instead.
+    scaling_factor: AtomicCell<Option<f32>>,
+}
+
+/// The baseview window handler for [`BaseviewEditor`].
+struct BaseviewEditorWindowHandler<T> {
+    context: Arc<dyn GuiContext>,
+    editor_state: Arc<EditorState>,
+    user_state: Arc<RwLock<T>>,
+    callback: Arc<EditorCallback<T>>,
+}
+
+/// The editor handle returned from [`BaseviewEditor::spawn()`].
+struct BaseviewEditorHandle {
+    editor_state: Arc<EditorState>,
+    window: WindowHandle,
+}
+
+/// The window handle enum stored within 'WindowHandle' contains raw pointers.
+unsafe impl Send for BaseviewEditorHandle {}
+unsafe impl Sync for BaseviewEditorHandle {}
+
+impl<T: 'static + Send + Sync> Editor for BaseviewEditor<T> {
+    fn spawn(
+        &self,
+        parent: ParentWindowHandle,
+        context: Arc<dyn GuiContext>,
+    ) -> Option<Box<dyn Any + Send + Sync>> {
+        let (unscaled_width, unscaled_height) = self.editor_state.size();
+        let scaling_factor = self.scaling_factor.load();
+
+        let editor_state = self.editor_state.clone();
+        let user_state = self.user_state.clone();
+        let callback = self.callback.clone();
+        let window = Window::open_parented(
+            &parent,
+            WindowOpenOptions {
+                title: String::from("editor window"),
+                // Baseview does the DPI scaling for us
+                size: Size::new(unscaled_width as f64, unscaled_height as f64),
+                scale: scaling_factor
+                    .map(|factor| WindowScalePolicy::ScaleFactor(factor as f64))
+                    .unwrap_or(WindowScalePolicy::SystemScaleFactor),
+                gl_config: None,
+            },
+            move |_window| BaseviewEditorWindowHandler {
+                context,
+                editor_state,
+                user_state,
+                callback,
+            },
+        );
+
+        self.editor_state.open.store(true, Ordering::Release);
+        Some(Box::new(BaseviewEditorHandle {
+            editor_state: self.editor_state.clone(),
+            window,
+        }))
+    }
+
+    fn size(&self) -> (u32, u32) {
+        self.editor_state.size()
+    }
+
+    fn set_scale_factor(&self, factor: f32) -> bool {
+        // Baseview can't change the scale factor of a window that's already open, so the new factor
+        // is rejected while the editor is open and the window keeps using its current scale
+        if self.editor_state.is_open() {
+            return false;
+        }
+
+        self.scaling_factor.store(Some(factor));
+        true
+    }
+
+    fn param_values_changed(&self) {
+        // The callback gets a frame event every frame, so it can always redraw with the latest
+        // parameter values
+    }
+}
+
+impl<T: 'static + Send + Sync> WindowHandler for BaseviewEditorWindowHandler<T> {
+    fn on_frame(&mut self, window: &mut Window) {
+        let setter = ParamSetter::new(self.context.as_ref());
+        (self.callback)(
+            window,
+            EditorEvent::Frame,
+            &setter,
+            &mut self.user_state.write(),
+        );
+    }
+
+    fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus {
+        if let Event::Window(WindowEvent::Resized(window_info)) = &event {
+            let size = window_info.logical_size();
+            self.editor_state
+                .size
+                .store((size.width.round() as u32, size.height.round() as u32));
+        }
+
+        let setter = ParamSetter::new(self.context.as_ref());
+        (self.callback)(
+            window,
+            EditorEvent::Window(&event),
+            &setter,
+            &mut self.user_state.write(),
+        )
+    }
+}
+
+impl Drop for BaseviewEditorHandle {
+    fn drop(&mut self) {
+        self.editor_state.open.store(false, Ordering::Release);
+        self.window.close();
+    }
+}
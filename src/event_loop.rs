@@ -1,24 +1,29 @@
 //! An internal event loop for spooling tasks to the/a GUI thread.
 
+use std::collections::HashSet;
+use std::hash::Hash;
 use std::sync::Weak;
+use std::time::{Duration, Instant};
+
+use crate::plugin::Plugin;
 
 #[cfg(all(target_family = "unix", not(target_os = "macos")))]
 mod linux;
-// For now, also use the Linux event loop on macOS so it at least compiles
 #[cfg(target_os = "macos")]
-mod linux;
+mod macos;
 #[cfg(target_os = "windows")]
 mod windows;
 
 #[cfg(all(target_family = "unix", not(target_os = "macos")))]
 pub(crate) use self::linux::LinuxEventLoop as OsEventLoop;
-// For now, also use the Linux event loop on macOS so it at least compiles
 #[cfg(target_os = "macos")]
-pub(crate) use self::linux::LinuxEventLoop as OsEventLoop;
+pub(crate) use self::macos::MacOSEventLoop as OsEventLoop;
 #[cfg(target_os = "windows")]
 pub(crate) use self::windows::WindowsEventLoop as OsEventLoop;
 
-pub(crate) const TASK_QUEUE_CAPACITY: usize = 512;
+/// How long the wrappers' event loops get to execute the remaining tasks when they're dropped in
+/// [`ShutdownMode::Drain`].
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// A trait describing the functionality of the platform-specific event loop that can execute tasks
 /// of type `T` in executor `E`. Posting a task to the internal task queue should be realtime safe.
@@ -33,24 +38,193 @@ pub(crate) const TASK_QUEUE_CAPACITY: usize = 512;
 ///       all instances of a plugin.
 pub(crate) trait EventLoop<T, E>
 where
-    T: Send + 'static,
+    T: Send + PartialEq + 'static,
     E: MainThreadExecutor<T> + 'static,
 {
     /// Create and start a new event loop. The thread this is called on will be designated as the
-    /// main thread, so this should be called when constructing the wrapper.
-    fn new_and_spawn(executor: Weak<E>) -> Self;
+    /// main thread, so this should be called when constructing the wrapper. `plugin_name` is used
+    /// to name the event loop's worker thread if it has one, so the thread can be identified when
+    /// profiling the host. The task queue can hold `capacity` tasks, after which
+    /// [`do_maybe_async()`][Self::do_maybe_async()] starts rejecting tasks. The wrappers use
+    /// [`Plugin::TASK_QUEUE_CAPACITY`][crate::prelude::Plugin::TASK_QUEUE_CAPACITY] for this.
+    ///
+    /// `shutdown_mode` determines what happens to the tasks that are still queued when the event
+    /// loop is dropped. Event loops without a worker thread use
+    /// [`ShutdownMode::finish_tasks()`] for this, which requires the executor to still be alive.
+    fn new_and_spawn(
+        executor: Weak<E>,
+        plugin_name: &str,
+        capacity: usize,
+        shutdown_mode: ShutdownMode,
+    ) -> Self
+    where
+        Self: Sized;
 
     /// Either post the function to the task queue so it can be delegated to the main thread, or
     /// execute the task directly if this is the main thread. This function needs to be callable at
     /// any time without blocking.
     ///
-    /// If the task queue is full, then this will return false.
+    /// Tasks are always executed in the order they were submitted in. When a task is executed
+    /// directly on the main thread, then any tasks that are still in the queue are executed first.
+    /// Event loops with a worker thread add tasks posted from the main thread to the queue instead
+    /// when the worker thread is busy executing other tasks, since waiting for it to finish would
+    /// block the main thread.
+    ///
+    /// If the task queue is full or if the task can no longer be executed, then this will return
+    /// false. The task will not be executed in that case. Use
+    /// [`do_maybe_async_status()`][Self::do_maybe_async_status()] to also find out whether the
+    /// task has already been executed, and why it was dropped.
+    #[must_use]
+    fn do_maybe_async(&self, task: T) -> bool {
+        matches!(
+            self.do_maybe_async_status(task),
+            TaskDispatch::ExecutedInline | TaskDispatch::Queued
+        )
+    }
+
+    /// The same as [`do_maybe_async()`][Self::do_maybe_async()], but this returns whether the task
+    /// was executed directly, whether it was added to the task queue, or whether it was dropped.
     #[must_use]
-    fn do_maybe_async(&self, task: T) -> bool;
+    fn do_maybe_async_status(&self, task: T) -> TaskDispatch;
+
+    /// The same as [`do_maybe_async_status()`][Self::do_maybe_async_status()], but the task is
+    /// executed before any regular tasks that are still waiting in the queue. This is meant for
+    /// small, urgent tasks like GUI updates that should not have to wait for a backlog of slower
+    /// tasks. High priority tasks are still executed in the order they were submitted in relative
+    /// to each other.
+    ///
+    /// Event loops that don't distinguish between task priorities treat these as regular tasks.
+    #[must_use]
+    fn do_maybe_async_high_priority(&self, task: T) -> TaskDispatch {
+        self.do_maybe_async_status(task)
+    }
 
     /// Whether the calling thread is the event loop's main thread. This is usually the thread the
     /// event loop instance was initialized on.
     fn is_main_thread(&self) -> bool;
+
+    /// Stop the event loop's worker thread from executing regular queued tasks until
+    /// [`resume()`][Self::resume()] is called, for instance while the plugin is deactivated. Tasks
+    /// posted from other threads are still added to the queue in the meantime. Tasks posted from
+    /// the main thread are still executed directly, but the regular tasks that are waiting in the
+    /// queue are left there instead of being executed first. Tasks posted through
+    /// [`do_maybe_async_high_priority()`][Self::do_maybe_async_high_priority()] are still executed
+    /// while the loop is paused, so tasks that cannot wait should be posted that way. Dropping a
+    /// paused event loop still executes the remaining queued tasks.
+    ///
+    /// This is a no-op for event loops that don't have their own worker thread.
+    fn pause(&self) {}
+
+    /// Let the worker thread execute tasks again after a call to [`pause()`][Self::pause()]. Any
+    /// tasks that were queued while the loop was paused are executed right away.
+    fn resume(&self) {}
+
+    /// Execute `task` on the main thread once `delay` has passed, for instance to debounce saving
+    /// some state until a while after the last edit. If an identical task is already scheduled,
+    /// then that task is rescheduled with the new deadline instead of being scheduled twice. Just
+    /// like posting a task, this needs to be realtime safe.
+    ///
+    /// Scheduled tasks that become due while the event loop is paused are executed when it is
+    /// resumed. When the event loop is dropped, the tasks that are still scheduled are handled
+    /// right away together with the queued tasks according to the loop's [`ShutdownMode`].
+    ///
+    /// Event loops that don't have their own worker thread don't support this, and they return
+    /// [`TaskDispatch::Unsupported`].
+    // None of the wrappers' own tasks need to be delayed yet
+    #[allow(dead_code)]
+    #[must_use]
+    fn schedule_after(&self, _delay: Duration, _task: T) -> TaskDispatch {
+        TaskDispatch::Unsupported
+    }
+
+    /// Cancel the task scheduled with [`schedule_after()`][Self::schedule_after()] that's identical
+    /// to `task`, if it has not been executed yet. This returns false if the cancellation could not
+    /// be posted to the event loop, in which case the scheduled task may still be executed.
+    #[allow(dead_code)]
+    #[must_use]
+    fn cancel_scheduled(&self, _task: T) -> bool {
+        false
+    }
+}
+
+/// What happened to a task that needed to be run on the main thread. This is returned by
+/// [`EventLoop::do_maybe_async_status()`] so the wrappers can tell why a task was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskDispatch {
+    /// This was called from the main thread, and the task has already been executed.
+    ExecutedInline,
+    /// The task was added to the task queue, and it will be executed on the main thread at some
+    /// point in the future.
+    Queued,
+    /// The task queue is full, and the task has been dropped without being executed.
+    QueueFull,
+    /// The task has been dropped without being executed because the event loop can no longer
+    /// execute any tasks, for instance because its worker thread has shut down. Unlike with
+    /// [`QueueFull`][Self::QueueFull], trying again later won't help.
+    ExecutorGone,
+    /// The task has been dropped without being executed because the event loop does not support
+    /// this kind of task, like delayed tasks on event loops without a worker thread.
+    Unsupported,
+}
+
+/// What an event loop does with the tasks that are still queued when it gets dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ShutdownMode {
+    /// Execute the remaining tasks before shutting down. Tasks that have not been executed after
+    /// `timeout` are dropped so a slow executor can't hang the host. A task that is still being
+    /// executed at that point is allowed to finish.
+    Drain { timeout: Duration },
+    /// Drop the remaining tasks without executing them.
+    Discard,
+}
+
+impl ShutdownMode {
+    /// The shutdown mode for `P`'s event loop based on
+    /// [`Plugin::DRAIN_TASKS_ON_SHUTDOWN`][crate::prelude::Plugin::DRAIN_TASKS_ON_SHUTDOWN].
+    pub fn for_plugin<P: Plugin>() -> Self {
+        if P::DRAIN_TASKS_ON_SHUTDOWN {
+            ShutdownMode::Drain {
+                timeout: SHUTDOWN_TIMEOUT,
+            }
+        } else {
+            ShutdownMode::Discard
+        }
+    }
+
+    /// Execute or drop the remaining `tasks` on the current thread according to this shutdown
+    /// mode. This is for event loops and wrappers that execute their tasks on the host's main
+    /// thread, since they can't leave the remaining tasks to a worker thread when they shut down.
+    /// The tasks are always dropped if `executor` no longer exists.
+    ///
+    /// # Safety
+    ///
+    /// This must be called from the main thread, see [`MainThreadExecutor::execute()`].
+    pub unsafe fn finish_tasks<T, E: MainThreadExecutor<T>>(
+        self,
+        executor: Option<&E>,
+        mut tasks: impl Iterator<Item = T>,
+    ) {
+        let deadline = match self {
+            ShutdownMode::Drain { timeout } => Instant::now() + timeout,
+            ShutdownMode::Discard => Instant::now(),
+        };
+        if let Some(executor) = executor {
+            executor.execute_batch(std::iter::from_fn(|| {
+                if Instant::now() >= deadline {
+                    None
+                } else {
+                    tasks.next()
+                }
+            }));
+        }
+
+        let num_dropped_tasks = tasks.count();
+        if num_dropped_tasks > 0 {
+            nih_log!(
+                "Dropping {num_dropped_tasks} tasks that were not executed before the event loop shut down"
+            );
+        }
+    }
 }
 
 /// Something that can execute tasks of type `T`.
@@ -62,4 +236,109 @@ pub(crate) trait MainThreadExecutor<T>: Send + Sync {
     /// This is not actually unsafe in the typical Rust sense. But the implemnting function will
     /// assume (and can only assume) that this is called from the main thread.
     unsafe fn execute(&self, task: T);
+
+    /// Execute all tasks from `tasks` on the current thread. The event loops call this with the
+    /// tasks that are queued at the time the loop wakes up, followed by the task being posted when
+    /// this is called from [`EventLoop::do_maybe_async()`] on the main thread, so executors can
+    /// coalesce redundant tasks instead of handling them one by one. The default implementation
+    /// simply calls [`execute()`][Self::execute()] for every task, in order.
+    ///
+    /// This is also where duplicate tasks should be deduplicated. Replacing a task that's already
+    /// in the queue is not possible without locking, and posting a task needs to be realtime safe.
+    /// So instead identical tasks are all queued, and executors can use [`coalesce_tasks()`] to
+    /// only execute the first copy of every task in a batch.
+    ///
+    /// # Safety
+    ///
+    /// The same as with [`execute()`][Self::execute()], this should only be called from the main
+    /// thread.
+    unsafe fn execute_batch(&self, tasks: impl Iterator<Item = T>)
+    where
+        Self: Sized,
+    {
+        for task in tasks {
+            self.execute(task);
+        }
+    }
+}
+
+/// Remove duplicate tasks from a batch of tasks. When the same task occurs more than once, then
+/// only the first copy is kept. The tasks are not reordered, so the remaining tasks stay in the
+/// order they were first submitted in. This is meant to be used in
+/// [`MainThreadExecutor::execute_batch()`].
+pub(crate) fn coalesce_tasks<T: Hash + Eq>(tasks: impl Iterator<Item = T>) -> Vec<T> {
+    let tasks: Vec<T> = tasks.collect();
+    let mut seen_tasks = HashSet::with_capacity(tasks.len());
+    let is_first_copy: Vec<bool> = tasks.iter().map(|task| seen_tasks.insert(task)).collect();
+
+    tasks
+        .into_iter()
+        .zip(is_first_copy)
+        .filter_map(|(task, is_first_copy)| is_first_copy.then_some(task))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Records the tasks it executes.
+    #[derive(Default)]
+    struct TaskRecorder(Mutex<Vec<i32>>);
+
+    impl MainThreadExecutor<i32> for TaskRecorder {
+        unsafe fn execute(&self, task: i32) {
+            self.0.lock().unwrap().push(task);
+        }
+    }
+
+    #[test]
+    fn coalesce_identical_tasks() {
+        assert_eq!(coalesce_tasks(std::iter::repeat_n(1, 100)), [1]);
+        assert!(coalesce_tasks(std::iter::empty::<i32>()).is_empty());
+    }
+
+    #[test]
+    fn coalesce_keeps_first_copy() {
+        assert_eq!(coalesce_tasks([1, 2, 1, 3, 2].into_iter()), [1, 2, 3]);
+        assert_eq!(coalesce_tasks([1, 2, 3].into_iter()), [1, 2, 3]);
+    }
+
+    #[test]
+    fn coalesce_keeps_order() {
+        // Distinct tasks should never be reordered, no matter how their duplicates are interleaved
+        assert_eq!(coalesce_tasks([3, 1, 3, 2, 1, 3].into_iter()), [3, 1, 2]);
+        assert_eq!(
+            coalesce_tasks((0..100).rev().chain(0..100)),
+            (0..100).rev().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn finish_tasks_drains_or_discards() {
+        let executor = TaskRecorder::default();
+        unsafe {
+            ShutdownMode::Drain {
+                timeout: SHUTDOWN_TIMEOUT,
+            }
+            .finish_tasks(Some(&executor), [1, 2, 3].into_iter())
+        };
+        assert_eq!(*executor.0.lock().unwrap(), [1, 2, 3]);
+
+        let executor = TaskRecorder::default();
+        unsafe { ShutdownMode::Discard.finish_tasks(Some(&executor), [1, 2, 3].into_iter()) };
+        assert!(executor.0.lock().unwrap().is_empty());
+
+        // Without an executor the tasks can only be dropped
+        let mut tasks = vec![1, 2, 3].into_iter();
+        unsafe {
+            ShutdownMode::Drain {
+                timeout: SHUTDOWN_TIMEOUT,
+            }
+            .finish_tasks(None::<&TaskRecorder>, tasks.by_ref())
+        };
+        assert_eq!(tasks.len(), 0);
+    }
 }
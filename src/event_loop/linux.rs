@@ -2,18 +2,19 @@
 //! of a main thread does not exist there. Because of that, this mostly just serves as a way to
 //! delegate expensive processing to another thread.
 
-use crossbeam::channel;
-use std::sync::Weak;
+use crossbeam::atomic::AtomicCell;
+use crossbeam::channel::{self, RecvTimeoutError};
+use parking_lot::ReentrantMutex;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
 use std::thread::{self, JoinHandle, ThreadId};
+use std::time::{Duration, Instant};
 
-use super::{EventLoop, MainThreadExecutor};
+use super::{EventLoop, MainThreadExecutor, ShutdownMode, TaskDispatch};
 use crate::util::permit_alloc;
 
 /// See [`EventLoop`][super::EventLoop].
-#[cfg_attr(
-    target_os = "macos",
-    deprecated = "macOS needs to have its own event loop implementation, this implementation may not work correctly"
-)]
 pub(crate) struct LinuxEventLoop<T, E> {
     /// The thing that ends up executing these tasks. The tasks are usually executed from the worker
     /// thread, but if the current thread is the main thread then the task cna also be executed
@@ -25,98 +26,409 @@ pub(crate) struct LinuxEventLoop<T, E> {
     main_thread_id: ThreadId,
 
     /// A thread that act as our worker thread. When [`do_maybe_async()`][Self::do_maybe_async()] is
-    /// called, this thread will be woken up to execute the task on the executor. This is wrapped in
-    /// an `Option` so the thread can be taken out of it and joined when this struct gets dropped.
+    /// called, this thread will be woken up to execute the queued tasks on the executor. This is
+    /// wrapped in an `Option` so the thread can be taken out of it and joined when this struct gets
+    /// dropped.
     worker_thread: Option<JoinHandle<()>>,
     /// A channel for waking up the worker thread and having it perform one of the tasks from
     /// [`Message`].
-    worker_thread_channel: channel::Sender<Message<T>>,
+    worker_thread_channel: channel::Sender<Message>,
+    /// The queued tasks. Both the sending and the receiving end are stored here since the main
+    /// thread also drains this queue when it executes a task directly.
+    task_sender: channel::Sender<T>,
+    task_receiver: channel::Receiver<T>,
+    /// The same as `task_sender` and `task_receiver`, but for high priority tasks. This queue is
+    /// always drained before taking the next task from the regular queue.
+    high_priority_task_sender: channel::Sender<T>,
+    high_priority_task_receiver: channel::Receiver<T>,
+    /// Requests for scheduling and cancelling delayed tasks. The worker thread keeps track of the
+    /// scheduled tasks and their deadlines, so scheduling a task doesn't need to allocate or lock.
+    timer_request_sender: channel::Sender<TimerRequest<T>>,
+    /// Tasks are only taken from the queue and executed while holding this lock. That way a task
+    /// executed directly on the main thread can never overtake a task that was posted to the queue
+    /// earlier, since the main thread first executes any tasks that are still in the queue. This
+    /// is reentrant because executing a task on the main thread may cause more tasks to be
+    /// executed on the main thread.
+    execution_lock: Arc<ReentrantMutex<()>>,
+    /// Whether the worker thread is still running. This is set to `false` when the worker thread
+    /// exits for any reason other than this struct being dropped, after which tasks can no longer
+    /// be posted to the queue.
+    worker_alive: Arc<AtomicBool>,
+    /// What happens to the tasks that are still queued when this struct gets dropped.
+    shutdown_mode: ShutdownMode,
+    /// Set when this struct gets dropped. The worker thread stops executing tasks once this
+    /// deadline has passed, and the remaining tasks are dropped.
+    shutdown_deadline: Arc<AtomicCell<Option<Instant>>>,
+    /// Whether the worker thread is paused. While this is set the worker thread only executes high
+    /// priority tasks when it's woken up, so the regular tasks stay in the queue.
+    paused: Arc<AtomicBool>,
 }
 
 /// A message for communicating with the worker thread.
-enum Message<T> {
-    /// A new task for the event loop to execute.
-    Task(T),
-    /// Shut down the worker thread.
+enum Message {
+    /// Execute all tasks that are currently in the task queue.
+    Wake,
+    /// Execute the remaining queued tasks until the shutdown deadline, and then shut down the
+    /// worker thread.
     Shutdown,
 }
 
+/// A request sent to the worker thread by
+/// [`schedule_after()`][EventLoop::schedule_after()] and
+/// [`cancel_scheduled()`][EventLoop::cancel_scheduled()].
+enum TimerRequest<T> {
+    /// Execute the task once the deadline has passed, replacing any identical scheduled task.
+    Schedule { deadline: Instant, task: T },
+    /// Remove the identical scheduled task, if there is one.
+    Cancel(T),
+}
+
 impl<T, E> EventLoop<T, E> for LinuxEventLoop<T, E>
 where
-    T: Send + 'static,
+    T: Send + PartialEq + 'static,
     E: MainThreadExecutor<T> + 'static,
 {
-    fn new_and_spawn(executor: Weak<E>) -> Self {
-        let (sender, receiver) = channel::bounded(super::TASK_QUEUE_CAPACITY);
+    fn new_and_spawn(
+        executor: Weak<E>,
+        plugin_name: &str,
+        capacity: usize,
+        shutdown_mode: ShutdownMode,
+    ) -> Self {
+        // If there's already a wake up message in the channel, then the worker thread will see any
+        // newly queued tasks when it handles that message
+        let (worker_sender, worker_receiver) = channel::bounded(1);
+        let (task_sender, task_receiver) = channel::bounded(capacity);
+        let (high_priority_task_sender, high_priority_task_receiver) = channel::bounded(capacity);
+        let (timer_request_sender, timer_request_receiver) = channel::bounded(capacity);
+        let execution_lock = Arc::new(ReentrantMutex::new(()));
+        let worker_alive = Arc::new(AtomicBool::new(true));
+        let paused = Arc::new(AtomicBool::new(false));
+        let shutdown_deadline = Arc::new(AtomicCell::new(None));
 
         Self {
             executor: executor.clone(),
             main_thread_id: thread::current().id(),
             // With our drop implementation we guarentee that this thread never outlives this struct
-            worker_thread: Some(
+            worker_thread: Some({
+                let task_receiver = task_receiver.clone();
+                let high_priority_task_receiver = high_priority_task_receiver.clone();
+                let execution_lock = execution_lock.clone();
+                let worker_alive = worker_alive.clone();
+                let paused = paused.clone();
+                let shutdown_deadline = shutdown_deadline.clone();
+
                 thread::Builder::new()
-                    .name(String::from("worker"))
-                    .spawn(move || worker_thread(receiver, executor))
-                    .expect("Could not spawn worker thread"),
-            ),
-            worker_thread_channel: sender,
+                    .name(worker_thread_name(plugin_name))
+                    .spawn(move || {
+                        worker_thread(
+                            worker_receiver,
+                            high_priority_task_receiver,
+                            task_receiver,
+                            timer_request_receiver,
+                            execution_lock,
+                            paused,
+                            shutdown_deadline,
+                            executor,
+                        );
+                        worker_alive.store(false, Ordering::SeqCst);
+                    })
+                    .expect("Could not spawn worker thread")
+            }),
+            worker_thread_channel: worker_sender,
+            task_sender,
+            task_receiver,
+            high_priority_task_sender,
+            high_priority_task_receiver,
+            timer_request_sender,
+            execution_lock,
+            worker_alive,
+            paused,
+            shutdown_mode,
+            shutdown_deadline,
         }
     }
 
-    fn do_maybe_async(&self, task: T) -> bool {
+    fn do_maybe_async_status(&self, task: T) -> TaskDispatch {
+        self.dispatch(task, &self.task_sender)
+    }
+
+    fn do_maybe_async_high_priority(&self, task: T) -> TaskDispatch {
+        self.dispatch(task, &self.high_priority_task_sender)
+    }
+
+    fn is_main_thread(&self) -> bool {
+        // FIXME: `thread::current()` may allocate the first time it's called, is there a safe
+        //        nonallocating version of this without using huge OS-specific libraries?
+        permit_alloc(|| thread::current().id() == self.main_thread_id)
+    }
+
+    fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+
+        // The wake up messages sent while the loop was paused have already been ignored, so the
+        // worker thread needs to be woken up again to execute those tasks. If the channel is full
+        // then the worker thread is going to wake up anyways.
+        let _ = self.worker_thread_channel.try_send(Message::Wake);
+    }
+
+    fn schedule_after(&self, delay: Duration, task: T) -> TaskDispatch {
+        let deadline = Instant::now() + delay;
+        self.post(
+            TimerRequest::Schedule { deadline, task },
+            &self.timer_request_sender,
+        )
+    }
+
+    fn cancel_scheduled(&self, task: T) -> bool {
+        self.post(TimerRequest::Cancel(task), &self.timer_request_sender) == TaskDispatch::Queued
+    }
+}
+
+impl<T, E> LinuxEventLoop<T, E>
+where
+    T: Send + PartialEq + 'static,
+    E: MainThreadExecutor<T> + 'static,
+{
+    /// The implementation for [`EventLoop::do_maybe_async_status()`] and
+    /// [`EventLoop::do_maybe_async_high_priority()`]. If the task cannot be executed directly, then
+    /// it's posted to the queue `sender` sends to.
+    fn dispatch(&self, task: T, sender: &channel::Sender<T>) -> TaskDispatch {
         if self.is_main_thread() {
             match self.executor.upgrade() {
                 Some(e) => {
-                    unsafe { e.execute(task) };
-                    true
+                    // If the worker thread is currently executing tasks, then waiting for it to
+                    // finish would block the main thread. The task is queued instead, and the
+                    // worker thread picks it up after the tasks that were queued before it.
+                    let _execution_guard = match self.execution_lock.try_lock() {
+                        Some(guard) => guard,
+                        None => return self.post(task, sender),
+                    };
+
+                    // Any task that's still in the queue was posted before this one, so those need
+                    // to be executed first. High priority tasks still go before regular tasks, and
+                    // regular tasks stay in the queue while the event loop is paused.
+                    let paused = self.paused.load(Ordering::SeqCst);
+                    let queued_tasks = std::iter::from_fn(|| {
+                        if paused {
+                            self.high_priority_task_receiver.try_recv().ok()
+                        } else {
+                            next_task(&self.high_priority_task_receiver, &self.task_receiver)
+                        }
+                    });
+                    unsafe { e.execute_batch(queued_tasks.chain(std::iter::once(task))) };
+
+                    TaskDispatch::ExecutedInline
                 }
                 None => {
                     nih_trace!("The executor doesn't exist but somehow it's still submitting tasks, this shouldn't be possible!");
-                    false
+                    TaskDispatch::ExecutorGone
                 }
             }
         } else {
-            self.worker_thread_channel
-                .try_send(Message::Task(task))
-                .is_ok()
+            self.post(task, sender)
         }
     }
 
-    fn is_main_thread(&self) -> bool {
-        // FIXME: `thread::current()` may allocate the first time it's called, is there a safe
-        //        nonallocating version of this without using huge OS-specific libraries?
-        permit_alloc(|| thread::current().id() == self.main_thread_id)
+    /// Add a task or a timer request to the queue `sender` sends to, and wake up the worker thread
+    /// so it can handle it.
+    fn post<M>(&self, message: M, sender: &channel::Sender<M>) -> TaskDispatch {
+        // Tasks posted after the worker thread has died would never be executed
+        if !self.worker_alive.load(Ordering::SeqCst) {
+            return TaskDispatch::ExecutorGone;
+        }
+        if sender.try_send(message).is_err() {
+            return TaskDispatch::QueueFull;
+        }
+
+        // This can only fail if there's already a wake up message in the channel, in which case
+        // the worker thread will pick up this task anyways
+        let _ = self.worker_thread_channel.try_send(Message::Wake);
+
+        TaskDispatch::Queued
     }
 }
 
 impl<T, E> Drop for LinuxEventLoop<T, E> {
     fn drop(&mut self) {
-        self.worker_thread_channel
-            .send(Message::Shutdown)
-            .expect("Failed while sending worker thread shutdown request");
+        // The worker thread stops taking new tasks from the queues once the deadline has passed, so
+        // joining it only has to wait for the task that's currently being executed
+        let deadline = match self.shutdown_mode {
+            ShutdownMode::Drain { timeout } => Instant::now() + timeout,
+            ShutdownMode::Discard => Instant::now(),
+        };
+        self.shutdown_deadline.store(Some(deadline));
+
+        // If the worker thread has already shut down then this will fail, and there's nothing left
+        // to do except for joining the thread. If there's still a wake up message in the channel,
+        // then this blocks until the worker thread has finished its current batch.
+        let _ = self.worker_thread_channel.send(Message::Shutdown);
         if let Some(join_handle) = self.worker_thread.take() {
-            join_handle.join().expect("Worker thread panicked");
+            if join_handle.join().is_err() {
+                nih_log!("The event loop's worker thread panicked");
+            }
         }
     }
 }
 
+/// Take the next task from the high priority queue, or from the regular queue if there are no high
+/// priority tasks.
+fn next_task<T>(
+    high_priority_tasks: &channel::Receiver<T>,
+    tasks: &channel::Receiver<T>,
+) -> Option<T> {
+    high_priority_tasks
+        .try_recv()
+        .or_else(|_| tasks.try_recv())
+        .ok()
+}
+
+/// The name for a plugin's worker thread, like `MyComp-worker`. Linux truncates thread names to 15
+/// bytes, so the plugin's name is shortened to make sure the `-worker` suffix stays intact.
+fn worker_thread_name(plugin_name: &str) -> String {
+    const SUFFIX: &str = "-worker";
+    const MAX_LEN: usize = 15;
+
+    let mut name_len = plugin_name.len().min(MAX_LEN - SUFFIX.len());
+    while !plugin_name.is_char_boundary(name_len) {
+        name_len -= 1;
+    }
+
+    format!("{}{}", &plugin_name[..name_len], SUFFIX)
+}
+
 /// The worker thread used in [`EventLoop`] that executes incmoing tasks on the event loop's
-/// executor.
-fn worker_thread<T, E>(receiver: channel::Receiver<Message<T>>, executor: Weak<E>)
-where
-    T: Send,
+/// executor. Every time the thread wakes up it drains all tasks that are currently in the queue and
+/// hands them to the executor as a single batch, followed by any scheduled tasks that have become
+/// due. A panicking task is logged, and the remaining tasks will still be executed. Only high
+/// priority tasks are executed while the event loop is paused, but shutdown messages still drain
+/// both queues and the scheduled tasks. The high priority queue is checked again before every
+/// regular task, so high priority tasks posted while a batch is being executed don't need to wait
+/// for the rest of the batch. Once `shutdown_deadline` has been set and has passed, no more tasks
+/// are executed and the tasks that are still queued or scheduled are dropped when shutting down.
+#[allow(clippy::too_many_arguments)]
+fn worker_thread<T, E>(
+    receiver: channel::Receiver<Message>,
+    high_priority_tasks: channel::Receiver<T>,
+    tasks: channel::Receiver<T>,
+    timer_requests: channel::Receiver<TimerRequest<T>>,
+    execution_lock: Arc<ReentrantMutex<()>>,
+    paused: Arc<AtomicBool>,
+    shutdown_deadline: Arc<AtomicCell<Option<Instant>>>,
+    executor: Weak<E>,
+) where
+    T: Send + PartialEq,
     E: MainThreadExecutor<T>,
 {
+    // The tasks scheduled through `schedule_after()`, sorted by their deadlines
+    let mut scheduled_tasks: Vec<(Instant, T)> = Vec::new();
+
     loop {
-        match receiver.recv() {
-            Ok(Message::Task(task)) => match executor.upgrade() {
-                Some(e) => unsafe { e.execute(task) },
-                None => {
-                    nih_trace!("Received a new task but the executor is no longer alive, shutting down worker");
+        // Scheduled tasks aren't executed while the event loop is paused, and resuming the loop
+        // wakes up the worker thread again
+        let next_deadline = scheduled_tasks
+            .first()
+            .filter(|_| !paused.load(Ordering::SeqCst))
+            .map(|(deadline, _)| *deadline);
+        let message = match next_deadline {
+            Some(deadline) => {
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(message) => Ok(message),
+                    // A scheduled task has become due, which is handled the same way as being woken
+                    // up to execute a queued task
+                    Err(RecvTimeoutError::Timeout) => Ok(Message::Wake),
+                    Err(RecvTimeoutError::Disconnected) => Err(channel::RecvError),
+                }
+            }
+            None => receiver.recv(),
+        };
+        match message {
+            Ok(message) => {
+                let shutting_down = matches!(message, Message::Shutdown);
+
+                // Regular tasks stay in the queue while the event loop is paused, but high priority
+                // tasks are still executed. This is checked before handling the timer requests so a
+                // task that was cancelled before the loop got resumed can't be executed anymore.
+                let only_high_priority = !shutting_down && paused.load(Ordering::SeqCst);
+
+                for request in timer_requests.try_iter() {
+                    match request {
+                        TimerRequest::Schedule { deadline, task } => {
+                            scheduled_tasks.retain(|(_, scheduled_task)| *scheduled_task != task);
+                            let idx = scheduled_tasks.partition_point(|(scheduled_deadline, _)| {
+                                *scheduled_deadline <= deadline
+                            });
+                            scheduled_tasks.insert(idx, (deadline, task));
+                        }
+                        TimerRequest::Cancel(task) => {
+                            scheduled_tasks.retain(|(_, scheduled_task)| *scheduled_task != task);
+                        }
+                    }
+                }
+
+                // When shutting down, all of the remaining scheduled tasks are handled right away
+                let num_due_tasks = if shutting_down {
+                    scheduled_tasks.len()
+                } else if only_high_priority {
+                    0
+                } else {
+                    let now = Instant::now();
+                    scheduled_tasks.partition_point(|(deadline, _)| *deadline <= now)
+                };
+                let mut due_tasks = scheduled_tasks
+                    .drain(..num_due_tasks)
+                    .map(|(_, task)| task)
+                    .collect::<Vec<_>>()
+                    .into_iter();
+
+                let _execution_guard = execution_lock.lock();
+                match executor.upgrade() {
+                    Some(e) => {
+                        // The task that panicked has already been taken out of the queue, so this
+                        // continues with the task after it. The panic itself has already been
+                        // logged by the panic hook.
+                        while panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+                            e.execute_batch(std::iter::from_fn(|| {
+                                if shutdown_deadline
+                                    .load()
+                                    .is_some_and(|deadline| Instant::now() >= deadline)
+                                {
+                                    None
+                                } else if only_high_priority {
+                                    high_priority_tasks.try_recv().ok()
+                                } else {
+                                    next_task(&high_priority_tasks, &tasks)
+                                        .or_else(|| due_tasks.next())
+                                }
+                            }))
+                        }))
+                        .is_err()
+                        {
+                            nih_log!("A task panicked on the event loop's worker thread, continuing with the next task");
+                        }
+                    }
+                    None => {
+                        nih_trace!("Received a new task but the executor is no longer alive, shutting down worker");
+                        return;
+                    }
+                }
+
+                if shutting_down {
+                    let num_dropped_tasks = high_priority_tasks.try_iter().count()
+                        + tasks.try_iter().count()
+                        + due_tasks.count();
+                    if num_dropped_tasks > 0 {
+                        nih_log!(
+                            "Dropping {num_dropped_tasks} tasks that were not executed before the event loop shut down"
+                        );
+                    }
+
                     return;
                 }
-            },
-            Ok(Message::Shutdown) => return,
+            }
             Err(err) => {
                 nih_trace!(
                     "Worker thread got disconnected unexpectedly, shutting down: {}",
@@ -127,3 +439,524 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::event_loop::coalesce_tasks;
+
+    /// Gives the worker thread more than enough time to execute the remaining tasks when the event
+    /// loop is dropped.
+    const DRAIN_ON_SHUTDOWN: ShutdownMode = ShutdownMode::Drain {
+        timeout: Duration::from_secs(5),
+    };
+
+    /// A task that panics instead of being recorded.
+    const PANICKING_TASK: usize = usize::MAX;
+
+    /// How long [`TaskRecorder::wait_for_tasks()`] waits before failing the test, so a broken event
+    /// loop can't hang the test suite.
+    const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Records the order tasks were executed in.
+    struct TaskRecorder {
+        executed: Mutex<Vec<usize>>,
+        /// Receives a message for every executed task, see
+        /// [`wait_for_tasks()`][Self::wait_for_tasks()].
+        executed_sender: channel::Sender<()>,
+        executed_receiver: channel::Receiver<()>,
+    }
+
+    impl Default for TaskRecorder {
+        fn default() -> Self {
+            let (executed_sender, executed_receiver) = channel::unbounded();
+
+            Self {
+                executed: Mutex::new(Vec::new()),
+                executed_sender,
+                executed_receiver,
+            }
+        }
+    }
+
+    impl TaskRecorder {
+        /// Wait until `num_tasks` more tasks have been executed.
+        fn wait_for_tasks(&self, num_tasks: usize) {
+            for _ in 0..num_tasks {
+                self.executed_receiver
+                    .recv_timeout(WAIT_TIMEOUT)
+                    .expect("Timed out waiting for the worker thread to execute a task");
+            }
+        }
+    }
+
+    impl MainThreadExecutor<usize> for TaskRecorder {
+        unsafe fn execute(&self, task: usize) {
+            if task == PANICKING_TASK {
+                panic!("Task {task} panicked");
+            }
+
+            // Slow the tasks down a bit to give inline tasks a chance to overtake queued tasks
+            thread::sleep(Duration::from_millis(1));
+            self.executed.lock().push(task);
+            self.executed_sender.send(()).unwrap();
+        }
+    }
+
+    /// Records the order tasks were executed in like [`TaskRecorder`], but executing task 0 blocks
+    /// until the test lets it finish. This makes it possible to keep the worker thread busy.
+    struct BlockingRecorder {
+        executed: Mutex<Vec<usize>>,
+        started: channel::Sender<()>,
+        finish: channel::Receiver<()>,
+    }
+
+    impl BlockingRecorder {
+        /// Also returns a receiver that receives a message once task 0 starts executing, and a
+        /// sender that lets task 0 finish.
+        fn new() -> (Self, channel::Receiver<()>, channel::Sender<()>) {
+            let (started_sender, started_receiver) = channel::bounded(1);
+            let (finish_sender, finish_receiver) = channel::bounded(1);
+            let executor = Self {
+                executed: Mutex::new(Vec::new()),
+                started: started_sender,
+                finish: finish_receiver,
+            };
+
+            (executor, started_receiver, finish_sender)
+        }
+    }
+
+    impl MainThreadExecutor<usize> for BlockingRecorder {
+        unsafe fn execute(&self, task: usize) {
+            if task == 0 {
+                self.started.send(()).unwrap();
+                self.finish.recv().unwrap();
+            }
+
+            self.executed.lock().push(task);
+        }
+    }
+
+    #[test]
+    fn interleaved_task_order() {
+        let executor = Arc::new(TaskRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+
+        // Even tasks are posted from another thread and thus end up in the queue, odd tasks are
+        // executed directly on the main thread unless the worker thread is busy
+        for task in 0..50 {
+            if task % 2 == 0 {
+                thread::scope(|s| {
+                    s.spawn(|| {
+                        assert_eq!(event_loop.do_maybe_async_status(task), TaskDispatch::Queued)
+                    });
+                });
+            } else {
+                assert!(event_loop.do_maybe_async(task));
+            }
+        }
+        drop(event_loop);
+
+        assert_eq!(*executor.executed.lock(), (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn panicking_task() {
+        let executor = Arc::new(TaskRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+
+        // The worker thread should survive the panic and still execute the tasks after it
+        thread::scope(|s| {
+            s.spawn(|| {
+                for task in [0, PANICKING_TASK, 1] {
+                    assert_eq!(event_loop.do_maybe_async_status(task), TaskDispatch::Queued);
+                }
+            });
+        });
+        drop(event_loop);
+
+        assert_eq!(*executor.executed.lock(), [0, 1]);
+    }
+
+    #[test]
+    fn paused_worker_thread() {
+        let executor = Arc::new(TaskRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+
+        // Tasks posted while paused should stay in the queue until the loop is resumed, but high
+        // priority tasks are still executed
+        event_loop.pause();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for task in 0..5 {
+                    assert_eq!(event_loop.do_maybe_async_status(task), TaskDispatch::Queued);
+                }
+                assert_eq!(
+                    event_loop.do_maybe_async_high_priority(100),
+                    TaskDispatch::Queued
+                );
+            });
+        });
+        executor.wait_for_tasks(1);
+        assert_eq!(*executor.executed.lock(), [100]);
+        assert_eq!(event_loop.task_receiver.len(), 5);
+
+        event_loop.resume();
+        executor.wait_for_tasks(5);
+        assert_eq!(*executor.executed.lock(), [100, 0, 1, 2, 3, 4]);
+
+        // Shutting down a paused event loop should still flush the queue
+        event_loop.pause();
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(event_loop.do_maybe_async_status(5), TaskDispatch::Queued));
+        });
+        drop(event_loop);
+
+        assert_eq!(*executor.executed.lock(), [100, 0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn paused_main_thread_tasks() {
+        let executor = Arc::new(TaskRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+
+        // Tasks posted from the main thread are still executed directly while the loop is paused,
+        // but the regular tasks in the queue should stay there until the loop is resumed
+        event_loop.pause();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for task in 0..2 {
+                    assert_eq!(event_loop.do_maybe_async_status(task), TaskDispatch::Queued);
+                }
+            });
+        });
+        assert_eq!(
+            event_loop.do_maybe_async_status(2),
+            TaskDispatch::ExecutedInline
+        );
+        assert_eq!(*executor.executed.lock(), [2]);
+
+        event_loop.resume();
+        drop(event_loop);
+
+        assert_eq!(*executor.executed.lock(), [2, 0, 1]);
+    }
+
+    #[test]
+    fn busy_worker_thread() {
+        let (executor, started_receiver, finish_sender) = BlockingRecorder::new();
+        let executor = Arc::new(executor);
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+
+        // While the worker thread is executing a task, the main thread should queue its task
+        // instead of waiting for the worker thread to finish
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(event_loop.do_maybe_async_status(0), TaskDispatch::Queued));
+        });
+        started_receiver.recv().unwrap();
+        assert_eq!(event_loop.do_maybe_async_status(1), TaskDispatch::Queued);
+
+        finish_sender.send(()).unwrap();
+        drop(event_loop);
+
+        assert_eq!(*executor.executed.lock(), [0, 1]);
+    }
+
+    #[test]
+    fn executor_gone() {
+        let executor = Arc::new(TaskRecorder::default());
+        let mut event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+        drop(executor);
+
+        assert_eq!(
+            event_loop.do_maybe_async_status(0),
+            TaskDispatch::ExecutorGone
+        );
+
+        // The worker thread shuts down when it notices that the executor is gone, and tasks posted
+        // after that should not be reported as being queued. Joining the worker thread makes sure
+        // it has noticed.
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(event_loop.do_maybe_async_status(1), TaskDispatch::Queued));
+        });
+        event_loop
+            .worker_thread
+            .take()
+            .unwrap()
+            .join()
+            .expect("The worker thread panicked");
+        thread::scope(|s| {
+            s.spawn(|| {
+                assert_eq!(
+                    event_loop.do_maybe_async_status(2),
+                    TaskDispatch::ExecutorGone
+                );
+                assert!(!event_loop.do_maybe_async(3));
+            });
+        });
+    }
+
+    #[test]
+    fn named_worker_thread() {
+        assert_eq!(worker_thread_name("MyComp"), "MyComp-worker");
+        assert_eq!(worker_thread_name("Spectral Compressor"), "Spectral-worker");
+        assert_eq!(worker_thread_name("Überdrive"), "Überdri-worker");
+
+        /// Records the name of the thread the tasks were executed on.
+        #[derive(Default)]
+        struct ThreadNameRecorder {
+            thread_name: Mutex<Option<String>>,
+        }
+
+        impl MainThreadExecutor<()> for ThreadNameRecorder {
+            unsafe fn execute(&self, _task: ()) {
+                *self.thread_name.lock() = thread::current().name().map(String::from);
+            }
+        }
+
+        let executor = Arc::new(ThreadNameRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "MyComp",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(event_loop.do_maybe_async_status(()), TaskDispatch::Queued));
+        });
+        drop(event_loop);
+
+        assert_eq!(
+            executor.thread_name.lock().as_deref(),
+            Some("MyComp-worker")
+        );
+    }
+
+    #[test]
+    fn high_priority_tasks_first() {
+        let executor = Arc::new(TaskRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+
+        // The high priority tasks should overtake the backlog of regular tasks
+        event_loop.pause();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for task in 0..10 {
+                    assert_eq!(event_loop.do_maybe_async_status(task), TaskDispatch::Queued);
+                }
+                for task in [100, 101] {
+                    assert_eq!(
+                        event_loop.do_maybe_async_high_priority(task),
+                        TaskDispatch::Queued
+                    );
+                }
+            });
+        });
+        event_loop.resume();
+        drop(event_loop);
+
+        let mut expected = vec![100, 101];
+        expected.extend(0..10);
+        assert_eq!(*executor.executed.lock(), expected);
+    }
+
+    #[test]
+    fn deduplicated_tasks() {
+        /// Only executes the first copy of every distinct task in a batch.
+        #[derive(Default)]
+        struct DedupRecorder {
+            executed: Mutex<Vec<usize>>,
+        }
+
+        impl MainThreadExecutor<usize> for DedupRecorder {
+            unsafe fn execute(&self, task: usize) {
+                self.executed.lock().push(task);
+            }
+
+            unsafe fn execute_batch(&self, tasks: impl Iterator<Item = usize>) {
+                for task in coalesce_tasks(tasks) {
+                    self.execute(task);
+                }
+            }
+        }
+
+        let executor = Arc::new(DedupRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+
+        // Pausing the loop makes sure all of these tasks end up in the same batch
+        event_loop.pause();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for task in [1, 2, 1].into_iter().chain(std::iter::repeat_n(3, 100)) {
+                    assert_eq!(event_loop.do_maybe_async_status(task), TaskDispatch::Queued);
+                }
+            });
+        });
+        event_loop.resume();
+        drop(event_loop);
+
+        assert_eq!(*executor.executed.lock(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn drain_on_shutdown() {
+        // The remaining tasks are executed when the event loop is dropped
+        let executor = Arc::new(TaskRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+        event_loop.pause();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for task in 0..10 {
+                    assert_eq!(event_loop.do_maybe_async_status(task), TaskDispatch::Queued);
+                }
+            });
+        });
+        drop(event_loop);
+        assert_eq!(*executor.executed.lock(), (0..10).collect::<Vec<_>>());
+
+        // But tasks that take too long should not block the drop forever. The task that's being
+        // executed when the deadline passes is allowed to finish, the tasks after it are dropped,
+        // and the worker thread is still joined.
+        let (executor, started_receiver, finish_sender) = BlockingRecorder::new();
+        let executor = Arc::new(executor);
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            ShutdownMode::Drain {
+                timeout: Duration::ZERO,
+            },
+        );
+        let shutdown_deadline = event_loop.shutdown_deadline.clone();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for task in 0..100 {
+                    assert_eq!(event_loop.do_maybe_async_status(task), TaskDispatch::Queued);
+                }
+            });
+        });
+        started_receiver.recv().unwrap();
+        thread::scope(|s| {
+            s.spawn(|| drop(event_loop));
+
+            // The first task can only be allowed to finish after the deadline has been set
+            while shutdown_deadline.load().is_none() {
+                thread::yield_now();
+            }
+            finish_sender.send(()).unwrap();
+        });
+
+        assert_eq!(*executor.executed.lock(), [0]);
+        assert_eq!(Arc::strong_count(&executor), 1);
+    }
+
+    #[test]
+    fn discard_on_shutdown() {
+        let executor = Arc::new(TaskRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            ShutdownMode::Discard,
+        );
+        event_loop.pause();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for task in 0..10 {
+                    assert_eq!(event_loop.do_maybe_async_status(task), TaskDispatch::Queued);
+                }
+            });
+        });
+        drop(event_loop);
+
+        assert!(executor.executed.lock().is_empty());
+    }
+
+    #[test]
+    fn scheduled_tasks() {
+        let executor = Arc::new(TaskRecorder::default());
+        let event_loop = LinuxEventLoop::new_and_spawn(
+            Arc::downgrade(&executor),
+            "Test Plugin",
+            512,
+            DRAIN_ON_SHUTDOWN,
+        );
+
+        // Scheduling the same task again replaces the earlier deadline, so task 0 is executed once
+        // without having to wait for the first deadline
+        assert_eq!(
+            event_loop.schedule_after(Duration::from_secs(60), 0),
+            TaskDispatch::Queued
+        );
+        assert_eq!(
+            event_loop.schedule_after(Duration::ZERO, 0),
+            TaskDispatch::Queued
+        );
+        executor.wait_for_tasks(1);
+        assert_eq!(*executor.executed.lock(), [0]);
+
+        // Cancelled tasks are not executed, not even when the remaining scheduled tasks are drained
+        // on shutdown
+        assert_eq!(
+            event_loop.schedule_after(Duration::from_secs(60), 1),
+            TaskDispatch::Queued
+        );
+        assert_eq!(
+            event_loop.schedule_after(Duration::from_secs(60), 2),
+            TaskDispatch::Queued
+        );
+        assert!(event_loop.cancel_scheduled(1));
+        drop(event_loop);
+
+        assert_eq!(*executor.executed.lock(), [0, 2]);
+    }
+}
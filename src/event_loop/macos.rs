@@ -0,0 +1,188 @@
+//! An event loop for macOS, using a `CFRunLoopSource` attached to the main thread's run loop. Tasks
+//! posted from other threads are sent over a channel, and the run loop source is then signalled so
+//! the host's run loop drains that channel on the main thread.
+
+use core_foundation::base::{kCFAllocatorDefault, CFRelease, CFTypeRef};
+use core_foundation::runloop::{
+    kCFRunLoopCommonModes, CFRunLoopAddSource, CFRunLoopGetMain, CFRunLoopRemoveSource,
+    CFRunLoopSourceContext, CFRunLoopSourceCreate, CFRunLoopSourceInvalidate, CFRunLoopSourceRef,
+    CFRunLoopSourceSignal, CFRunLoopWakeUp,
+};
+use crossbeam::channel;
+use std::ffi::c_void;
+use std::os::raw::c_int;
+use std::sync::Weak;
+
+use super::{EventLoop, MainThreadExecutor, ShutdownMode, TaskDispatch};
+
+extern "C" {
+    /// Returns a non-zero value if the calling thread is the process' main thread. This is part of
+    /// libSystem, but it's not exposed through the `libc` crate.
+    fn pthread_main_np() -> c_int;
+}
+
+/// See [`EventLoop`][super::EventLoop].
+pub(crate) struct MacOSEventLoop<T, E>
+where
+    T: Send,
+    E: MainThreadExecutor<T>,
+{
+    /// The thing that ends up executing these tasks. The tasks are usually executed from the run
+    /// loop source's callback, but if the current thread is the main thread then the task can also
+    /// be executed directly.
+    executor: Weak<E>,
+
+    /// A channel for posting tasks to the main thread. The receiving end lives in
+    /// `callback_data`, and it gets drained whenever `loop_source` fires.
+    main_thread_channel: channel::Sender<T>,
+    /// The run loop source attached to the main thread's run loop. Signalling this and waking up
+    /// the main run loop causes [`loop_source_callback()`] to be called on the main thread.
+    loop_source: CFRunLoopSourceRef,
+    /// The data passed to [`loop_source_callback()`] through the source's `info` pointer. This is
+    /// owned by this struct and it's freed in the `Drop` implementation after the source has been
+    /// invalidated.
+    callback_data: *mut CallbackData<T, E>,
+    /// What to do with the tasks that are still in the channel when the event loop is dropped.
+    shutdown_mode: ShutdownMode,
+}
+
+/// The data [`loop_source_callback()`] needs to execute tasks.
+struct CallbackData<T, E> {
+    executor: Weak<E>,
+    receiver: channel::Receiver<T>,
+}
+
+// SAFETY: `CFRunLoopSourceSignal()` and `CFRunLoopWakeUp()` are fine to call from any thread, and
+//         `callback_data` is only ever dereferenced from the main thread
+unsafe impl<T: Send, E: MainThreadExecutor<T>> Send for MacOSEventLoop<T, E> {}
+unsafe impl<T: Send, E: MainThreadExecutor<T>> Sync for MacOSEventLoop<T, E> {}
+
+impl<T, E> EventLoop<T, E> for MacOSEventLoop<T, E>
+where
+    T: Send + PartialEq + 'static,
+    E: MainThreadExecutor<T> + 'static,
+{
+    fn new_and_spawn(
+        executor: Weak<E>,
+        _plugin_name: &str,
+        capacity: usize,
+        shutdown_mode: ShutdownMode,
+    ) -> Self {
+        let (sender, receiver) = channel::bounded(capacity);
+        let callback_data = Box::into_raw(Box::new(CallbackData {
+            executor: executor.clone(),
+            receiver,
+        }));
+
+        // The context is copied by `CFRunLoopSourceCreate()`, so it doesn't need to outlive this
+        // function
+        let mut source_context = CFRunLoopSourceContext {
+            version: 0,
+            info: callback_data as *mut c_void,
+            retain: None,
+            release: None,
+            copyDescription: None,
+            equal: None,
+            hash: None,
+            schedule: None,
+            cancel: None,
+            perform: loop_source_callback::<T, E>,
+        };
+        let loop_source =
+            unsafe { CFRunLoopSourceCreate(kCFAllocatorDefault, 0, &mut source_context) };
+        assert!(!loop_source.is_null());
+        unsafe { CFRunLoopAddSource(CFRunLoopGetMain(), loop_source, kCFRunLoopCommonModes) };
+
+        Self {
+            executor,
+            main_thread_channel: sender,
+            loop_source,
+            callback_data,
+            shutdown_mode,
+        }
+    }
+
+    fn do_maybe_async_status(&self, task: T) -> TaskDispatch {
+        if self.is_main_thread() {
+            match self.executor.upgrade() {
+                Some(e) => {
+                    // The run loop source may not have fired yet for tasks that were posted
+                    // earlier, and those need to be executed before this one
+                    let receiver = unsafe { &(*self.callback_data).receiver };
+                    unsafe { e.execute_batch(receiver.try_iter().chain(std::iter::once(task))) };
+
+                    TaskDispatch::ExecutedInline
+                }
+                None => {
+                    nih_trace!("The executor doesn't exist but somehow it's still submitting tasks, this shouldn't be possible!");
+                    TaskDispatch::ExecutorGone
+                }
+            }
+        } else {
+            if self.main_thread_channel.try_send(task).is_err() {
+                return TaskDispatch::QueueFull;
+            }
+
+            // The source only fires when it has been signalled, and the main thread's run loop may
+            // be sleeping so it also needs to be woken up
+            unsafe {
+                CFRunLoopSourceSignal(self.loop_source);
+                CFRunLoopWakeUp(CFRunLoopGetMain());
+            }
+
+            TaskDispatch::Queued
+        }
+    }
+
+    fn is_main_thread(&self) -> bool {
+        unsafe { pthread_main_np() != 0 }
+    }
+}
+
+impl<T, E> Drop for MacOSEventLoop<T, E>
+where
+    T: Send,
+    E: MainThreadExecutor<T>,
+{
+    fn drop(&mut self) {
+        let is_main_thread = unsafe { pthread_main_np() != 0 };
+        nih_debug_assert!(
+            is_main_thread,
+            "The event loop should be dropped on the main thread"
+        );
+
+        // After the source has been invalidated its callback can no longer be called, so the
+        // callback data can safely be freed afterwards. The tasks the source's callback hasn't
+        // gotten to yet are executed here instead, if the executor is still around.
+        unsafe {
+            CFRunLoopRemoveSource(CFRunLoopGetMain(), self.loop_source, kCFRunLoopCommonModes);
+            CFRunLoopSourceInvalidate(self.loop_source);
+            CFRelease(self.loop_source as CFTypeRef);
+
+            let callback_data = Box::from_raw(self.callback_data);
+            self.shutdown_mode.finish_tasks(
+                self.executor.upgrade().as_deref(),
+                callback_data.receiver.try_iter(),
+            );
+        }
+    }
+}
+
+/// The callback for the main thread run loop source. This executes tasks from the channel on the
+/// main thread as a single batch until the channel is empty.
+extern "C" fn loop_source_callback<T, E>(info: *const c_void)
+where
+    T: Send,
+    E: MainThreadExecutor<T>,
+{
+    let callback_data = unsafe { &*(info as *const CallbackData<T, E>) };
+    let executor = match callback_data.executor.upgrade() {
+        Some(e) => e,
+        None => {
+            nih_debug_assert_failure!("Executor died before the run loop source was removed");
+            return;
+        }
+    };
+
+    unsafe { executor.execute_batch(callback_data.receiver.try_iter()) };
+}
@@ -1,4 +1,4 @@
-//! An event loop for windows, using an invisible window to hook into the host's message loop. This
+//! An event loop for windows, using a message-only window to hook into the host's message loop. This
 //! has only been tested under Wine with [yabridge](https://github.com/robbert-vdh/yabridge).
 
 use crossbeam::queue::ArrayQueue;
@@ -7,19 +7,18 @@ use std::mem;
 use std::ptr;
 use std::sync::Arc;
 use std::sync::Weak;
-use std::thread::{self, ThreadId};
 use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, PSTR, WPARAM};
 use windows::Win32::System::{
     LibraryLoader::GetModuleHandleA, Performance::QueryPerformanceCounter,
+    Threading::GetCurrentThreadId,
 };
 use windows::Win32::UI::WindowsAndMessaging::{
-    CloseWindow, CreateWindowExA, DefWindowProcA, GetWindowLongPtrA, PostMessageA,
+    CreateWindowExA, DefWindowProcA, DestroyWindow, GetWindowLongPtrA, PostMessageA,
     RegisterClassExA, SetWindowLongPtrA, UnregisterClassA, CREATESTRUCTA, GWLP_USERDATA, HMENU,
-    WINDOW_EX_STYLE, WINDOW_STYLE, WM_CREATE, WM_DESTROY, WM_USER, WNDCLASSEXA,
+    HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE, WM_CREATE, WM_DESTROY, WM_USER, WNDCLASSEXA,
 };
 
-use super::{EventLoop, MainThreadExecutor};
-use crate::util::permit_alloc;
+use super::{EventLoop, MainThreadExecutor, ShutdownMode, TaskDispatch};
 
 /// The custom message ID for our notify event. If the hidden event loop window receives this, then
 /// it knows it should start polling events.
@@ -34,17 +33,21 @@ const NOTIFY_MESSAGE_ID: u32 = WM_USER;
 type PollCallback = Box<dyn Fn()>;
 
 /// See [`EventLoop`][super::EventLoop].
-pub(crate) struct WindowsEventLoop<T, E> {
+pub(crate) struct WindowsEventLoop<T, E>
+where
+    T: Send,
+    E: MainThreadExecutor<T>,
+{
     /// The thing that ends up executing these tasks. The tasks are usually executed from the worker
     /// thread, but if the current thread is the main thread then the task cna also be executed
     /// directly.
     executor: Weak<E>,
 
-    /// The ID of the main thread. In practice this is the ID of the thread that created this task
-    /// queue.
-    main_thread_id: ThreadId,
+    /// The Win32 thread ID of the main thread. In practice this is the ID of the thread that created
+    /// this task queue, which is also the thread that owns `message_window`.
+    main_thread_id: u32,
 
-    /// An invisible window that we can post a message to when we need to do something on the main
+    /// A message-only window that we can post a message to when we need to do something on the main
     /// thread. The host's message loop will then cause our message to be proceded.
     message_window: HWND,
     /// The unique class for the message window, we'll clean this up together with the window.
@@ -53,16 +56,23 @@ pub(crate) struct WindowsEventLoop<T, E> {
     /// we'll wake up the window, which then continues to pop tasks off this queue until it is
     /// empty.
     tasks: Arc<ArrayQueue<T>>,
+    /// What to do with the tasks that are still in `tasks` when the event loop is dropped.
+    shutdown_mode: ShutdownMode,
 }
 
 impl<T, E> EventLoop<T, E> for WindowsEventLoop<T, E>
 where
-    T: Send + 'static,
+    T: Send + PartialEq + 'static,
     E: MainThreadExecutor<T> + 'static,
 {
-    fn new_and_spawn(executor: Weak<E>) -> Self {
+    fn new_and_spawn(
+        executor: Weak<E>,
+        _plugin_name: &str,
+        capacity: usize,
+        shutdown_mode: ShutdownMode,
+    ) -> Self {
         // We'll pass one copy of the this to the window, and we'll keep the other copy here
-        let tasks = Arc::new(ArrayQueue::new(super::TASK_QUEUE_CAPACITY));
+        let tasks = Arc::new(ArrayQueue::new(capacity));
 
         // Window classes need to have unique names or else multiple plugins loaded into the same
         // process will end up calling the other plugin's callbacks
@@ -97,9 +107,7 @@ where
                     }
                 };
 
-                while let Some(task) = tasks.pop() {
-                    unsafe { executor.execute(task) };
-                }
+                unsafe { executor.execute_batch(std::iter::from_fn(|| tasks.pop())) };
             })
         };
 
@@ -113,7 +121,9 @@ where
                 0,
                 0,
                 0,
-                HWND(0),
+                // This makes it a message-only window, so it will never be shown and it won't
+                // receive any broadcast messages
+                HWND_MESSAGE,
                 HMENU(0),
                 HINSTANCE(0),
                 // NOTE: We're boxing a box here. As mentioend in [PollCallback], we c an't directly
@@ -126,55 +136,79 @@ where
 
         Self {
             executor,
-            main_thread_id: thread::current().id(),
+            main_thread_id: unsafe { GetCurrentThreadId() },
             message_window: window,
             message_window_class_name: class_name,
             tasks,
+            shutdown_mode,
         }
     }
 
-    fn do_maybe_async(&self, task: T) -> bool {
+    fn do_maybe_async_status(&self, task: T) -> TaskDispatch {
         if self.is_main_thread() {
             match self.executor.upgrade() {
                 Some(e) => {
-                    unsafe { e.execute(task) };
-                    true
+                    // Tasks that were posted earlier but that haven't been handled by the message
+                    // loop yet need to be executed before this one
+                    let queued_tasks = std::iter::from_fn(|| self.tasks.pop());
+                    unsafe { e.execute_batch(queued_tasks.chain(std::iter::once(task))) };
+
+                    TaskDispatch::ExecutedInline
                 }
                 None => {
                     nih_trace!("The executor doesn't exist but somehow it's still submitting tasks, this shouldn't be possible!");
-                    false
+                    TaskDispatch::ExecutorGone
                 }
             }
         } else {
-            let success = self.tasks.push(task).is_ok();
-            if success {
-                // Instead of polling on a timer, we can just wake up the window whenever there's a
-                // new message.
-                unsafe {
-                    PostMessageA(self.message_window, NOTIFY_MESSAGE_ID, WPARAM(0), LPARAM(0))
-                };
+            if self.tasks.push(task).is_err() {
+                return TaskDispatch::QueueFull;
             }
 
-            success
+            // Instead of polling on a timer, we can just wake up the window whenever there's a new
+            // message.
+            unsafe { PostMessageA(self.message_window, NOTIFY_MESSAGE_ID, WPARAM(0), LPARAM(0)) };
+
+            TaskDispatch::Queued
         }
     }
 
     fn is_main_thread(&self) -> bool {
-        // FIXME: `thread::current()` may allocate the first time it's called, is there a safe
-        //        nonallocating version of this without using huge OS-specific libraries?
-        permit_alloc(|| thread::current().id() == self.main_thread_id)
+        // Unlike `thread::current().id()`, this never allocates
+        unsafe { GetCurrentThreadId() == self.main_thread_id }
     }
 }
 
-impl<T, E> Drop for WindowsEventLoop<T, E> {
+impl<T, E> Drop for WindowsEventLoop<T, E>
+where
+    T: Send,
+    E: MainThreadExecutor<T>,
+{
     fn drop(&mut self) {
-        unsafe { CloseWindow(self.message_window) };
+        // `CloseWindow()` would only minimize the window. Destroying it sends the `WM_DESTROY`
+        // message that frees the polling callback, and it also needs to happen before the class can
+        // be unregistered.
+        let current_thread_id = unsafe { GetCurrentThreadId() };
+        nih_debug_assert!(
+            current_thread_id == self.main_thread_id,
+            "The event loop should be dropped on the thread that created it"
+        );
+        unsafe { DestroyWindow(self.message_window) };
         unsafe {
             UnregisterClassA(
                 PSTR(self.message_window_class_name.as_bytes_with_nul().as_ptr()),
                 HINSTANCE(0),
             )
         };
+
+        // The message loop can no longer execute the tasks that were posted after its last wake up,
+        // so those are handled here instead if the executor is still around
+        unsafe {
+            self.shutdown_mode.finish_tasks(
+                self.executor.upgrade().as_deref(),
+                std::iter::from_fn(|| self.tasks.pop()),
+            )
+        };
     }
 }
 
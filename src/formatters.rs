@@ -19,16 +19,20 @@ pub fn v2s_f32_rounded(digits: usize) -> Arc<dyn Fn(f32) -> String + Send + Sync
 }
 
 /// Format a `[0, 1]` number as a percentage. Does not include the percent sign, you should specify
-/// this as the parameter's unit.
+/// this as the parameter's unit. Values outside of that range, for instance because of modulation,
+/// are formatted the same way, so -0.1 becomes `-10`.
 pub fn v2s_f32_percentage(digits: usize) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
     Arc::new(move |value| format!("{:.digits$}", value * 100.0))
 }
 
-/// Parse a `[0, 100]` percentage to a `[0, 1]` number. Handles the percentage unit for you. Used in
-/// conjunction with [`v2s_f32_percentage()`].
+/// Parse a `[0, 100]` percentage to a `[0, 1]` number. Handles the optional percentage unit for
+/// you. Negative percentages and percentages above 100 are parsed as is, and it's up to the
+/// parameter's range to clamp the resulting value. Used in conjunction with
+/// [`v2s_f32_percentage()`].
 pub fn s2v_f32_percentage() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
     Arc::new(|string| {
         string
+            .trim()
             .trim_end_matches(&[' ', '%'])
             .parse()
             .ok()
@@ -37,20 +41,35 @@ pub fn s2v_f32_percentage() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
 }
 
 /// Turn an `f32` value from voltage gain to decibels using the semantics described in
-/// [`util::gain_to_db()]. You should use either `" dB"` or `" dBFS"` for the parameter's unit.
+/// [`util::gain_to_db()]. Gain values at or below [`util::MINUS_INFINITY_GAIN`] are displayed as
+/// `-inf`. You should use either `" dB"` or `" dBFS"` for the parameter's unit, so a gain of 0.5
+/// with one digit will be displayed as `-6.0 dB`.
 pub fn v2s_f32_gain_to_db(digits: usize) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
-    Arc::new(move |value| format!("{:.digits$}", util::gain_to_db(value)))
+    Arc::new(move |value| {
+        if value <= util::MINUS_INFINITY_GAIN {
+            String::from("-inf")
+        } else {
+            format!("{:.digits$}", util::gain_to_db(value))
+        }
+    })
 }
 
-/// Parse a decibel value to a linear voltage gain ratio. Handles the `dB` or `dBFS` units for you.
-/// Used in conjunction with [`v2s_f32_gain_to_db()`].
+/// Parse a decibel value to a linear voltage gain ratio. Handles the `dB` or `dBFS` units for you,
+/// but the unit is also optional. `-inf` is parsed as a gain of zero. Used in conjunction with
+/// [`v2s_f32_gain_to_db()`].
 pub fn s2v_f32_gain_to_db() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
     Arc::new(|string| {
-        string
-            .trim_end_matches(&[' ', 'd', 'D', 'b', 'B', 'f', 'F', 's', 'S'])
-            .parse()
-            .ok()
-            .map(util::db_to_gain)
+        let string = string.trim();
+        let string = strip_suffix_ignore_ascii_case(string, "dbfs")
+            .or_else(|| strip_suffix_ignore_ascii_case(string, "db"))
+            .unwrap_or(string)
+            .trim_end();
+
+        if string.eq_ignore_ascii_case("-inf") {
+            Some(0.0)
+        } else {
+            string.parse().ok().map(util::db_to_gain)
+        }
     })
 }
 
@@ -81,10 +100,12 @@ pub fn s2v_f32_panning() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
 }
 
 /// Format a `f32` Hertz value as a rounded `Hz` below 1000 Hz, and as a rounded `kHz` value above
-/// 1000 Hz. This already includes the unit.
+/// 1000 Hz. This already includes the unit. The switch happens at exactly 1000 Hz after rounding,
+/// so 999.9 Hz with zero digits will be displayed as `1.0 kHz` instead of as `1000 Hz`.
 pub fn v2s_f32_hz_then_khz(digits: usize) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    let rounding_factor = 10.0f32.powi(digits as i32);
     Arc::new(move |value| {
-        if value < 1000.0 {
+        if (value * rounding_factor).round() / rounding_factor < 1000.0 {
             format!("{:.digits$} Hz", value)
         } else {
             format!("{:.digits$} kHz", value / 1000.0, digits = digits.max(1))
@@ -92,18 +113,58 @@ pub fn v2s_f32_hz_then_khz(digits: usize) -> Arc<dyn Fn(f32) -> String + Send +
     })
 }
 
-/// Convert an input in the same format at that of [`v2s_f32_hz_then_khz()] to a Hertz value.
+/// Convert an input in the same format at that of [`v2s_f32_hz_then_khz()] to a Hertz value. Both
+/// `Hz` and `kHz` units are accepted, and a value without a unit is interpreted as Hertz.
 pub fn s2v_f32_hz_then_khz() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
     Arc::new(move |string| {
         let string = string.trim();
-        let cleaned_string = string
-            .trim_end_matches(&[' ', 'k', 'K', 'h', 'H', 'z', 'Z'])
-            .parse()
-            .ok();
-        match string.get(string.len().saturating_sub(3)..) {
-            Some(unit) if unit.eq_ignore_ascii_case("khz") => cleaned_string.map(|x| x * 1000.0),
+        if let Some(khz_string) = strip_suffix_ignore_ascii_case(string, "khz") {
+            khz_string.trim_end().parse().ok().map(|x: f32| x * 1000.0)
+        } else {
             // Even if there's no unit at all, just assume the input is in Hertz
-            _ => cleaned_string,
+            strip_suffix_ignore_ascii_case(string, "hz")
+                .unwrap_or(string)
+                .trim_end()
+                .parse()
+                .ok()
+        }
+    })
+}
+
+/// Ratios at or above this value are displayed as `∞:1` by [`v2s_f32_ratio()`].
+const INFINITE_RATIO_THRESHOLD: f32 = 100.0;
+
+/// Format a ratio like a compressor's ratio as `4.0:1`. Ratios of 100 and above are treated as
+/// limiting, and they are displayed as `∞:1`.
+pub fn v2s_f32_ratio(digits: usize) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        if value >= INFINITE_RATIO_THRESHOLD {
+            String::from("∞:1")
+        } else {
+            format!("{value:.digits$}:1")
+        }
+    })
+}
+
+/// Parse a ratio in the same format as [`v2s_f32_ratio()`]. Both `4:1` and a plain `4` are
+/// accepted, and there may be whitespace around the colon. `∞` and `inf` are parsed as an infinite
+/// ratio, so the parameter's range will clamp them to its maximum value.
+pub fn s2v_f32_ratio() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
+    Arc::new(|string| {
+        let parse_number = |string: &str| -> Option<f32> {
+            let string = string.trim();
+            if string == "∞" || string.eq_ignore_ascii_case("inf") {
+                Some(f32::INFINITY)
+            } else {
+                string.parse().ok()
+            }
+        };
+
+        match string.split_once(':') {
+            Some((numerator, denominator)) => {
+                Some(parse_number(numerator)? / parse_number(denominator)?)
+            }
+            None => parse_number(string),
         }
     })
 }
@@ -120,30 +181,130 @@ pub fn s2v_i32_power_of_two() -> Arc<dyn Fn(&str) -> Option<i32> + Send + Sync>
     Arc::new(|string| string.parse().ok().map(|n: i32| (n as f32).log2() as i32))
 }
 
+/// Format an integer with its digits grouped in thousands, so 48000 with `','` as the separator
+/// becomes `48,000`. Values between -999 and 999 are displayed as is.
+pub fn v2s_i32_grouped(separator: char) -> Arc<dyn Fn(i32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        let digits = value.unsigned_abs().to_string();
+
+        let mut string = String::with_capacity(digits.len() + (digits.len() / 3) + 1);
+        if value < 0 {
+            string.push('-');
+        }
+        for (idx, digit) in digits.chars().enumerate() {
+            if idx > 0 && (digits.len() - idx) % 3 == 0 {
+                string.push(separator);
+            }
+            string.push(digit);
+        }
+
+        string
+    })
+}
+
+/// Parse an integer in the same format as [`v2s_i32_grouped()`]. All occurrences of `separator` are
+/// removed before parsing, so the digits don't need to be grouped correctly.
+pub fn s2v_i32_grouped(separator: char) -> Arc<dyn Fn(&str) -> Option<i32> + Send + Sync> {
+    Arc::new(move |string| {
+        let string: String = string.trim().chars().filter(|&c| c != separator).collect();
+        string.parse().ok()
+    })
+}
+
+/// Display an integer as one of a fixed set of labels, where `labels[0]` corresponds to
+/// `first_value`, `labels[1]` to `first_value + 1`, and so on. Values without a label are displayed
+/// as plain integers. [`IntParam::with_value_labels()`][crate::prelude::IntParam::with_value_labels()]
+/// sets up both this and [`s2v_i32_labels()`] using the parameter's range.
+pub fn v2s_i32_labels(
+    first_value: i32,
+    labels: &'static [&'static str],
+) -> Arc<dyn Fn(i32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        match usize::try_from(value as i64 - first_value as i64)
+            .ok()
+            .and_then(|idx| labels.get(idx))
+        {
+            Some(label) => String::from(*label),
+            None => value.to_string(),
+        }
+    })
+}
+
+/// Parse a string in the same format as [`v2s_i32_labels()`]. Labels are compared case
+/// insensitively, and plain integers are also accepted.
+pub fn s2v_i32_labels(
+    first_value: i32,
+    labels: &'static [&'static str],
+) -> Arc<dyn Fn(&str) -> Option<i32> + Send + Sync> {
+    Arc::new(move |string| {
+        let string = string.trim();
+        match labels
+            .iter()
+            .position(|label| label.eq_ignore_ascii_case(string))
+        {
+            Some(idx) => Some(first_value + idx as i32),
+            None => string.parse().ok(),
+        }
+    })
+}
+
 /// Turns an integer MIDI note number (usually in the range [0, 127]) into a note name, where 60 is
-/// C4 and 69 is A4 (nice).
+/// C4 and 69 is A4 (nice). This is the same as [`v2s_i32_note_name(4)`][v2s_i32_note_name()].
+#[deprecated(note = "Use `v2s_i32_note_name(4)` instead")]
 pub fn v2s_i32_note_formatter() -> Arc<dyn Fn(i32) -> String + Send + Sync> {
+    v2s_i32_note_name(4)
+}
+
+/// Parse a note name to a MIDI number using the inverse mapping from
+/// [`v2s_i32_note_formatter()`]. This is the same as
+/// [`s2v_i32_note_name(4)`][s2v_i32_note_name()].
+#[deprecated(note = "Use `s2v_i32_note_name(4)` instead")]
+pub fn s2v_i32_note_formatter() -> Arc<dyn Fn(&str) -> Option<i32> + Send + Sync> {
+    s2v_i32_note_name(4)
+}
+
+/// Turn an integer MIDI note number into a note name like `C#3`. Notes are always displayed using
+/// sharps. DAWs don't agree on the octave numbering, so `middle_c_octave` is the octave MIDI note
+/// 60 belongs to. With a value of 3 note 60 is displayed as `C3`, and with a value of 4 it is
+/// displayed as `C4`.
+pub fn v2s_i32_note_name(middle_c_octave: i32) -> Arc<dyn Fn(i32) -> String + Send + Sync> {
     Arc::new(move |value| {
-        let note_name = util::NOTES[value as usize % 12];
-        let octave = (value / 12) - 1;
+        let note_name = util::NOTES[value.rem_euclid(12) as usize];
+        let octave = value.div_euclid(12) - 5 + middle_c_octave;
         format!("{note_name}{octave}")
     })
 }
 
-/// Parse a note name to a MIDI number using the inverse mapping from [`v2s_i32_note_formatter()].
-pub fn s2v_i32_note_formatter() -> Arc<dyn Fn(&str) -> Option<i32> + Send + Sync> {
-    Arc::new(|string| {
-        let (note_name, octave) = string
-            .trim()
-            .split_once(|c: char| c.is_whitespace() || c.is_ascii_digit())?;
+/// Parse a note name in the same format as [`v2s_i32_note_name()`] to a MIDI note number. Both
+/// sharps and flats are accepted, so `Db3` and `C#3` are parsed as the same note. The note letter
+/// is case insensitive, and `middle_c_octave` should be the same value passed to
+/// [`v2s_i32_note_name()`].
+pub fn s2v_i32_note_name(middle_c_octave: i32) -> Arc<dyn Fn(&str) -> Option<i32> + Send + Sync> {
+    Arc::new(move |string| {
+        let mut chars = string.trim().chars();
+        let mut note_id = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
 
-        let note_id = util::NOTES
-            .iter()
-            .position(|&candidate| note_name.eq_ignore_ascii_case(candidate))?
-            as i32;
+        // A lowercase `b` after the note letter is a flat
+        let mut octave = chars.as_str();
+        if let Some(rest) = octave.strip_prefix(['#', '♯']) {
+            note_id += 1;
+            octave = rest;
+        } else if let Some(rest) = octave.strip_prefix(['b', '♭']) {
+            note_id -= 1;
+            octave = rest;
+        }
         let octave: i32 = octave.trim().parse().ok()?;
 
-        Some((octave + 1) + (12 * note_id))
+        Some(((octave - middle_c_octave + 5) * 12) + note_id)
     })
 }
 
@@ -172,3 +333,273 @@ pub fn s2v_bool_bypass() -> Arc<dyn Fn(&str) -> Option<bool> + Send + Sync> {
         }
     })
 }
+
+/// Display `true_label` or `false_label` depending on whether the parameter is true or false.
+/// [`BoolParam::with_labels()`][crate::prelude::BoolParam::with_labels()] sets up both this and
+/// [`s2v_bool_labels()`].
+pub fn v2s_bool_labels(
+    true_label: &'static str,
+    false_label: &'static str,
+) -> Arc<dyn Fn(bool) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        if value {
+            String::from(true_label)
+        } else {
+            String::from(false_label)
+        }
+    })
+}
+
+/// Parse a string in the same format as [`v2s_bool_labels()`]. Labels are compared case
+/// insensitively. Strings that don't match either label are parsed the same way as they would be
+/// for a [`BoolParam`][crate::prelude::BoolParam] without custom labels, so 'On', 'Off', 'true',
+/// and 'false' are also accepted.
+pub fn s2v_bool_labels(
+    true_label: &'static str,
+    false_label: &'static str,
+) -> Arc<dyn Fn(&str) -> Option<bool> + Send + Sync> {
+    Arc::new(move |string| {
+        let string = string.trim();
+        if string.eq_ignore_ascii_case(true_label)
+            || string.eq_ignore_ascii_case("on")
+            || string.eq_ignore_ascii_case("true")
+        {
+            Some(true)
+        } else if string.eq_ignore_ascii_case(false_label)
+            || string.eq_ignore_ascii_case("off")
+            || string.eq_ignore_ascii_case("false")
+        {
+            Some(false)
+        } else {
+            None
+        }
+    })
+}
+
+/// Strip `suffix` from the end of `string` using a case insensitive comparison.
+fn strip_suffix_ignore_ascii_case<'a>(string: &'a str, suffix: &str) -> Option<&'a str> {
+    let split_pos = string.len().checked_sub(suffix.len())?;
+    match string.get(split_pos..) {
+        Some(string_suffix) if string_suffix.eq_ignore_ascii_case(suffix) => {
+            Some(&string[..split_pos])
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gain_to_db_formatting() {
+        let v2s = v2s_f32_gain_to_db(1);
+        assert_eq!(v2s(0.5), "-6.0");
+        assert_eq!(v2s(1.0), "0.0");
+        assert_eq!(v2s(0.0), "-inf");
+        assert_eq!(v2s(util::MINUS_INFINITY_GAIN), "-inf");
+    }
+
+    #[test]
+    fn gain_to_db_parsing() {
+        let s2v = s2v_f32_gain_to_db();
+        assert_eq!(s2v("0"), Some(1.0));
+        assert_eq!(s2v("0 dB"), Some(1.0));
+        assert_eq!(s2v(" 0.0dBFS "), Some(1.0));
+        assert_eq!(s2v("-inf"), Some(0.0));
+        assert_eq!(s2v("-inf dB"), Some(0.0));
+        assert_eq!(s2v("-INF DB"), Some(0.0));
+        assert_eq!(s2v("dB"), None);
+        assert_eq!(s2v("six"), None);
+    }
+
+    #[test]
+    fn gain_to_db_roundtrip() {
+        let v2s = v2s_f32_gain_to_db(1);
+        let s2v = s2v_f32_gain_to_db();
+        for gain in [0.0, 0.001, 0.25, 0.5, 0.7, 1.0, 2.0] {
+            let string = v2s(gain);
+            let roundtripped_string = v2s(s2v(&string).unwrap());
+            assert_eq!(string, roundtripped_string);
+
+            // The same thing should work when the unit is included
+            let roundtripped_string = v2s(s2v(&format!("{string} dB")).unwrap());
+            assert_eq!(string, roundtripped_string);
+        }
+    }
+
+    #[test]
+    fn hz_then_khz_formatting() {
+        let v2s = v2s_f32_hz_then_khz(0);
+        assert_eq!(v2s(440.0), "440 Hz");
+        assert_eq!(v2s(999.0), "999 Hz");
+        assert_eq!(v2s(999.9), "1.0 kHz");
+        assert_eq!(v2s(1000.0), "1.0 kHz");
+        assert_eq!(v2s(2500.0), "2.5 kHz");
+
+        let v2s = v2s_f32_hz_then_khz(2);
+        assert_eq!(v2s(440.0), "440.00 Hz");
+        assert_eq!(v2s(2500.0), "2.50 kHz");
+    }
+
+    #[test]
+    fn hz_then_khz_parsing() {
+        let s2v = s2v_f32_hz_then_khz();
+        assert_eq!(s2v("2.5 kHz"), Some(2500.0));
+        assert_eq!(s2v("2.5khz"), Some(2500.0));
+        assert_eq!(s2v("2500"), Some(2500.0));
+        assert_eq!(s2v("2500 Hz"), Some(2500.0));
+        assert_eq!(s2v(" 440HZ "), Some(440.0));
+        assert_eq!(s2v("kHz"), None);
+    }
+
+    #[test]
+    fn percentage_formatting() {
+        let v2s = v2s_f32_percentage(0);
+        assert_eq!(v2s(0.5), "50");
+        assert_eq!(v2s(0.0), "0");
+        assert_eq!(v2s(1.0), "100");
+        assert_eq!(v2s(-0.1), "-10");
+        assert_eq!(v2s(1.05), "105");
+
+        let v2s = v2s_f32_percentage(1);
+        assert_eq!(v2s(0.125), "12.5");
+    }
+
+    #[test]
+    fn percentage_parsing() {
+        let s2v = s2v_f32_percentage();
+        assert_eq!(s2v("50%"), Some(0.5));
+        assert_eq!(s2v("50"), Some(0.5));
+        assert_eq!(s2v(" 50 % "), Some(0.5));
+        assert_eq!(s2v("-10%"), Some(-0.1));
+        assert_eq!(s2v("105%"), Some(1.05));
+        assert_eq!(s2v("%"), None);
+    }
+
+    #[test]
+    fn labels_roundtrip() {
+        const LABELS: &[&str] = &["1x", "2x", "4x", "8x"];
+        let v2s = v2s_i32_labels(0, LABELS);
+        let s2v = s2v_i32_labels(0, LABELS);
+        for (value, label) in LABELS.iter().enumerate() {
+            assert_eq!(v2s(value as i32), *label);
+            assert_eq!(s2v(label), Some(value as i32));
+        }
+
+        // Values outside of the table are displayed as plain integers
+        assert_eq!(v2s(-1), "-1");
+        assert_eq!(v2s(4), "4");
+        assert_eq!(s2v("4"), Some(4));
+        assert_eq!(s2v(" 2X "), Some(1));
+        assert_eq!(s2v("16x"), None);
+    }
+
+    #[test]
+    fn labels_with_offset() {
+        const LABELS: &[&str] = &["Low", "Mid", "High"];
+        let v2s = v2s_i32_labels(-1, LABELS);
+        let s2v = s2v_i32_labels(-1, LABELS);
+        assert_eq!(v2s(-1), "Low");
+        assert_eq!(v2s(1), "High");
+        assert_eq!(v2s(i32::MIN), i32::MIN.to_string());
+        assert_eq!(s2v("mid"), Some(0));
+    }
+
+    #[test]
+    fn bool_labels_roundtrip() {
+        let v2s = v2s_bool_labels("Inverted", "Normal");
+        let s2v = s2v_bool_labels("Inverted", "Normal");
+        assert_eq!(v2s(true), "Inverted");
+        assert_eq!(v2s(false), "Normal");
+        assert_eq!(s2v(&v2s(true)), Some(true));
+        assert_eq!(s2v(&v2s(false)), Some(false));
+        assert_eq!(s2v(" inverted "), Some(true));
+        assert_eq!(s2v("NORMAL"), Some(false));
+        assert_eq!(s2v("On"), Some(true));
+        assert_eq!(s2v("off"), Some(false));
+        assert_eq!(s2v("sideways"), None);
+    }
+
+    #[test]
+    fn note_name_roundtrip() {
+        let v2s = v2s_i32_note_name(3);
+        let s2v = s2v_i32_note_name(3);
+        assert_eq!(v2s(60), "C3");
+        assert_eq!(v2s(61), "C#3");
+        assert_eq!(v2s(0), "C-2");
+        assert_eq!(v2s_i32_note_name(4)(60), "C4");
+        for note in 60..72 {
+            assert_eq!(s2v(&v2s(note)), Some(note));
+        }
+
+        assert_eq!(s2v("Db3"), s2v("C#3"));
+        assert_eq!(s2v("db3"), Some(61));
+        assert_eq!(s2v("B#2"), Some(60));
+        assert_eq!(s2v("Cb3"), Some(59));
+        assert_eq!(s2v("C-2"), Some(0));
+        assert_eq!(s2v_i32_note_name(4)("C4"), Some(60));
+        assert_eq!(s2v("H3"), None);
+        assert_eq!(s2v("C"), None);
+
+        for middle_c_octave in [3, 4] {
+            let v2s = v2s_i32_note_name(middle_c_octave);
+            let s2v = s2v_i32_note_name(middle_c_octave);
+            for note in 0..=127 {
+                assert_eq!(s2v(&v2s(note)), Some(note), "{}", v2s(note));
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn note_formatter_roundtrip() {
+        let v2s = v2s_i32_note_formatter();
+        let s2v = s2v_i32_note_formatter();
+        assert_eq!(v2s(60), "C4");
+        assert_eq!(v2s(69), "A4");
+        assert_eq!(s2v("A4"), Some(69));
+        assert_eq!(s2v("C10"), Some(132));
+        for note in 0..=127 {
+            assert_eq!(s2v(&v2s(note)), Some(note), "{}", v2s(note));
+        }
+    }
+
+    #[test]
+    fn ratio_roundtrip() {
+        let v2s = v2s_f32_ratio(1);
+        let s2v = s2v_f32_ratio();
+        assert_eq!(v2s(4.0), "4.0:1");
+        assert_eq!(v2s(1000.0), "∞:1");
+        assert_eq!(v2s(f32::INFINITY), "∞:1");
+        assert_eq!(s2v(&v2s(4.0)), Some(4.0));
+        assert_eq!(s2v(&v2s(f32::INFINITY)), Some(f32::INFINITY));
+
+        assert_eq!(s2v("4:1"), Some(4.0));
+        assert_eq!(s2v(" 4.5 : 1 "), Some(4.5));
+        assert_eq!(s2v("4"), Some(4.0));
+        assert_eq!(s2v("inf:1"), Some(f32::INFINITY));
+        assert_eq!(s2v("four"), None);
+        assert_eq!(s2v("4:"), None);
+    }
+
+    #[test]
+    fn grouped_roundtrip() {
+        let v2s = v2s_i32_grouped(',');
+        let s2v = s2v_i32_grouped(',');
+        assert_eq!(v2s(48000), "48,000");
+        assert_eq!(v2s(1234567), "1,234,567");
+        assert_eq!(v2s(-48000), "-48,000");
+        assert_eq!(v2s(999), "999");
+        assert_eq!(v2s(-999), "-999");
+        assert_eq!(v2s(i32::MIN), "-2,147,483,648");
+        for value in [0, 999, 1000, -1000, 48000, -123456, i32::MAX, i32::MIN] {
+            assert_eq!(s2v(&v2s(value)), Some(value));
+        }
+
+        assert_eq!(s2v("48000"), Some(48000));
+        assert_eq!(s2v(" 4,8000 "), Some(48000));
+        assert_eq!(s2v_i32_grouped('.')("48.000"), Some(48000));
+        assert_eq!(s2v("48 000"), None);
+    }
+}
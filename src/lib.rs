@@ -10,7 +10,8 @@
 //!   to. By default, the output is logged to STDERR unless you're running Windows and a Windows
 //!   debugger is attached, in which case the output is logged to the debug console instead. The
 //!   `NIH_LOG` environment variable controls whether output is logged to STDERR, the Windows debug
-//!   console, or to a file. Check the [`nih_log!()`] macro for more information.
+//!   console, or to a file, and `NIH_LOG_LEVEL` can be used to filter out less important messages.
+//!   Check the [`nih_log!()`] macro for more information.
 //! - The abovementioned debug module also contains non-fatal debug-assertions macros that are only
 //!   evaluated during debug builds. The framework uses these all over the place to check for
 //!   invariants, so it's important to test your plugins using debug builds while developing.
@@ -76,12 +77,14 @@
 //!  - After calling `.with_smoother()` during an integer or floating point parameter's creation,
 //!    you can use `param.smoothed` to access smoothed values for that parameter. Be sure to check
 //!    out the [`Smoother`][prelude::Smoother] API for more details. If you want to generate entire
-//!    blocks of smoothed values, be sure to call the predefined
-//!    `[Plugin::initialize_block_smoothers()]` method from your plugin's `initialize()` function.
+//!    blocks of smoothed values, then you can use `Smoother::next_block()` with a buffer you
+//!    allocated in your plugin's `initialize()` function.
 //!
 //! There's a whole lot more to discuss, but once you understand the above you should be able to
 //! figure out the rest by reading through the examples and the API documetnation. Good luck!
 
+#![cfg_attr(feature = "debug_break", allow(internal_features))]
+#![cfg_attr(feature = "debug_break", feature(core_intrinsics))]
 #![cfg_attr(feature = "docs", feature(doc_auto_cfg))]
 #![cfg_attr(feature = "simd", feature(portable_simd))]
 
@@ -102,8 +105,11 @@ pub mod util;
 
 pub mod buffer;
 pub mod context;
+#[cfg(feature = "editor")]
+pub mod editor;
 mod event_loop;
 pub mod midi;
 pub mod param;
 pub mod plugin;
+pub mod preset;
 pub mod wrapper;
@@ -24,12 +24,38 @@ pub enum MidiConfig {
 ///
 /// All of the timings are sample offsets withing the current buffer. All sample, channel and note
 /// numbers are zero-indexed.
+///
+/// # Voice IDs
+///
+/// CLAP hosts, and some VST3 hosts, assign a voice ID to every note. A voice's lifecycle looks
+/// like this:
+///
+/// 1. The plugin receives a [`NoteOn`][Self::NoteOn] event with `voice_id: Some(id)`. A
+///    polyphonic plugin should start a new voice and store this ID together with it, even if
+///    another voice is already playing the same note.
+/// 2. Any polyphonic expression and [`PolyModulation`][Self::PolyModulation] events for that
+///    voice carry the same ID, so they can be routed to the right voice.
+/// 3. The [`NoteOff`][Self::NoteOff] event for the voice also carries the ID. The voice may keep
+///    sounding for a while after this, for instance while its release stage is playing.
+/// 4. Once the voice has actually stopped producing sound, or when it gets stolen to make room for
+///    another voice, the plugin should send a [`VoiceTerminated`][Self::VoiceTerminated] event
+///    with the same ID, channel, and note through
+///    [`ProcessContext::send_event()`][crate::prelude::ProcessContext::send_event()]. That lets
+///    the host free any polyphonic modulation state it kept for the voice, and it also clears the
+///    voice's offsets stored on polyphonically modulated parameters.
+///
+/// If the host did not assign a voice ID, then the voice is identified by its channel and note
+/// number instead.
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[non_exhaustive]
 pub enum NoteEvent {
     /// A note on event, available on [`MidiConfig::Basic`] and up.
     NoteOn {
         timing: u32,
+        /// The note's voice ID, if the host assigned one. All later events for this voice, like
+        /// note expressions and the voice's note off event, will carry the same voice ID. This
+        /// makes it possible to tell apart multiple voices playing the same note.
+        voice_id: Option<i32>,
         /// The note's channel, from 0 to 16.
         channel: u8,
         /// The note's MIDI key number, from 0 to 127.
@@ -41,6 +67,9 @@ pub enum NoteEvent {
     /// A note off event, available on [`MidiConfig::Basic`] and up.
     NoteOff {
         timing: u32,
+        /// The ID of the voice this event belongs to, if the host sends voice IDs. This matches the
+        /// `voice_id` from the voice's [`NoteOn`][Self::NoteOn] event.
+        voice_id: Option<i32>,
         /// The note's channel, from 0 to 16.
         channel: u8,
         /// The note's MIDI key number, from 0 to 127.
@@ -59,6 +88,9 @@ pub enum NoteEvent {
     /// you may manually combine the polyphonic key pressure and MPE channel pressure.
     PolyPressure {
         timing: u32,
+        /// The ID of the voice this event belongs to, if the host sends voice IDs. This matches the
+        /// `voice_id` from the voice's [`NoteOn`][Self::NoteOn] event.
+        voice_id: Option<i32>,
         /// The note's channel, from 0 to 16.
         channel: u8,
         /// The note's MIDI key number, from 0 to 127.
@@ -70,6 +102,9 @@ pub enum NoteEvent {
     /// support these expressions.
     PolyVolume {
         timing: u32,
+        /// The ID of the voice this event belongs to, if the host sends voice IDs. This matches the
+        /// `voice_id` from the voice's [`NoteOn`][Self::NoteOn] event.
+        voice_id: Option<i32>,
         /// The note's channel, from 0 to 16.
         channel: u8,
         /// The note's MIDI key number, from 0 to 127.
@@ -81,6 +116,9 @@ pub enum NoteEvent {
     /// support these expressions.
     PolyPan {
         timing: u32,
+        /// The ID of the voice this event belongs to, if the host sends voice IDs. This matches the
+        /// `voice_id` from the voice's [`NoteOn`][Self::NoteOn] event.
+        voice_id: Option<i32>,
         /// The note's channel, from 0 to 16.
         channel: u8,
         /// The note's MIDI key number, from 0 to 127.
@@ -93,6 +131,9 @@ pub enum NoteEvent {
     /// these expressions.
     PolyTuning {
         timing: u32,
+        /// The ID of the voice this event belongs to, if the host sends voice IDs. This matches the
+        /// `voice_id` from the voice's [`NoteOn`][Self::NoteOn] event.
+        voice_id: Option<i32>,
         /// The note's channel, from 0 to 16.
         channel: u8,
         /// The note's MIDI key number, from 0 to 127.
@@ -104,6 +145,9 @@ pub enum NoteEvent {
     /// these expressions.
     PolyVibrato {
         timing: u32,
+        /// The ID of the voice this event belongs to, if the host sends voice IDs. This matches the
+        /// `voice_id` from the voice's [`NoteOn`][Self::NoteOn] event.
+        voice_id: Option<i32>,
         /// The note's channel, from 0 to 16.
         channel: u8,
         /// The note's MIDI key number, from 0 to 127.
@@ -115,6 +159,9 @@ pub enum NoteEvent {
     /// [`MidiConfig::Basic`] and up. Not all hosts may support these expressions.
     PolyExpression {
         timing: u32,
+        /// The ID of the voice this event belongs to, if the host sends voice IDs. This matches the
+        /// `voice_id` from the voice's [`NoteOn`][Self::NoteOn] event.
+        voice_id: Option<i32>,
         /// The note's channel, from 0 to 16.
         channel: u8,
         /// The note's MIDI key number, from 0 to 127.
@@ -126,6 +173,9 @@ pub enum NoteEvent {
     /// these expressions.
     PolyBrightness {
         timing: u32,
+        /// The ID of the voice this event belongs to, if the host sends voice IDs. This matches the
+        /// `voice_id` from the voice's [`NoteOn`][Self::NoteOn] event.
+        voice_id: Option<i32>,
         /// The note's channel, from 0 to 16.
         channel: u8,
         /// The note's MIDI key number, from 0 to 127.
@@ -133,6 +183,37 @@ pub enum NoteEvent {
         /// The note's brightness amount, from 0 to 1.
         brightness: f32,
     },
+    /// A polyphonic modulation event, available on [`MidiConfig::Basic`] and up. This is only sent
+    /// for parameters that have been assigned a poly modulation ID with
+    /// [`FloatParam::with_poly_modulation_id()`][crate::prelude::FloatParam::with_poly_modulation_id()],
+    /// and only CLAP hosts send these events. The offset also gets stored on the parameter itself,
+    /// so it can be read back with
+    /// [`FloatParam::modulated_value_for_voice()`][crate::prelude::FloatParam::modulated_value_for_voice()].
+    PolyModulation {
+        timing: u32,
+        /// The ID of the voice being modulated. This is the host's note ID.
+        voice_id: i32,
+        /// The poly modulation ID of the parameter being modulated.
+        poly_modulation_id: u32,
+        /// The modulation offset in normalized units. This offset replaces any previous offset for
+        /// the same voice, and it is added on top of the parameter's current (smoothed) value.
+        normalized_offset: f32,
+    },
+    /// Sent by the plugin to let the host know that a voice has ended, see the section on voice IDs
+    /// above. This requires [`Plugin::MIDI_INPUT`][crate::prelude::Plugin::MIDI_INPUT] to be set
+    /// to [`MidiConfig::Basic`] or higher, and it works regardless of the
+    /// [`Plugin::MIDI_OUTPUT`][crate::prelude::Plugin::MIDI_OUTPUT] setting. Only the CLAP wrapper
+    /// sends this event to the host, the other wrappers ignore it. The plugin will never receive
+    /// this event.
+    VoiceTerminated {
+        timing: u32,
+        /// The voice's ID, if it had one.
+        voice_id: Option<i32>,
+        /// The voice's channel, from 0 to 16.
+        channel: u8,
+        /// The voice's MIDI key number, from 0 to 127.
+        note: u8,
+    },
     /// A MIDI channel pressure event, available on [`MidiConfig::MidiCCs`] and up.
     MidiChannelPressure {
         timing: u32,
@@ -165,6 +246,16 @@ pub enum NoteEvent {
         /// The CC's value, normalized to `[0, 1]`. Multiply by 127 to get the original raw value.
         value: f32,
     },
+    /// A MIDI program change event, available on [`MidiConfig::MidiCCs`] and up. VST3 plugins
+    /// cannot receive these events since the VST3 API handles program changes through parameters,
+    /// but they can still be sent to the host.
+    MidiProgramChange {
+        timing: u32,
+        /// The affected channel, from 0 to 16.
+        channel: u8,
+        /// The program number, from 0 to 127.
+        program: u8,
+    },
 }
 
 impl NoteEvent {
@@ -180,9 +271,12 @@ impl NoteEvent {
             NoteEvent::PolyVibrato { timing, .. } => *timing,
             NoteEvent::PolyExpression { timing, .. } => *timing,
             NoteEvent::PolyBrightness { timing, .. } => *timing,
+            NoteEvent::PolyModulation { timing, .. } => *timing,
+            NoteEvent::VoiceTerminated { timing, .. } => *timing,
             NoteEvent::MidiChannelPressure { timing, .. } => *timing,
             NoteEvent::MidiPitchBend { timing, .. } => *timing,
             NoteEvent::MidiCC { timing, .. } => *timing,
+            NoteEvent::MidiProgramChange { timing, .. } => *timing,
         }
     }
 
@@ -195,18 +289,21 @@ impl NoteEvent {
         match event_type {
             midi::NOTE_ON => Ok(NoteEvent::NoteOn {
                 timing,
+                voice_id: None,
                 channel,
                 note: midi_data[1],
                 velocity: midi_data[2] as f32 / 127.0,
             }),
             midi::NOTE_OFF => Ok(NoteEvent::NoteOff {
                 timing,
+                voice_id: None,
                 channel,
                 note: midi_data[1],
                 velocity: midi_data[2] as f32 / 127.0,
             }),
             midi::POLYPHONIC_KEY_PRESSURE => Ok(NoteEvent::PolyPressure {
                 timing,
+                voice_id: None,
                 channel,
                 note: midi_data[1],
                 pressure: midi_data[2] as f32 / 127.0,
@@ -228,6 +325,11 @@ impl NoteEvent {
                 cc: midi_data[1],
                 value: midi_data[2] as f32 / 127.0,
             }),
+            midi::PROGRAM_CHANGE => Ok(NoteEvent::MidiProgramChange {
+                timing,
+                channel,
+                program: midi_data[1],
+            }),
             n => Err(n),
         }
     }
@@ -239,6 +341,7 @@ impl NoteEvent {
         match self {
             NoteEvent::NoteOn {
                 timing: _,
+                voice_id: _,
                 channel,
                 note,
                 velocity,
@@ -249,6 +352,7 @@ impl NoteEvent {
             ]),
             NoteEvent::NoteOff {
                 timing: _,
+                voice_id: _,
                 channel,
                 note,
                 velocity,
@@ -259,6 +363,7 @@ impl NoteEvent {
             ]),
             NoteEvent::PolyPressure {
                 timing: _,
+                voice_id: _,
                 channel,
                 note,
                 pressure,
@@ -302,12 +407,19 @@ impl NoteEvent {
                 cc,
                 (value * 127.0).round().clamp(0.0, 127.0) as u8,
             ]),
+            NoteEvent::MidiProgramChange {
+                timing: _,
+                channel,
+                program,
+            } => Some([midi::PROGRAM_CHANGE | channel, program, 0]),
             NoteEvent::PolyVolume { .. }
             | NoteEvent::PolyPan { .. }
             | NoteEvent::PolyTuning { .. }
             | NoteEvent::PolyVibrato { .. }
             | NoteEvent::PolyExpression { .. }
-            | NoteEvent::PolyBrightness { .. } => None,
+            | NoteEvent::PolyBrightness { .. }
+            | NoteEvent::PolyModulation { .. }
+            | NoteEvent::VoiceTerminated { .. } => None,
         }
     }
 
@@ -324,9 +436,12 @@ impl NoteEvent {
             NoteEvent::PolyVibrato { timing, .. } => *timing -= samples,
             NoteEvent::PolyExpression { timing, .. } => *timing -= samples,
             NoteEvent::PolyBrightness { timing, .. } => *timing -= samples,
+            NoteEvent::PolyModulation { timing, .. } => *timing -= samples,
+            NoteEvent::VoiceTerminated { timing, .. } => *timing -= samples,
             NoteEvent::MidiChannelPressure { timing, .. } => *timing -= samples,
             NoteEvent::MidiPitchBend { timing, .. } => *timing -= samples,
             NoteEvent::MidiCC { timing, .. } => *timing -= samples,
+            NoteEvent::MidiProgramChange { timing, .. } => *timing -= samples,
         }
     }
 }
@@ -341,6 +456,7 @@ mod tests {
     fn test_note_on_midi_conversion() {
         let event = NoteEvent::NoteOn {
             timing: TIMING,
+            voice_id: None,
             channel: 1,
             note: 2,
             // The value will be rounded in the conversion to MIDI, hence this overly specific value
@@ -357,6 +473,7 @@ mod tests {
     fn test_note_off_midi_conversion() {
         let event = NoteEvent::NoteOff {
             timing: TIMING,
+            voice_id: None,
             channel: 1,
             note: 2,
             velocity: 0.6929134,
@@ -372,6 +489,7 @@ mod tests {
     fn test_poly_pressure_midi_conversion() {
         let event = NoteEvent::PolyPressure {
             timing: TIMING,
+            voice_id: None,
             channel: 1,
             note: 2,
             pressure: 0.6929134,
@@ -425,4 +543,57 @@ mod tests {
             event
         );
     }
+
+    #[test]
+    fn test_program_change_midi_conversion() {
+        let event = NoteEvent::MidiProgramChange {
+            timing: TIMING,
+            channel: 1,
+            program: 42,
+        };
+
+        assert_eq!(
+            NoteEvent::from_midi(TIMING, event.as_midi().unwrap()).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn test_raw_midi_decoding() {
+        assert_eq!(
+            NoteEvent::from_midi(TIMING, [0xB3, 74, 127]),
+            Ok(NoteEvent::MidiCC {
+                timing: TIMING,
+                channel: 3,
+                cc: 74,
+                value: 1.0,
+            })
+        );
+        assert_eq!(
+            NoteEvent::from_midi(TIMING, [0xE0, 0x00, 0x40]),
+            Ok(NoteEvent::MidiPitchBend {
+                timing: TIMING,
+                channel: 0,
+                value: 0x2000 as f32 / 0x3FFF as f32,
+            })
+        );
+        assert_eq!(
+            NoteEvent::from_midi(TIMING, [0xDF, 0, 0]),
+            Ok(NoteEvent::MidiChannelPressure {
+                timing: TIMING,
+                channel: 15,
+                pressure: 0.0,
+            })
+        );
+        assert_eq!(
+            NoteEvent::from_midi(TIMING, [0xC2, 5, 0]),
+            Ok(NoteEvent::MidiProgramChange {
+                timing: TIMING,
+                channel: 2,
+                program: 5,
+            })
+        );
+        // System messages are not supported
+        assert_eq!(NoteEvent::from_midi(TIMING, [0xF8, 0, 0]), Err(0xF0));
+    }
 }
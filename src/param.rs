@@ -18,7 +18,7 @@ pub mod smoothing;
 
 pub use boolean::BoolParam;
 pub use enums::EnumParam;
-pub use float::FloatParam;
+pub use float::{FloatParam, MAX_POLY_MODULATION_VOICES};
 pub use integer::IntParam;
 
 bitflags::bitflags! {
@@ -38,10 +38,22 @@ bitflags::bitflags! {
         /// Hides the parameter in the host's generic UI for this plugin. This also implies
         /// `NON_AUTOMATABLE`. Setting this does not prevent you from changing the parameter in the
         /// plugin's editor GUI.
+        ///
+        /// Hidden parameters are still saved and restored as part of the plugin's state just like
+        /// any other parameter. This makes them a good fit for fine-grained internal state that
+        /// should be tied to the rest of the plugin's parameters, but that should not be exposed
+        /// to the user through the host.
         const HIDDEN = 1 << 2;
         /// Don't show this parameter when generating a generic UI for the plugin using one of
         /// NIH-plug's generic UI widgets.
         const HIDE_IN_GENERIC_UI = 1 << 3;
+        /// The parameter is only used to display a value to the user, like a gain reduction
+        /// readout. The host will still show the parameter and its current value, but it cannot
+        /// change it. This also implies `NON_AUTOMATABLE`. Any changes sent by the host are
+        /// ignored. The plugin can still change the parameter's value from its editor using a
+        /// [`ParamSetter`][crate::prelude::ParamSetter], and those changes are reported to the
+        /// host as usual.
+        const READ_ONLY = 1 << 4;
     }
 }
 
@@ -133,11 +145,6 @@ pub trait Param: Display {
     /// wrappers. This **does** snap to step sizes for continuous parameters (i.e. [`FloatParam`]).
     fn preview_plain(&self, normalized: f32) -> Self::Plain;
 
-    /// Allocate memory for block-based smoothing. The
-    /// [`Plugin::initialize_block_smoothers()`][crate::prelude::Plugin::initialize_block_smoothers()] method
-    /// will do this for every smoother.
-    fn initialize_block_smoother(&mut self, max_block_size: usize);
-
     /// Flags to control the parameter's behavior. See [`ParamFlags`].
     fn flags(&self) -> ParamFlags;
 
@@ -5,6 +5,7 @@ use std::sync::Arc;
 
 use super::internals::ParamPtr;
 use super::{Param, ParamFlags, ParamMut};
+use crate::formatters;
 
 /// A simple boolean parameter.
 #[repr(C, align(4))]
@@ -133,8 +134,6 @@ impl Param for BoolParam {
         normalized > 0.5
     }
 
-    fn initialize_block_smoother(&mut self, _max_block_size: usize) {}
-
     fn flags(&self) -> ParamFlags {
         self.flags
     }
@@ -212,6 +211,8 @@ impl BoolParam {
     /// is the parameter's new value. This should not do anything expensive as it may be called
     /// multiple times in rapid succession, and it can be run from both the GUI and the audio
     /// thread.
+    ///
+    /// This has the same realtime safety requirements as [`FloatParam::with_callback()`][crate::prelude::FloatParam::with_callback()].
     pub fn with_callback(mut self, callback: Arc<dyn Fn(bool) + Send + Sync>) -> Self {
         self.value_changed = Some(callback);
         self
@@ -226,6 +227,18 @@ impl BoolParam {
         self
     }
 
+    /// Display the parameter's value as `true_label` or `false_label` instead of 'On' and 'Off',
+    /// for instance to show 'Inverted' and 'Normal' for a polarity switch. This sets both the
+    /// [`value_to_string`][Self::with_value_to_string()] and the
+    /// [`string_to_value`][Self::with_string_to_value()] functions using
+    /// [`formatters::v2s_bool_labels()`][crate::formatters::v2s_bool_labels()] and
+    /// [`formatters::s2v_bool_labels()`][crate::formatters::s2v_bool_labels()].
+    pub fn with_labels(mut self, true_label: &'static str, false_label: &'static str) -> Self {
+        self.value_to_string = Some(formatters::v2s_bool_labels(true_label, false_label));
+        self.string_to_value = Some(formatters::s2v_bool_labels(true_label, false_label));
+        self
+    }
+
     /// Use a custom conversion function to convert from a string to a boolean value. If the string
     /// cannot be parsed, then this should return a `None`. If this happens while the parameter is
     /// being updated then the update will be canceled.
@@ -240,7 +253,9 @@ impl BoolParam {
     /// Mark this parameter as a bypass parameter. Plugin hosts can integrate this parameter into
     /// their UI. Only a single [`BoolParam`] can be a bypass parameter, and NIH-plug will add one
     /// if you don't create one yourself. You will need to implement this yourself if your plugin
-    /// introduces latency.
+    /// introduces latency. Otherwise you can set
+    /// [`Plugin::BYPASS_PASSTHROUGH`][crate::prelude::Plugin::BYPASS_PASSTHROUGH] to have the
+    /// wrapper pass the main input through to the main output while bypassed.
     pub fn make_bypass(mut self) -> Self {
         self.flags.insert(ParamFlags::BYPASS);
         self
@@ -254,9 +269,19 @@ impl BoolParam {
         self
     }
 
+    /// Mark the parameter as read-only. The host will display the parameter's value, but neither
+    /// the host nor the user can change it from outside of the plugin. The plugin's editor can
+    /// still change the value with a [`ParamSetter`][crate::prelude::ParamSetter]. This also
+    /// implies `NON_AUTOMATABLE`.
+    pub fn read_only(mut self) -> Self {
+        self.flags.insert(ParamFlags::READ_ONLY);
+        self
+    }
+
     /// Hide the parameter in the host's generic UI for this plugin. This also implies
     /// `NON_AUTOMATABLE`. Setting this does not prevent you from changing the parameter in the
-    /// plugin's editor GUI.
+    /// plugin's editor GUI, and the parameter is still stored in the plugin's state. See
+    /// [`ParamFlags::HIDDEN`].
     pub fn hide(mut self) -> Self {
         self.flags.insert(ParamFlags::HIDDEN);
         self
@@ -33,18 +33,19 @@ pub use nih_plug_derive::Enum;
 /// ```ignore
 /// #[derive(Enum)]
 /// enum Foo {
-///     #[id = "bar"],
+///     #[id = "bar"]
 ///     Bar,
-///     #[id = "baz"],
+///     #[id = "baz"]
 ///     Baz,
-///     #[id = "contains-spaces"],
+///     #[id = "contains-spaces"]
 ///     #[name = "Contains Spaces"]
 ///     ContainsSpaces,
 /// }
 /// ```
 ///
 /// You can safely move from not using IDs to using IDs without breaking patches, but you cannot go
-/// back to not using IDs after that.
+/// back to not using IDs after that. When a patch refers to a variant (ID) that no longer exists,
+/// the parameter will be reset to its default value instead.
 pub trait Enum {
     /// The human readable names for the variants. These are displayed in the GUI or parameter list,
     /// and also used for parsing text back to a parameter value. The length of this slice
@@ -171,10 +172,6 @@ impl<T: Enum + PartialEq> Param for EnumParam<T> {
         T::from_index(self.inner.preview_plain(normalized) as usize)
     }
 
-    fn initialize_block_smoother(&mut self, max_block_size: usize) {
-        self.inner.initialize_block_smoother(max_block_size)
-    }
-
     fn flags(&self) -> ParamFlags {
         self.inner.flags()
     }
@@ -253,10 +250,6 @@ impl Param for EnumParamInner {
         self.inner.preview_plain(normalized)
     }
 
-    fn initialize_block_smoother(&mut self, max_block_size: usize) {
-        self.inner.initialize_block_smoother(max_block_size)
-    }
-
     fn flags(&self) -> ParamFlags {
         self.inner.flags()
     }
@@ -330,6 +323,8 @@ impl<T: Enum + PartialEq + 'static> EnumParam<T> {
     /// is the parameter's new value. This should not do anything expensive as it may be called
     /// multiple times in rapid succession, and it can be run from both the GUI and the audio
     /// thread.
+    ///
+    /// This has the same realtime safety requirements as [`FloatParam::with_callback()`][crate::prelude::FloatParam::with_callback()].
     pub fn with_callback(mut self, callback: Arc<dyn Fn(T) + Send + Sync>) -> Self {
         self.inner.inner = self.inner.inner.with_callback(Arc::new(move |value| {
             callback(T::from_index(value as usize))
@@ -345,9 +340,19 @@ impl<T: Enum + PartialEq + 'static> EnumParam<T> {
         self
     }
 
+    /// Mark the parameter as read-only. The host will display the parameter's value, but neither
+    /// the host nor the user can change it from outside of the plugin. The plugin's editor can
+    /// still change the value with a [`ParamSetter`][crate::prelude::ParamSetter]. This also
+    /// implies `NON_AUTOMATABLE`.
+    pub fn read_only(mut self) -> Self {
+        self.inner.inner = self.inner.inner.read_only();
+        self
+    }
+
     /// Hide the parameter in the host's generic UI for this plugin. This also implies
     /// `NON_AUTOMATABLE`. Setting this does not prevent you from changing the parameter in the
-    /// plugin's editor GUI.
+    /// plugin's editor GUI, and the parameter is still stored in the plugin's state. See
+    /// [`ParamFlags::HIDDEN`].
     pub fn hide(mut self) -> Self {
         self.inner.inner = self.inner.inner.hide();
         self
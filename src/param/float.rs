@@ -7,6 +7,11 @@ use super::internals::ParamPtr;
 use super::range::FloatRange;
 use super::smoothing::{Smoother, SmoothingStyle};
 use super::{Param, ParamFlags, ParamMut};
+use crate::formatters;
+
+/// The maximum number of voices a [`FloatParam`] can track polyphonic modulation offsets for at
+/// the same time. When more voices are modulated at once, older offsets get overwritten.
+pub const MAX_POLY_MODULATION_VOICES: usize = 64;
 
 /// A floating point parameter that's stored unnormalized. The range is used for the normalization
 /// process.
@@ -35,10 +40,25 @@ pub struct FloatParam {
     /// `unmodulated_normalized_`. This needs to be stored separately since the normalied values are
     /// clamped, and this value persists after new automation events.
     modulation_offset: f32,
+    /// The ID used to route polyphonic modulation events to this parameter. If this is `None`,
+    /// then the parameter is not polyphonically modulatable. See
+    /// [`with_poly_modulation_id()`][Self::with_poly_modulation_id()].
+    poly_modulation_id: Option<u32>,
+    /// Per-voice normalized modulation offsets as `(voice_id, normalized_offset)` pairs. Unused
+    /// slots have a voice ID of -1. This is empty unless a poly modulation ID has been set, and it
+    /// will never be resized afterwards so setting an offset doesn't allocate.
+    poly_modulation_offsets: Vec<(i32, f32)>,
+    /// The slot in `poly_modulation_offsets` that will be overwritten next when all slots are in
+    /// use. Slots are reused in a round robin fashion.
+    next_poly_modulation_slot: usize,
     /// The field's default plain, unnormalized value.
     default: f32,
     /// An optional smoother that will automatically interpolate between the new automation values
-    /// set by the host.
+    /// set by the host. The smoother's target is always the parameter's modulated
+    /// [`value`][Self::value], so changes to both the host's automation and the host's
+    /// monophonic CLAP modulation are smoothed the same way. The wrappers overwrite the target
+    /// every time either of those changes, so there's no need to call
+    /// [`Smoother::set_target()`] on this yourself.
     pub smoothed: Smoother<f32>,
 
     /// Flags to control the parameter's behavior. See [`ParamFlags`].
@@ -125,7 +145,10 @@ impl Param for FloatParam {
     }
 
     fn step_count(&self) -> Option<usize> {
-        None
+        // This lets hosts treat the parameter as a stepped parameter when the step size divides the
+        // range evenly
+        self.step_size
+            .and_then(|step_size| self.range.step_count(step_size))
     }
 
     fn previous_step(&self, from: Self::Plain) -> Self::Plain {
@@ -191,10 +214,6 @@ impl Param for FloatParam {
         }
     }
 
-    fn initialize_block_smoother(&mut self, max_block_size: usize) {
-        self.smoothed.initialize_block_smoother(max_block_size);
-    }
-
     fn flags(&self) -> ParamFlags {
         self.flags
     }
@@ -261,6 +280,9 @@ impl FloatParam {
             unmodulated_value: default,
             unmodulated_normalized_value: range.normalize(default),
             modulation_offset: 0.0,
+            poly_modulation_id: None,
+            poly_modulation_offsets: Vec::new(),
+            next_poly_modulation_slot: 0,
             default,
             smoothed: Smoother::none(),
 
@@ -276,18 +298,27 @@ impl FloatParam {
         }
     }
 
+    /// Build a wet/dry mix parameter. This ranges from 0.0 for a fully dry signal to 1.0 for a
+    /// fully wet signal, it's displayed as a percentage, and it's smoothed over 10 milliseconds.
+    /// Use [`util::equal_power_gains()`][crate::util::equal_power_gains()] to get the gains for the
+    /// dry and the wet signals from the parameter's (smoothed) value.
+    pub fn new_mix(name: impl Into<String>, default: f32) -> Self {
+        Self::new(name, default, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(10.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage())
+    }
+
     /// Set up a smoother that can gradually interpolate changes made to this parameter, preventing
     /// clicks and zipper noises.
     pub fn with_smoother(mut self, style: SmoothingStyle) -> Self {
         // Logarithmic smoothing will cause problems if the range goes through zero since then you
         // end up multplying by zero
-        let goes_through_zero = match (&style, &self.range) {
-            (
-                SmoothingStyle::Logarithmic(_),
-                FloatRange::Linear { min, max }
-                | FloatRange::Skewed { min, max, .. }
-                | FloatRange::SymmetricalSkewed { min, max, .. },
-            ) => *min == 0.0 || *max == 0.0 || min.signum() != max.signum(),
+        let goes_through_zero = match (&style, self.range.min(), self.range.max()) {
+            (SmoothingStyle::Logarithmic(_), min, max) => {
+                min == 0.0 || max == 0.0 || min.signum() != max.signum()
+            }
             _ => false,
         };
         nih_debug_assert!(
@@ -303,6 +334,14 @@ impl FloatParam {
     /// is the parameter's new value. This should not do anything expensive as it may be called
     /// multiple times in rapid succession, and it can be run from both the GUI and the audio
     /// thread.
+    ///
+    /// The callback is called after the new value has been stored, so the parameter already
+    /// returns the new value when reading it from within the callback. The value passed to the
+    /// callback includes any monophonic modulation, and the callback will also be called when only
+    /// the modulation changes. Because the host usually changes parameter values from the audio
+    /// thread, the callback needs to be realtime safe. It should not allocate, block, or take any
+    /// locks that may be contended. A common pattern is to set an `AtomicBool` flag in the callback
+    /// and to recompute any derived coefficients in the process function when that flag is set.
     pub fn with_callback(mut self, callback: Arc<dyn Fn(f32) + Send + Sync>) -> Self {
         self.value_changed = Some(callback);
         self
@@ -320,6 +359,11 @@ impl FloatParam {
     /// this is set and a [`value_to_string`][Self::with_value_to_string()] function is not set,
     /// then this is also used when formatting the parameter. This must be a positive, nonzero
     /// number.
+    ///
+    /// Values set by the host are snapped to this step size, and when the parameter has a linear
+    /// range whose endpoints lie on the step grid, the parameter will also be reported to the host
+    /// as a stepped parameter with [`FloatRange::step_count()`] steps. The smoother still
+    /// interpolates from the old value to the new snapped value.
     pub fn with_step_size(mut self, step_size: f32) -> Self {
         self.step_size = Some(step_size);
         self
@@ -349,6 +393,20 @@ impl FloatParam {
         self
     }
 
+    /// Allow the host to polyphonically modulate this parameter. Modulation events for individual
+    /// voices will be sent to the plugin as [`NoteEvent::PolyModulation`] events with this ID, and
+    /// the per-voice values can also be read back using
+    /// [`modulated_value_for_voice()`][Self::modulated_value_for_voice()]. The ID needs to be
+    /// unique among all of the plugin's parameters, and the wrapper panics when creating the plugin
+    /// if two parameters share the same ID. This is currently only supported for CLAP plugins.
+    ///
+    /// [`NoteEvent::PolyModulation`]: crate::midi::NoteEvent::PolyModulation
+    pub fn with_poly_modulation_id(mut self, poly_modulation_id: u32) -> Self {
+        self.poly_modulation_id = Some(poly_modulation_id);
+        self.poly_modulation_offsets = vec![(-1, 0.0); MAX_POLY_MODULATION_VOICES];
+        self
+    }
+
     /// Mark the paramter as non-automatable. This means that the parameter cannot be changed from
     /// an automation lane. The parameter can however still be manually changed by the user from
     /// either the plugin's own GUI or from the host's generic UI.
@@ -357,9 +415,19 @@ impl FloatParam {
         self
     }
 
+    /// Mark the parameter as read-only. The host will display the parameter's value, but neither
+    /// the host nor the user can change it from outside of the plugin. The plugin's editor can
+    /// still change the value with a [`ParamSetter`][crate::prelude::ParamSetter]. This also
+    /// implies `NON_AUTOMATABLE`.
+    pub fn read_only(mut self) -> Self {
+        self.flags.insert(ParamFlags::READ_ONLY);
+        self
+    }
+
     /// Hide the parameter in the host's generic UI for this plugin. This also implies
     /// `NON_AUTOMATABLE`. Setting this does not prevent you from changing the parameter in the
-    /// plugin's editor GUI.
+    /// plugin's editor GUI, and the parameter is still stored in the plugin's state. See
+    /// [`ParamFlags::HIDDEN`].
     pub fn hide(mut self) -> Self {
         self.flags.insert(ParamFlags::HIDDEN);
         self
@@ -371,6 +439,89 @@ impl FloatParam {
         self.flags.insert(ParamFlags::HIDE_IN_GENERIC_UI);
         self
     }
+
+    /// The poly modulation ID set with
+    /// [`with_poly_modulation_id()`][Self::with_poly_modulation_id()], if any.
+    pub fn poly_modulation_id(&self) -> Option<u32> {
+        self.poly_modulation_id
+    }
+
+    /// Get the parameter's plain value for a specific voice. This takes the parameter's current
+    /// smoothed value and adds the voice's polyphonic modulation offset to it in the normalized
+    /// domain. If the voice has not been modulated, then this is simply the parameter's current
+    /// smoothed value. The voice's offset is removed once the plugin sends a
+    /// [`NoteEvent::VoiceTerminated`] event for it.
+    ///
+    /// [`NoteEvent::VoiceTerminated`]: crate::midi::NoteEvent::VoiceTerminated
+    pub fn modulated_value_for_voice(&self, voice_id: i32) -> f32 {
+        let base_value = self.smoothed.previous_value();
+        match self
+            .poly_modulation_offsets
+            .iter()
+            .find(|(id, _)| *id == voice_id && voice_id != -1)
+        {
+            Some((_, normalized_offset)) => self.preview_plain(
+                (self.preview_normalized(base_value) + normalized_offset).clamp(0.0, 1.0),
+            ),
+            None => base_value,
+        }
+    }
+
+    /// Store a polyphonic modulation offset for a voice. A zero offset removes the voice's entry.
+    /// This will not allocate. When all [`MAX_POLY_MODULATION_VOICES`] slots are in use, the slots
+    /// are overwritten in a round robin fashion.
+    pub(crate) fn set_poly_modulation(&mut self, voice_id: i32, normalized_offset: f32) {
+        if self.poly_modulation_offsets.is_empty() {
+            nih_debug_assert_failure!(
+                "Received polyphonic modulation for a parameter without a poly modulation ID"
+            );
+            return;
+        }
+
+        if let Some(slot) = self
+            .poly_modulation_offsets
+            .iter_mut()
+            .find(|(id, _)| *id == voice_id)
+        {
+            if normalized_offset == 0.0 {
+                *slot = (-1, 0.0);
+            } else {
+                slot.1 = normalized_offset;
+            }
+
+            return;
+        }
+
+        if normalized_offset == 0.0 {
+            return;
+        }
+
+        let slot_idx = match self
+            .poly_modulation_offsets
+            .iter()
+            .position(|(id, _)| *id == -1)
+        {
+            Some(idx) => idx,
+            None => {
+                let idx = self.next_poly_modulation_slot;
+                self.next_poly_modulation_slot = (idx + 1) % self.poly_modulation_offsets.len();
+                idx
+            }
+        };
+        self.poly_modulation_offsets[slot_idx] = (voice_id, normalized_offset);
+    }
+
+    /// Remove a voice's polyphonic modulation offset after the voice has terminated, so a later
+    /// voice that reuses the same ID doesn't start out modulated.
+    pub(crate) fn clear_poly_modulation(&mut self, voice_id: i32) {
+        for slot in self
+            .poly_modulation_offsets
+            .iter_mut()
+            .filter(|(id, _)| *id == voice_id)
+        {
+            *slot = (-1, 0.0);
+        }
+    }
 }
 
 /// Caldculate how many decimals to round to when displaying a floating point value with a specific
@@ -390,3 +541,91 @@ fn decimals_from_step_size(step_size: f32) -> usize {
 
     num_digits as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn callback_receives_stored_value() {
+        let last_value = Arc::new(AtomicU32::new(0));
+        let mut param = FloatParam::new(
+            "Test",
+            0.0,
+            FloatRange::Linear {
+                min: 0.0,
+                max: 10.0,
+            },
+        )
+        .with_callback({
+            let last_value = last_value.clone();
+            Arc::new(move |value| last_value.store(value.to_bits(), Ordering::Relaxed))
+        });
+
+        param.set_plain_value(5.0);
+        assert_eq!(f32::from_bits(last_value.load(Ordering::Relaxed)), 5.0);
+        assert_eq!(param.plain_value(), 5.0);
+
+        // Modulation should also trigger the callback with the modulated value
+        param.modulate_value(0.2);
+        assert_eq!(f32::from_bits(last_value.load(Ordering::Relaxed)), 7.0);
+        assert_eq!(param.plain_value(), 7.0);
+    }
+
+    #[test]
+    fn modulation_is_smoothed() {
+        let mut param = FloatParam::new(
+            "Test",
+            0.0,
+            FloatRange::Linear {
+                min: 0.0,
+                max: 10.0,
+            },
+        )
+        .with_smoother(SmoothingStyle::Linear(10.0));
+        param.update_smoother(1000.0, true);
+
+        // This is what the CLAP wrapper does when it receives a modulation event. 10 ms at 1 kHz
+        // results in a ten step ramp.
+        param.modulate_value(0.5);
+        param.update_smoother(1000.0, false);
+        assert_eq!(param.smoothed.next(), 0.5);
+        for _ in 0..8 {
+            param.smoothed.next();
+        }
+        assert_eq!(param.smoothed.next(), 5.0);
+
+        // Changing the unmodulated value keeps the modulation offset, so the smoother ramps towards
+        // the new modulated value
+        param.set_plain_value(2.0);
+        param.update_smoother(1000.0, false);
+        assert_eq!(param.plain_value(), 7.0);
+        assert_eq!(param.smoothed.next(), 5.2);
+    }
+
+    #[test]
+    fn clear_poly_modulation() {
+        let mut param = FloatParam::new(
+            "Test",
+            5.0,
+            FloatRange::Linear {
+                min: 0.0,
+                max: 10.0,
+            },
+        )
+        .with_poly_modulation_id(1);
+        param.update_smoother(1000.0, true);
+
+        param.set_poly_modulation(3, 0.2);
+        param.set_poly_modulation(4, -0.2);
+        assert_eq!(param.modulated_value_for_voice(3), 7.0);
+        assert_eq!(param.modulated_value_for_voice(4), 3.0);
+
+        // A terminated voice's ID can be reused by a new voice, which should not be modulated
+        param.clear_poly_modulation(3);
+        assert_eq!(param.modulated_value_for_voice(3), 5.0);
+        assert_eq!(param.modulated_value_for_voice(4), 3.0);
+    }
+}
@@ -7,6 +7,7 @@ use super::internals::ParamPtr;
 use super::range::IntRange;
 use super::smoothing::{Smoother, SmoothingStyle};
 use super::{Param, ParamFlags, ParamMut};
+use crate::formatters;
 
 /// A discrete integer parameter that's stored unnormalized. The range is used for the normalization
 /// process.
@@ -38,7 +39,8 @@ pub struct IntParam {
     /// The field's default plain, unnormalized value.
     default: i32,
     /// An optional smoother that will automatically interpolate between the new automation values
-    /// set by the host.
+    /// set by the host. Like with [`FloatParam`][super::FloatParam]'s smoother, the target
+    /// includes the host's monophonic modulation and is updated by the wrappers.
     pub smoothed: Smoother<i32>,
 
     /// Flags to control the parameter's behavior. See [`ParamFlags`].
@@ -156,10 +158,6 @@ impl Param for IntParam {
         self.range.unnormalize(normalized)
     }
 
-    fn initialize_block_smoother(&mut self, max_block_size: usize) {
-        self.smoothed.initialize_block_smoother(max_block_size);
-    }
-
     fn flags(&self) -> ParamFlags {
         self.flags
     }
@@ -245,9 +243,9 @@ impl IntParam {
     pub fn with_smoother(mut self, style: SmoothingStyle) -> Self {
         // Logarithmic smoothing will cause problems if the range goes through zero since then you
         // end up multplying by zero
-        let goes_through_zero = match (&style, &self.range) {
-            (SmoothingStyle::Logarithmic(_), IntRange::Linear { min, max }) => {
-                *min == 0 || *max == 0 || min.signum() != max.signum()
+        let goes_through_zero = match (&style, self.range.min(), self.range.max()) {
+            (SmoothingStyle::Logarithmic(_), min, max) => {
+                min == 0 || max == 0 || min.signum() != max.signum()
             }
             _ => false,
         };
@@ -264,6 +262,8 @@ impl IntParam {
     /// is the parameter's new value. This should not do anything expensive as it may be called
     /// multiple times in rapid succession, and it can be run from both the GUI and the audio
     /// thread.
+    ///
+    /// This has the same realtime safety requirements as [`FloatParam::with_callback()`][crate::prelude::FloatParam::with_callback()].
     pub fn with_callback(mut self, callback: Arc<dyn Fn(i32) + Send + Sync>) -> Self {
         self.value_changed = Some(callback);
         self
@@ -289,6 +289,23 @@ impl IntParam {
 
     // `with_step_size` is only implemented for the f32 version
 
+    /// Display each value in the parameter's range using a label, where `labels[0]` is used for the
+    /// range's minimum value. Values without a label are displayed as plain integers. This sets
+    /// both the [`value_to_string`][Self::with_value_to_string()] and the
+    /// [`string_to_value`][Self::with_string_to_value()] functions using
+    /// [`formatters::v2s_i32_labels()`][crate::formatters::v2s_i32_labels()] and
+    /// [`formatters::s2v_i32_labels()`][crate::formatters::s2v_i32_labels()].
+    pub fn with_value_labels(mut self, labels: &'static [&'static str]) -> Self {
+        nih_debug_assert!(
+            labels.len() <= self.range.step_count() + 1,
+            "There are more labels than values in the parameter's range"
+        );
+
+        self.value_to_string = Some(formatters::v2s_i32_labels(self.range.min(), labels));
+        self.string_to_value = Some(formatters::s2v_i32_labels(self.range.min(), labels));
+        self
+    }
+
     /// Use a custom conversion function to convert from a string to a plain, unnormalized
     /// value. If the string cannot be parsed, then this should return a `None`. If this
     /// happens while the parameter is being updated then the update will be canceled.
@@ -311,9 +328,19 @@ impl IntParam {
         self
     }
 
+    /// Mark the parameter as read-only. The host will display the parameter's value, but neither
+    /// the host nor the user can change it from outside of the plugin. The plugin's editor can
+    /// still change the value with a [`ParamSetter`][crate::prelude::ParamSetter]. This also
+    /// implies `NON_AUTOMATABLE`.
+    pub fn read_only(mut self) -> Self {
+        self.flags.insert(ParamFlags::READ_ONLY);
+        self
+    }
+
     /// Hide the parameter in the host's generic UI for this plugin. This also implies
     /// `NON_AUTOMATABLE`. Setting this does not prevent you from changing the parameter in the
-    /// plugin's editor GUI.
+    /// plugin's editor GUI, and the parameter is still stored in the plugin's state. See
+    /// [`ParamFlags::HIDDEN`].
     pub fn hide(mut self) -> Self {
         self.flags.insert(ParamFlags::HIDDEN);
         self
@@ -1,6 +1,7 @@
 //! Implementation details for the parameter management.
 
 use std::collections::HashMap;
+use std::io::{Read, Write};
 
 use super::{Param, ParamFlags, ParamMut};
 
@@ -10,26 +11,66 @@ pub use serde_json::from_str as deserialize_field;
 /// Re-export for use in the [`Params`] proc-macro.
 pub use serde_json::to_string as serialize_field;
 
+/// Used in the [`Params`] proc-macro to put a nested object's parameter in `group`. Returns
+/// `nested_group_name` unchanged if the nested object does not have its own group.
+pub fn nest_group_name(group: Option<&str>, nested_group_name: String) -> String {
+    match group {
+        Some(group) if nested_group_name.is_empty() => group.to_string(),
+        Some(group) => format!("{}/{}", group, nested_group_name),
+        None => nested_group_name,
+    }
+}
+
 /// Describes a struct containing parameters and other persistent fields.
 ///
 /// This trait can be derived on a struct containing [`FloatParam`][super::FloatParam] and other
 /// parameter fields. When deriving this trait, any of those parameter fields should have the `#[id
 /// = "stable"]` attribute, where `stable` is an up to 6 character long string (to avoid collisions)
 /// that will be used to identify the parameter internall so you can safely move it around and
-/// rename the field without breaking compatibility with old presets.
+/// rename the field without breaking compatibility with old presets. Parameter fields can also be
+/// put in a group by adding a `#[group = "Group Name"]` attribute. Groups can be nested by
+/// separating the group names with a slash, like `#[group = "Filter/Envelope"]`. Parameters with
+/// the same group are shown together by hosts that support parameter groups.
 ///
 /// The struct can also contain other fields that should be persisted along with the rest of the
 /// preset data. These fields should be [`PersistentField`]s annotated with the `#[persist = "key"]`
 /// attribute containing types that can be serialized and deserialized with
 /// [Serde](https://serde.rs/).
 ///
+/// The host saves and restores the plugin's state from the main thread. The wrappers build the
+/// state from this `Params` object alone, so saving the state never locks or waits on the plugin's
+/// audio processing. Persisted fields are read through their `RwLock` or `Mutex` while the state is
+/// being saved, so avoid holding a write lock on the audio thread. Holding a read lock while
+/// processing audio is fine, since saving the state only needs a read lock as well. A write lock is
+/// only taken when state gets restored, which happens before the plugin is reinitialized.
+///
 /// And finally when deriving this trait, it is also possible to inherit the parameters from other
 /// `Params` objects by adding the `#[nested = "Group Name"]` attribute to those fields. These
 /// groups will be displayed as a tree-like structure if your DAW supports it. Parameter IDs and
-/// persisting keys still need to be **unique** when usting nested parameter structs. This currently
-/// has the following caveats:
+/// persisting keys still need to be **unique** when usting nested parameter structs. For more
+/// control, the `#[nested(...)]` form of the attribute accepts the following options, all of which
+/// are optional:
+///
+/// - `group = "Group Name"` puts the nested parameters in a group, just like `#[nested = "Group
+///   Name"]`. Without a group or an ID prefix the nested parameters are added to this object's own
+///   group.
+/// - `id_prefix = "foo"` prefixes the nested object's parameter IDs and persist keys with `foo_`.
+///   This makes it possible to use the same `Params` struct multiple times. If no `group` is set,
+///   then the prefix is also used as the group name so the different instances can be told apart
+///   in the host's parameter list.
+/// - `array` can be used for arrays and `Vec`s of `Params` objects. The parameter IDs and persist
+///   keys of every object get a `_1`, `_2`, etc. suffix, and the group names get a ` 1`, ` 2`, etc.
+///   suffix. When combined with `id_prefix = "band"`, a parameter with the ID `freq` in the second
+///   element ends up with the ID `band_freq_2`.
+///
+/// The struct itself can be annotated with `#[state_version = 2]` to version the plugin's saved
+/// state. See [`state_version()`][Self::state_version()].
 ///
-/// - Enforcing that parameter IDs and persist keys are unique does not work across nested structs.
+/// Nested parameter structs currently have the following caveats:
+///
+/// - Enforcing that parameter IDs and persist keys are unique does not work across nested structs
+///   at compile time. Duplicate parameter IDs are instead caught when the plugin instance is
+///   created.
 /// - Deserializing persisted fields will give false positives about fields not existing.
 ///
 /// Take a look at the example gain plugin to see how this should be used.
@@ -43,12 +84,13 @@ pub unsafe trait Params: 'static + Send + Sync {
     /// group/unit/module they are in, as a `(param_id, param_ptr, group)` triple. The order of the
     /// `Vec` determines the display order in the (host's) generic UI. The group name is either an
     /// empty string for top level parameters, or a slash/delimited `"Group Name 1/Group Name 2"` if
-    /// this `Params` object contains nested child objects. All components of a group path must
-    /// exist or you may encounter panics. The derive macro does this for every parameter field
-    /// marked with `#[id = "stable"]`, and it also inlines all fields from nested child `Params`
-    /// structs marked with `#[nested = "Group Name"]` while prefixing that group name before the
-    /// parameter's originanl group name. Dereferencing the pointers stored in the values is only
-    /// valid as long as this object is valid.
+    /// this `Params` object contains nested child objects. Groups don't need to directly contain
+    /// any parameters, so a `"Group Name 1"` group will be created automatically when there are
+    /// only parameters in `"Group Name 1/Group Name 2"`. The derive macro does this for every
+    /// parameter field marked with `#[id = "stable"]`, and it also inlines all fields from nested
+    /// child `Params` structs marked with `#[nested = "Group Name"]` while prefixing that group
+    /// name before the parameter's originanl group name. Dereferencing the pointers stored in the
+    /// values is only valid as long as this object is valid.
     ///
     /// # Note
     ///
@@ -63,6 +105,29 @@ pub unsafe trait Params: 'static + Send + Sync {
         HashMap::new()
     }
 
+    /// Write large amounts of additional state data, like sample data, directly to the host's state
+    /// stream. This is written after the rest of the plugin's state, and it avoids having to store
+    /// the entire state in memory first like with `#[persist]` fields. The data is read back with
+    /// [`load_stream()`][Self::load_stream()]. This is called from the main thread, just like
+    /// [`serialize_fields()`][Self::serialize_fields()]. Most plugins don't need this.
+    ///
+    /// This data is only part of the state the host saves. It's not included in the
+    /// [`PluginState`][crate::prelude::PluginState] objects used for the editor's
+    /// [`GuiContext::get_state()`][crate::prelude::GuiContext::get_state()].
+    #[allow(unused_variables)]
+    fn save_stream(&self, writer: &mut dyn Write) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Read the data written by [`save_stream()`][Self::save_stream()] back from the host's state
+    /// stream. When loading state that was saved without any streamed data the reader will
+    /// immediately reach the end of its input. This is called right before the rest of the state is
+    /// restored, and the plugin will be reinitialized afterwards.
+    #[allow(unused_variables)]
+    fn load_stream(&self, reader: &mut dyn Read) -> std::io::Result<()> {
+        Ok(())
+    }
+
     /// Restore all fields marked with `#[persist = "stable_name"]` from a hashmap created by
     /// [`serialize_fields()`][Self::serialize_fields()]. All of thse fields should be wrapped in a
     /// [`PersistentField`] with thread safe interior mutability, like an `RwLock` or a `Mutex`.
@@ -70,6 +135,18 @@ pub unsafe trait Params: 'static + Send + Sync {
     /// under the hood.
     #[allow(unused_variables)]
     fn deserialize_fields(&self, serialized: &HashMap<String, String>) {}
+
+    /// The version of the plugin's state layout. This is stored in the plugin's state when it gets
+    /// saved. When loading state with a different version, the wrapper first calls
+    /// [`Plugin::migrate_state()`][crate::prelude::Plugin::migrate_state()] so the plugin can
+    /// update the old state, for instance to rename parameter IDs. Increase this whenever the
+    /// layout changes in a way that would otherwise cause old presets to load incorrectly. States
+    /// saved before versioning was introduced have version 0. The derive macro sets this using the
+    /// `#[state_version = 2]` attribute on the struct. Only the top level `Params` object's version
+    /// is used.
+    fn state_version(&self) -> u32 {
+        0
+    }
 }
 
 /// Internal pointers to parameters. This is an implementation detail used by the wrappers for type
@@ -154,7 +231,6 @@ impl ParamPtr {
     param_ptr_forward!(pub unsafe fn step_count(&self) -> Option<usize>);
     param_ptr_forward!(pub unsafe fn previous_normalized_step(&self, from: f32) -> f32);
     param_ptr_forward!(pub unsafe fn next_normalized_step(&self, from: f32) -> f32);
-    param_ptr_forward!(pub unsafe fn initialize_block_smoother(&mut self, max_block_size: usize));
     param_ptr_forward!(pub unsafe fn normalized_value_to_string(&self, normalized: f32, include_unit: bool) -> String);
     param_ptr_forward!(pub unsafe fn string_to_normalized_value(&self, string: &str) -> Option<f32>);
     param_ptr_forward!(pub unsafe fn flags(&self) -> ParamFlags);
@@ -252,6 +328,50 @@ impl ParamPtr {
             ParamPtr::EnumParam(p) => (**p).preview_plain(normalized) as f32,
         }
     }
+
+    /// Get the parameter's poly modulation ID, if it has one. Only
+    /// [`FloatParam`][super::FloatParam]s can be polyphonically modulated.
+    ///
+    /// # Safety
+    ///
+    /// Calling this function is only safe as long as the object this `ParamPtr` was created for is
+    /// still alive.
+    pub unsafe fn poly_modulation_id(&self) -> Option<u32> {
+        match &self {
+            ParamPtr::FloatParam(p) => (**p).poly_modulation_id(),
+            ParamPtr::IntParam(_) | ParamPtr::BoolParam(_) | ParamPtr::EnumParam(_) => None,
+        }
+    }
+
+    /// Store a per-voice polyphonic modulation offset on the parameter. Does nothing for parameters
+    /// that aren't [`FloatParam`][super::FloatParam]s.
+    ///
+    /// # Safety
+    ///
+    /// Calling this function is only safe as long as the object this `ParamPtr` was created for is
+    /// still alive.
+    pub(crate) unsafe fn set_poly_modulation(&self, voice_id: i32, normalized_offset: f32) {
+        match &self {
+            ParamPtr::FloatParam(p) => (**p).set_poly_modulation(voice_id, normalized_offset),
+            ParamPtr::IntParam(_) | ParamPtr::BoolParam(_) | ParamPtr::EnumParam(_) => {
+                nih_debug_assert_failure!("Only float parameters can be polyphonically modulated")
+            }
+        }
+    }
+
+    /// Remove a voice's polyphonic modulation offset from the parameter. Does nothing for
+    /// parameters that aren't [`FloatParam`][super::FloatParam]s.
+    ///
+    /// # Safety
+    ///
+    /// Calling this function is only safe as long as the object this `ParamPtr` was created for is
+    /// still alive.
+    pub(crate) unsafe fn clear_poly_modulation(&self, voice_id: i32) {
+        match &self {
+            ParamPtr::FloatParam(p) => (**p).clear_poly_modulation(voice_id),
+            ParamPtr::IntParam(_) | ParamPtr::BoolParam(_) | ParamPtr::EnumParam(_) => (),
+        }
+    }
 }
 
 impl<'a, T> PersistentField<'a, T> for std::sync::RwLock<T>
@@ -20,6 +20,18 @@ pub enum FloatRange {
         factor: f32,
         center: f32,
     },
+    /// A reversed range that goes from high to low instead of from low to high. The plain value
+    /// decreases as the normalized value increases, so a normalized value of 0 corresponds to the
+    /// wrapped range's maximum value. [`min()`][Self::min()] and [`max()`][Self::max()] still
+    /// return the smallest and largest plain values.
+    ///
+    /// ```
+    /// # use nih_plug::prelude::FloatRange;
+    /// let range = FloatRange::Reversed(&FloatRange::Linear { min: 0.0, max: 10.0 });
+    /// assert_eq!(range.normalize(10.0), 0.0);
+    /// assert_eq!(range.unnormalize(0.25), 7.5);
+    /// ```
+    Reversed(&'static FloatRange),
 }
 
 /// A distribution for an integer parameter's range. All range endpoints are inclusive. Only linear
@@ -29,6 +41,9 @@ pub enum FloatRange {
 pub enum IntRange {
     /// The values are uniformly distributed between `min` and `max`.
     Linear { min: i32, max: i32 },
+    /// A reversed range that goes from high to low instead of from low to high. See
+    /// [`FloatRange::Reversed`].
+    Reversed(&'static IntRange),
 }
 
 impl FloatRange {
@@ -39,6 +54,31 @@ impl FloatRange {
         2.0f32.powf(factor)
     }
 
+    /// Calculate a skew factor for [`FloatRange::Skewed`] that places `center` at the middle of the
+    /// range, so that normalizing `center` results in 0.5. `center` must lie strictly between `min`
+    /// and `max`.
+    ///
+    /// ```
+    /// # use nih_plug::prelude::FloatRange;
+    /// // A gain knob from -60 dB to +6 dB with 0 dB at the center of its travel
+    /// let range = FloatRange::Skewed {
+    ///     min: -60.0,
+    ///     max: 6.0,
+    ///     factor: FloatRange::skew_factor_for_center(-60.0, 6.0, 0.0),
+    /// };
+    /// assert!((range.normalize(0.0) - 0.5).abs() < 1e-5);
+    /// ```
+    pub fn skew_factor_for_center(min: f32, max: f32, center: f32) -> f32 {
+        nih_debug_assert!(
+            center > min && center < max,
+            "The center value needs to lie within the range"
+        );
+
+        // We need to solve `((center - min) / (max - min)) ^ factor = 0.5` for `factor`
+        let center_proportion = (center - min) / (max - min);
+        0.5f32.log(center_proportion)
+    }
+
     /// Normalize a plain, unnormalized value. Will be clamped to the bounds of the range if the
     /// normalized value exceeds `[0, 1]`.
     pub fn normalize(&self, plain: f32) -> f32 {
@@ -73,6 +113,7 @@ impl FloatRange {
                     (1.0 - inverted_scaled_proportion.powf(*factor)) * 0.5
                 }
             }
+            FloatRange::Reversed(range) => 1.0 - range.normalize(plain),
         }
     }
 
@@ -104,6 +145,7 @@ impl FloatRange {
 
                 (skewed_proportion * (max - min)) + min
             }
+            FloatRange::Reversed(range) => range.unnormalize(1.0 - normalized),
         }
     }
 
@@ -113,6 +155,7 @@ impl FloatRange {
             FloatRange::Linear { min, .. }
             | FloatRange::Skewed { min, .. }
             | FloatRange::SymmetricalSkewed { min, .. } => *min,
+            FloatRange::Reversed(range) => range.min(),
         }
     }
 
@@ -122,18 +165,39 @@ impl FloatRange {
             FloatRange::Linear { max, .. }
             | FloatRange::Skewed { max, .. }
             | FloatRange::SymmetricalSkewed { max, .. } => *max,
+            FloatRange::Reversed(range) => range.max(),
         }
     }
 
     /// Snap a vlue to a step size, clamping to the minimum and maximum value of the range.
     pub fn snap_to_step(&self, value: f32, step_size: f32) -> f32 {
-        let (min, max) = match &self {
-            FloatRange::Linear { min, max } => (min, max),
-            FloatRange::Skewed { min, max, .. } => (min, max),
-            FloatRange::SymmetricalSkewed { min, max, .. } => (min, max),
+        ((value / step_size).round() * step_size).clamp(self.min(), self.max())
+    }
+
+    /// The number of discrete steps in this range when values are snapped to `step_size` with
+    /// [`snap_to_step()`][Self::snap_to_step()]. This is only known for linear ranges where both
+    /// the minimum and the maximum values lie on the step grid, since hosts expect the steps to be
+    /// spread out evenly over the normalized `[0, 1]` range. Returns `None` otherwise.
+    pub fn step_count(&self, step_size: f32) -> Option<usize> {
+        // Some tolerance is needed since step sizes like 0.1 cannot be represented exactly
+        const EPSILON: f32 = 1e-4;
+        let is_on_grid = |value: f32| {
+            let steps = value / step_size;
+            (steps - steps.round()).abs() < EPSILON
         };
 
-        ((value / step_size).round() * step_size).clamp(*min, *max)
+        match self {
+            FloatRange::Linear { min, max } if step_size > 0.0 => {
+                if is_on_grid(*min) && is_on_grid(*max) {
+                    Some(((max - min) / step_size).round() as usize)
+                } else {
+                    None
+                }
+            }
+            // Reversing the range doesn't change how the steps are distributed
+            FloatRange::Reversed(range) => range.step_count(step_size),
+            _ => None,
+        }
     }
 }
 
@@ -142,9 +206,11 @@ impl IntRange {
     /// normalized value exceeds `[0, 1]`.
     pub fn normalize(&self, plain: i32) -> f32 {
         match &self {
-            IntRange::Linear { min, max } => (plain - min) as f32 / (max - min) as f32,
+            IntRange::Linear { min, max } => {
+                ((plain - min) as f32 / (max - min) as f32).clamp(0.0, 1.0)
+            }
+            IntRange::Reversed(range) => 1.0 - range.normalize(plain),
         }
-        .clamp(0.0, 1.0)
     }
 
     /// Unnormalize a normalized value. Will be clamped to `[0, 1]` if the plain, unnormalized value
@@ -153,6 +219,7 @@ impl IntRange {
         let normalized = normalized.clamp(0.0, 1.0);
         match &self {
             IntRange::Linear { min, max } => (normalized * (max - min) as f32).round() as i32 + min,
+            IntRange::Reversed(range) => range.unnormalize(1.0 - normalized),
         }
     }
 
@@ -160,6 +227,7 @@ impl IntRange {
     pub fn min(&self) -> i32 {
         match self {
             IntRange::Linear { min, .. } => *min,
+            IntRange::Reversed(range) => range.min(),
         }
     }
 
@@ -167,6 +235,7 @@ impl IntRange {
     pub fn max(&self) -> i32 {
         match self {
             IntRange::Linear { max, .. } => *max,
+            IntRange::Reversed(range) => range.max(),
         }
     }
 
@@ -174,6 +243,7 @@ impl IntRange {
     pub fn step_count(&self) -> usize {
         match self {
             IntRange::Linear { min, max } => (max - min) as usize,
+            IntRange::Reversed(range) => range.step_count(),
         }
     }
 }
@@ -225,6 +295,35 @@ mod tests {
         assert_eq!(range.snap_to_step(20.0, 6.73), 20.0);
     }
 
+    #[test]
+    fn skew_factor_for_center() {
+        let factor = FloatRange::skew_factor_for_center(10.0, 20.0, 12.5);
+        let range = make_skewed_float_range(factor);
+        assert!((range.normalize(12.5) - 0.5).abs() < 1e-6);
+        assert!((range.unnormalize(0.5) - 12.5).abs() < 1e-5);
+
+        // A centered linear range doesn't need any skewing
+        assert_eq!(FloatRange::skew_factor_for_center(10.0, 20.0, 15.0), 1.0);
+    }
+
+    #[test]
+    fn step_count() {
+        let range = make_linear_float_range();
+        assert_eq!(range.step_count(0.5), Some(20));
+        assert_eq!(range.step_count(0.1), Some(100));
+        assert_eq!(range.step_count(2.5), Some(4));
+    }
+
+    #[test]
+    fn step_count_off_grid() {
+        let range = make_linear_float_range();
+        assert_eq!(range.step_count(3.0), None);
+
+        // The steps wouldn't be spread out evenly over the normalized range
+        let range = make_skewed_float_range(FloatRange::skew_factor(-2.0));
+        assert_eq!(range.step_count(0.5), None);
+    }
+
     mod linear {
         use super::*;
 
@@ -292,6 +391,67 @@ mod tests {
         }
     }
 
+    mod reversed {
+        use super::*;
+
+        #[test]
+        fn range_normalize_float() {
+            let range = FloatRange::Reversed(&FloatRange::Linear {
+                min: 10.0,
+                max: 20.0,
+            });
+            assert_eq!(range.normalize(17.5), 0.25);
+            assert_eq!(range.normalize(20.0), 0.0);
+            assert_eq!(range.normalize(10.0), 1.0);
+        }
+
+        #[test]
+        fn range_unnormalize_float() {
+            let range = FloatRange::Reversed(&FloatRange::Linear {
+                min: 10.0,
+                max: 20.0,
+            });
+            assert_eq!(range.unnormalize(0.25), 17.5);
+            assert_eq!(range.min(), 10.0);
+            assert_eq!(range.max(), 20.0);
+        }
+
+        #[test]
+        fn range_normalize_skewed_float() {
+            let range = FloatRange::Reversed(&FloatRange::Skewed {
+                min: 10.0,
+                max: 20.0,
+                factor: 0.25,
+            });
+            assert_eq!(range.normalize(17.5), 1.0 - 0.9306049);
+            assert_eq!(range.unnormalize(1.0 - 0.9306049), 17.5);
+        }
+
+        #[test]
+        fn range_normalize_int() {
+            let range = IntRange::Reversed(&IntRange::Linear { min: -10, max: 10 });
+            assert_eq!(range.normalize(-5), 0.75);
+            assert_eq!(range.normalize(20), 0.0);
+        }
+
+        #[test]
+        fn range_unnormalize_int() {
+            let range = IntRange::Reversed(&IntRange::Linear { min: -10, max: 10 });
+            assert_eq!(range.unnormalize(0.75), -5);
+            assert_eq!(range.step_count(), 20);
+        }
+
+        #[test]
+        fn step_count() {
+            let range = FloatRange::Reversed(&FloatRange::Linear {
+                min: 10.0,
+                max: 20.0,
+            });
+            assert_eq!(range.step_count(0.5), Some(20));
+            assert_eq!(range.snap_to_step(13.4, 0.5), 13.5);
+        }
+    }
+
     mod symmetrical_skewed {
         use super::*;
 
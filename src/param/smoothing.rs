@@ -1,16 +1,15 @@
 //! Utilities to handle smoothing parameter changes over time.
 
 use atomic_float::AtomicF32;
-use atomic_refcell::{AtomicRefCell, AtomicRefMut};
 use std::sync::atomic::{AtomicI32, Ordering};
 
-use crate::buffer::Block;
-
 /// Controls if and how parameters gets smoothed.
 #[derive(Debug, Clone, Copy)]
 pub enum SmoothingStyle {
     /// No smoothing is applied. The parameter's `value` field contains the latest sample value
-    /// available for the parameters.
+    /// available for the parameters. Calling [`Smoother::set_target()`] on a smoother with this
+    /// style is the same as calling [`Smoother::reset()`], so [`Smoother::next()`] immediately
+    /// returns the new value and [`Smoother::is_smoothing()`] never returns true.
     None,
     /// Smooth parameter changes so the current value approaches the target value at a constant
     /// rate. The target value will be reached in exactly this many milliseconds.
@@ -18,9 +17,12 @@ pub enum SmoothingStyle {
     /// Smooth parameter changes such that the rate matches the curve of a logarithmic function,
     /// starting out slow and then constantly increasing the slope until the value is reached. The
     /// target value will be reached in exactly this many milliseconds. This is useful for smoothing
-    /// things like frequencies and decibel gain value. **The caveat is that the value may never
-    /// reach 0**, or you will end up multiplying and dividing things by zero. Make sure your value
-    /// ranges don't include 0.
+    /// things like frequencies and decibel gain value, since the value is interpolated in the log
+    /// domain. A sweep from 100 Hz to 10 kHz will pass 1 kHz halfway through.
+    ///
+    /// Interpolating in the log domain only works when both the current and the target values are
+    /// positive. If either of them is zero or negative, then the smoother falls back to linear
+    /// smoothing for that transition instead of producing `NaN` values.
     Logarithmic(f32),
     /// Smooth parameter changes such that the rate matches the curve of an exponential function,
     /// starting out fast and then tapering off until the end. This is a single-pole IIR filter
@@ -33,6 +35,13 @@ pub enum SmoothingStyle {
 }
 
 /// A smoother, providing a smoothed value for each sample.
+///
+/// Smoothers can also be used on their own, outside of parameters. For instance, a polyphonic
+/// synth can store a smoother per voice for things like portamento. Smoothers implement `Clone` so
+/// an array of them can be created with `[(); N].map(|_| smoother.clone())`, or with
+/// `std::array::from_fn()`. Call [`reset()`][Self::reset()] when a voice starts playing a new note,
+/// and [`set_target()`][Self::set_target()] to glide towards a new note. Neither of these
+/// functions allocate, and every smoother has its own independent state.
 //
 // TODO: We need to use atomics here so we can share the params object with the GUI. Is there a
 //       better alternative to allow the process function to mutate these smoothers?
@@ -51,15 +60,17 @@ pub struct Smoother<T> {
     /// In the case of the `Exponential` smoothing style this is the coefficient `x` that the
     /// previous sample is multplied by.
     step_size: f32,
+    /// Set to true when the `Logarithmic` style cannot be used for the current transition because
+    /// the current or target value is not positive. `step_size` will then contain a linear step
+    /// size instead.
+    logarithmic_fallback: bool,
+    /// The sample rate passed to the last [`set_target()`][Self::set_target()] or
+    /// [`set_sample_rate()`][Self::set_sample_rate()] call, or 0 if neither has been called yet.
+    sample_rate: f32,
     /// The value for the current sample. Always stored as floating point for obvious reasons.
     current: AtomicF32,
     /// The value we're smoothing towards
     target: T,
-
-    /// A dense buffer containing smoothed values for an entire block of audio. Useful when using
-    /// [`Buffer::iter_blocks()`][crate::prelude::Buffer::iter_blocks()] to process small blocks of audio
-    /// multiple times.
-    block_values: AtomicRefCell<Vec<T>>,
 }
 
 /// An iterator that continuously produces smoothed values. Can be used as an alternative to the
@@ -83,10 +94,24 @@ impl<T: Smoothable> Default for Smoother<T> {
             style: SmoothingStyle::None,
             steps_left: AtomicI32::new(0),
             step_size: Default::default(),
+            logarithmic_fallback: false,
+            sample_rate: 0.0,
             current: AtomicF32::new(0.0),
             target: Default::default(),
+        }
+    }
+}
 
-            block_values: AtomicRefCell::new(Vec::new()),
+impl<T: Smoothable> Clone for Smoother<T> {
+    fn clone(&self) -> Self {
+        Self {
+            style: self.style,
+            steps_left: AtomicI32::new(self.steps_left.load(Ordering::Relaxed)),
+            step_size: self.step_size,
+            logarithmic_fallback: self.logarithmic_fallback,
+            sample_rate: self.sample_rate,
+            current: AtomicF32::new(self.current.load(Ordering::Relaxed)),
+            target: self.target,
         }
     }
 }
@@ -115,10 +140,13 @@ impl<T: Smoothable> Smoother<T> {
     }
 
     /// The number of steps left until calling [`next()`][Self::next()] will stop yielding new
-    /// values.
+    /// values. This is updated immediately when calling [`set_target()`][Self::set_target()], and
+    /// it will be 0 once the target value has been reached.
     #[inline]
-    pub fn steps_left(&self) -> i32 {
-        self.steps_left.load(Ordering::Relaxed)
+    pub fn steps_left(&self) -> u32 {
+        // The internal counter can never stay negative after a call to `next_step()`, but clamping
+        // it here avoids a wraparound just in case
+        self.steps_left.load(Ordering::Relaxed).max(0) as u32
     }
 
     /// Whether calling [`next()`][Self::next()] will yield a new value or an old value. Useful if
@@ -136,43 +164,82 @@ impl<T: Smoothable> Smoother<T> {
         SmootherIter { smoother: self }
     }
 
-    /// Allocate memory to store smoothed values for an entire block of audio. Call this in
-    /// [`Plugin::initialize()`][crate::prelude::Plugin::initialize()] with the same max block size you are
-    /// going to pass to [`Buffer::iter_blocks()`][crate::prelude::Buffer::iter_blocks()].
-    pub fn initialize_block_smoother(&mut self, max_block_size: usize) {
-        self.block_values
-            .borrow_mut()
-            .resize_with(max_block_size, || T::default());
-    }
-
-    /// Reset the smoother the specified value.
+    /// Reset the smoother the specified value. Both the current value and the target value are set
+    /// to `value` and any smoothing in progress is cancelled, so the next call to
+    /// [`next()`][Self::next()] already returns `value`. The wrappers do this for every parameter
+    /// when the plugin gets initialized and when the host or the plugin's editor loads a preset, so
+    /// those changes don't get smoothed. Use [`set_target()`][Self::set_target()] instead to
+    /// smoothly transition to a new value.
     pub fn reset(&mut self, value: T) {
         self.target = value;
         self.current.store(value.to_f32(), Ordering::Relaxed);
         self.steps_left.store(0, Ordering::Relaxed);
     }
 
-    /// Set the target value.
+    /// Set the target value. The smoother starts smoothing from its current value, even if it was
+    /// still in the middle of another transition.
     pub fn set_target(&mut self, sample_rate: f32, target: T) {
-        self.target = target;
+        self.sample_rate = sample_rate;
 
         let steps_left = match self.style {
-            SmoothingStyle::None => 1,
+            // There's no need to do any work in `next()` when the value isn't smoothed
+            SmoothingStyle::None => return self.reset(target),
             SmoothingStyle::Linear(time)
             | SmoothingStyle::Logarithmic(time)
             | SmoothingStyle::Exponential(time) => (sample_rate * time / 1000.0).round() as i32,
         };
+        self.target = target;
         self.steps_left.store(steps_left, Ordering::Relaxed);
+        self.update_step_size(steps_left);
+    }
+
+    /// Change the sample rate the smoother runs at. If the smoother is currently smoothing, then
+    /// the remaining number of steps is scaled so the transition takes the same amount of time as
+    /// it would have taken at the old sample rate. Future calls to
+    /// [`set_target()`][Self::set_target()] already receive the sample rate, so this is only needed
+    /// when the sample rate changes in the middle of a transition. The wrappers reset the
+    /// parameters' smoothers whenever the sample rate changes, so this is mostly useful for the
+    /// plugin's own smoothers that should continue smoothing across
+    /// [`Plugin::initialize()`][crate::prelude::Plugin::initialize()] calls.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let old_sample_rate = std::mem::replace(&mut self.sample_rate, sample_rate);
+        let old_steps_left = self.steps_left.load(Ordering::Relaxed);
+        if old_steps_left <= 0 || old_sample_rate <= 0.0 || old_sample_rate == sample_rate {
+            return;
+        }
+
+        let ratio = sample_rate / old_sample_rate;
+        let steps_left = ((old_steps_left as f32 * ratio).round() as i32).max(1);
+        self.steps_left.store(steps_left, Ordering::Relaxed);
+        match self.style {
+            // Recomputing the coefficient from the number of steps left would only cover the
+            // remaining distance, so the old coefficient is adjusted to keep the same time
+            // constant instead
+            SmoothingStyle::Exponential(_) => {
+                self.step_size = (self.step_size as f64).powf(1.0 / ratio as f64) as f32
+            }
+            _ => self.update_step_size(steps_left),
+        }
+    }
 
+    /// Compute the step size needed to get from the current value to the target value in
+    /// `steps_left` steps.
+    fn update_step_size(&mut self, steps_left: i32) {
         let current = self.current.load(Ordering::Relaxed);
+        let target = self.target.to_f32();
+        self.logarithmic_fallback = matches!(self.style, SmoothingStyle::Logarithmic(_))
+            && (current <= 0.0 || target <= 0.0);
         self.step_size = match self.style {
             SmoothingStyle::None => 0.0,
-            SmoothingStyle::Linear(_) => (self.target.to_f32() - current) / steps_left as f32,
+            SmoothingStyle::Linear(_) => (target - current) / steps_left as f32,
+            // The log domain is not defined for these values, so we'll smooth linearly instead
+            SmoothingStyle::Logarithmic(_) if self.logarithmic_fallback => {
+                (target - current) / steps_left as f32
+            }
             SmoothingStyle::Logarithmic(_) => {
                 // We need to solve `current * (step_size ^ steps_left) = target` for
                 // `step_size`
-                nih_debug_assert_ne!(current, 0.0);
-                ((self.target.to_f32() / current) as f64).powf((steps_left as f64).recip()) as f32
+                ((target / current) as f64).powf((steps_left as f64).recip()) as f32
             }
             // In this case the step size value is the coefficient the current value will be
             // multiplied by, while the target value is multipled by one minus the coefficient. This
@@ -216,6 +283,9 @@ impl<T: Smoothable> Smoother<T> {
                 match &self.style {
                     SmoothingStyle::None => target,
                     SmoothingStyle::Linear(_) => current + (self.step_size * steps as f32),
+                    SmoothingStyle::Logarithmic(_) if self.logarithmic_fallback => {
+                        current + (self.step_size * steps as f32)
+                    }
                     SmoothingStyle::Logarithmic(_) => current * (self.step_size.powi(steps as i32)),
                     SmoothingStyle::Exponential(_) => {
                         // This is the same as calculating `current = (current * step_size) +
@@ -241,43 +311,93 @@ impl<T: Smoothable> Smoother<T> {
         T::from_f32(self.current.load(Ordering::Relaxed))
     }
 
-    /// Produce smoothed values for an entire block of audio. Used in conjunction with
-    /// [`Buffer::iter_blocks()`][crate::prelude::Buffer::iter_blocks()]. Make sure to call
-    /// [`Plugin::initialize_block_smoothers()`][crate::prelude::Plugin::initialize_block_smoothers()] with
-    /// the same maximum buffer block size as the one passed to `iter_blocks()` in your
-    /// [`Plugin::initialize()`][crate::prelude::Plugin::initialize()] function first to allocate memory for
-    /// the block smoothing.
-    ///
-    /// Returns a `None` value if the block length exceed's the allocated capacity.
+    /// Produce smoothed values for an entire block of audio. This fills the first `block_len`
+    /// values of `block_values` with the same values that calling [`next()`][Self::next()]
+    /// `block_len` times would produce, without the per-sample overhead. Useful in conjunction with
+    /// [`Buffer::iter_blocks()`][crate::prelude::Buffer::iter_blocks()]. Since the slice is
+    /// provided by you, you can allocate it once in
+    /// [`Plugin::initialize()`][crate::prelude::Plugin::initialize()] with the maximum block size
+    /// you are going to use.
     ///
     /// # Panics
     ///
-    /// Panics if this function is called again while another block value slice is still alive.
-    pub fn next_block(&self, block: &Block) -> Option<AtomicRefMut<[T]>> {
-        self.next_block_mapped(block, |x| x)
+    /// Panics if `block_len` is larger than `block_values.len()`.
+    #[inline]
+    pub fn next_block(&self, block_values: &mut [T], block_len: usize) {
+        self.next_block_mapped(block_values, block_len, |x| x)
     }
 
     /// The same as [`next_block()`][Self::next_block()], but with a function applied to each
     /// produced value. Useful when applying modulation to a smoothed parameter.
     pub fn next_block_mapped(
         &self,
-        block: &Block,
+        block_values: &mut [T],
+        block_len: usize,
         mut f: impl FnMut(T) -> T,
-    ) -> Option<AtomicRefMut<[T]>> {
-        let mut block_values = self.block_values.borrow_mut();
-        if block_values.len() < block.len() {
-            return None;
-        }
+    ) {
+        let block_values = &mut block_values[..block_len];
+
+        // The smoothed part of the block is computed using local copies of the smoother's state,
+        // and the unsmoothed part is simply filled with the target value. The last smoothed step
+        // snaps to the target value, just like in `next_step()`.
+        let steps_left = self.steps_left() as usize;
+        let num_smoothed = block_len.min(steps_left);
+        let (smoothed_values, target_values) = block_values.split_at_mut(num_smoothed);
+        if num_smoothed > 0 {
+            let target = self.target.to_f32();
+            let mut current = self.current.load(Ordering::Relaxed);
 
-        // TODO: As a small optimization we could split this up into two loops for the smoothed and
-        //       unsmoothed parts. Another worthwhile optimization would be to remember if the
-        //       buffer is already filled with the target value and [Self::is_smoothing()] is false.
-        //       In that case we wouldn't need to do anything ehre.
-        (&mut block_values[..block.len()]).fill_with(|| f(self.next()));
+            let num_steps = if steps_left > num_smoothed {
+                num_smoothed
+            } else {
+                num_smoothed - 1
+            };
+            let (stepped_values, snapped_values) = smoothed_values.split_at_mut(num_steps);
+            match self.style {
+                // This style never smooths, so this won't be reached
+                SmoothingStyle::None => (),
+                SmoothingStyle::Linear(_) => {
+                    for value in stepped_values {
+                        current += self.step_size;
+                        *value = f(T::from_f32(current));
+                    }
+                }
+                SmoothingStyle::Logarithmic(_) if self.logarithmic_fallback => {
+                    for value in stepped_values {
+                        current += self.step_size;
+                        *value = f(T::from_f32(current));
+                    }
+                }
+                SmoothingStyle::Logarithmic(_) => {
+                    for value in stepped_values {
+                        current *= self.step_size;
+                        *value = f(T::from_f32(current));
+                    }
+                }
+                SmoothingStyle::Exponential(_) => {
+                    let target_coefficient = 1.0 - self.step_size;
+                    for value in stepped_values {
+                        current = (current * self.step_size) + (target * target_coefficient);
+                        *value = f(T::from_f32(current));
+                    }
+                }
+            }
 
-        Some(AtomicRefMut::map(block_values, |values| {
-            &mut values[..block.len()]
-        }))
+            // This is either the final step that reaches the target, or nothing at all if the
+            // smoother is still smoothing after this block
+            if let Some(value) = snapped_values.first_mut() {
+                current = target;
+                *value = f(self.target);
+            }
+
+            self.current.store(current, Ordering::Relaxed);
+            self.steps_left
+                .fetch_sub(num_smoothed as i32, Ordering::Relaxed);
+        }
+
+        for value in target_values {
+            *value = f(self.target);
+        }
     }
 }
 
@@ -309,6 +429,42 @@ impl Smoothable for i32 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn no_smoothing() {
+        let mut smoother: Smoother<f32> = Smoother::none();
+        smoother.reset(10.0);
+        smoother.set_target(100.0, 20.0);
+        assert!(!smoother.is_smoothing());
+        assert_eq!(smoother.previous_value(), 20.0);
+        assert_eq!(smoother.next(), 20.0);
+    }
+
+    #[test]
+    fn independent_voice_smoothers() {
+        let mut voices: [Smoother<f32>; 2] =
+            [(); 2].map(|_| Smoother::new(SmoothingStyle::Linear(100.0)));
+        voices[0].reset(440.0);
+        voices[1].reset(220.0);
+
+        // Only the first voice glides, and the clone starts where the second voice left off
+        voices[0].set_target(100.0, 880.0);
+        let clone = voices[1].clone();
+        for _ in 0..5 {
+            voices[0].next();
+            assert_eq!(voices[1].next(), 220.0);
+        }
+        assert!(voices[0].is_smoothing());
+        assert!(!voices[1].is_smoothing());
+
+        voices[1].set_target(100.0, 110.0);
+        for _ in 0..(10 - 5) {
+            voices[0].next();
+        }
+        assert_eq!(voices[0].previous_value(), 880.0);
+        assert!(voices[1].is_smoothing());
+        assert_eq!(clone.next(), 220.0);
+    }
+
     #[test]
     fn linear_f32_smoothing() {
         let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
@@ -371,6 +527,214 @@ mod tests {
         assert_eq!(smoother.next(), 20);
     }
 
+    /// The values should be interpolated in the log domain, so halfway between 100 and 10000 the
+    /// value should be close to 1000 rather than to 5050.
+    #[test]
+    fn logarithmic_f32_smoothing_midpoint() {
+        let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Logarithmic(100.0));
+        smoother.reset(100.0);
+
+        // This results in 100 steps
+        smoother.set_target(1000.0, 10000.0);
+        for _ in 0..(50 - 1) {
+            smoother.next();
+        }
+        let midpoint = smoother.next();
+        assert!((midpoint - 1000.0).abs() < 1.0, "{midpoint} != 1000.0");
+    }
+
+    /// Logarithmic smoothing from or to zero should fall back to linear smoothing instead of
+    /// producing `NaN` values.
+    #[test]
+    fn logarithmic_f32_smoothing_zero_fallback() {
+        let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Logarithmic(100.0));
+        smoother.reset(0.0);
+
+        smoother.set_target(100.0, 20.0);
+        for _ in 0..(5 - 1) {
+            assert!(smoother.next().is_finite());
+        }
+        assert_eq!(smoother.next(), 10.0);
+        for _ in 0..(5 - 2) {
+            smoother.next();
+        }
+        assert_ne!(smoother.next(), 20.0);
+        assert_eq!(smoother.next(), 20.0);
+
+        // And the same thing when smoothing to a negative value
+        smoother.set_target(100.0, -20.0);
+        for _ in 0..(10 - 1) {
+            assert!(smoother.next().is_finite());
+        }
+        assert_eq!(smoother.next(), -20.0);
+    }
+
+    /// Resetting should cancel any smoothing, both for the per-sample and the block based APIs.
+    #[test]
+    fn reset_snaps_to_value() {
+        let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10.0);
+        smoother.set_target(100.0, 20.0);
+        smoother.next();
+        assert!(smoother.is_smoothing());
+
+        smoother.reset(30.0);
+        assert!(!smoother.is_smoothing());
+        assert_eq!(smoother.previous_value(), 30.0);
+        assert_eq!(smoother.next(), 30.0);
+
+        smoother.set_target(100.0, 20.0);
+        smoother.next();
+        smoother.reset(40.0);
+        let mut block_values = [0.0; 4];
+        smoother.next_block(&mut block_values, 4);
+        assert_eq!(block_values, [40.0; 4]);
+    }
+
+    /// Changing the sample rate halfway through should not change the remaining time.
+    #[test]
+    fn linear_f32_smoothing_sample_rate_change() {
+        let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10.0);
+
+        // 10 steps at 100 Hz, 5 of which are taken before switching to 200 Hz. The remaining 50
+        // milliseconds should then take 10 steps.
+        smoother.set_target(100.0, 20.0);
+        for _ in 0..5 {
+            smoother.next();
+        }
+        assert_eq!(smoother.previous_value(), 15.0);
+
+        smoother.set_sample_rate(200.0);
+        assert_eq!(smoother.steps_left(), 10);
+        assert_eq!(smoother.next(), 15.5);
+        for _ in 0..(10 - 3) {
+            smoother.next();
+        }
+        assert_ne!(smoother.next(), 20.0);
+        assert_eq!(smoother.next(), 20.0);
+    }
+
+    /// The exponential smoother's curve should stay the same after changing the sample rate.
+    #[test]
+    fn exponential_f32_smoothing_sample_rate_change() {
+        let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Exponential(100.0));
+        let mut reference: Smoother<f32> = Smoother::new(SmoothingStyle::Exponential(100.0));
+        smoother.reset(10.0);
+        reference.reset(10.0);
+
+        smoother.set_target(1000.0, 20.0);
+        reference.set_target(2000.0, 20.0);
+        for _ in 0..20 {
+            smoother.next();
+        }
+        for _ in 0..40 {
+            reference.next();
+        }
+
+        smoother.set_sample_rate(2000.0);
+        assert_eq!(smoother.steps_left(), reference.steps_left());
+        for _ in 0..40 {
+            let (value, expected) = (smoother.next(), reference.next());
+            assert!((value - expected).abs() < 1e-3, "{value} != {expected}");
+        }
+    }
+
+    #[test]
+    fn smoothing_status() {
+        let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10.0);
+        assert!(!smoother.is_smoothing());
+        assert_eq!(smoother.steps_left(), 0);
+
+        smoother.set_target(100.0, 20.0);
+        assert!(smoother.is_smoothing());
+        assert_eq!(smoother.steps_left(), 10);
+
+        for steps_left in (1..10).rev() {
+            smoother.next();
+            assert!(smoother.is_smoothing());
+            assert_eq!(smoother.steps_left(), steps_left);
+        }
+
+        assert_eq!(smoother.next(), 20.0);
+        assert!(!smoother.is_smoothing());
+        assert_eq!(smoother.steps_left(), 0);
+
+        // Skipping past the end should not cause the counter to become negative
+        smoother.set_target(100.0, 10.0);
+        smoother.next_step(20);
+        assert!(!smoother.is_smoothing());
+        assert_eq!(smoother.steps_left(), 0);
+    }
+
+    /// The block smoothing should produce the exact same values as calling `next()` repeatedly.
+    #[test]
+    fn linear_f32_block_smoothing() {
+        let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        let mut block_smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10.0);
+        block_smoother.reset(10.0);
+
+        // The second block crosses the end of the smoothing period, and the third block should
+        // only contain the target value
+        smoother.set_target(1000.0, 20.0);
+        block_smoother.set_target(1000.0, 20.0);
+        let mut block_values = [0.0; 64];
+        for block_len in [40, 64, 32] {
+            block_smoother.next_block(&mut block_values, block_len);
+            for block_value in &block_values[..block_len] {
+                assert_eq!(*block_value, smoother.next());
+            }
+        }
+
+        assert_eq!(block_values[31], 20.0);
+        assert_eq!(block_smoother.steps_left(), 0);
+        assert_eq!(block_smoother.previous_value(), smoother.previous_value());
+    }
+
+    #[test]
+    fn linear_i32_block_smoothing() {
+        let mut smoother: Smoother<i32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        let mut block_smoother: Smoother<i32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10);
+        block_smoother.reset(10);
+
+        smoother.set_target(1000.0, 20);
+        block_smoother.set_target(1000.0, 20);
+        let mut block_values = [0; 64];
+        for block_len in [40, 64, 32] {
+            block_smoother.next_block(&mut block_values, block_len);
+            for block_value in &block_values[..block_len] {
+                assert_eq!(*block_value, smoother.next());
+            }
+        }
+    }
+
+    #[test]
+    fn exponential_f32_block_smoothing() {
+        let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Exponential(100.0));
+        let mut block_smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Exponential(100.0));
+        smoother.reset(10.0);
+        block_smoother.reset(10.0);
+
+        smoother.set_target(1000.0, 20.0);
+        block_smoother.set_target(1000.0, 20.0);
+        let mut block_values = [0.0; 64];
+        for block_len in [40, 64, 32] {
+            block_smoother.next_block(&mut block_values, block_len);
+            for block_value in &block_values[..block_len] {
+                let value = smoother.next();
+                assert!(
+                    (block_value - value).abs() < 1e-5,
+                    "{block_value} != {value}"
+                );
+            }
+        }
+
+        assert_eq!(block_values[31], 20.0);
+    }
+
     /// Same as [linear_f32_smoothing], but skipping steps instead.
     #[test]
     fn skipping_linear_f32_smoothing() {
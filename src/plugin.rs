@@ -8,7 +8,9 @@ use crate::buffer::Buffer;
 use crate::context::{GuiContext, InitContext, ProcessContext};
 use crate::midi::MidiConfig;
 use crate::param::internals::Params;
+use crate::preset::Preset;
 use crate::wrapper::clap::features::ClapFeature;
+use crate::wrapper::state::PluginState;
 
 /// Basic functionality that needs to be implemented by a plugin. The wrappers will use this to
 /// expose the plugin in a particular plugin format.
@@ -47,13 +49,26 @@ pub trait Plugin: Default + Send + Sync + 'static {
 
     /// If set, then the plugin will have this many sidechain input busses with a default number of
     /// channels. Not all hosts support more than one sidechain input bus. Negotiating the actual
-    /// configuration wroks the same was as with `DEFAULT_NUM_INPUTS`.
+    /// configuration works the same way as with `DEFAULT_NUM_INPUTS`. The sidechain inputs are
+    /// passed to [`process()`][Self::process()] through [`AuxiliaryBuffers::inputs`].
     const DEFAULT_AUX_INPUTS: Option<AuxiliaryIOConfig> = None;
     /// If set, then the plugin will have this many auxiliary output busses with a default number of
     /// channels. Negotiating the actual configuration wroks the same was as with
     /// `DEFAULT_NUM_INPUTS`.
     const DEFAULT_AUX_OUTPUTS: Option<AuxiliaryIOConfig> = None;
 
+    /// An explicit list of bus configurations the plugin supports. If this is left empty, then the
+    /// wrapper will probe common mono and stereo configurations using
+    /// [`accepts_bus_config()`][Self::accepts_bus_config()] instead. When this is set, the
+    /// configurations are offered to the host exactly as declared, without calling
+    /// `accepts_bus_config()`, and the host can only select one of these configurations. The
+    /// default configuration from `DEFAULT_NUM_INPUTS` and friends should be part of this list.
+    ///
+    /// In CLAP every configuration is exposed through the `audio-ports-config` extension, and the
+    /// selected configuration is passed to [`initialize()`][Self::initialize()] the next time the
+    /// plugin is activated.
+    const AUDIO_PORT_CONFIGS: &'static [BusConfig] = &[];
+
     /// Optional names for the main and auxiliary input and output ports. Will be generated if not
     /// set. This is mostly useful to give descriptive names to the outputs for multi-output
     /// plugins.
@@ -65,18 +80,55 @@ pub trait Plugin: Default + Send + Sync + 'static {
     };
 
     /// Whether the plugin accepts note events, and what which events it wants to receive. If this
-    /// is set to [`MidiConfig::None`], then the plugin won't receive any note events.
+    /// is set to [`MidiConfig::None`], then the plugin won't receive any note events and it won't
+    /// have a note input port. With [`MidiConfig::Basic`] the CLAP version of the plugin only
+    /// accepts CLAP note events, and [`MidiConfig::MidiCCs`] also lets the host send raw MIDI. These
+    /// are all translated to [`NoteEvent`][crate::prelude::NoteEvent]s.
     const MIDI_INPUT: MidiConfig = MidiConfig::None;
     /// Whether the plugin can output note events. If this is set to [`MidiConfig::None`], then the
-    /// plugin won't have a note output port. When this is set to another value, then in most hsots
-    /// the plugin will consume all note and MIDI CC input. If you don't want that, then you will
-    /// need to forward those events yourself.
+    /// plugin won't have a note output port. Otherwise the plugin gets a CLAP note output port and
+    /// a VST3 event output bus, and the events sent through
+    /// [`ProcessContext::send_event()`][crate::prelude::ProcessContext::send_event()] are passed to
+    /// the host through those after each block. When this is set to another value, then in most
+    /// hosts the plugin will consume all note and MIDI CC input. If you don't want that, then you
+    /// will need to forward those events yourself.
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
     /// If enabled, the audio processing cycle may be split up into multiple smaller chunks if
     /// parameter values change occur in the middle of the buffer. Depending on the host these
     /// blocks may be as small as a single sample. Bitwig Studio sends at most one parameter change
     /// every 64 samples.
+    ///
+    /// Each chunk is passed to a separate [`process()`][Self::process()] call. Parameter changes
+    /// are applied, and their smoothers' targets are updated, right before the chunk that starts at
+    /// the change's sample offset, so smoothed values start moving at the exact sample the host
+    /// requested. Note events within a chunk have their timings relative to the start of that
+    /// chunk. When this is disabled, all parameter changes for a buffer are applied at the start
+    /// of that buffer, which is cheaper for plugins that don't need this precision.
     const SAMPLE_ACCURATE_AUTOMATION: bool = false;
+    /// If enabled and the plugin has a bypass parameter (see
+    /// [`BoolParam::make_bypass()`][crate::prelude::BoolParam::make_bypass()]), then the wrapper
+    /// won't call [`process()`][Self::process()] while that parameter is enabled. The main input
+    /// is then passed through to the main output unchanged. This only happens when the number of
+    /// main input and output channels match. Otherwise `process()` is still called as usual, and
+    /// the plugin needs to handle the bypass itself. Any note events received while bypassed are
+    /// dropped.
+    const BYPASS_PASSTHROUGH: bool = false;
+    /// How many tasks the wrapper's task queues can hold. Tasks are things like notifying the host
+    /// about parameter value changes and editor resize requests, and they're queued when they're
+    /// posted from a thread other than the main thread, like the audio thread. Once the queue is
+    /// full, new tasks are dropped until the main thread has caught up. Increase this if the
+    /// plugin causes bursts of these tasks, for instance by changing latency or restoring presets
+    /// many times in quick succession. This needs to be at least 1, and plugins that set this to 0
+    /// will fail to compile.
+    const TASK_QUEUE_CAPACITY: usize = 512;
+    /// Whether the wrapper should still execute the tasks that are left in its task queues when the
+    /// plugin instance is destroyed, instead of dropping them. This waits at most a couple seconds
+    /// for those tasks to finish. Set this to `false` to drop the remaining tasks right away.
+    ///
+    /// This is currently only honored by the CLAP wrapper. The VST3 wrapper never shuts down its
+    /// event loop, so tasks that are still queued when a VST3 plugin instance is destroyed are
+    /// never executed regardless of this setting.
+    const DRAIN_TASKS_ON_SHUTDOWN: bool = true;
 
     /// The plugin's parameters. The host will update the parameter values before calling
     /// `process()`. These parameters are identified by strings that should never change when the
@@ -89,10 +141,41 @@ pub trait Plugin: Default + Send + Sync + 'static {
     /// access into the editor. You can later modify the parameters through the
     /// [`GuiContext`][crate::prelude::GuiContext] and [`ParamSetter`][crate::prelude::ParamSetter] after the editor
     /// GUI has been created.
+    ///
+    /// This is called once when the plugin instance gets created. Plugins without a GUI should
+    /// return `None` here, which is also the default. The CLAP wrapper then won't expose the GUI
+    /// extension and the VST3 wrapper won't create an `IPlugView`, so hosts will fall back to
+    /// their own generic parameter UI instead of showing an empty window. The standalone target
+    /// won't open a window at all for these plugins.
     fn editor(&self) -> Option<Box<dyn Editor>> {
         None
     }
 
+    /// Update state that was saved with an older version of the plugin's parameters. This is called
+    /// before restoring a state whose version does not match the
+    /// [`Params::state_version()`][crate::prelude::Params::state_version()] of this plugin's
+    /// parameters. `old_version` is the version the state was saved with, and it is 0 for states
+    /// saved before the plugin declared a version. Use this to for instance move a parameter's
+    /// value to its new parameter ID in `state.params` when it has been renamed. Values for
+    /// parameters that no longer exist are ignored, and parameters that are not in the state keep
+    /// their current values.
+    ///
+    /// This is called from the main thread or the GUI thread while loading the state, before any
+    /// parameter values have been changed.
+    fn migrate_state(old_version: u32, state: &mut PluginState) {}
+
+    /// The plugin's factory presets. Each preset consists of a name and a serialized state, see
+    /// [`Preset`] for more information. CLAP hosts that support the preset discovery factory can
+    /// index these presets and show them in their own preset browsers. If this returns an empty
+    /// list, which is the default, then no preset discovery factory is exposed.
+    ///
+    /// This may be called without an instance of the plugin existing, so it should not rely on any
+    /// global state. Loading a preset goes through
+    /// [`migrate_state()`][Self::migrate_state()] like any other state would.
+    fn factory_presets() -> Vec<Preset> {
+        Vec::new()
+    }
+
     //
     // The following functions follow the lifetime of the plugin.
     //
@@ -110,8 +193,18 @@ pub trait Plugin: Default + Send + Sync + 'static {
     /// have already been restored at this point. If based on those parameters (or for any reason
     /// whatsoever) the plugin needs to introduce latency, then you can do so here using the process
     /// context. Depending on how the host restores plugin state, this function may also be called
-    /// twice in rapid succession. If the plugin fails to inialize for whatever reason, then this
-    /// should return `false`.
+    /// twice in rapid succession.
+    ///
+    /// If the plugin fails to inialize for whatever reason, for instance because it cannot work
+    /// with the sample rate or maximum buffer size from `buffer_config`, then this should return
+    /// `false`. The wrapper then reports the activation failure to the host instead of letting
+    /// the host start processing audio. When this happens while reinitializing the plugin after
+    /// restoring state from the host, then the wrapper reports that the state could not be
+    /// loaded. The standalone target returns an error instead of opening audio devices.
+    ///
+    /// The host calls this when the plugin gets activated, which also happens whenever the sample
+    /// rate or the maximum block size changes. This is not called from the audio thread, so this
+    /// is the place to allocate any buffers that depend on the [`BufferConfig`].
     ///
     /// Before this point, the plugin should not have done any expensive initialization. Please
     /// don't be that plugin that takes twenty seconds to scan.
@@ -129,7 +222,13 @@ pub trait Plugin: Default + Send + Sync + 'static {
 
     /// Clear internal state such as filters and envelopes. This is always called after
     /// [`initialize()`][Self::initialize()], and it may also be called at any other time from the
-    /// audio thread. You should thus not do any allocations in this function.
+    /// audio thread. Hosts do this when playback starts, when the transport jumps to another
+    /// position, or when they otherwise want the plugin to start over from a clean slate. The
+    /// bus and buffer configurations don't change between these calls, so anything allocated in
+    /// `initialize()` can be reused here.
+    ///
+    /// Like [`process()`][Self::process()], this function must be realtime-safe and it should not
+    /// allocate. The `assert_process_allocs` feature also checks this function.
     fn reset(&mut self) {}
 
     /// Process audio. The host's input buffers have already been copied to the output buffers if
@@ -139,16 +238,20 @@ pub trait Plugin: Default + Send + Sync + 'static {
     /// abort the program when any allocation accurs in the process function while running in debug
     /// mode.
     ///
+    /// NIH-plug enables the CPU's flush-to-zero and denormals-are-zero modes for the audio thread
+    /// while this function, [`reset()`][Self::reset()], and the wrapper's own processing code are
+    /// running, and it restores the thread's previous settings afterwards. This affects all
+    /// floating point math on that thread during that time, including any libraries you call into.
+    /// You can opt out of this by enabling the `allow_denormals` feature.
+    ///
     /// The framework provides convenient iterators on the [`Buffer`] object to process audio either
     /// either per-sample per-channel, or per-block per-channel per-sample. The first approach is
     /// preferred for plugins that don't require block-based processing because of their use of
     /// per-sample SIMD or excessive branching. The parameter smoothers can also work in both modes:
     /// use [`Smoother::next()`][crate::prelude::Smoother::next()] for per-sample processing, and
     /// [`Smoother::next_block()`][crate::prelude::Smoother::next_block()] for block-based
-    /// processing. In order to use block-based smoothing, you will need to call
-    /// [`initialize_block_smoothers()`][Self::initialize_block_smoothers()] in your
-    /// [`initialize()`][Self::initialize()] function first to reserve enough capacity in the
-    /// smoothers.
+    /// processing. Block-based smoothing fills a slice you provide, so you can allocate a buffer
+    /// for that in your [`initialize()`][Self::initialize()] function.
     ///
     /// The `context` object contains context information as well as callbacks for working with note
     /// events. The [`AuxiliaryBuffers`] contain the plugin's sidechain input buffers and
@@ -172,17 +275,6 @@ pub trait Plugin: Default + Send + Sync + 'static {
     /// `initialize()` may be called more than once before `deactivate()` is called, for instance
     /// when restoring state while the plugin is still activate.
     fn deactivate(&mut self) {}
-
-    /// Convenience function provided to allocate memory for block-based smoothing for this plugin.
-    /// Since this allocates memory, this should be called in [`initialize()`][Self::initialize()].
-    /// If you are going to use [`Buffer::iter_blocks()`] and want to use parameter smoothing in
-    /// those blocks, then call this function with the same maximum block size first before calling
-    /// [`Smoother::next_block()`][crate::prelude::Smoother::next_block()].
-    fn initialize_block_smoothers(&mut self, max_block_size: usize) {
-        for (_, mut param, _) in self.params().param_map() {
-            unsafe { param.initialize_block_smoother(max_block_size) };
-        }
-    }
 }
 
 /// Provides auxiliary metadata needed for a CLAP plugin.
@@ -213,7 +305,8 @@ pub trait ClapPlugin: Plugin {
 /// Provides auxiliary metadata needed for a VST3 plugin.
 pub trait Vst3Plugin: Plugin {
     /// The unique class ID that identifies this particular plugin. You can use the
-    /// `*b"fooofooofooofooo"` syntax for this.
+    /// `*b"fooofooofooofooo"` syntax for this, or the [`uid!()`][crate::uid!()] macro to make sure
+    /// the ID has the correct length.
     ///
     /// This will be shuffled into a different byte order on Windows for project-compatibility.
     const VST3_CLASS_ID: [u8; 16];
@@ -262,7 +355,10 @@ pub trait Editor: Send + Sync {
     ///
     /// This function should return a handle to the editor, which will be dropped when the editor
     /// gets closed. Implement the [`Drop`] trait on the returned handle if you need to explicitly
-    /// handle the editor's closing behavior.
+    /// handle the editor's closing behavior. If the editor cannot be opened, then this should
+    /// return `None` instead, and the wrapper will report the failure to the host. The wrapper
+    /// already refuses to open the editor when the host passes a null parent window handle, see
+    /// [`ParentWindowHandle::is_valid()`].
     ///
     /// If [`set_scale_factor()`][Self::set_scale_factor()] has been called, then any created
     /// windows should have their sizes multiplied by that factor.
@@ -276,27 +372,36 @@ pub trait Editor: Send + Sync {
     //       otherwise be basically impossible to have this still be GUI-framework agnostic. Any
     //       callback that deos involve actual GUI operations will still be spooled to the IRunLoop
     //       instance.
-    // TODO: This function should return an `Option` instead. Right now window opening failures are
-    //       always fatal. This would need to be fixed in basevie first.
+    // TODO: The editor adapters can't return `None` for all window opening failures yet. This
+    //       would need to be fixed in baseview first.
     fn spawn(
         &self,
         parent: ParentWindowHandle,
         context: Arc<dyn GuiContext>,
-    ) -> Box<dyn Any + Send + Sync>;
+    ) -> Option<Box<dyn Any + Send + Sync>>;
 
     /// Returns the (currnent) size of the editor in pixels as a `(width, height)` pair. This size
     /// must be reported in _logical pixels_, i.e. the size before being multiplied by the DPI
     /// scaling factor to get the actual physical screen pixels.
     fn size(&self) -> (u32, u32);
 
-    /// Set the DPI scaling factor, if supported. The plugin APIs don't make any guarantees on when
-    /// this is called, but for now just assume it will be the first function that gets called
-    /// before creating the editor. If this is set, then any windows created by this editor should
-    /// have their sizes multiplied by this scaling factor on Windows and Linux.
+    /// Set the DPI scaling factor, if supported. Return `true` if the editor will use the new
+    /// factor. This is usually called before the editor is created, but the host can also call it
+    /// while the editor is open, for instance when the window is moved to a monitor with a
+    /// different scale on Windows. If this is set, then any windows created by this editor should
+    /// have their sizes multiplied by this scaling factor on Windows and Linux, and an open window
+    /// should resize and redraw itself using the new factor. The wrapper then informs the host
+    /// about the window's new physical size. The window is not recreated.
+    ///
+    /// The default implementation rejects the new factor, in which case the wrapper keeps using a
+    /// factor of 1.0.
     ///
     /// Right now this is never called on macOS since DPI scaling is built into the operating system
     /// there.
-    fn set_scale_factor(&self, factor: f32) -> bool;
+    fn set_scale_factor(&self, factor: f32) -> bool {
+        let _ = factor;
+        false
+    }
 
     /// A callback that will be called wheneer the parameter values changed while the editor is
     /// open. You don't need to do anything with this, but this can be used to force a redraw when
@@ -314,7 +419,10 @@ pub trait Editor: Send + Sync {
     // TODO: Host->Plugin resizing
 }
 
-/// A raw window handle for platform and GUI framework agnostic editors.
+/// A raw window handle for platform and GUI framework agnostic editors. This implements
+/// [`HasRawWindowHandle`], so it can be passed directly to libraries built on `raw-window-handle`
+/// like baseview or wgpu. The handle contains an X11 (XCB) window on Linux, an `NSView` on macOS,
+/// and an `HWND` on Windows.
 pub struct ParentWindowHandle {
     pub handle: RawWindowHandle,
 }
@@ -325,6 +433,22 @@ unsafe impl HasRawWindowHandle for ParentWindowHandle {
     }
 }
 
+impl ParentWindowHandle {
+    /// Whether this handle points to an actual window. This only checks that the handle for the
+    /// X11, AppKit, or Win32 window is not null, since there's no way to check whether a window
+    /// handle is still valid without talking to the windowing system. Handles for any other
+    /// platform are always considered invalid.
+    pub fn is_valid(&self) -> bool {
+        match self.handle {
+            RawWindowHandle::Xcb(handle) => handle.window != 0,
+            RawWindowHandle::Xlib(handle) => handle.window != 0,
+            RawWindowHandle::AppKit(handle) => !handle.ns_view.is_null(),
+            RawWindowHandle::Win32(handle) => !handle.hwnd.is_null(),
+            _ => false,
+        }
+    }
+}
+
 /// The plugin's IO configuration.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BusConfig {
@@ -338,7 +462,8 @@ pub struct BusConfig {
     pub aux_output_busses: AuxiliaryIOConfig,
 }
 
-/// Configuration for auxiliary inputs or outputs on [`BusCofnig`].
+/// Configuration for auxiliary inputs or outputs on [`BusConfig`]. Every auxiliary bus in a
+/// direction has the same number of channels.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct AuxiliaryIOConfig {
     /// The number of auxiliary input or output busses.
@@ -380,13 +505,22 @@ pub struct BufferConfig {
 }
 
 /// Contains auxiliary (sidechain) input and output buffers for a process call.
+///
+/// There is one buffer for every auxiliary bus in the current [`BusConfig`], in the same order, and
+/// each of those buffers has [`AuxiliaryIOConfig::num_channels`] channels. If the host did not
+/// provide any audio for an auxiliary output bus, then that output's buffer will be empty.
+/// Multi-output plugins can check for this with [`Buffer::is_empty()`] to skip rendering outputs
+/// the host doesn't need.
 pub struct AuxiliaryBuffers<'a> {
     /// All auxiliary (sidechain) inputs defined for this plugin. The data in these buffers can
     /// safely be overwritten. Auxiliary inputs can be defined by setting
-    /// [`Plugin::DEFAULT_AUX_INPUTS`][`crate::prelude::Plugin::DEFAULT_AUX_INPUTS`].
+    /// [`Plugin::DEFAULT_AUX_INPUTS`][`crate::prelude::Plugin::DEFAULT_AUX_INPUTS`]. If the host
+    /// has not connected anything to a sidechain input, then that input's buffer will contain
+    /// silence.
     pub inputs: &'a mut [Buffer<'a>],
     /// Get all auxiliary outputs defined for this plugin. Auxiliary outputs can be defined by
-    /// setting [`Plugin::DEFAULT_AUX_OUTPUTS`][`crate::prelude::Plugin::DEFAULT_AUX_OUTPUTS`].
+    /// setting [`Plugin::DEFAULT_AUX_OUTPUTS`][`crate::prelude::Plugin::DEFAULT_AUX_OUTPUTS`]. An
+    /// output's buffer is empty when the host has not connected that output.
     pub outputs: &'a mut [Buffer<'a>],
 }
 
@@ -398,7 +532,11 @@ pub enum ProcessStatus {
     /// The plugin has finished processing audio. When the input is silent, the most may suspend the
     /// plugin to save resources as it sees fit.
     Normal,
-    /// The plugin has a (reverb) tail with a specific length in samples.
+    /// The plugin has a (reverb) tail with a specific length in samples. This is reported to the
+    /// host through CLAP's tail extension and VST3's `IAudioProcessor::getTailSamples()`, so the
+    /// host keeps processing the plugin until the tail has been rendered, also when bouncing
+    /// offline. Once this reaches `Tail(0)` the host may suspend the plugin when its input is
+    /// silent.
     Tail(u32),
     /// This plugin will continue to produce sound regardless of whether or not the input is silent,
     /// and should thus not be deactivated by the host. This is essentially the same as having an
@@ -406,8 +544,10 @@ pub enum ProcessStatus {
     KeepAlive,
 }
 
-/// The plugin's current processing mode. Can be queried through [`ProcessContext::process_mode()`].
-/// The host will reinitialize the plugin whenever this changes.
+/// The plugin's current processing mode. Can be queried through [`ProcessContext::process_mode()`],
+/// and it's also part of the [`BufferConfig`] passed to [`Plugin::initialize()`]. Hosts usually
+/// reinitialize the plugin whenever this changes. This is [`Realtime`][Self::Realtime] if the host
+/// never specified a mode.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProcessMode {
     /// The plugin is processing audio in real time at a fixed rate.
@@ -421,3 +561,57 @@ pub enum ProcessMode {
     /// processed.
     Offline,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_parent_window_handles() {
+        let mut xcb = raw_window_handle::XcbHandle::empty();
+        xcb.window = 42;
+        let parent = ParentWindowHandle {
+            handle: RawWindowHandle::Xcb(xcb),
+        };
+        match parent.raw_window_handle() {
+            RawWindowHandle::Xcb(handle) => assert_eq!(handle.window, 42),
+            handle => panic!("Unexpected window handle: {handle:?}"),
+        }
+
+        let mut win32 = raw_window_handle::Win32Handle::empty();
+        win32.hwnd = 0x1234 as *mut std::ffi::c_void;
+        let parent = ParentWindowHandle {
+            handle: RawWindowHandle::Win32(win32),
+        };
+        match parent.raw_window_handle() {
+            RawWindowHandle::Win32(handle) => assert_eq!(handle.hwnd, win32.hwnd),
+            handle => panic!("Unexpected window handle: {handle:?}"),
+        }
+    }
+
+    #[test]
+    fn null_parent_window_handles() {
+        let mut xcb = raw_window_handle::XcbHandle::empty();
+        assert!(!ParentWindowHandle {
+            handle: RawWindowHandle::Xcb(xcb)
+        }
+        .is_valid());
+        xcb.window = 42;
+        assert!(ParentWindowHandle {
+            handle: RawWindowHandle::Xcb(xcb)
+        }
+        .is_valid());
+
+        let app_kit = raw_window_handle::AppKitHandle::empty();
+        assert!(!ParentWindowHandle {
+            handle: RawWindowHandle::AppKit(app_kit)
+        }
+        .is_valid());
+
+        let win32 = raw_window_handle::Win32Handle::empty();
+        assert!(!ParentWindowHandle {
+            handle: RawWindowHandle::Win32(win32)
+        }
+        .is_valid());
+    }
+}
@@ -7,11 +7,15 @@ pub use crate::nih_export_vst3;
 #[cfg(feature = "standalone")]
 pub use crate::wrapper::standalone::{nih_export_standalone, nih_export_standalone_with_args};
 
+pub use crate::uid;
+
 pub use crate::formatters;
 pub use crate::util;
 
 pub use crate::buffer::Buffer;
-pub use crate::context::{GuiContext, InitContext, ParamSetter, PluginApi, ProcessContext};
+pub use crate::context::{
+    AutomationState, GuiContext, InitContext, ParamSetter, PluginApi, ProcessContext, Transport,
+};
 // This also includes the derive macro
 pub use crate::midi::{control_change, MidiConfig, NoteEvent};
 pub use crate::param::enums::{Enum, EnumParam};
@@ -23,5 +27,6 @@ pub use crate::plugin::{
     AuxiliaryBuffers, AuxiliaryIOConfig, BufferConfig, BusConfig, ClapPlugin, Editor,
     ParentWindowHandle, Plugin, PortNames, ProcessMode, ProcessStatus, Vst3Plugin,
 };
+pub use crate::preset::Preset;
 pub use crate::wrapper::clap::features::ClapFeature;
 pub use crate::wrapper::state::PluginState;
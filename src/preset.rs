@@ -0,0 +1,289 @@
+//! Utilities for working with presets, including presets from other plugin formats.
+//!
+//! The factory presets returned from
+//! [`Plugin::factory_presets()`][crate::prelude::Plugin::factory_presets()] are described using
+//! [`Preset`]s.
+//!
+//! The [`parse_fxp()`] and [`parse_fxb()`] functions can be used to read VST2 `.fxp` program and
+//! `.fxb` bank files. These only extract the preset's program name and its raw data. Interpreting
+//! that data is up to the plugin, for instance from within
+//! [`Plugin::migrate_state()`][crate::prelude::Plugin::migrate_state()] or a preset import feature
+//! in the plugin's editor.
+
+/// A factory preset that ships with the plugin. See
+/// [`Plugin::factory_presets()`][crate::prelude::Plugin::factory_presets()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset {
+    /// The preset's name, as shown in the host's preset browser.
+    pub name: String,
+    /// The preset's state, serialized as a JSON [`PluginState`][crate::prelude::PluginState]
+    /// object. This is the same format the plugin's state is saved in, so the easiest way to create
+    /// these is to save the plugin's state with the desired settings and to then embed that file
+    /// using `include_bytes!()`.
+    pub state: Vec<u8>,
+}
+
+impl Preset {
+    /// Create a factory preset from a name and a serialized JSON
+    /// [`PluginState`][crate::prelude::PluginState] object.
+    pub fn new(name: impl Into<String>, state: impl Into<Vec<u8>>) -> Self {
+        Self {
+            name: name.into(),
+            state: state.into(),
+        }
+    }
+}
+
+/// The magic bytes at the start of every `.fxp` and `.fxb` file, as well as every program stored
+/// in an `.fxb` bank.
+const CHUNK_MAGIC: [u8; 4] = *b"CcnK";
+/// A program containing a list of normalized parameter values.
+const FXP_PARAMS_MAGIC: [u8; 4] = *b"FxCk";
+/// A program containing an opaque chunk.
+const FXP_CHUNK_MAGIC: [u8; 4] = *b"FPCh";
+/// A bank containing a list of regular programs.
+const FXB_PARAMS_MAGIC: [u8; 4] = *b"FxBk";
+/// A bank containing an opaque chunk.
+const FXB_CHUNK_MAGIC: [u8; 4] = *b"FBCh";
+
+/// The length of a program name in an `.fxp` file.
+const FXP_NAME_LEN: usize = 28;
+/// The number of reserved bytes in an `.fxb` file's header. Version 2 banks store the current
+/// program's index in the first four of these bytes.
+const FXB_RESERVED_LEN: usize = 128;
+
+/// A VST2 program read from an `.fxp` file with [`parse_fxp()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vst2Program {
+    /// The VST2 plugin's four character unique ID, like `b"Abcd"`.
+    pub plugin_id: [u8; 4],
+    /// The version of the plugin that saved the program.
+    pub plugin_version: i32,
+    /// The program's name.
+    pub name: String,
+    /// The program's data.
+    pub data: Vst2PresetData,
+}
+
+/// A VST2 bank read from an `.fxb` file with [`parse_fxb()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vst2Bank {
+    /// The VST2 plugin's four character unique ID, like `b"Abcd"`.
+    pub plugin_id: [u8; 4],
+    /// The version of the plugin that saved the bank.
+    pub plugin_version: i32,
+    /// The bank's data. For banks containing parameter values instead of an opaque chunk this
+    /// contains the bank's individual programs.
+    pub data: Vst2BankData,
+}
+
+/// The data stored in a VST2 program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Vst2PresetData {
+    /// The plugin stored its state as a list of normalized parameter values.
+    Params(Vec<f32>),
+    /// The plugin stored its state as an opaque chunk of data.
+    Chunk(Vec<u8>),
+}
+
+/// The data stored in a VST2 bank.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Vst2BankData {
+    /// The bank contains individual programs.
+    Programs(Vec<Vst2Program>),
+    /// The plugin stored the entire bank as an opaque chunk of data.
+    Chunk(Vec<u8>),
+}
+
+/// Errors that may arise while parsing an `.fxp` or `.fxb` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vst2PresetError {
+    /// The file ended before all of the data declared in its header could be read.
+    UnexpectedEnd,
+    /// The file does not start with the `CcnK` magic bytes.
+    InvalidMagic,
+    /// The file contains a type of preset that was not expected here, like a bank passed to
+    /// [`parse_fxp()`]. Contains the preset type's magic bytes.
+    UnexpectedType([u8; 4]),
+}
+
+/// Parse a VST2 `.fxp` program file.
+pub fn parse_fxp(data: &[u8]) -> Result<Vst2Program, Vst2PresetError> {
+    let mut reader = Reader { data };
+    read_program(&mut reader)
+}
+
+/// Parse a VST2 `.fxb` bank file.
+pub fn parse_fxb(data: &[u8]) -> Result<Vst2Bank, Vst2PresetError> {
+    let mut reader = Reader { data };
+    let (fx_magic, mut reader) = read_header(&mut reader)?;
+    let is_chunk = match fx_magic {
+        FXB_PARAMS_MAGIC => false,
+        FXB_CHUNK_MAGIC => true,
+        fx_magic => return Err(Vst2PresetError::UnexpectedType(fx_magic)),
+    };
+
+    let _version = reader.read_i32()?;
+    let plugin_id = reader.read_bytes::<4>()?;
+    let plugin_version = reader.read_i32()?;
+    let num_programs = reader.read_i32()?;
+    reader.skip(FXB_RESERVED_LEN)?;
+
+    let data = if is_chunk {
+        Vst2BankData::Chunk(read_chunk(&mut reader)?)
+    } else {
+        Vst2BankData::Programs(
+            (0..num_programs.max(0))
+                .map(|_| read_program(&mut reader))
+                .collect::<Result<_, _>>()?,
+        )
+    };
+
+    Ok(Vst2Bank {
+        plugin_id,
+        plugin_version,
+        data,
+    })
+}
+
+/// Read a single program, either from an `.fxp` file or from within an `.fxb` bank.
+fn read_program(reader: &mut Reader) -> Result<Vst2Program, Vst2PresetError> {
+    let (fx_magic, mut reader) = read_header(reader)?;
+    let is_chunk = match fx_magic {
+        FXP_PARAMS_MAGIC => false,
+        FXP_CHUNK_MAGIC => true,
+        fx_magic => return Err(Vst2PresetError::UnexpectedType(fx_magic)),
+    };
+
+    let _version = reader.read_i32()?;
+    let plugin_id = reader.read_bytes::<4>()?;
+    let plugin_version = reader.read_i32()?;
+    let num_params = reader.read_i32()?;
+
+    // The name is padded with null bytes
+    let name = reader.read_bytes::<FXP_NAME_LEN>()?;
+    let name_len = name.iter().position(|&c| c == 0).unwrap_or(FXP_NAME_LEN);
+    let name = String::from_utf8_lossy(&name[..name_len]).into_owned();
+
+    let data = if is_chunk {
+        Vst2PresetData::Chunk(read_chunk(&mut reader)?)
+    } else {
+        Vst2PresetData::Params(
+            (0..num_params.max(0))
+                .map(|_| reader.read_i32().map(|value| f32::from_bits(value as u32)))
+                .collect::<Result<_, _>>()?,
+        )
+    };
+
+    Ok(Vst2Program {
+        plugin_id,
+        plugin_version,
+        name,
+        data,
+    })
+}
+
+/// Read the `CcnK` magic bytes, the size, and the preset type's magic bytes. Returns the preset
+/// type's magic bytes and a reader for the rest of the data covered by the size.
+fn read_header<'a>(reader: &mut Reader<'a>) -> Result<([u8; 4], Reader<'a>), Vst2PresetError> {
+    if reader.read_bytes::<4>()? != CHUNK_MAGIC {
+        return Err(Vst2PresetError::InvalidMagic);
+    }
+
+    // The size does not include the magic bytes or the size itself
+    let size = reader.read_i32()?.max(0) as usize;
+    let mut contents = Reader {
+        data: reader.take(size)?,
+    };
+    let fx_magic = contents.read_bytes::<4>()?;
+
+    Ok((fx_magic, contents))
+}
+
+/// Read a size-prefixed opaque chunk.
+fn read_chunk(reader: &mut Reader) -> Result<Vec<u8>, Vst2PresetError> {
+    let size = reader.read_i32()?.max(0) as usize;
+    Ok(reader.take(size)?.to_vec())
+}
+
+/// Reads big-endian values from a byte slice.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Vst2PresetError> {
+        if len > self.data.len() {
+            return Err(Vst2PresetError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), Vst2PresetError> {
+        self.take(len).map(|_| ())
+    }
+
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], Vst2PresetError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn read_i32(&mut self) -> Result<i32, Vst2PresetError> {
+        Ok(i32::from_be_bytes(self.read_bytes()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an `.fxp` file containing an opaque chunk.
+    fn chunk_fxp(name: &str, chunk: &[u8]) -> Vec<u8> {
+        let mut contents = Vec::new();
+        contents.extend_from_slice(&FXP_CHUNK_MAGIC);
+        contents.extend_from_slice(&1i32.to_be_bytes());
+        contents.extend_from_slice(b"Abcd");
+        contents.extend_from_slice(&3i32.to_be_bytes());
+        contents.extend_from_slice(&0i32.to_be_bytes());
+        let mut name_bytes = [0u8; FXP_NAME_LEN];
+        name_bytes[..name.len()].copy_from_slice(name.as_bytes());
+        contents.extend_from_slice(&name_bytes);
+        contents.extend_from_slice(&(chunk.len() as i32).to_be_bytes());
+        contents.extend_from_slice(chunk);
+
+        let mut fxp = Vec::new();
+        fxp.extend_from_slice(&CHUNK_MAGIC);
+        fxp.extend_from_slice(&(contents.len() as i32).to_be_bytes());
+        fxp.extend_from_slice(&contents);
+        fxp
+    }
+
+    #[test]
+    fn parse_chunk_fxp() {
+        let fxp = chunk_fxp("Init", &[1, 2, 3, 4, 5]);
+        assert_eq!(
+            parse_fxp(&fxp),
+            Ok(Vst2Program {
+                plugin_id: *b"Abcd",
+                plugin_version: 3,
+                name: String::from("Init"),
+                data: Vst2PresetData::Chunk(vec![1, 2, 3, 4, 5]),
+            })
+        );
+
+        assert_eq!(
+            parse_fxp(&fxp[..fxp.len() - 1]),
+            Err(Vst2PresetError::UnexpectedEnd)
+        );
+        assert_eq!(
+            parse_fxp(b"RIFF\0\0\0\0"),
+            Err(Vst2PresetError::InvalidMagic)
+        );
+        assert_eq!(
+            parse_fxb(&fxp),
+            Err(Vst2PresetError::UnexpectedType(FXP_CHUNK_MAGIC))
+        );
+    }
+}
@@ -1,8 +1,18 @@
 //! General conversion functions and utilities.
 
+mod block_timer;
+mod clipping;
+mod delay_line;
+pub mod filter;
+mod gui_to_dsp;
+pub mod oversampling;
 mod stft;
 pub mod window;
 
+pub use block_timer::BlockTimer;
+pub use clipping::{hard_clip, hard_clip_in_place, soft_clip, soft_clip_in_place};
+pub use delay_line::DelayLine;
+pub use gui_to_dsp::{gui_to_dsp_channel, DspReceiver, GuiSender};
 pub use stft::StftHelper;
 
 pub const MINUS_INFINITY_DB: f32 = -100.0;
@@ -44,16 +54,191 @@ pub fn gain_to_db(gain: f32) -> f32 {
     }
 }
 
+/// Get the `(dry, wet)` gains for a wet/dry mix value between 0.0 and 1.0 using an equal-power
+/// crossfade. This keeps the combined loudness constant for uncorrelated signals, which is usually
+/// what you want when mixing an effect's output with its input. The sum of the squared gains is
+/// always 1, and a mix of 0.0 and 1.0 results in exactly `(1.0, 0.0)` and `(0.0, 1.0)`. Values
+/// outside of that range are clamped. See
+/// [`FloatParam::new_mix()`][crate::prelude::FloatParam::new_mix()] for a matching parameter.
+pub fn equal_power_gains(mix: f32) -> (f32, f32) {
+    let mix = mix.clamp(0.0, 1.0);
+    ((1.0 - mix).sqrt(), mix.sqrt())
+}
+
 /// Convert a MIDI note ID to a frequency at A4 = 440 Hz equal temperament and middle C = note 60 =
 /// C4.
 pub fn midi_note_to_freq(pitch: u8) -> f32 {
     2.0f32.powf((pitch as f32 - 69.0) / 12.0) * 440.0
 }
 
+/// A musical note length, for use with [`note_value_to_samples()`]. A beat is always a quarter
+/// note, just like in [`Transport`][crate::prelude::Transport].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteValue {
+    Whole(NoteModifier),
+    Half(NoteModifier),
+    Quarter(NoteModifier),
+    Eighth(NoteModifier),
+    Sixteenth(NoteModifier),
+}
+
+/// Modifies the length of a [`NoteValue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteModifier {
+    /// The note's regular length.
+    Straight,
+    /// One and a half times the note's regular length.
+    Dotted,
+    /// Two thirds of the note's regular length, so three triplets fit in the space of two regular
+    /// notes.
+    Triplet,
+}
+
+impl NoteValue {
+    /// The length of this note value in beats, or quarter notes.
+    pub fn beats(&self) -> f64 {
+        let (beats, modifier) = match self {
+            NoteValue::Whole(modifier) => (4.0, modifier),
+            NoteValue::Half(modifier) => (2.0, modifier),
+            NoteValue::Quarter(modifier) => (1.0, modifier),
+            NoteValue::Eighth(modifier) => (0.5, modifier),
+            NoteValue::Sixteenth(modifier) => (0.25, modifier),
+        };
+
+        match modifier {
+            NoteModifier::Straight => beats,
+            NoteModifier::Dotted => beats * 1.5,
+            NoteModifier::Triplet => beats * (2.0 / 3.0),
+        }
+    }
+}
+
+/// The number of samples in a single beat, or quarter note, at a tempo in beats per minute.
+pub fn samples_per_beat(tempo: f64, sample_rate: f32) -> f64 {
+    sample_rate as f64 * 60.0 / tempo
+}
+
+/// The length of a [`NoteValue`] in samples at a tempo in beats per minute. The result is not
+/// rounded, since that would cause errors to accumulate for things like tempo synced LFOs.
+pub fn note_value_to_samples(note_value: NoteValue, tempo: f64, sample_rate: f32) -> f64 {
+    note_value.beats() * samples_per_beat(tempo, sample_rate)
+}
+
+/// Convert a four character code, like the ones used to identify Audio Unit plugins and their
+/// manufacturers, to a big endian `u32`. `fourcc(b"Dist")` results in `0x44697374`. Passing a
+/// byte string of any other length fails to compile.
+pub const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*code)
+}
+
+/// Convert a 16 character string to a VST3 class ID. This panics if the string does not contain
+/// exactly 16 bytes. Use the [`uid!()`][crate::uid!()] macro instead to check this at compile time.
+pub const fn vst3_class_id(id: &str) -> [u8; 16] {
+    let bytes = id.as_bytes();
+    assert!(
+        bytes.len() == 16,
+        "VST3 class IDs need to be exactly 16 bytes long"
+    );
+
+    let mut uid = [0; 16];
+    let mut i = 0;
+    while i < 16 {
+        uid[i] = bytes[i];
+        i += 1;
+    }
+
+    uid
+}
+
+/// Create a VST3 class ID for [`Vst3Plugin::VST3_CLASS_ID`][crate::prelude::Vst3Plugin] from a
+/// readable 16 character string. The string's length is checked at compile time, so an ID that's
+/// too short or too long results in a compile error instead of a truncated or zero padded ID.
+/// Including your own name or your company's name in the ID makes it less likely for two plugins
+/// to end up with the same ID.
+///
+/// ```
+/// # use nih_plug::prelude::*;
+/// const VST3_CLASS_ID: [u8; 16] = uid!("DistortionRvdH..");
+/// assert_eq!(&VST3_CLASS_ID, b"DistortionRvdH..");
+/// ```
+///
+/// ```compile_fail
+/// # use nih_plug::prelude::*;
+/// const VST3_CLASS_ID: [u8; 16] = uid!("TooShort");
+/// ```
+#[macro_export]
+macro_rules! uid {
+    ($id:expr) => {{
+        const UID: [u8; 16] = $crate::util::vst3_class_id($id);
+        UID
+    }};
+}
+pub use uid;
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_fourcc() {
+        const CODE: u32 = fourcc(b"Dist");
+        assert_eq!(CODE, 0x44697374);
+        assert_eq!(fourcc(b"\0\0\0\x01"), 1);
+    }
+
+    #[test]
+    fn test_vst3_class_id() {
+        assert_eq!(&uid!("DistortionRvdH.."), b"DistortionRvdH..");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_vst3_class_id_wrong_length() {
+        vst3_class_id("TooShort");
+    }
+
+    #[test]
+    fn test_samples_per_beat() {
+        assert_eq!(samples_per_beat(120.0, 48000.0), 24000.0);
+        assert_eq!(samples_per_beat(60.0, 44100.0), 44100.0);
+    }
+
+    #[test]
+    fn test_note_value_to_samples() {
+        assert_eq!(
+            note_value_to_samples(NoteValue::Quarter(NoteModifier::Straight), 120.0, 48000.0),
+            24000.0
+        );
+        assert_eq!(
+            note_value_to_samples(NoteValue::Whole(NoteModifier::Straight), 120.0, 48000.0),
+            96000.0
+        );
+        assert_eq!(
+            note_value_to_samples(NoteValue::Eighth(NoteModifier::Dotted), 120.0, 48000.0),
+            18000.0
+        );
+        assert_eq!(
+            note_value_to_samples(NoteValue::Quarter(NoteModifier::Triplet), 120.0, 48000.0),
+            16000.0
+        );
+    }
+
+    #[test]
+    fn test_equal_power_gains() {
+        assert_eq!(equal_power_gains(0.0), (1.0, 0.0));
+        assert_eq!(equal_power_gains(1.0), (0.0, 1.0));
+
+        let (dry, wet) = equal_power_gains(0.5);
+        assert_eq!(dry, wet);
+        for mix in [0.1, 0.25, 0.5, 0.75, 0.9] {
+            let (dry, wet) = equal_power_gains(mix);
+            assert!((dry * dry + wet * wet - 1.0).abs() < 1e-6, "mix {mix}");
+        }
+
+        assert_eq!(equal_power_gains(-1.0), (1.0, 0.0));
+        assert_eq!(equal_power_gains(2.0), (0.0, 1.0));
+    }
+
     #[test]
     fn test_db_to_gain_positive() {
         assert_eq!(db_to_gain(3.0), 1.4125376);
@@ -0,0 +1,106 @@
+//! Measuring how long processing a block of audio took, so plugins can adapt their processing to
+//! the system's load.
+
+use std::time::{Duration, Instant};
+
+/// Records the wall-clock duration of the previous
+/// [`Plugin::process()`][crate::prelude::Plugin::process()] call. Store one of these on your
+/// plugin's struct, and call [`begin()`][Self::begin()] at the start of the process function and
+/// [`end()`][Self::end()] at the end of it. During the next process call
+/// [`last_load()`][Self::last_load()] can then be used to decide whether to scale down the
+/// processing quality.
+///
+/// This uses a monotonic clock and only reads the time twice per block, so it's cheap enough to use
+/// on the audio thread. The measured time also includes any time the audio thread was preempted, so
+/// these values are only advisory.
+///
+/// ```ignore
+/// fn process(&mut self, buffer: &mut Buffer, ...) -> ProcessStatus {
+///     self.block_timer.begin();
+///     let high_quality = self.block_timer.last_load(self.sample_rate).unwrap_or(0.0) < 0.5;
+///     // ...
+///     self.block_timer.end(buffer.len());
+///
+///     ProcessStatus::Normal
+/// }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct BlockTimer {
+    /// The time [`begin()`][Self::begin()] was last called, if [`end()`][Self::end()] has not been
+    /// called since then.
+    start: Option<Instant>,
+    /// How long the last block took to process, and how many samples it contained.
+    last_block: Option<(Duration, usize)>,
+}
+
+impl BlockTimer {
+    /// Create a new timer. [`last_duration()`][Self::last_duration()] will return `None` until the
+    /// first block has been timed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start timing a block. This should be called at the start of the process function.
+    #[inline]
+    pub fn begin(&mut self) {
+        self.start = Some(Instant::now());
+    }
+
+    /// Stop timing the current block, which contained `num_samples` samples. This should be called
+    /// at the end of the process function.
+    #[inline]
+    pub fn end(&mut self, num_samples: usize) {
+        match self.start.take() {
+            Some(start) => self.last_block = Some((start.elapsed(), num_samples)),
+            None => nih_debug_assert_failure!("BlockTimer::end() called without calling begin()"),
+        }
+    }
+
+    /// How long processing the last timed block took. Returns `None` if no block has been timed
+    /// yet.
+    pub fn last_duration(&self) -> Option<Duration> {
+        self.last_block.map(|(duration, _)| duration)
+    }
+
+    /// The fraction of the last block's real time budget spent processing it, at the given sample
+    /// rate. A value of 0.25 means processing took a quarter of the time it took to play the block
+    /// back, and values above 1.0 mean the plugin could not keep up. Returns `None` if no block has
+    /// been timed yet, or if the last block was empty.
+    pub fn last_load(&self, sample_rate: f32) -> Option<f32> {
+        match self.last_block {
+            Some((duration, num_samples)) if num_samples > 0 => {
+                let budget = num_samples as f32 / sample_rate;
+                Some(duration.as_secs_f32() / budget)
+            }
+            _ => None,
+        }
+    }
+
+    /// Forget the last block's timing, for instance after the plugin has been reset.
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.last_block = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_after_process() {
+        let mut timer = BlockTimer::new();
+        assert_eq!(timer.last_duration(), None);
+        assert_eq!(timer.last_load(44100.0), None);
+
+        timer.begin();
+        std::thread::sleep(Duration::from_millis(1));
+        timer.end(512);
+
+        assert!(timer.last_duration().unwrap() >= Duration::from_millis(1));
+        assert!(timer.last_load(44100.0).unwrap() > 0.0);
+
+        timer.reset();
+        assert_eq!(timer.last_duration(), None);
+    }
+}
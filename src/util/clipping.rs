@@ -0,0 +1,70 @@
+//! Cheap soft and hard clippers. These don't use any branches beyond a clamp, so the in-place
+//! variants can be vectorized by the compiler.
+
+/// A cubic soft clipper. The input is clamped to `[-1, 1]` and then shaped with `1.5x - 0.5x^3`.
+/// This curve has a slope of 1.5 around zero and it smoothly flattens out to a slope of zero at
+/// `±1`, so the output is always bounded to `[-1, 1]` without the hard corner of a regular clipper.
+/// Inputs that are already within `[-1, 1]` are still shaped, so this also adds some saturation to
+/// quieter signals. Scale the input down first if you want it to start clipping later.
+#[inline]
+pub fn soft_clip(sample: f32) -> f32 {
+    let x = sample.clamp(-1.0, 1.0);
+    (1.5 * x) - (0.5 * x * x * x)
+}
+
+/// Hard clip a sample to `[-threshold, threshold]`. `threshold` should be positive.
+#[inline]
+pub fn hard_clip(sample: f32, threshold: f32) -> f32 {
+    nih_debug_assert!(threshold >= 0.0);
+    sample.clamp(-threshold, threshold)
+}
+
+/// Apply [`soft_clip()`] to every sample in a slice, like a channel slice from a
+/// [`Buffer`][crate::buffer::Buffer].
+pub fn soft_clip_in_place(samples: &mut [f32]) {
+    for sample in samples {
+        *sample = soft_clip(*sample);
+    }
+}
+
+/// Apply [`hard_clip()`] to every sample in a slice, like a channel slice from a
+/// [`Buffer`][crate::buffer::Buffer].
+pub fn hard_clip_in_place(samples: &mut [f32], threshold: f32) {
+    nih_debug_assert!(threshold >= 0.0);
+    for sample in samples {
+        *sample = sample.clamp(-threshold, threshold);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs from -4 to 4 in increasing order.
+    fn ramp() -> Vec<f32> {
+        (-400..=400).map(|i| i as f32 / 100.0).collect()
+    }
+
+    #[test]
+    fn soft_clip_is_bounded_and_monotonic() {
+        let mut samples = ramp();
+        soft_clip_in_place(&mut samples);
+
+        assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+        assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(soft_clip(0.0), 0.0);
+        assert_eq!(soft_clip(1.0), 1.0);
+        assert_eq!(soft_clip(-10.0), -1.0);
+    }
+
+    #[test]
+    fn hard_clip_is_bounded_and_monotonic() {
+        let mut samples = ramp();
+        hard_clip_in_place(&mut samples, 1.0);
+
+        assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+        assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(hard_clip(0.5, 1.0), 0.5);
+        assert_eq!(hard_clip(-3.0, 0.25), -0.25);
+    }
+}
@@ -0,0 +1,124 @@
+//! A fractional delay line for delays, choruses, reverbs, and other effects that need to read back
+//! previous samples.
+
+/// A single channel ring buffer delay line with linear interpolation for fractional delay times.
+/// All memory is allocated in [`new()`][Self::new()], so [`push()`][Self::push()] and
+/// [`read()`][Self::read()] are realtime-safe. Use one delay line per channel:
+///
+/// ```ignore
+/// for (channel, delay_line) in buffer.as_slice().iter_mut().zip(self.delay_lines.iter_mut()) {
+///     delay_line.process(channel, delay_samples);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DelayLine {
+    /// The ring buffer containing the delay line's samples. This contains two more samples than
+    /// the maximum delay so the sample right before the oldest sample can also be interpolated.
+    buffer: Vec<f32>,
+    /// The index in `buffer` the next sample will be written to. The most recently pushed sample is
+    /// stored right before this index.
+    write_pos: usize,
+}
+
+impl DelayLine {
+    /// Create a delay line that can delay the signal by up to `max_delay_samples` samples. The
+    /// delay line starts out filled with silence.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_delay_samples == 0`.
+    pub fn new(max_delay_samples: usize) -> Self {
+        assert_ne!(max_delay_samples, 0);
+
+        Self {
+            buffer: vec![0.0; max_delay_samples + 2],
+            write_pos: 0,
+        }
+    }
+
+    /// The maximum delay in samples that can be passed to [`read()`][Self::read()].
+    pub fn max_delay(&self) -> usize {
+        self.buffer.len() - 2
+    }
+
+    /// Fill the delay line with silence again.
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_pos = 0;
+    }
+
+    /// Add a new sample to the delay line, overwriting the oldest sample.
+    #[inline]
+    pub fn push(&mut self, sample: f32) {
+        self.buffer[self.write_pos] = sample;
+
+        self.write_pos += 1;
+        if self.write_pos == self.buffer.len() {
+            self.write_pos = 0;
+        }
+    }
+
+    /// Read the sample that was pushed `delay` samples ago, where a delay of 0 returns the most
+    /// recently pushed sample. Fractional delays are linearly interpolated. The delay is clamped to
+    /// `[0, max_delay()]`.
+    #[inline]
+    pub fn read(&self, delay: f32) -> f32 {
+        nih_debug_assert!(delay >= 0.0 && delay <= self.max_delay() as f32);
+        let delay = delay.clamp(0.0, self.max_delay() as f32);
+
+        let delay_samples = delay as usize;
+        let fraction = delay - delay_samples as f32;
+
+        // This is the index of the sample that was pushed `delay_samples` ago. Since the buffer
+        // contains two more samples than the maximum delay, the next older sample is always
+        // available as well.
+        let len = self.buffer.len();
+        let newer_idx = (self.write_pos + len - 1 - delay_samples) % len;
+        let older_idx = if newer_idx == 0 {
+            len - 1
+        } else {
+            newer_idx - 1
+        };
+
+        let newer = self.buffer[newer_idx];
+        let older = self.buffer[older_idx];
+        newer + ((older - newer) * fraction)
+    }
+
+    /// Delay every sample in a channel's audio by `delay` samples in place. This is the same as
+    /// calling [`push()`][Self::push()] followed by [`read()`][Self::read()] for every sample, so
+    /// a delay of 0 outputs the input unchanged.
+    pub fn process(&mut self, samples: &mut [f32], delay: f32) {
+        for sample in samples {
+            self.push(*sample);
+            *sample = self.read(delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractional_delay() {
+        let mut delay_line = DelayLine::new(8);
+        let mut samples = [0.0; 8];
+        samples[0] = 1.0;
+
+        delay_line.process(&mut samples, 2.25);
+        assert_eq!(samples, [0.0, 0.0, 0.75, 0.25, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn max_delay() {
+        let mut delay_line = DelayLine::new(4);
+        let mut samples = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+
+        delay_line.process(&mut samples, 4.0);
+        assert_eq!(samples, [0.0, 0.0, 0.0, 0.0, 1.0, 2.0]);
+
+        delay_line.reset();
+        assert_eq!(delay_line.read(4.0), 0.0);
+    }
+}
@@ -0,0 +1,253 @@
+//! Biquad filters with coefficients from the
+//! [Audio EQ Cookbook](https://webaudio.github.io/Audio-EQ-Cookbook/audio-eq-cookbook.html).
+
+use std::f32::consts;
+
+/// A single channel biquad filter using the transposed direct form II structure. This structure
+/// deals well with coefficients that change while the filter is running, so when a cutoff
+/// frequency parameter is being smoothed the coefficients can simply be recomputed for every
+/// sample (or every couple of samples) while the smoother is still moving:
+///
+/// ```ignore
+/// for sample in channel_samples {
+///     if self.params.cutoff.smoothed.is_smoothing() {
+///         let cutoff = self.params.cutoff.smoothed.next();
+///         self.filter.coefficients = BiquadCoefficients::lowpass(sample_rate, cutoff, q);
+///     }
+///
+///     *sample = self.filter.process(*sample);
+/// }
+/// ```
+///
+/// Based on <https://en.wikipedia.org/wiki/Digital_biquad_filter#Transposed_direct_forms>.
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    /// The filter's coefficients. These can be replaced at any time without resetting the filter.
+    pub coefficients: BiquadCoefficients,
+    s1: f32,
+    s2: f32,
+}
+
+/// The coefficients `[b0, b1, b2, a1, a2]` for a [`Biquad`]. These coefficients are all
+/// prenormalized, i.e. they have been divided by `a0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiquadCoefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Default for Biquad {
+    /// Before setting coefficients the filter just acts as an identity function.
+    fn default() -> Self {
+        Self::new(BiquadCoefficients::identity())
+    }
+}
+
+impl Biquad {
+    /// Create a filter with the specified coefficients and a cleared state.
+    pub fn new(coefficients: BiquadCoefficients) -> Self {
+        Self {
+            coefficients,
+            s1: 0.0,
+            s2: 0.0,
+        }
+    }
+
+    /// Process a single sample.
+    #[inline]
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let result = self.coefficients.b0 * sample + self.s1;
+
+        self.s1 = self.coefficients.b1 * sample - self.coefficients.a1 * result + self.s2;
+        self.s2 = self.coefficients.b2 * sample - self.coefficients.a2 * result;
+
+        result
+    }
+
+    /// Filter every sample in a slice in place, like a channel slice from a
+    /// [`Buffer`][crate::buffer::Buffer].
+    pub fn process_block(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            *sample = self.process(*sample);
+        }
+    }
+
+    /// Reset the state to zero, useful after making large, non-interpolatable changes to the
+    /// filter coefficients or when the plugin gets reset.
+    pub fn reset(&mut self) {
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+    }
+}
+
+impl BiquadCoefficients {
+    /// Filter coefficients that would cause the sound to be passed through as is.
+    pub fn identity() -> Self {
+        Self {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+        }
+    }
+
+    /// Compute the coefficients for a low-pass filter.
+    pub fn lowpass(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let (cos_omega0, alpha) = Self::omega0_alpha(sample_rate, frequency, q);
+
+        Self::normalize(
+            (1.0 - cos_omega0) / 2.0,
+            1.0 - cos_omega0,
+            (1.0 - cos_omega0) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos_omega0,
+            1.0 - alpha,
+        )
+    }
+
+    /// Compute the coefficients for a high-pass filter.
+    pub fn highpass(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let (cos_omega0, alpha) = Self::omega0_alpha(sample_rate, frequency, q);
+
+        Self::normalize(
+            (1.0 + cos_omega0) / 2.0,
+            -(1.0 + cos_omega0),
+            (1.0 + cos_omega0) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos_omega0,
+            1.0 - alpha,
+        )
+    }
+
+    /// Compute the coefficients for a band-pass filter with a constant 0 dB peak gain.
+    pub fn bandpass(sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let (cos_omega0, alpha) = Self::omega0_alpha(sample_rate, frequency, q);
+
+        Self::normalize(
+            alpha,
+            0.0,
+            -alpha,
+            1.0 + alpha,
+            -2.0 * cos_omega0,
+            1.0 - alpha,
+        )
+    }
+
+    /// Compute the coefficients for a peaking EQ filter that boosts or cuts the frequencies around
+    /// `frequency` by `gain_db` decibels.
+    pub fn peak(sample_rate: f32, frequency: f32, q: f32, gain_db: f32) -> Self {
+        let (cos_omega0, alpha) = Self::omega0_alpha(sample_rate, frequency, q);
+        let a = 10.0f32.powf(gain_db / 40.0);
+
+        Self::normalize(
+            1.0 + alpha * a,
+            -2.0 * cos_omega0,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos_omega0,
+            1.0 - alpha / a,
+        )
+    }
+
+    /// Compute the coefficients for a low shelf filter that boosts or cuts the frequencies below
+    /// `frequency` by `gain_db` decibels. A Q value of `1 / sqrt(2)` results in the steepest slope
+    /// without any overshoot.
+    pub fn low_shelf(sample_rate: f32, frequency: f32, q: f32, gain_db: f32) -> Self {
+        let (cos_omega0, alpha) = Self::omega0_alpha(sample_rate, frequency, q);
+        let a = 10.0f32.powf(gain_db / 40.0);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        Self::normalize(
+            a * ((a + 1.0) - (a - 1.0) * cos_omega0 + sqrt_a_alpha),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos_omega0),
+            a * ((a + 1.0) - (a - 1.0) * cos_omega0 - sqrt_a_alpha),
+            (a + 1.0) + (a - 1.0) * cos_omega0 + sqrt_a_alpha,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos_omega0),
+            (a + 1.0) + (a - 1.0) * cos_omega0 - sqrt_a_alpha,
+        )
+    }
+
+    /// Compute the coefficients for a high shelf filter that boosts or cuts the frequencies above
+    /// `frequency` by `gain_db` decibels. A Q value of `1 / sqrt(2)` results in the steepest slope
+    /// without any overshoot.
+    pub fn high_shelf(sample_rate: f32, frequency: f32, q: f32, gain_db: f32) -> Self {
+        let (cos_omega0, alpha) = Self::omega0_alpha(sample_rate, frequency, q);
+        let a = 10.0f32.powf(gain_db / 40.0);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        Self::normalize(
+            a * ((a + 1.0) + (a - 1.0) * cos_omega0 + sqrt_a_alpha),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_omega0),
+            a * ((a + 1.0) + (a - 1.0) * cos_omega0 - sqrt_a_alpha),
+            (a + 1.0) - (a - 1.0) * cos_omega0 + sqrt_a_alpha,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos_omega0),
+            (a + 1.0) - (a - 1.0) * cos_omega0 - sqrt_a_alpha,
+        )
+    }
+
+    /// The `cos(omega0)` and `alpha` intermediate values shared by all of the filter types.
+    fn omega0_alpha(sample_rate: f32, frequency: f32, q: f32) -> (f32, f32) {
+        nih_debug_assert!(sample_rate > 0.0);
+        nih_debug_assert!(frequency > 0.0);
+        nih_debug_assert!(frequency < sample_rate / 2.0);
+        nih_debug_assert!(q > 0.0);
+
+        let omega0 = consts::TAU * (frequency / sample_rate);
+        (omega0.cos(), omega0.sin() / (2.0 * q))
+    }
+
+    /// Prenormalize the coefficients with `a0`.
+    fn normalize(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The filter's magnitude response at `frequency`, computed by evaluating the transfer function
+    /// at `e^(j * omega)`.
+    fn magnitude(coefficients: &BiquadCoefficients, sample_rate: f32, frequency: f32) -> f32 {
+        let omega = consts::TAU * (frequency / sample_rate);
+        let (cos1, sin1) = (omega.cos(), -omega.sin());
+        let (cos2, sin2) = ((2.0 * omega).cos(), -(2.0 * omega).sin());
+
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } = *coefficients;
+        let num_re = b0 + b1 * cos1 + b2 * cos2;
+        let num_im = b1 * sin1 + b2 * sin2;
+        let den_re = 1.0 + a1 * cos1 + a2 * cos2;
+        let den_im = a1 * sin1 + a2 * sin2;
+
+        (num_re.hypot(num_im)) / (den_re.hypot(den_im))
+    }
+
+    #[test]
+    fn lowpass_response() {
+        let coefficients = BiquadCoefficients::lowpass(44100.0, 1000.0, consts::FRAC_1_SQRT_2);
+
+        assert!((magnitude(&coefficients, 44100.0, 0.0) - 1.0).abs() < 1e-4);
+        assert!((magnitude(&coefficients, 44100.0, 1000.0) - consts::FRAC_1_SQRT_2).abs() < 1e-3);
+        // A second order filter rolls off at 12 dB per octave
+        assert!(magnitude(&coefficients, 44100.0, 4000.0) < 0.07);
+
+        // The filter itself should settle on the same DC gain
+        let mut filter = Biquad::new(coefficients);
+        let mut samples = [1.0; 4096];
+        filter.process_block(&mut samples);
+        assert!((samples[4095] - 1.0).abs() < 1e-4);
+
+        filter.reset();
+        assert_eq!(filter.process(0.0), 0.0);
+    }
+}
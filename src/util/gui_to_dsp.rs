@@ -0,0 +1,95 @@
+//! A lock-free queue for sending messages from a plugin's editor to its audio processing code,
+//! without involving the host.
+
+use crossbeam::queue::ArrayQueue;
+use std::sync::Arc;
+
+/// The sending half of a [`gui_to_dsp_channel()`]. This is meant to be moved into the plugin's
+/// [`Editor`][crate::prelude::Editor].
+pub struct GuiSender<T> {
+    queue: Arc<ArrayQueue<T>>,
+}
+
+/// The receiving half of a [`gui_to_dsp_channel()`]. This is meant to be stored on the plugin's
+/// main struct so it can be read from [`Plugin::process()`][crate::prelude::Plugin::process()].
+pub struct DspReceiver<T> {
+    queue: Arc<ArrayQueue<T>>,
+}
+
+/// Create a bounded queue that can hold up to `capacity` messages of type `T`, for sending state
+/// that should not be exposed as a parameter from the editor to the audio thread. Think of things
+/// like a spectrum analyzer's view mode, or a sample the user has dropped onto the editor. Changing
+/// parameters should still be done through a [`ParamSetter`][crate::prelude::ParamSetter] so the
+/// host knows about the change. Unlike the editor's [`GuiContext`][crate::prelude::GuiContext],
+/// this queue is not tied to the main thread, and it works the same way for every plugin API.
+///
+/// Neither sending nor receiving will ever block or allocate. Messages are received in the order
+/// they were sent in. Keep in mind that messages that own heap data will also deallocate that data
+/// when they are dropped on the audio thread.
+///
+/// # Panics
+///
+/// Panics if `capacity == 0`.
+pub fn gui_to_dsp_channel<T: Send>(capacity: usize) -> (GuiSender<T>, DspReceiver<T>) {
+    let queue = Arc::new(ArrayQueue::new(capacity));
+
+    (
+        GuiSender {
+            queue: queue.clone(),
+        },
+        DspReceiver { queue },
+    )
+}
+
+impl<T> Clone for GuiSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T: Send> GuiSender<T> {
+    /// Send a message to the audio thread. If the queue is full, then the message is not sent and
+    /// it is returned back as an `Err` value instead.
+    pub fn send(&self, message: T) -> Result<(), T> {
+        self.queue.push(message)
+    }
+}
+
+impl<T: Send> DspReceiver<T> {
+    /// Receive the oldest message that has not yet been received, if there is one. This is
+    /// realtime-safe.
+    ///
+    /// # Usage
+    ///
+    /// You will likely want to drain the queue at the start of the process function:
+    ///
+    /// ```ignore
+    /// while let Some(message) = self.gui_receiver.recv() {
+    ///     match message {
+    ///         GuiMessage::SetViewMode(mode) => self.view_mode = mode,
+    ///     }
+    /// }
+    /// ```
+    pub fn recv(&self) -> Option<T> {
+        self.queue.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_received_in_order() {
+        let (sender, receiver) = gui_to_dsp_channel(2);
+        assert_eq!(sender.send(1), Ok(()));
+        assert_eq!(sender.clone().send(2), Ok(()));
+        assert_eq!(sender.send(3), Err(3));
+
+        assert_eq!(receiver.recv(), Some(1));
+        assert_eq!(receiver.recv(), Some(2));
+        assert_eq!(receiver.recv(), None);
+    }
+}
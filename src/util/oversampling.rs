@@ -0,0 +1,398 @@
+//! Polyphase FIR oversampling for nonlinear processing, e.g. for saturators and clippers that would
+//! otherwise produce audible aliasing.
+
+use std::f32;
+
+use super::window;
+use crate::buffer::Buffer;
+
+/// The number of filter taps used for each successive 2x oversampling stage. The first stage needs
+/// the steepest filter since it operates closest to the signal's bandwidth. The later stages only
+/// need to remove images that are already much further away from the passband. These lengths are
+/// chosen so every stage's latency is a whole number of samples at the base sample rate.
+const STAGE_NUM_TAPS: [usize; 4] = [49, 25, 17, 17];
+
+/// The highest supported oversampling factor.
+pub const MAX_OVERSAMPLING_FACTOR: usize = 1 << STAGE_NUM_TAPS.len();
+
+/// Oversamples audio by a power of two factor using a cascade of linear phase half-band filters.
+/// Call [`process_up()`][Self::process_up()] to get the upsampled version of a channel's audio,
+/// process that at the higher sample rate, and then call
+/// [`process_down()`][Self::process_down()] to decimate it back to the original sample rate. Or
+/// use [`process()`][Self::process()] to do all of this for every channel in a [`Buffer`].
+///
+/// The filters introduce [`latency()`][Self::latency()] samples of latency. Report this to the host
+/// with [`InitContext::set_latency_samples()`][crate::prelude::InitContext::set_latency_samples()]
+/// if the plugin uses oversampling.
+pub struct Oversampler {
+    /// The oversampling stages for every channel. Stage `i` converts between `2^i` and `2^(i + 1)`
+    /// times the base sample rate. Only the first `factor_log2` stages are used.
+    channel_stages: Vec<Vec<Stage>>,
+    /// Two scratch buffers per channel with room for `max_block_size * max_factor` samples. The
+    /// stages alternate between these buffers, and the upsampled audio lives in one of them
+    /// between [`process_up()`][Self::process_up()] and [`process_down()`][Self::process_down()].
+    channel_scratch_buffers: Vec<[Vec<f32>; 2]>,
+    /// The length of the last block passed to [`process_up()`][Self::process_up()] for every
+    /// channel.
+    channel_block_len: Vec<usize>,
+
+    max_block_size: usize,
+    max_factor_log2: usize,
+    /// The current oversampling factor is `2^factor_log2`.
+    factor_log2: usize,
+}
+
+/// A single 2x oversampling stage, with separate state for the upsampling and downsampling filters.
+#[derive(Clone)]
+struct Stage {
+    /// The filter's nonzero taps for the two polyphase branches of the upsampling filter, as
+    /// `(coefficient, delay)` pairs. The delay is in samples at the stage's input rate. The
+    /// coefficients have been multiplied by two to compensate for the zero stuffing.
+    up_taps: [Vec<(f32, usize)>; 2],
+    /// The filter's nonzero taps as `(coefficient, delay)` pairs for the downsampling filter. Here
+    /// the delay is in samples at the stage's higher input rate.
+    down_taps: Vec<(f32, usize)>,
+
+    /// The last `up_history_len` input samples followed by the current input block.
+    up_buffer: Vec<f32>,
+    up_history_len: usize,
+    /// The last `down_history_len` input samples followed by the current input block.
+    down_buffer: Vec<f32>,
+    down_history_len: usize,
+}
+
+impl Oversampler {
+    /// Create an oversampler for `num_channels` channels of audio, with blocks of at most
+    /// `max_block_size` samples at the base sample rate. `max_factor` is the highest oversampling
+    /// factor [`set_factor()`][Self::set_factor()] can be called with. The oversampler initially
+    /// uses `max_factor` as its oversampling factor. All memory is allocated up front.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_channels == 0 || max_block_size == 0`, or if `max_factor` is not a power of
+    /// two between 1 and [`MAX_OVERSAMPLING_FACTOR`].
+    pub fn new(num_channels: usize, max_block_size: usize, max_factor: usize) -> Self {
+        assert_ne!(num_channels, 0);
+        assert_ne!(max_block_size, 0);
+        assert!(max_factor.is_power_of_two() && max_factor <= MAX_OVERSAMPLING_FACTOR);
+
+        let max_factor_log2 = max_factor.trailing_zeros() as usize;
+        let stages: Vec<Stage> = STAGE_NUM_TAPS[..max_factor_log2]
+            .iter()
+            .enumerate()
+            .map(|(stage_idx, &num_taps)| Stage::new(num_taps, max_block_size << stage_idx))
+            .collect();
+
+        Self {
+            channel_stages: (0..num_channels).map(|_| stages.clone()).collect(),
+            channel_scratch_buffers: (0..num_channels)
+                .map(|_| {
+                    [
+                        vec![0.0; max_block_size * max_factor],
+                        vec![0.0; max_block_size * max_factor],
+                    ]
+                })
+                .collect(),
+            channel_block_len: vec![0; num_channels],
+
+            max_block_size,
+            max_factor_log2,
+            factor_log2: max_factor_log2,
+        }
+    }
+
+    /// The current oversampling factor.
+    pub fn factor(&self) -> usize {
+        1 << self.factor_log2
+    }
+
+    /// Change the oversampling factor. This also resets the filters. Changing the factor also
+    /// changes the oversampler's [latency][Self::latency()], so the plugin needs to report the new
+    /// latency to the host when it does this.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not a power of two or if it is higher than the maximum factor passed
+    /// to [`new()`][Self::new()].
+    pub fn set_factor(&mut self, factor: usize) {
+        assert!(factor.is_power_of_two());
+        let factor_log2 = factor.trailing_zeros() as usize;
+        assert!(factor_log2 <= self.max_factor_log2);
+
+        self.factor_log2 = factor_log2;
+        self.reset();
+    }
+
+    /// The latency introduced by a round trip through [`process_up()`][Self::process_up()] and
+    /// [`process_down()`][Self::process_down()] at the current oversampling factor, in samples at
+    /// the base sample rate.
+    pub fn latency(&self) -> u32 {
+        // A linear phase filter with `n` taps delays the signal by `(n - 1) / 2` samples, and both
+        // the upsampling and the downsampling filter of a stage run at the stage's higher rate
+        STAGE_NUM_TAPS[..self.factor_log2]
+            .iter()
+            .enumerate()
+            .map(|(stage_idx, num_taps)| ((num_taps - 1) >> (stage_idx + 1)) as u32)
+            .sum()
+    }
+
+    /// Clear the filters' state.
+    pub fn reset(&mut self) {
+        for stage in self.channel_stages.iter_mut().flatten() {
+            stage.reset();
+        }
+    }
+
+    /// Upsample a block of audio for a channel. The upsampled audio contains `input.len() *
+    /// self.factor()` samples. This can be processed in place, after which
+    /// [`process_down()`][Self::process_down()] should be called for this channel to decimate the
+    /// result back to the base sample rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of bounds, or if `input` contains more than `max_block_size`
+    /// samples.
+    pub fn process_up(&mut self, channel: usize, input: &[f32]) -> &mut [f32] {
+        assert!(input.len() <= self.max_block_size);
+
+        let block_len = input.len();
+        let stages = &mut self.channel_stages[channel][..self.factor_log2];
+        let [first_buffer, second_buffer] = &mut self.channel_scratch_buffers[channel];
+        self.channel_block_len[channel] = block_len;
+
+        if stages.is_empty() {
+            first_buffer[..block_len].copy_from_slice(input);
+            return &mut first_buffer[..block_len];
+        }
+
+        // Every stage doubles the length of the signal, alternating between the two buffers
+        let (mut source, mut target) = (first_buffer, second_buffer);
+        stages[0].upsample(input, &mut source[..block_len * 2]);
+        for (stage_idx, stage) in stages.iter_mut().enumerate().skip(1) {
+            let input_len = block_len << stage_idx;
+            stage.upsample(&source[..input_len], &mut target[..input_len * 2]);
+            std::mem::swap(&mut source, &mut target);
+        }
+
+        &mut source[..block_len << self.factor_log2]
+    }
+
+    /// Decimate the audio returned from the last call to [`process_up()`][Self::process_up()]
+    /// for a channel back to the base sample rate, and write the results to `output`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is out of bounds, or if `output` does not have the same length as the
+    /// block passed to `process_up()`.
+    pub fn process_down(&mut self, channel: usize, output: &mut [f32]) {
+        let block_len = self.channel_block_len[channel];
+        assert_eq!(output.len(), block_len);
+
+        let stages = &mut self.channel_stages[channel][..self.factor_log2];
+        let [first_buffer, second_buffer] = &mut self.channel_scratch_buffers[channel];
+        if stages.is_empty() {
+            output.copy_from_slice(&first_buffer[..block_len]);
+            return;
+        }
+
+        // This retraces the steps from `process_up()`, so the upsampled audio lives in the first
+        // buffer if there was an odd number of stages
+        let (mut source, mut target) = if stages.len() % 2 == 1 {
+            (first_buffer, second_buffer)
+        } else {
+            (second_buffer, first_buffer)
+        };
+        for (stage_idx, stage) in stages.iter_mut().enumerate().skip(1).rev() {
+            let output_len = block_len << stage_idx;
+            stage.downsample(&source[..output_len * 2], &mut target[..output_len]);
+            std::mem::swap(&mut source, &mut target);
+        }
+        stages[0].downsample(&source[..block_len * 2], output);
+    }
+
+    /// Oversample every channel in `buffer`, run `f` on each channel's upsampled audio, and then
+    /// write the decimated results back to the buffer. `f` receives the channel's index and the
+    /// channel's audio at the oversampled rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer has more channels than the oversampler, or if it contains more than
+    /// `max_block_size` samples.
+    pub fn process(&mut self, buffer: &mut Buffer, mut f: impl FnMut(usize, &mut [f32])) {
+        for (channel_idx, channel) in buffer.as_slice().iter_mut().enumerate() {
+            f(channel_idx, self.process_up(channel_idx, channel));
+            self.process_down(channel_idx, channel);
+        }
+    }
+}
+
+impl Stage {
+    /// Create a stage with a half-band filter with `num_taps` taps, that can upsample blocks of
+    /// up to `max_input_len` samples.
+    fn new(num_taps: usize, max_input_len: usize) -> Self {
+        let coefficients = halfband_coefficients(num_taps);
+
+        // Upsampling by zero stuffing means that every output sample only depends on every other
+        // filter tap, which is what makes this a polyphase filter
+        let mut up_taps = [Vec::new(), Vec::new()];
+        for (tap_idx, &coefficient) in coefficients.iter().enumerate() {
+            if coefficient != 0.0 {
+                up_taps[tap_idx % 2].push((coefficient * 2.0, tap_idx / 2));
+            }
+        }
+        let down_taps: Vec<(f32, usize)> = coefficients
+            .iter()
+            .enumerate()
+            .filter(|(_, coefficient)| **coefficient != 0.0)
+            .map(|(tap_idx, &coefficient)| (coefficient, tap_idx))
+            .collect();
+
+        let up_history_len = (num_taps - 1) / 2;
+        let down_history_len = num_taps - 1;
+
+        Self {
+            up_taps,
+            down_taps,
+            up_buffer: vec![0.0; up_history_len + max_input_len],
+            up_history_len,
+            down_buffer: vec![0.0; down_history_len + (max_input_len * 2)],
+            down_history_len,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.up_buffer.fill(0.0);
+        self.down_buffer.fill(0.0);
+    }
+
+    /// Upsample `input` by a factor two. `output` needs to be twice as long as `input`.
+    fn upsample(&mut self, input: &[f32], output: &mut [f32]) {
+        let history_len = self.up_history_len;
+        self.up_buffer[history_len..history_len + input.len()].copy_from_slice(input);
+
+        for (sample_idx, output_samples) in output.chunks_exact_mut(2).enumerate() {
+            // Index `history_len + sample_idx` contains the current input sample
+            let current_idx = history_len + sample_idx;
+            for (phase, output_sample) in output_samples.iter_mut().enumerate() {
+                *output_sample = self.up_taps[phase]
+                    .iter()
+                    .map(|&(coefficient, delay)| coefficient * self.up_buffer[current_idx - delay])
+                    .sum();
+            }
+        }
+
+        self.up_buffer
+            .copy_within(input.len()..input.len() + history_len, 0);
+    }
+
+    /// Decimate `input` by a factor two. `input` needs to be twice as long as `output`.
+    fn downsample(&mut self, input: &[f32], output: &mut [f32]) {
+        let history_len = self.down_history_len;
+        self.down_buffer[history_len..history_len + input.len()].copy_from_slice(input);
+
+        for (sample_idx, output_sample) in output.iter_mut().enumerate() {
+            let current_idx = history_len + (sample_idx * 2);
+            *output_sample = self
+                .down_taps
+                .iter()
+                .map(|&(coefficient, delay)| coefficient * self.down_buffer[current_idx - delay])
+                .sum();
+        }
+
+        self.down_buffer
+            .copy_within(input.len()..input.len() + history_len, 0);
+    }
+}
+
+/// Design a linear phase half-band lowpass filter with `num_taps` taps using a Blackman windowed
+/// sinc. Every other tap except for the center tap is zero, and both polyphase branches are
+/// normalized to have a DC gain of 0.5 so the upsampled signal doesn't get modulated by the
+/// sample rate.
+fn halfband_coefficients(num_taps: usize) -> Vec<f32> {
+    let mut coefficients = window::blackman(num_taps);
+    let center = (num_taps - 1) / 2;
+    for (tap_idx, coefficient) in coefficients.iter_mut().enumerate() {
+        let distance = tap_idx as isize - center as isize;
+        if tap_idx == center {
+            *coefficient *= 0.5;
+        } else if distance % 2 == 0 {
+            // The sinc function's zero crossings fall exactly on these taps
+            *coefficient = 0.0;
+        } else {
+            let x = distance as f32 * f32::consts::FRAC_PI_2;
+            *coefficient *= 0.5 * x.sin() / x;
+        }
+    }
+
+    for phase in 0..2 {
+        let phase_sum: f32 = coefficients.iter().skip(phase).step_by(2).sum();
+        for coefficient in coefficients.iter_mut().skip(phase).step_by(2) {
+            *coefficient *= 0.5 / phase_sum;
+        }
+    }
+
+    coefficients
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1 kHz sine wave at 44.1 kHz.
+    fn sine(num_samples: usize, oversampling_factor: usize) -> Vec<f32> {
+        let step = 1000.0 / (44100.0 * oversampling_factor as f32) * f32::consts::TAU;
+        (0..num_samples).map(|i| (i as f32 * step).sin()).collect()
+    }
+
+    #[test]
+    fn latency_is_whole_samples() {
+        let mut oversampler = Oversampler::new(1, 64, MAX_OVERSAMPLING_FACTOR);
+        assert_eq!(oversampler.latency(), 33);
+
+        oversampler.set_factor(2);
+        assert_eq!(oversampler.latency(), 24);
+
+        oversampler.set_factor(1);
+        assert_eq!(oversampler.latency(), 0);
+    }
+
+    #[test]
+    fn upsampled_sine_has_little_aliasing() {
+        let mut oversampler = Oversampler::new(1, 512, 2);
+        let input = sine(512, 1);
+        let upsampled = oversampler.process_up(0, &input).to_vec();
+
+        // The upsampling filter delays the signal by half of its length at the higher rate
+        let expected = sine(1024, 2);
+        let delay = (STAGE_NUM_TAPS[0] - 1) / 2;
+        for (sample_idx, sample) in upsampled.iter().enumerate().skip(delay * 4) {
+            assert!(
+                (sample - expected[sample_idx - delay]).abs() < 1e-3,
+                "{sample} at index {sample_idx}"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip_is_delayed_by_latency() {
+        for factor in [2, 4, 16] {
+            let mut oversampler = Oversampler::new(1, 128, factor);
+            let latency = oversampler.latency() as usize;
+            let input = sine(512, 1);
+
+            // This also checks that the filters' state is carried over between blocks
+            let mut output = vec![0.0; 512];
+            for (input_block, output_block) in input.chunks(128).zip(output.chunks_mut(128)) {
+                oversampler.process_up(0, input_block);
+                oversampler.process_down(0, output_block);
+            }
+
+            for (sample_idx, sample) in output.iter().enumerate().skip(latency * 2) {
+                assert!(
+                    (sample - input[sample_idx - latency]).abs() < 1e-3,
+                    "{sample} at index {sample_idx} with {factor}x oversampling"
+                );
+            }
+        }
+    }
+}
@@ -5,12 +5,12 @@ mod context;
 mod descriptor;
 mod factory;
 pub mod features;
+mod preset_discovery;
 mod wrapper;
 
 /// Re-export for the wrapper.
 pub use self::factory::Factory;
 pub use clap_sys::entry::clap_plugin_entry;
-pub use clap_sys::plugin_factory::CLAP_PLUGIN_FACTORY_ID;
 pub use clap_sys::version::CLAP_VERSION;
 pub use lazy_static::lazy_static;
 
@@ -40,18 +40,11 @@ macro_rules! nih_export_clap {
             pub extern "C" fn get_factory(
                 factory_id: *const ::std::os::raw::c_char,
             ) -> *const ::std::ffi::c_void {
-                if !factory_id.is_null()
-                    && unsafe { ::std::ffi::CStr::from_ptr(factory_id) }
-                        == unsafe {
-                            ::std::ffi::CStr::from_ptr(
-                                ::nih_plug::wrapper::clap::CLAP_PLUGIN_FACTORY_ID,
-                            )
-                        }
-                {
-                    &(*FACTORY).clap_plugin_factory as *const _ as *const ::std::ffi::c_void
-                } else {
-                    std::ptr::null()
+                if factory_id.is_null() {
+                    return std::ptr::null();
                 }
+
+                (*FACTORY).get_factory(unsafe { ::std::ffi::CStr::from_ptr(factory_id) })
             }
         }
 
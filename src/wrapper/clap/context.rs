@@ -1,12 +1,16 @@
 use atomic_refcell::AtomicRefMut;
 use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use super::wrapper::{OutputParamEvent, Wrapper};
-use crate::context::{GuiContext, InitContext, PluginApi, ProcessContext, Transport};
+use crate::context::{
+    AutomationState, GuiContext, InitContext, PluginApi, ProcessContext, Transport,
+};
 use crate::midi::NoteEvent;
 use crate::param::internals::ParamPtr;
-use crate::plugin::ClapPlugin;
+use crate::plugin::{ClapPlugin, ProcessMode};
+use crate::wrapper::util::clamp_output_event_timing;
 
 /// A [`GuiContext`] implementation for the wrapper. This is passed to the plugin in
 /// [`Editor::spawn()`][crate::prelude::Editor::spawn()] so it can interact with the rest of the plugin and
@@ -30,6 +34,8 @@ pub(crate) struct WrapperProcessContext<'a, P: ClapPlugin> {
     pub(super) input_events_guard: AtomicRefMut<'a, VecDeque<NoteEvent>>,
     pub(super) output_events_guard: AtomicRefMut<'a, VecDeque<NoteEvent>>,
     pub(super) transport: Transport,
+    /// The number of samples in the current block. Output events are clamped to this length.
+    pub(super) block_len: usize,
 }
 
 impl<P: ClapPlugin> GuiContext for WrapperGuiContext<P> {
@@ -37,13 +43,30 @@ impl<P: ClapPlugin> GuiContext for WrapperGuiContext<P> {
         PluginApi::Clap
     }
 
+    fn plugin_instance_id(&self) -> u64 {
+        self.wrapper.plugin_instance_id
+    }
+
     fn request_resize(&self) -> bool {
         self.wrapper.request_resize()
     }
 
+    fn scale_factor(&self) -> f32 {
+        self.wrapper.editor_scaling_factor.load(Ordering::Relaxed)
+    }
+
+    fn automation_state(&self) -> AutomationState {
+        // This version of the CLAP API does not report the host's automation mode
+        AutomationState::Unknown
+    }
+
     // All of these functions are supposed to be called from the main thread, so we'll put some
     // trust in the caller and assume that this is indeed the case
     unsafe fn raw_begin_set_parameter(&self, param: ParamPtr) {
+        if !self.wrapper.gesture_group.begin_param(param) {
+            return;
+        }
+
         match self.wrapper.param_ptr_to_hash.get(&param) {
             Some(hash) => {
                 let success = self
@@ -57,6 +80,11 @@ impl<P: ClapPlugin> GuiContext for WrapperGuiContext<P> {
     }
 
     unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
+        // Parameters changed during a gesture don't need an explicit begin call
+        if self.wrapper.gesture_group.is_active() {
+            self.raw_begin_set_parameter(param);
+        }
+
         match self.wrapper.param_ptr_to_hash.get(&param) {
             Some(hash) => {
                 // We queue the parameter change event here, and it will be sent to the host either
@@ -79,6 +107,11 @@ impl<P: ClapPlugin> GuiContext for WrapperGuiContext<P> {
     }
 
     unsafe fn raw_end_set_parameter(&self, param: ParamPtr) {
+        // During a gesture this is deferred until the gesture ends
+        if self.wrapper.gesture_group.is_active() {
+            return;
+        }
+
         match self.wrapper.param_ptr_to_hash.get(&param) {
             Some(hash) => {
                 let success = self
@@ -91,6 +124,34 @@ impl<P: ClapPlugin> GuiContext for WrapperGuiContext<P> {
         }
     }
 
+    fn raw_param_by_id(&self, id: &str) -> Option<ParamPtr> {
+        let hash = self.wrapper.param_id_to_hash.get(id)?;
+        self.wrapper.param_by_hash.get(hash).copied()
+    }
+
+    fn raw_begin_gesture(&self) {
+        let started = self.wrapper.gesture_group.begin();
+        nih_debug_assert!(
+            started,
+            "begin_gesture() was called while a gesture was already active"
+        );
+    }
+
+    fn raw_end_gesture(&self) {
+        // CLAP doesn't have a way to group gestures, so the best we can do is to make the gestures
+        // for all of these parameters overlap
+        match self.wrapper.gesture_group.end() {
+            Some(params) => {
+                for param in params {
+                    unsafe { self.raw_end_set_parameter(param) };
+                }
+            }
+            None => nih_debug_assert_failure!(
+                "end_gesture() was called without a matching begin_gesture()"
+            ),
+        }
+    }
+
     fn get_state(&self) -> crate::wrapper::state::PluginState {
         self.wrapper.get_state_object()
     }
@@ -115,16 +176,31 @@ impl<P: ClapPlugin> ProcessContext for WrapperProcessContext<'_, P> {
         PluginApi::Clap
     }
 
+    fn plugin_instance_id(&self) -> u64 {
+        self.wrapper.plugin_instance_id
+    }
+
     fn transport(&self) -> &Transport {
         &self.transport
     }
 
+    fn process_mode(&self) -> ProcessMode {
+        self.wrapper.current_process_mode.load()
+    }
+
     fn next_event(&mut self) -> Option<NoteEvent> {
         self.input_events_guard.pop_front()
     }
 
-    fn send_event(&mut self, event: NoteEvent) {
-        self.output_events_guard.push_back(event);
+    fn send_event(&mut self, mut event: NoteEvent) {
+        clamp_output_event_timing(&mut event, self.block_len);
+
+        // The events are kept sorted by timing, with events at the same sample keeping the order
+        // they were sent in
+        let insert_idx = self
+            .output_events_guard
+            .partition_point(|e| e.timing() <= event.timing());
+        self.output_events_guard.insert(insert_idx, event);
     }
 
     fn set_latency_samples(&self, samples: u32) {
@@ -1,12 +1,13 @@
 use clap_sys::host::clap_host;
 use clap_sys::plugin::{clap_plugin, clap_plugin_descriptor};
-use clap_sys::plugin_factory::clap_plugin_factory;
-use std::ffi::CStr;
+use clap_sys::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID};
+use std::ffi::{c_void, CStr};
 use std::os::raw::c_char;
 use std::ptr;
 use std::sync::Arc;
 
 use super::descriptor::PluginDescriptor;
+use super::preset_discovery::PresetDiscoveryFactory;
 use super::wrapper::Wrapper;
 use crate::plugin::ClapPlugin;
 
@@ -19,6 +20,8 @@ pub struct Factory<P: ClapPlugin> {
     pub clap_plugin_factory: clap_plugin_factory,
 
     plugin_descriptor: PluginDescriptor<P>,
+    /// This is only exposed to the host if the plugin has any factory presets.
+    preset_discovery_factory: PresetDiscoveryFactory<P>,
 }
 
 impl<P: ClapPlugin> Default for Factory<P> {
@@ -30,11 +33,24 @@ impl<P: ClapPlugin> Default for Factory<P> {
                 create_plugin: Self::create_plugin,
             },
             plugin_descriptor: PluginDescriptor::default(),
+            preset_discovery_factory: PresetDiscoveryFactory::default(),
         }
     }
 }
 
 impl<P: ClapPlugin> Factory<P> {
+    /// Get a pointer to the factory with the specified ID for the entry point's `get_factory()`
+    /// function. Returns a null pointer if the plugin doesn't provide that factory.
+    pub fn get_factory(&self, factory_id: &CStr) -> *const c_void {
+        if factory_id == unsafe { CStr::from_ptr(CLAP_PLUGIN_FACTORY_ID) } {
+            &self.clap_plugin_factory as *const _ as *const c_void
+        } else if self.preset_discovery_factory.matches_id(factory_id) {
+            &self.preset_discovery_factory as *const _ as *const c_void
+        } else {
+            ptr::null()
+        }
+    }
+
     unsafe extern "C" fn get_plugin_count(_factory: *const clap_plugin_factory) -> u32 {
         1
     }
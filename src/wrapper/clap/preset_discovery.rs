@@ -0,0 +1,518 @@
+//! CLAP's preset discovery factory, which lets hosts index the plugin's
+//! [factory presets][crate::prelude::Plugin::factory_presets()] so they can be shown in the host's
+//! own preset browser.
+
+// These bindings follow the CLAP headers, so they use the same naming conventions
+#![allow(non_camel_case_types)]
+
+use clap_sys::plugin::clap_plugin;
+use clap_sys::version::{clap_version, CLAP_VERSION};
+use std::ffi::{c_void, CStr, CString};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::os::raw::c_char;
+use std::ptr;
+
+use crate::plugin::ClapPlugin;
+
+// clap-sys does not contain bindings for the preset discovery factory and the final version of the
+// preset load extension yet, so these definitions mirror `factory/preset-discovery.h` and
+// `ext/preset-load.h` from the CLAP 1.2 headers.
+
+pub const CLAP_PRESET_DISCOVERY_FACTORY_ID: *const c_char =
+    c"clap.preset-discovery-factory/2".as_ptr();
+/// The ID used by hosts implementing the CLAP 1.1 draft version of the factory.
+pub const CLAP_PRESET_DISCOVERY_FACTORY_ID_COMPAT: *const c_char =
+    c"clap.preset-discovery-factory/draft-2".as_ptr();
+
+pub const CLAP_EXT_PRESET_LOAD: *const c_char = c"clap.preset-load/2".as_ptr();
+/// The ID used by hosts implementing the CLAP 1.1 draft version of the extension.
+pub const CLAP_EXT_PRESET_LOAD_COMPAT: *const c_char = c"clap.preset-load.draft/2".as_ptr();
+
+/// The presets are stored in the plugin itself. The location's path should be a null pointer.
+pub const CLAP_PRESET_DISCOVERY_LOCATION_PLUGIN: u32 = 1;
+
+pub const CLAP_PRESET_DISCOVERY_IS_FACTORY_CONTENT: u32 = 1 << 0;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct clap_universal_plugin_id {
+    pub abi: *const c_char,
+    pub id: *const c_char,
+}
+
+#[repr(C)]
+pub struct clap_preset_discovery_metadata_receiver {
+    pub receiver_data: *mut c_void,
+    pub on_error: unsafe extern "C" fn(
+        receiver: *const clap_preset_discovery_metadata_receiver,
+        os_error: i32,
+        error_message: *const c_char,
+    ),
+    pub begin_preset: unsafe extern "C" fn(
+        receiver: *const clap_preset_discovery_metadata_receiver,
+        name: *const c_char,
+        load_key: *const c_char,
+    ) -> bool,
+    pub add_plugin_id: unsafe extern "C" fn(
+        receiver: *const clap_preset_discovery_metadata_receiver,
+        plugin_id: *const clap_universal_plugin_id,
+    ),
+    pub set_soundpack_id: unsafe extern "C" fn(
+        receiver: *const clap_preset_discovery_metadata_receiver,
+        soundpack_id: *const c_char,
+    ),
+    pub set_flags:
+        unsafe extern "C" fn(receiver: *const clap_preset_discovery_metadata_receiver, flags: u32),
+    pub add_creator: unsafe extern "C" fn(
+        receiver: *const clap_preset_discovery_metadata_receiver,
+        creator: *const c_char,
+    ),
+    pub set_description: unsafe extern "C" fn(
+        receiver: *const clap_preset_discovery_metadata_receiver,
+        description: *const c_char,
+    ),
+    pub set_timestamps: unsafe extern "C" fn(
+        receiver: *const clap_preset_discovery_metadata_receiver,
+        creation_time: u64,
+        modification_time: u64,
+    ),
+    pub add_feature: unsafe extern "C" fn(
+        receiver: *const clap_preset_discovery_metadata_receiver,
+        feature: *const c_char,
+    ),
+    pub add_extra_info: unsafe extern "C" fn(
+        receiver: *const clap_preset_discovery_metadata_receiver,
+        key: *const c_char,
+        value: *const c_char,
+    ),
+}
+
+#[repr(C)]
+pub struct clap_preset_discovery_filetype {
+    pub name: *const c_char,
+    pub description: *const c_char,
+    pub file_extension: *const c_char,
+}
+
+#[repr(C)]
+pub struct clap_preset_discovery_location {
+    pub flags: u32,
+    pub name: *const c_char,
+    pub kind: u32,
+    pub location: *const c_char,
+}
+
+#[repr(C)]
+pub struct clap_preset_discovery_soundpack {
+    pub flags: u64,
+    pub id: *const c_char,
+    pub name: *const c_char,
+    pub description: *const c_char,
+    pub homepage_url: *const c_char,
+    pub vendor: *const c_char,
+    pub image_path: *const c_char,
+    pub release_timestamp: u64,
+}
+
+#[repr(C)]
+pub struct clap_preset_discovery_provider_descriptor {
+    pub clap_version: clap_version,
+    pub id: *const c_char,
+    pub name: *const c_char,
+    pub vendor: *const c_char,
+}
+
+#[repr(C)]
+pub struct clap_preset_discovery_provider {
+    pub desc: *const clap_preset_discovery_provider_descriptor,
+    pub provider_data: *mut c_void,
+    pub init: unsafe extern "C" fn(provider: *const clap_preset_discovery_provider) -> bool,
+    pub destroy: unsafe extern "C" fn(provider: *const clap_preset_discovery_provider),
+    pub get_metadata: unsafe extern "C" fn(
+        provider: *const clap_preset_discovery_provider,
+        location_kind: u32,
+        location: *const c_char,
+        metadata_receiver: *const clap_preset_discovery_metadata_receiver,
+    ) -> bool,
+    pub get_extension: unsafe extern "C" fn(
+        provider: *const clap_preset_discovery_provider,
+        extension_id: *const c_char,
+    ) -> *const c_void,
+}
+
+#[repr(C)]
+pub struct clap_preset_discovery_indexer {
+    pub clap_version: clap_version,
+    pub name: *const c_char,
+    pub vendor: *const c_char,
+    pub url: *const c_char,
+    pub version: *const c_char,
+    pub indexer_data: *mut c_void,
+    pub declare_filetype: unsafe extern "C" fn(
+        indexer: *const clap_preset_discovery_indexer,
+        filetype: *const clap_preset_discovery_filetype,
+    ) -> bool,
+    pub declare_location: unsafe extern "C" fn(
+        indexer: *const clap_preset_discovery_indexer,
+        location: *const clap_preset_discovery_location,
+    ) -> bool,
+    pub declare_soundpack: unsafe extern "C" fn(
+        indexer: *const clap_preset_discovery_indexer,
+        soundpack: *const clap_preset_discovery_soundpack,
+    ) -> bool,
+    pub get_extension: unsafe extern "C" fn(
+        indexer: *const clap_preset_discovery_indexer,
+        extension_id: *const c_char,
+    ) -> *const c_void,
+}
+
+#[repr(C)]
+pub struct clap_preset_discovery_factory {
+    pub count: unsafe extern "C" fn(factory: *const clap_preset_discovery_factory) -> u32,
+    pub get_descriptor: unsafe extern "C" fn(
+        factory: *const clap_preset_discovery_factory,
+        index: u32,
+    )
+        -> *const clap_preset_discovery_provider_descriptor,
+    pub create: unsafe extern "C" fn(
+        factory: *const clap_preset_discovery_factory,
+        indexer: *const clap_preset_discovery_indexer,
+        provider_id: *const c_char,
+    ) -> *const clap_preset_discovery_provider,
+}
+
+#[repr(C)]
+pub struct clap_plugin_preset_load {
+    pub from_location: unsafe extern "C" fn(
+        plugin: *const clap_plugin,
+        location_kind: u32,
+        location: *const c_char,
+        load_key: *const c_char,
+    ) -> bool,
+}
+
+/// The ABI name used in [`clap_universal_plugin_id`] for CLAP plugins.
+const CLAP_ABI: &CStr = c"clap";
+
+/// The load key for the factory preset at `idx`. The presets are identified by their index in
+/// [`Plugin::factory_presets()`][crate::prelude::Plugin::factory_presets()].
+pub fn preset_load_key(idx: usize) -> CString {
+    CString::new(idx.to_string()).unwrap()
+}
+
+/// Parse a load key created by [`preset_load_key()`] back to the preset's index. Returns `None` if
+/// the load key is not a valid index. This does not check whether the preset actually exists.
+pub fn parse_preset_load_key(load_key: &CStr) -> Option<usize> {
+    load_key.to_str().ok()?.parse().ok()
+}
+
+/// The plugin's preset discovery factory. This exposes a single provider with a single location
+/// containing all of the plugin's factory presets. The presets are identified by their index in
+/// the list returned by [`Plugin::factory_presets()`][crate::prelude::Plugin::factory_presets()],
+/// and they're loaded through the preset load extension in the wrapper.
+#[doc(hidden)]
+#[repr(C)]
+pub struct PresetDiscoveryFactory<P: ClapPlugin> {
+    // Keep the vtable as the first field so we can do a simple pointer cast
+    pub clap_preset_discovery_factory: clap_preset_discovery_factory,
+
+    /// Whether the plugin has any factory presets. If it doesn't, then this factory should not be
+    /// returned to the host at all.
+    has_presets: bool,
+
+    provider_id: CString,
+    provider_name: CString,
+    vendor: CString,
+    plugin_id: CString,
+    location_name: CString,
+
+    /// This references the `CString`s above, so it can only be initialized afterwards.
+    provider_descriptor: MaybeUninit<clap_preset_discovery_provider_descriptor>,
+
+    /// The plugin's type.
+    _phantom: PhantomData<P>,
+}
+
+/// A provider created by the [`PresetDiscoveryFactory`]. This is allocated on the heap and freed
+/// again in [`destroy()`][Self::destroy()].
+#[repr(C)]
+struct PresetDiscoveryProvider<P: ClapPlugin> {
+    // Keep the vtable as the first field so we can do a simple pointer cast
+    clap_preset_discovery_provider: clap_preset_discovery_provider,
+
+    /// The factory this provider was created from. This lives in a static so it outlives the
+    /// provider.
+    factory: *const PresetDiscoveryFactory<P>,
+    indexer: *const clap_preset_discovery_indexer,
+}
+
+impl<P: ClapPlugin> Default for PresetDiscoveryFactory<P> {
+    fn default() -> Self {
+        let mut factory = Self {
+            clap_preset_discovery_factory: clap_preset_discovery_factory {
+                count: Self::count,
+                get_descriptor: Self::get_descriptor,
+                create: Self::create,
+            },
+
+            has_presets: !P::factory_presets().is_empty(),
+
+            provider_id: CString::new(format!("{}.factory-presets", P::CLAP_ID))
+                .expect("`CLAP_ID` contained null bytes"),
+            provider_name: CString::new(format!("{} Factory Presets", P::NAME))
+                .expect("`NAME` contained null bytes"),
+            vendor: CString::new(P::VENDOR).expect("`VENDOR` contained null bytes"),
+            plugin_id: CString::new(P::CLAP_ID).expect("`CLAP_ID` contained null bytes"),
+            location_name: CString::new("Factory Presets").unwrap(),
+
+            provider_descriptor: MaybeUninit::uninit(),
+
+            _phantom: PhantomData,
+        };
+
+        factory
+            .provider_descriptor
+            .write(clap_preset_discovery_provider_descriptor {
+                clap_version: CLAP_VERSION,
+                id: factory.provider_id.as_ptr(),
+                name: factory.provider_name.as_ptr(),
+                vendor: factory.vendor.as_ptr(),
+            });
+
+        factory
+    }
+}
+
+unsafe impl<P: ClapPlugin> Send for PresetDiscoveryFactory<P> {}
+unsafe impl<P: ClapPlugin> Sync for PresetDiscoveryFactory<P> {}
+
+impl<P: ClapPlugin> PresetDiscoveryFactory<P> {
+    /// Whether the factory should be returned for `factory_id`. This is only the case if the plugin
+    /// has any factory presets.
+    pub fn matches_id(&self, factory_id: &CStr) -> bool {
+        self.has_presets
+            && (factory_id == unsafe { CStr::from_ptr(CLAP_PRESET_DISCOVERY_FACTORY_ID) }
+                || factory_id == unsafe { CStr::from_ptr(CLAP_PRESET_DISCOVERY_FACTORY_ID_COMPAT) })
+    }
+
+    unsafe extern "C" fn count(factory: *const clap_preset_discovery_factory) -> u32 {
+        check_null_ptr!(0, factory);
+        let factory = &*(factory as *const Self);
+
+        if factory.has_presets {
+            1
+        } else {
+            0
+        }
+    }
+
+    unsafe extern "C" fn get_descriptor(
+        factory: *const clap_preset_discovery_factory,
+        index: u32,
+    ) -> *const clap_preset_discovery_provider_descriptor {
+        check_null_ptr!(ptr::null(), factory);
+        let factory = &*(factory as *const Self);
+
+        if factory.has_presets && index == 0 {
+            factory.provider_descriptor.as_ptr()
+        } else {
+            ptr::null()
+        }
+    }
+
+    unsafe extern "C" fn create(
+        factory: *const clap_preset_discovery_factory,
+        indexer: *const clap_preset_discovery_indexer,
+        provider_id: *const c_char,
+    ) -> *const clap_preset_discovery_provider {
+        check_null_ptr!(ptr::null(), factory, indexer, provider_id);
+        let factory = &*(factory as *const Self);
+
+        if factory.has_presets && CStr::from_ptr(provider_id) == factory.provider_id.as_c_str() {
+            // This is freed again in `PresetDiscoveryProvider::destroy()`
+            let provider = Box::new(PresetDiscoveryProvider {
+                clap_preset_discovery_provider: clap_preset_discovery_provider {
+                    desc: factory.provider_descriptor.as_ptr(),
+                    provider_data: ptr::null_mut(),
+                    init: PresetDiscoveryProvider::<P>::init,
+                    destroy: PresetDiscoveryProvider::<P>::destroy,
+                    get_metadata: PresetDiscoveryProvider::<P>::get_metadata,
+                    get_extension: PresetDiscoveryProvider::<P>::get_extension,
+                },
+                factory,
+                indexer,
+            });
+
+            &Box::leak(provider).clap_preset_discovery_provider
+        } else {
+            ptr::null()
+        }
+    }
+}
+
+impl<P: ClapPlugin> PresetDiscoveryProvider<P> {
+    unsafe extern "C" fn init(provider: *const clap_preset_discovery_provider) -> bool {
+        check_null_ptr!(false, provider);
+        let provider = &*(provider as *const Self);
+        let factory = &*provider.factory;
+
+        // All presets are stored in the plugin itself, so there's only a single location
+        let location = clap_preset_discovery_location {
+            flags: CLAP_PRESET_DISCOVERY_IS_FACTORY_CONTENT,
+            name: factory.location_name.as_ptr(),
+            kind: CLAP_PRESET_DISCOVERY_LOCATION_PLUGIN,
+            location: ptr::null(),
+        };
+
+        ((*provider.indexer).declare_location)(provider.indexer, &location)
+    }
+
+    unsafe extern "C" fn destroy(provider: *const clap_preset_discovery_provider) {
+        if provider.is_null() {
+            nih_debug_assert_failure!("Null pointer passed to function");
+            return;
+        }
+
+        drop(Box::from_raw(provider as *mut Self));
+    }
+
+    unsafe extern "C" fn get_metadata(
+        provider: *const clap_preset_discovery_provider,
+        location_kind: u32,
+        _location: *const c_char,
+        metadata_receiver: *const clap_preset_discovery_metadata_receiver,
+    ) -> bool {
+        check_null_ptr!(false, provider, metadata_receiver);
+        let provider = &*(provider as *const Self);
+        let factory = &*provider.factory;
+
+        if location_kind != CLAP_PRESET_DISCOVERY_LOCATION_PLUGIN {
+            nih_debug_assert_failure!("Unknown preset location kind {}", location_kind);
+            return false;
+        }
+
+        let receiver = &*metadata_receiver;
+        let plugin_id = clap_universal_plugin_id {
+            abi: CLAP_ABI.as_ptr(),
+            id: factory.plugin_id.as_ptr(),
+        };
+        for (idx, preset) in P::factory_presets().into_iter().enumerate() {
+            let name = match CString::new(preset.name) {
+                Ok(name) => name,
+                Err(_) => {
+                    nih_debug_assert_failure!(
+                        "Factory preset {} has a name containing null bytes",
+                        idx
+                    );
+                    continue;
+                }
+            };
+            // The load key is the preset's index, see `Wrapper::ext_preset_load_from_location()`
+            let load_key = preset_load_key(idx);
+
+            if !(receiver.begin_preset)(receiver, name.as_ptr(), load_key.as_ptr()) {
+                break;
+            }
+            (receiver.add_plugin_id)(receiver, &plugin_id);
+            (receiver.set_flags)(receiver, CLAP_PRESET_DISCOVERY_IS_FACTORY_CONTENT);
+        }
+
+        true
+    }
+
+    unsafe extern "C" fn get_extension(
+        _provider: *const clap_preset_discovery_provider,
+        _extension_id: *const c_char,
+    ) -> *const c_void {
+        ptr::null()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    struct NoParams;
+
+    unsafe impl Params for NoParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            Vec::new()
+        }
+    }
+
+    /// A plugin with (`HAS_PRESETS == true`) or without factory presets.
+    #[derive(Default)]
+    struct TestPlugin<const HAS_PRESETS: bool>;
+
+    impl<const HAS_PRESETS: bool> Plugin for TestPlugin<HAS_PRESETS> {
+        const NAME: &'static str = "Test";
+        const VENDOR: &'static str = "";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.1";
+
+        fn factory_presets() -> Vec<Preset> {
+            if HAS_PRESETS {
+                vec![Preset::new("Init", "{}")]
+            } else {
+                Vec::new()
+            }
+        }
+
+        fn params(&self) -> std::sync::Arc<dyn Params> {
+            std::sync::Arc::new(NoParams)
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    impl<const HAS_PRESETS: bool> ClapPlugin for TestPlugin<HAS_PRESETS> {
+        const CLAP_ID: &'static str = "com.nih-plug.test";
+        const CLAP_DESCRIPTION: &'static str = "";
+        const CLAP_FEATURES: &'static [ClapFeature] = &[];
+        const CLAP_MANUAL_URL: &'static str = "";
+        const CLAP_SUPPORT_URL: &'static str = "";
+    }
+
+    #[test]
+    fn factory_id_matching() {
+        let factory = PresetDiscoveryFactory::<TestPlugin<true>>::default();
+        assert!(factory.matches_id(c"clap.preset-discovery-factory/2"));
+        assert!(factory.matches_id(c"clap.preset-discovery-factory/draft-2"));
+        assert!(!factory.matches_id(c"clap.preset-discovery-factory/draft-1"));
+        assert!(!factory.matches_id(c"clap.preset-load/2"));
+        assert!(!factory.matches_id(c"clap.plugin-factory"));
+    }
+
+    #[test]
+    fn factory_without_presets() {
+        let factory = PresetDiscoveryFactory::<TestPlugin<false>>::default();
+        assert!(!factory.matches_id(c"clap.preset-discovery-factory/2"));
+        assert!(!factory.matches_id(c"clap.preset-discovery-factory/draft-2"));
+    }
+
+    #[test]
+    fn load_key_round_trip() {
+        for idx in [0, 1, 42, usize::MAX] {
+            assert_eq!(parse_preset_load_key(&preset_load_key(idx)), Some(idx));
+        }
+    }
+
+    #[test]
+    fn invalid_load_keys() {
+        assert_eq!(parse_preset_load_key(c""), None);
+        assert_eq!(parse_preset_load_key(c"-1"), None);
+        assert_eq!(parse_preset_load_key(c"1.0"), None);
+        assert_eq!(parse_preset_load_key(c"Init"), None);
+        assert_eq!(parse_preset_load_key(c"\xff"), None);
+    }
+}
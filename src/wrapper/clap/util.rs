@@ -1,5 +1,24 @@
+use clap_sys::ext::log::{
+    clap_host_log, CLAP_LOG_DEBUG, CLAP_LOG_ERROR, CLAP_LOG_INFO, CLAP_LOG_WARNING,
+};
+use clap_sys::ext::note_ports::{
+    clap_note_dialect, CLAP_NOTE_DIALECT_CLAP, CLAP_NOTE_DIALECT_MIDI,
+};
+use clap_sys::ext::params::{
+    clap_param_info_flags, CLAP_PARAM_IS_AUTOMATABLE, CLAP_PARAM_IS_BYPASS, CLAP_PARAM_IS_HIDDEN,
+    CLAP_PARAM_IS_MODULATABLE, CLAP_PARAM_IS_MODULATABLE_PER_NOTE_ID, CLAP_PARAM_IS_READONLY,
+    CLAP_PARAM_IS_STEPPED,
+};
+use clap_sys::host::clap_host;
+use clap_sys::stream::{clap_istream, clap_ostream};
+use std::ffi::{c_void, CString};
+use std::io;
 use std::ops::Deref;
 
+use crate::midi::MidiConfig;
+use crate::param::ParamFlags;
+use crate::wrapper::util::logger::HostLogSink;
+
 /// Early exit out of a function with the specified return value when one of the passed pointers is
 /// null.
 macro_rules! check_null_ptr {
@@ -44,3 +63,201 @@ impl<T> ClapPtr<T> {
         Self { inner: ptr }
     }
 }
+
+/// Compute the `clap_param_info::flags` for a parameter with the specified [`ParamFlags`].
+pub fn param_info_flags(
+    flags: ParamFlags,
+    is_stepped: bool,
+    is_poly_modulatable: bool,
+) -> clap_param_info_flags {
+    let automatable = !flags.intersects(ParamFlags::NON_AUTOMATABLE | ParamFlags::READ_ONLY);
+    let hidden = flags.contains(ParamFlags::HIDDEN);
+    let read_only = flags.contains(ParamFlags::READ_ONLY);
+    let is_bypass = flags.contains(ParamFlags::BYPASS);
+
+    // TODO: Somehow expose per key/channel/port modulation
+    let mut info_flags = 0;
+    if automatable && !hidden {
+        info_flags |= CLAP_PARAM_IS_AUTOMATABLE | CLAP_PARAM_IS_MODULATABLE;
+        if is_poly_modulatable {
+            info_flags |= CLAP_PARAM_IS_MODULATABLE_PER_NOTE_ID;
+        }
+    }
+    if hidden {
+        info_flags |= CLAP_PARAM_IS_HIDDEN | CLAP_PARAM_IS_READONLY;
+    }
+    if read_only {
+        info_flags |= CLAP_PARAM_IS_READONLY;
+    }
+    if is_bypass {
+        info_flags |= CLAP_PARAM_IS_BYPASS
+    }
+    if is_stepped {
+        info_flags |= CLAP_PARAM_IS_STEPPED
+    }
+
+    info_flags
+}
+
+/// The note dialects supported by a note port for a plugin's
+/// [`MIDI_INPUT`][crate::prelude::Plugin::MIDI_INPUT] or
+/// [`MIDI_OUTPUT`][crate::prelude::Plugin::MIDI_OUTPUT] setting. Returns `None` if the plugin should
+/// not have a note port in that direction at all. CLAP note events are always supported, and raw
+/// MIDI is only supported when the plugin also wants to receive or send MIDI CCs. The preferred
+/// dialect is always [`CLAP_NOTE_DIALECT_CLAP`].
+pub fn note_port_dialects(midi_config: MidiConfig) -> Option<clap_note_dialect> {
+    // TODO: Implement MPE (would just be a toggle for the plugin to expose it) and MIDI2
+    match midi_config {
+        MidiConfig::None => None,
+        MidiConfig::Basic => Some(CLAP_NOTE_DIALECT_CLAP),
+        MidiConfig::MidiCCs => Some(CLAP_NOTE_DIALECT_CLAP | CLAP_NOTE_DIALECT_MIDI),
+    }
+}
+
+/// Sends NIH-plug's log output to the host's log extension.
+pub struct ClapHostLogSink {
+    host_callback: ClapPtr<clap_host>,
+    host_log: ClapPtr<clap_host_log>,
+}
+
+impl ClapHostLogSink {
+    pub fn new(host_callback: ClapPtr<clap_host>, host_log: ClapPtr<clap_host_log>) -> Self {
+        Self {
+            host_callback,
+            host_log,
+        }
+    }
+}
+
+impl HostLogSink for ClapHostLogSink {
+    fn log(&self, level: log::Level, message: &str) {
+        let severity = match level {
+            log::Level::Error => CLAP_LOG_ERROR,
+            log::Level::Warn => CLAP_LOG_WARNING,
+            log::Level::Info => CLAP_LOG_INFO,
+            log::Level::Debug | log::Level::Trace => CLAP_LOG_DEBUG,
+        };
+        // The message would otherwise get truncated at the first null byte
+        let message = CString::new(message.replace('\0', "")).unwrap();
+
+        // The log extension is thread safe
+        unsafe { (self.host_log.log)(&*self.host_callback, severity, message.as_ptr()) };
+    }
+}
+
+/// A [`Read`][io::Read] implementation for a host's input stream, used to stream state data.
+pub struct ClapInputStream<'a>(pub &'a clap_istream);
+
+/// A [`Write`][io::Write] implementation for a host's output stream, used to stream state data.
+pub struct ClapOutputStream<'a>(pub &'a clap_ostream);
+
+impl io::Read for ClapInputStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // A return value of 0 means the end of the stream has been reached
+        let num_bytes_read =
+            unsafe { (self.0.read)(self.0, buf.as_mut_ptr() as *mut c_void, buf.len() as u64) };
+        if num_bytes_read < 0 {
+            Err(io::Error::other("The host could not read from the stream"))
+        } else {
+            Ok(num_bytes_read as usize)
+        }
+    }
+}
+
+impl io::Write for ClapOutputStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_bytes_written =
+            unsafe { (self.0.write)(self.0, buf.as_ptr() as *const c_void, buf.len() as u64) };
+        if num_bytes_written < 0 {
+            Err(io::Error::other("The host could not write to the stream"))
+        } else {
+            Ok(num_bytes_written as usize)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_param_info_flags() {
+        let info_flags = param_info_flags(ParamFlags::READ_ONLY, false, true);
+        assert_eq!(info_flags, CLAP_PARAM_IS_READONLY);
+
+        let info_flags = param_info_flags(ParamFlags::default(), true, false);
+        assert_eq!(
+            info_flags,
+            CLAP_PARAM_IS_AUTOMATABLE | CLAP_PARAM_IS_MODULATABLE | CLAP_PARAM_IS_STEPPED
+        );
+    }
+
+    #[test]
+    fn midi_less_plugins_have_no_note_ports() {
+        assert_eq!(note_port_dialects(MidiConfig::None), None);
+        assert_eq!(
+            note_port_dialects(MidiConfig::Basic),
+            Some(CLAP_NOTE_DIALECT_CLAP)
+        );
+        assert_eq!(
+            note_port_dialects(MidiConfig::MidiCCs),
+            Some(CLAP_NOTE_DIALECT_CLAP | CLAP_NOTE_DIALECT_MIDI)
+        );
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        use std::io::{Read, Write};
+
+        /// Writes at most three bytes at a time to the `Vec<u8>` in the context pointer, to make
+        /// sure partial writes are handled.
+        unsafe extern "C" fn write(
+            stream: *const clap_ostream,
+            buffer: *const c_void,
+            size: u64,
+        ) -> i64 {
+            let data = &mut *((*stream).ctx as *mut Vec<u8>);
+            let size = size.min(3) as usize;
+            data.extend_from_slice(std::slice::from_raw_parts(buffer as *const u8, size));
+            size as i64
+        }
+
+        /// Reads from the `&[u8]` in the context pointer.
+        unsafe extern "C" fn read(
+            stream: *const clap_istream,
+            buffer: *mut c_void,
+            size: u64,
+        ) -> i64 {
+            let data = &mut *((*stream).ctx as *mut &[u8]);
+            let size = (size as usize).min(data.len());
+            std::ptr::copy_nonoverlapping(data.as_ptr(), buffer as *mut u8, size);
+            *data = &data[size..];
+            size as i64
+        }
+
+        let mut data: Vec<u8> = Vec::new();
+        let ostream = clap_ostream {
+            ctx: &mut data as *mut Vec<u8> as *mut c_void,
+            write,
+        };
+        ClapOutputStream(&ostream)
+            .write_all(b"large amounts of sample data")
+            .unwrap();
+        assert_eq!(data, b"large amounts of sample data");
+
+        let mut remaining: &[u8] = &data;
+        let istream = clap_istream {
+            ctx: &mut remaining as *mut &[u8] as *mut c_void,
+            read,
+        };
+        let mut read_data = Vec::new();
+        ClapInputStream(&istream)
+            .read_to_end(&mut read_data)
+            .unwrap();
+        assert_eq!(read_data, data);
+    }
+}
@@ -8,14 +8,15 @@ use clap_sys::events::{
     clap_event_header, clap_event_midi, clap_event_note, clap_event_note_expression,
     clap_event_param_gesture, clap_event_param_mod, clap_event_param_value, clap_event_transport,
     clap_input_events, clap_output_events, CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_IS_LIVE,
-    CLAP_EVENT_MIDI, CLAP_EVENT_NOTE_EXPRESSION, CLAP_EVENT_NOTE_OFF, CLAP_EVENT_NOTE_ON,
-    CLAP_EVENT_PARAM_GESTURE_BEGIN, CLAP_EVENT_PARAM_GESTURE_END, CLAP_EVENT_PARAM_MOD,
-    CLAP_EVENT_PARAM_VALUE, CLAP_EVENT_TRANSPORT, CLAP_NOTE_EXPRESSION_BRIGHTNESS,
-    CLAP_NOTE_EXPRESSION_EXPRESSION, CLAP_NOTE_EXPRESSION_PAN, CLAP_NOTE_EXPRESSION_PRESSURE,
-    CLAP_NOTE_EXPRESSION_TUNING, CLAP_NOTE_EXPRESSION_VIBRATO, CLAP_NOTE_EXPRESSION_VOLUME,
-    CLAP_TRANSPORT_HAS_BEATS_TIMELINE, CLAP_TRANSPORT_HAS_SECONDS_TIMELINE,
-    CLAP_TRANSPORT_HAS_TEMPO, CLAP_TRANSPORT_HAS_TIME_SIGNATURE, CLAP_TRANSPORT_IS_LOOP_ACTIVE,
-    CLAP_TRANSPORT_IS_PLAYING, CLAP_TRANSPORT_IS_RECORDING, CLAP_TRANSPORT_IS_WITHIN_PRE_ROLL,
+    CLAP_EVENT_MIDI, CLAP_EVENT_NOTE_END, CLAP_EVENT_NOTE_EXPRESSION, CLAP_EVENT_NOTE_OFF,
+    CLAP_EVENT_NOTE_ON, CLAP_EVENT_PARAM_GESTURE_BEGIN, CLAP_EVENT_PARAM_GESTURE_END,
+    CLAP_EVENT_PARAM_MOD, CLAP_EVENT_PARAM_VALUE, CLAP_EVENT_TRANSPORT,
+    CLAP_NOTE_EXPRESSION_BRIGHTNESS, CLAP_NOTE_EXPRESSION_EXPRESSION, CLAP_NOTE_EXPRESSION_PAN,
+    CLAP_NOTE_EXPRESSION_PRESSURE, CLAP_NOTE_EXPRESSION_TUNING, CLAP_NOTE_EXPRESSION_VIBRATO,
+    CLAP_NOTE_EXPRESSION_VOLUME, CLAP_TRANSPORT_HAS_BEATS_TIMELINE,
+    CLAP_TRANSPORT_HAS_SECONDS_TIMELINE, CLAP_TRANSPORT_HAS_TEMPO,
+    CLAP_TRANSPORT_HAS_TIME_SIGNATURE, CLAP_TRANSPORT_IS_LOOP_ACTIVE, CLAP_TRANSPORT_IS_PLAYING,
+    CLAP_TRANSPORT_IS_RECORDING, CLAP_TRANSPORT_IS_WITHIN_PRE_ROLL,
 };
 use clap_sys::ext::audio_ports::{
     clap_audio_port_info, clap_plugin_audio_ports, CLAP_AUDIO_PORT_IS_MAIN, CLAP_EXT_AUDIO_PORTS,
@@ -29,21 +30,19 @@ use clap_sys::ext::gui::{
     CLAP_WINDOW_API_COCOA, CLAP_WINDOW_API_WIN32, CLAP_WINDOW_API_X11,
 };
 use clap_sys::ext::latency::{clap_host_latency, clap_plugin_latency, CLAP_EXT_LATENCY};
+use clap_sys::ext::log::{clap_host_log, CLAP_EXT_LOG};
 use clap_sys::ext::note_ports::{
     clap_note_port_info, clap_plugin_note_ports, CLAP_EXT_NOTE_PORTS, CLAP_NOTE_DIALECT_CLAP,
-    CLAP_NOTE_DIALECT_MIDI,
 };
 use clap_sys::ext::params::{
     clap_host_params, clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS,
-    CLAP_PARAM_IS_AUTOMATABLE, CLAP_PARAM_IS_BYPASS, CLAP_PARAM_IS_HIDDEN,
-    CLAP_PARAM_IS_MODULATABLE, CLAP_PARAM_IS_READONLY, CLAP_PARAM_IS_STEPPED,
     CLAP_PARAM_RESCAN_VALUES,
 };
 use clap_sys::ext::render::{
     clap_plugin_render, clap_plugin_render_mode, CLAP_RENDER_OFFLINE, CLAP_RENDER_REALTIME,
 };
 use clap_sys::ext::state::{clap_plugin_state, CLAP_EXT_STATE};
-use clap_sys::ext::tail::{clap_plugin_tail, CLAP_EXT_TAIL};
+use clap_sys::ext::tail::{clap_host_tail, clap_plugin_tail, CLAP_EXT_TAIL};
 use clap_sys::ext::thread_check::{clap_host_thread_check, CLAP_EXT_THREAD_CHECK};
 use clap_sys::fixedpoint::{CLAP_BEATTIME_FACTOR, CLAP_SECTIME_FACTOR};
 use clap_sys::host::clap_host;
@@ -51,7 +50,7 @@ use clap_sys::id::{clap_id, CLAP_INVALID_ID};
 use clap_sys::plugin::clap_plugin;
 use clap_sys::process::{
     clap_process, clap_process_status, CLAP_PROCESS_CONTINUE, CLAP_PROCESS_CONTINUE_IF_NOT_QUIET,
-    CLAP_PROCESS_ERROR,
+    CLAP_PROCESS_ERROR, CLAP_PROCESS_TAIL,
 };
 use clap_sys::stream::{clap_istream, clap_ostream};
 use crossbeam::atomic::AtomicCell;
@@ -62,7 +61,7 @@ use parking_lot::RwLock;
 use raw_window_handle::RawWindowHandle;
 use std::any::Any;
 use std::cmp;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, VecDeque};
 use std::ffi::{c_void, CStr};
 use std::mem;
 use std::os::raw::c_char;
@@ -74,10 +73,19 @@ use std::time::Duration;
 
 use super::context::{WrapperGuiContext, WrapperInitContext, WrapperProcessContext};
 use super::descriptor::PluginDescriptor;
-use super::util::ClapPtr;
+use super::preset_discovery::{
+    clap_plugin_preset_load, parse_preset_load_key, CLAP_EXT_PRESET_LOAD,
+    CLAP_EXT_PRESET_LOAD_COMPAT, CLAP_PRESET_DISCOVERY_LOCATION_PLUGIN,
+};
+use super::util::{
+    note_port_dialects, param_info_flags, ClapHostLogSink, ClapInputStream, ClapOutputStream,
+    ClapPtr,
+};
 use crate::buffer::Buffer;
 use crate::context::Transport;
-use crate::event_loop::{EventLoop, MainThreadExecutor, TASK_QUEUE_CAPACITY};
+use crate::event_loop::{
+    coalesce_tasks, EventLoop, MainThreadExecutor, ShutdownMode, TaskDispatch,
+};
 use crate::midi::{MidiConfig, NoteEvent};
 use crate::param::internals::{ParamPtr, Params};
 use crate::param::ParamFlags;
@@ -85,9 +93,16 @@ use crate::plugin::{
     AuxiliaryBuffers, BufferConfig, BusConfig, ClapPlugin, Editor, ParentWindowHandle, ProcessMode,
     ProcessStatus,
 };
+use crate::preset::Preset;
 use crate::util::permit_alloc;
 use crate::wrapper::state::{self, PluginState};
-use crate::wrapper::util::{hash_param_id, process_wrapper, strlcpy};
+use crate::wrapper::util::logger::{install_host_log_sink, HostLogSinkGuard};
+use crate::wrapper::util::{
+    assert_unique_param_ids, assert_unique_poly_modulation_ids, bypass_passthrough_active,
+    find_bypass_param, hash_param_id, next_plugin_instance_id, process_wrapper,
+    set_editor_scale_factor, strlcpy, supported_bus_configs, tail_samples, task_queue_capacity,
+    GestureGroup,
+};
 
 /// How many output parameter changes we can store in our output parameter change queue. Storing
 /// more than this many parameters at a time will cause changes to get lost.
@@ -118,9 +133,14 @@ pub struct Wrapper<P: ClapPlugin> {
     /// function. Defaults to 1.0, and will be kept there on macOS. When reporting and handling size
     /// the sizes communicated to and from the DAW should be scaled by this factor since NIH-plug's
     /// APIs only deal in logical pixels.
-    editor_scaling_factor: AtomicF32,
+    pub editor_scaling_factor: AtomicF32,
 
     is_processing: AtomicBool,
+    /// Set when the plugin could not be reinitialized after restoring its state. The plugin is in
+    /// an undefined state until the host restarts it, so audio processing fails until the plugin
+    /// has been reactivated. See
+    /// [`handle_reinitialization_failure()`][Self::handle_reinitialization_failure()].
+    reinitialization_failed: AtomicBool,
     /// The current IO configuration, modified through the `clap_plugin_audio_ports_config`
     /// extension.
     current_bus_config: AtomicCell<BusConfig>,
@@ -129,6 +149,8 @@ pub struct Wrapper<P: ClapPlugin> {
     current_buffer_config: AtomicCell<Option<BufferConfig>>,
     /// The current audio processing mode. Set through the render extension. Defaults to realtime.
     pub current_process_mode: AtomicCell<ProcessMode>,
+    /// This instance's ID, returned from `plugin_instance_id()` on the contexts.
+    pub plugin_instance_id: u64,
     /// The incoming events for the plugin, if `P::MIDI_INPUT` is set to `MidiConfig::Basic` or
     /// higher.
     ///
@@ -208,19 +230,22 @@ pub struct Wrapper<P: ClapPlugin> {
     /// A mapping from parameter ID hashes (obtained from the string parameter IDs) to pointers to
     /// parameters belonging to the plugin. These addresses will remain stable as long as the
     /// `params` object does not get deallocated.
-    param_by_hash: HashMap<u32, ParamPtr>,
+    pub param_by_hash: HashMap<u32, ParamPtr>,
     /// The group name of a parameter, indexed by the parameter's hash. Nested groups are delimited
     /// by slashes, and they're only used to allow the DAW to display parameters in a tree
     /// structure.
     param_group_by_hash: HashMap<u32, String>,
     /// Mappings from string parameter indentifiers to parameter hashes. Useful for debug logging
     /// and when storing and restoring plugin state.
-    param_id_to_hash: HashMap<String, u32>,
+    pub param_id_to_hash: HashMap<String, u32>,
     /// The inverse mapping from [`param_by_hash`][Self::param_by_hash]. This is needed to be able
     /// to have an ergonomic parameter setting API that uses references to the parameters instead of
     /// having to add a setter function to the parameter (or even worse, have it be completely
     /// untyped).
     pub param_ptr_to_hash: HashMap<ParamPtr, u32>,
+    /// The plugin's bypass parameter, if it has one. Used to implement
+    /// [`Plugin::BYPASS_PASSTHROUGH`][crate::prelude::Plugin::BYPASS_PASSTHROUGH].
+    bypass_param: Option<ParamPtr>,
     /// A queue of parameter changes and gestures that should be output in either the next process
     /// call or in the next parameter flush.
     ///
@@ -228,14 +253,27 @@ pub struct Wrapper<P: ClapPlugin> {
     ///      even if it does then that should still not be a problem because the host also reads it
     ///      in the same order, right?
     output_parameter_events: ArrayQueue<OutputParamEvent>,
+    /// The parameters touched during a multi-parameter gesture started from the editor's
+    /// [`GuiContext`][crate::prelude::GuiContext].
+    pub gesture_group: GestureGroup,
 
     host_thread_check: AtomicRefCell<Option<ClapPtr<clap_host_thread_check>>>,
+    /// Keeps NIH-plug's log output routed to the host's log extension for as long as this
+    /// instance exists. This is only set if the host supports that extension.
+    host_log_sink: AtomicRefCell<Option<HostLogSinkGuard>>,
+
+    clap_plugin_preset_load: clap_plugin_preset_load,
+    /// The plugin's [factory presets][crate::prelude::Plugin::factory_presets()]. The preset
+    /// discovery factory identifies these by their index in this list. The preset load extension is
+    /// only exposed if this is not empty.
+    factory_presets: Vec<Preset>,
 
     clap_plugin_render: clap_plugin_render,
 
     clap_plugin_state: clap_plugin_state,
 
     clap_plugin_tail: clap_plugin_tail,
+    host_tail: AtomicRefCell<Option<ClapPtr<clap_host_tail>>>,
 
     /// A queue of tasks that still need to be performed. Because CLAP lets the plugin request a
     /// host callback directly, we don't need to use the OsEventLoop we use in our other plugin
@@ -253,7 +291,7 @@ pub struct Wrapper<P: ClapPlugin> {
 /// Tasks that can be sent from the plugin to be executed on the main thread in a non-blocking
 /// realtime safe way. Instead of using a random thread or the OS' event loop like in the Linux
 /// implementation, this uses [`clap_host::request_callback()`] instead.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Task {
     /// Inform the host that the latency has changed.
     LatencyChanged,
@@ -295,23 +333,33 @@ pub enum OutputParamEvent {
 /// Because CLAP has this [`clap_host::request_host_callback()`] function, we don't need to use
 /// `OsEventLoop` and can instead just request a main thread callback directly.
 impl<P: ClapPlugin> EventLoop<Task, Wrapper<P>> for Wrapper<P> {
-    fn new_and_spawn(_executor: std::sync::Weak<Self>) -> Self {
+    fn new_and_spawn(
+        _executor: std::sync::Weak<Self>,
+        _plugin_name: &str,
+        _capacity: usize,
+        _shutdown_mode: ShutdownMode,
+    ) -> Self {
         panic!("What are you doing");
     }
 
-    fn do_maybe_async(&self, task: Task) -> bool {
+    fn do_maybe_async_status(&self, task: Task) -> TaskDispatch {
         if self.is_main_thread() {
-            unsafe { self.execute(task) };
-            true
+            // The host may not have called `on_main_thread()` yet for tasks that were posted
+            // earlier, so those are executed first to keep everything in order
+            let queued_tasks = std::iter::from_fn(|| self.tasks.pop());
+            unsafe { self.execute_batch(queued_tasks.chain(std::iter::once(task))) };
+
+            TaskDispatch::ExecutedInline
         } else {
-            let success = self.tasks.push(task).is_ok();
-            if success {
-                // CLAP lets us use the host's event loop instead of having to implement our own
-                let host = &self.host_callback;
-                unsafe { (host.request_callback)(&**host) };
+            if self.tasks.push(task).is_err() {
+                return TaskDispatch::QueueFull;
             }
 
-            success
+            // CLAP lets us use the host's event loop instead of having to implement our own
+            let host = &self.host_callback;
+            unsafe { (host.request_callback)(&**host) };
+
+            TaskDispatch::Queued
         }
     }
 
@@ -353,6 +401,14 @@ impl<P: ClapPlugin> MainThreadExecutor<Task> for Wrapper<P> {
             },
         };
     }
+
+    unsafe fn execute_batch(&self, tasks: impl Iterator<Item = Task>) {
+        // All of these tasks only notify the host about something, so it's enough to do that once
+        // per batch
+        for task in coalesce_tasks(tasks) {
+            self.execute(task);
+        }
+    }
 }
 
 impl<P: ClapPlugin> Wrapper<P> {
@@ -385,19 +441,17 @@ impl<P: ClapPlugin> Wrapper<P> {
                 (id, hash, ptr, group)
             })
             .collect();
-        if cfg!(debug_assertions) {
-            let param_map = params.param_map();
-            let param_ids: HashSet<_> = param_id_hashes_ptrs_groups
+        assert_unique_param_ids(
+            param_id_hashes_ptrs_groups
                 .iter()
-                .map(|(id, _, _, _)| id.clone())
-                .collect();
-            nih_debug_assert_eq!(
-                param_map.len(),
-                param_ids.len(),
-                "The plugin has duplicate parameter IDs, weird things may happen. \
-                 Consider using 6 character parameter IDs to avoid collissions.."
-            );
-
+                .map(|(id, _, _, _)| id.as_str()),
+        );
+        assert_unique_poly_modulation_ids(
+            param_id_hashes_ptrs_groups
+                .iter()
+                .filter_map(|(_, _, ptr, _)| unsafe { ptr.poly_modulation_id() }),
+        );
+        if cfg!(debug_assertions) {
             let mut bypass_param_exists = false;
             for (_, _, ptr, _) in &param_id_hashes_ptrs_groups {
                 let flags = unsafe { ptr.flags() };
@@ -417,6 +471,8 @@ impl<P: ClapPlugin> Wrapper<P> {
             .iter()
             .map(|(_, hash, _, _)| *hash)
             .collect();
+        let bypass_param =
+            find_bypass_param(param_id_hashes_ptrs_groups.iter().map(|(_, _, ptr, _)| ptr));
         let param_by_hash = param_id_hashes_ptrs_groups
             .iter()
             .map(|(_, hash, ptr, _)| (*hash, *ptr))
@@ -434,51 +490,7 @@ impl<P: ClapPlugin> Wrapper<P> {
             .map(|(_, hash, ptr, _)| (ptr, hash))
             .collect();
 
-        // Query all sensible bus configurations supported by the plugin. We don't do surround or
-        // anything beyond stereo right now.
-        let mut supported_bus_configs = Vec::new();
-        for num_output_channels in [1, 2] {
-            for num_input_channels in [0, num_output_channels] {
-                #[allow(clippy::single_element_loop)]
-                for num_aux_channels in [num_output_channels] {
-                    let bus_config = BusConfig {
-                        num_input_channels,
-                        num_output_channels,
-                        // We won't support a variable number of busses until that's required, so
-                        // we'll always use the number of auxiliary busses specified by the plugin
-                        aux_input_busses: P::DEFAULT_AUX_INPUTS
-                            .map(|mut aux| {
-                                aux.num_channels = num_aux_channels;
-                                aux
-                            })
-                            .unwrap_or_default(),
-                        aux_output_busses: P::DEFAULT_AUX_OUTPUTS
-                            .map(|mut aux| {
-                                aux.num_channels = num_aux_channels;
-                                aux
-                            })
-                            .unwrap_or_default(),
-                    };
-                    if plugin.accepts_bus_config(&bus_config) {
-                        supported_bus_configs.push(bus_config);
-                    }
-                }
-            }
-        }
-
-        // In the off chance that the default config specified by the plugin is not in the above
-        // list, we'll try that as well.
-        let default_bus_config = BusConfig {
-            num_input_channels: P::DEFAULT_NUM_INPUTS,
-            num_output_channels: P::DEFAULT_NUM_OUTPUTS,
-            aux_input_busses: P::DEFAULT_AUX_INPUTS.unwrap_or_default(),
-            aux_output_busses: P::DEFAULT_AUX_OUTPUTS.unwrap_or_default(),
-        };
-        if !supported_bus_configs.contains(&default_bus_config)
-            && plugin.accepts_bus_config(&default_bus_config)
-        {
-            supported_bus_configs.push(default_bus_config);
-        }
+        let supported_bus_configs = supported_bus_configs(&plugin);
 
         let wrapper = Self {
             clap_plugin: clap_plugin {
@@ -511,6 +523,7 @@ impl<P: ClapPlugin> Wrapper<P> {
             editor_scaling_factor: AtomicF32::new(1.0),
 
             is_processing: AtomicBool::new(false),
+            reinitialization_failed: AtomicBool::new(false),
             current_bus_config: AtomicCell::new(BusConfig {
                 num_input_channels: P::DEFAULT_NUM_INPUTS,
                 num_output_channels: P::DEFAULT_NUM_OUTPUTS,
@@ -519,6 +532,7 @@ impl<P: ClapPlugin> Wrapper<P> {
             }),
             current_buffer_config: AtomicCell::new(None),
             current_process_mode: AtomicCell::new(ProcessMode::Realtime),
+            plugin_instance_id: next_plugin_instance_id(),
             input_events: AtomicRefCell::new(VecDeque::with_capacity(512)),
             output_events: AtomicRefCell::new(VecDeque::with_capacity(512)),
             last_process_status: AtomicCell::new(ProcessStatus::Normal),
@@ -589,15 +603,23 @@ impl<P: ClapPlugin> Wrapper<P> {
             param_group_by_hash,
             param_id_to_hash,
             param_ptr_to_hash,
+            bypass_param,
             output_parameter_events: ArrayQueue::new(OUTPUT_EVENT_QUEUE_CAPACITY),
+            gesture_group: GestureGroup::default(),
 
             host_thread_check: AtomicRefCell::new(None),
+            host_log_sink: AtomicRefCell::new(None),
 
             clap_plugin_render: clap_plugin_render {
                 has_hard_realtime_requirement: Self::ext_render_has_hard_realtime_requirement,
                 set: Self::ext_render_set,
             },
 
+            clap_plugin_preset_load: clap_plugin_preset_load {
+                from_location: Self::ext_preset_load_from_location,
+            },
+            factory_presets: P::factory_presets(),
+
             clap_plugin_state: clap_plugin_state {
                 save: Self::ext_state_save,
                 load: Self::ext_state_load,
@@ -606,8 +628,9 @@ impl<P: ClapPlugin> Wrapper<P> {
             clap_plugin_tail: clap_plugin_tail {
                 get: Self::ext_tail_get,
             },
+            host_tail: AtomicRefCell::new(None),
 
-            tasks: ArrayQueue::new(TASK_QUEUE_CAPACITY),
+            tasks: ArrayQueue::new(task_queue_capacity::<P>()),
             main_thread_id: thread::current().id(),
         };
 
@@ -627,12 +650,17 @@ impl<P: ClapPlugin> Wrapper<P> {
         WrapperInitContext { wrapper: self }
     }
 
-    fn make_process_context(&self, transport: Transport) -> WrapperProcessContext<'_, P> {
+    fn make_process_context(
+        &self,
+        transport: Transport,
+        block_len: usize,
+    ) -> WrapperProcessContext<'_, P> {
         WrapperProcessContext {
             wrapper: self,
             input_events_guard: self.input_events.borrow_mut(),
             output_events_guard: self.output_events.borrow_mut(),
             transport,
+            block_len,
         }
     }
 
@@ -656,6 +684,59 @@ impl<P: ClapPlugin> Wrapper<P> {
         result
     }
 
+    /// Whether the host is allowed to change a parameter's value. Changes the host sends for
+    /// [`ParamFlags::READ_ONLY`] parameters should be ignored.
+    fn host_can_change_param(&self, hash: u32) -> bool {
+        match self.param_by_hash.get(&hash) {
+            Some(param_ptr) => !unsafe { param_ptr.flags() }.contains(ParamFlags::READ_ONLY),
+            None => true,
+        }
+    }
+
+    /// Restore the plugin's state from a serialized JSON [`PluginState`] object, and reinitialize
+    /// the plugin so it can respond to the new parameter values. This is used when the host loads
+    /// one of the plugin's factory presets. Returns `false` if the state could not be loaded.
+    unsafe fn load_json_state(&self, json: &[u8]) -> bool {
+        match state::parse_json(json, &*self.params, P::migrate_state) {
+            Some(state) => self.load_state(&state),
+            None => false,
+        }
+    }
+
+    /// Restore the plugin's state from a parsed and migrated [`PluginState`] object, and
+    /// reinitialize the plugin so it can respond to the new parameter values. Returns `false` if
+    /// the state could not be loaded.
+    unsafe fn load_state(&self, state: &PluginState) -> bool {
+        let success = state::deserialize_object(
+            state,
+            self.params.clone(),
+            state::make_params_getter(&self.param_by_hash, &self.param_id_to_hash),
+            self.current_buffer_config.load().as_ref(),
+        );
+        if !success {
+            return false;
+        }
+
+        // Reinitialize the plugin after loading state so it can respond to the new parameter values
+        self.notify_param_values_changed();
+
+        let bus_config = self.current_bus_config.load();
+        if let Some(buffer_config) = self.current_buffer_config.load() {
+            let mut plugin = self.plugin.write();
+            if !plugin.initialize(&bus_config, &buffer_config, &mut self.make_init_context()) {
+                self.handle_reinitialization_failure();
+                return false;
+            }
+
+            // TODO: This also goes for the VST3 version, but should we call reset here? Won't the
+            //       host always restart playback? Check this with a couple of hosts and remove the
+            //       duplicate reset if it's not needed.
+            process_wrapper(|| plugin.reset());
+        }
+
+        true
+    }
+
     /// If there's an editor open, let it know that parameter values have changed. This should be
     /// called whenever there's been a call or multiple calls to
     /// [`update_plain_value_by_hash()[Self::update_plain_value_by_hash()`].
@@ -906,9 +987,24 @@ impl<P: ClapPlugin> Wrapper<P> {
         while let Some(event) = output_events.pop_front() {
             let time = event.timing() + current_sample_idx as u32;
 
+            // The host won't modulate a voice anymore after it has terminated, so its polyphonic
+            // modulation offsets are no longer needed
+            if let NoteEvent::VoiceTerminated {
+                voice_id: Some(voice_id),
+                ..
+            } = event
+            {
+                for param_ptr in self.param_by_hash.values() {
+                    if param_ptr.poly_modulation_id().is_some() {
+                        param_ptr.clear_poly_modulation(voice_id);
+                    }
+                }
+            }
+
             let push_successful = match event {
                 NoteEvent::NoteOn {
                     timing: _,
+                    voice_id,
                     channel,
                     note,
                     velocity,
@@ -922,7 +1018,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                             // We don't have a way to denote live events
                             flags: 0,
                         },
-                        note_id: -1,
+                        note_id: voice_id.unwrap_or(-1),
                         port_index: 0,
                         channel: channel as i16,
                         key: note as i16,
@@ -933,6 +1029,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                 }
                 NoteEvent::NoteOff {
                     timing: _,
+                    voice_id,
                     channel,
                     note,
                     velocity,
@@ -945,7 +1042,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                             type_: CLAP_EVENT_NOTE_OFF,
                             flags: 0,
                         },
-                        note_id: -1,
+                        note_id: voice_id.unwrap_or(-1),
                         port_index: 0,
                         channel: channel as i16,
                         key: note as i16,
@@ -956,6 +1053,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                 }
                 NoteEvent::PolyPressure {
                     timing: _,
+                    voice_id,
                     channel,
                     note,
                     pressure,
@@ -969,7 +1067,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                             flags: 0,
                         },
                         expression_id: CLAP_NOTE_EXPRESSION_PRESSURE,
-                        note_id: -1,
+                        note_id: voice_id.unwrap_or(-1),
                         port_index: 0,
                         channel: channel as i16,
                         key: note as i16,
@@ -980,6 +1078,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                 }
                 NoteEvent::PolyVolume {
                     timing: _,
+                    voice_id,
                     channel,
                     note,
                     gain,
@@ -993,7 +1092,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                             flags: 0,
                         },
                         expression_id: CLAP_NOTE_EXPRESSION_VOLUME,
-                        note_id: -1,
+                        note_id: voice_id.unwrap_or(-1),
                         port_index: 0,
                         channel: channel as i16,
                         key: note as i16,
@@ -1004,6 +1103,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                 }
                 NoteEvent::PolyPan {
                     timing: _,
+                    voice_id,
                     channel,
                     note,
                     pan,
@@ -1017,7 +1117,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                             flags: 0,
                         },
                         expression_id: CLAP_NOTE_EXPRESSION_PAN,
-                        note_id: -1,
+                        note_id: voice_id.unwrap_or(-1),
                         port_index: 0,
                         channel: channel as i16,
                         key: note as i16,
@@ -1028,6 +1128,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                 }
                 NoteEvent::PolyTuning {
                     timing: _,
+                    voice_id,
                     channel,
                     note,
                     tuning,
@@ -1041,7 +1142,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                             flags: 0,
                         },
                         expression_id: CLAP_NOTE_EXPRESSION_TUNING,
-                        note_id: -1,
+                        note_id: voice_id.unwrap_or(-1),
                         port_index: 0,
                         channel: channel as i16,
                         key: note as i16,
@@ -1052,6 +1153,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                 }
                 NoteEvent::PolyVibrato {
                     timing: _,
+                    voice_id,
                     channel,
                     note,
                     vibrato,
@@ -1065,7 +1167,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                             flags: 0,
                         },
                         expression_id: CLAP_NOTE_EXPRESSION_VIBRATO,
-                        note_id: -1,
+                        note_id: voice_id.unwrap_or(-1),
                         port_index: 0,
                         channel: channel as i16,
                         key: note as i16,
@@ -1076,6 +1178,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                 }
                 NoteEvent::PolyExpression {
                     timing: _,
+                    voice_id,
                     channel,
                     note,
                     expression,
@@ -1089,7 +1192,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                             flags: 0,
                         },
                         expression_id: CLAP_NOTE_EXPRESSION_EXPRESSION,
-                        note_id: -1,
+                        note_id: voice_id.unwrap_or(-1),
                         port_index: 0,
                         channel: channel as i16,
                         key: note as i16,
@@ -1100,6 +1203,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                 }
                 NoteEvent::PolyBrightness {
                     timing: _,
+                    voice_id,
                     channel,
                     note,
                     brightness,
@@ -1113,7 +1217,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                             flags: 0,
                         },
                         expression_id: CLAP_NOTE_EXPRESSION_BRIGHTNESS,
-                        note_id: -1,
+                        note_id: voice_id.unwrap_or(-1),
                         port_index: 0,
                         channel: channel as i16,
                         key: note as i16,
@@ -1122,6 +1226,30 @@ impl<P: ClapPlugin> Wrapper<P> {
 
                     (out.try_push)(out, &event.header)
                 }
+                // This is sent through the note input port, so it doesn't depend on `MIDI_OUTPUT`
+                NoteEvent::VoiceTerminated {
+                    timing: _,
+                    voice_id,
+                    channel,
+                    note,
+                } if P::MIDI_INPUT >= MidiConfig::Basic => {
+                    let event = clap_event_note {
+                        header: clap_event_header {
+                            size: mem::size_of::<clap_event_note>() as u32,
+                            time,
+                            space_id: CLAP_CORE_EVENT_SPACE_ID,
+                            type_: CLAP_EVENT_NOTE_END,
+                            flags: 0,
+                        },
+                        note_id: voice_id.unwrap_or(-1),
+                        port_index: 0,
+                        channel: channel as i16,
+                        key: note as i16,
+                        velocity: 0.0,
+                    };
+
+                    (out.try_push)(out, &event.header)
+                }
                 NoteEvent::MidiChannelPressure {
                     timing: _,
                     channel,
@@ -1194,6 +1322,25 @@ impl<P: ClapPlugin> Wrapper<P> {
 
                     (out.try_push)(out, &event.header)
                 }
+                NoteEvent::MidiProgramChange {
+                    timing: _,
+                    channel,
+                    program,
+                } if P::MIDI_OUTPUT >= MidiConfig::MidiCCs => {
+                    let event = clap_event_midi {
+                        header: clap_event_header {
+                            size: mem::size_of::<clap_event_midi>() as u32,
+                            time,
+                            space_id: CLAP_CORE_EVENT_SPACE_ID,
+                            type_: CLAP_EVENT_MIDI,
+                            flags: 0,
+                        },
+                        port_index: 0,
+                        data: [midi::PROGRAM_CHANGE | channel as u8, program, 0],
+                    };
+
+                    (out.try_push)(out, &event.header)
+                }
                 _ => {
                     nih_debug_assert_failure!(
                         "Invalid output event for the current MIDI_OUTPUT setting"
@@ -1231,6 +1378,10 @@ impl<P: ClapPlugin> Wrapper<P> {
         match (raw_event.space_id, raw_event.type_) {
             (CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_VALUE) => {
                 let event = &*(event as *const clap_event_param_value);
+                if !self.host_can_change_param(event.param_id) {
+                    return false;
+                }
+
                 self.update_plain_value_by_hash(
                     event.param_id,
                     ClapParamUpdate::PlainValueSet(event.value),
@@ -1241,6 +1392,44 @@ impl<P: ClapPlugin> Wrapper<P> {
             }
             (CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_MOD) => {
                 let event = &*(event as *const clap_event_param_mod);
+
+                // Modulation events for a specific note ID are polyphonic modulation events. These
+                // don't change the parameter's global value, and they are only accepted for
+                // parameters with a poly modulation ID.
+                if event.note_id != -1 {
+                    let param_ptr = match self.param_by_hash.get(&event.param_id) {
+                        Some(param_ptr) => param_ptr,
+                        None => return false,
+                    };
+
+                    match param_ptr.poly_modulation_id() {
+                        Some(poly_modulation_id) => {
+                            let normalized_offset =
+                                event.amount as f32 / param_ptr.step_count().unwrap_or(1) as f32;
+                            param_ptr.set_poly_modulation(event.note_id, normalized_offset);
+
+                            if P::MIDI_INPUT >= MidiConfig::Basic {
+                                input_events.push_back(NoteEvent::PolyModulation {
+                                    timing: raw_event.time - current_sample_idx as u32,
+                                    voice_id: event.note_id,
+                                    poly_modulation_id,
+                                    normalized_offset,
+                                });
+                            }
+                        }
+                        None => nih_debug_assert_failure!(
+                            "The host sent polyphonic modulation for a parameter without a poly \
+                             modulation ID"
+                        ),
+                    }
+
+                    return false;
+                }
+
+                if !self.host_can_change_param(event.param_id) {
+                    return false;
+                }
+
                 self.update_plain_value_by_hash(
                     event.param_id,
                     ClapParamUpdate::PlainValueMod(event.amount),
@@ -1264,6 +1453,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         // When splitting up the buffer for sample accurate automation all events
                         // should be relative to the block
                         timing: raw_event.time - current_sample_idx as u32,
+                        voice_id: (event.note_id != -1).then_some(event.note_id),
                         channel: event.channel as u8,
                         note: event.key as u8,
                         velocity: event.velocity as f32,
@@ -1277,6 +1467,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                     let event = &*(event as *const clap_event_note);
                     input_events.push_back(NoteEvent::NoteOff {
                         timing: raw_event.time - current_sample_idx as u32,
+                        voice_id: (event.note_id != -1).then_some(event.note_id),
                         channel: event.channel as u8,
                         note: event.key as u8,
                         velocity: event.velocity as f32,
@@ -1293,6 +1484,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         CLAP_NOTE_EXPRESSION_PRESSURE => {
                             input_events.push_back(NoteEvent::PolyPressure {
                                 timing: raw_event.time - current_sample_idx as u32,
+                                voice_id: (event.note_id != -1).then_some(event.note_id),
                                 channel: event.channel as u8,
                                 note: event.key as u8,
                                 pressure: event.value as f32,
@@ -1301,6 +1493,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         CLAP_NOTE_EXPRESSION_VOLUME => {
                             input_events.push_back(NoteEvent::PolyVolume {
                                 timing: raw_event.time - current_sample_idx as u32,
+                                voice_id: (event.note_id != -1).then_some(event.note_id),
                                 channel: event.channel as u8,
                                 note: event.key as u8,
                                 gain: event.value as f32,
@@ -1309,6 +1502,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         CLAP_NOTE_EXPRESSION_PAN => {
                             input_events.push_back(NoteEvent::PolyPan {
                                 timing: raw_event.time - current_sample_idx as u32,
+                                voice_id: (event.note_id != -1).then_some(event.note_id),
                                 channel: event.channel as u8,
                                 note: event.key as u8,
                                 // In CLAP this value goes from [0, 1] instead of [-1, 1]
@@ -1318,6 +1512,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         CLAP_NOTE_EXPRESSION_TUNING => {
                             input_events.push_back(NoteEvent::PolyTuning {
                                 timing: raw_event.time - current_sample_idx as u32,
+                                voice_id: (event.note_id != -1).then_some(event.note_id),
                                 channel: event.channel as u8,
                                 note: event.key as u8,
                                 tuning: event.value as f32,
@@ -1326,6 +1521,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         CLAP_NOTE_EXPRESSION_VIBRATO => {
                             input_events.push_back(NoteEvent::PolyVibrato {
                                 timing: raw_event.time - current_sample_idx as u32,
+                                voice_id: (event.note_id != -1).then_some(event.note_id),
                                 channel: event.channel as u8,
                                 note: event.key as u8,
                                 vibrato: event.value as f32,
@@ -1334,6 +1530,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         CLAP_NOTE_EXPRESSION_EXPRESSION => {
                             input_events.push_back(NoteEvent::PolyExpression {
                                 timing: raw_event.time - current_sample_idx as u32,
+                                voice_id: (event.note_id != -1).then_some(event.note_id),
                                 channel: event.channel as u8,
                                 note: event.key as u8,
                                 expression: event.value as f32,
@@ -1342,6 +1539,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         CLAP_NOTE_EXPRESSION_BRIGHTNESS => {
                             input_events.push_back(NoteEvent::PolyBrightness {
                                 timing: raw_event.time - current_sample_idx as u32,
+                                voice_id: (event.note_id != -1).then_some(event.note_id),
                                 channel: event.channel as u8,
                                 note: event.key as u8,
                                 brightness: event.value as f32,
@@ -1402,6 +1600,9 @@ impl<P: ClapPlugin> Wrapper<P> {
     /// prevent corrupting data and changing parameters during processing the actual state is only
     /// updated at the end of the audio processing cycle.
     pub fn set_state_object(&self, mut state: PluginState) {
+        // This needs to happen here so the audio thread does not need to allocate
+        state::migrate_state(&mut state, &*self.params, P::migrate_state);
+
         // Use a loop and timeouts to handle the super rare edge case when this function gets called
         // between a process call and the host disabling the plugin
         loop {
@@ -1447,8 +1648,12 @@ impl<P: ClapPlugin> Wrapper<P> {
                 let bus_config = self.current_bus_config.load();
                 if let Some(buffer_config) = self.current_buffer_config.load() {
                     let mut plugin = self.plugin.write();
-                    plugin.initialize(&bus_config, &buffer_config, &mut self.make_init_context());
-                    process_wrapper(|| plugin.reset());
+                    if plugin.initialize(&bus_config, &buffer_config, &mut self.make_init_context())
+                    {
+                        process_wrapper(|| plugin.reset());
+                    } else {
+                        self.handle_reinitialization_failure();
+                    }
                 }
 
                 break;
@@ -1460,15 +1665,34 @@ impl<P: ClapPlugin> Wrapper<P> {
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
     }
 
+    /// Called when [`Plugin::initialize()`] returns `false` while reinitializing the plugin after
+    /// restoring its state. The plugin can't process audio until it has successfully been
+    /// initialized again, so audio processing fails until then, and the host is asked to
+    /// deactivate and reactivate the plugin. Reactivating the plugin reports the failure to the
+    /// host if initialization still fails. This can be called from any thread.
+    fn handle_reinitialization_failure(&self) {
+        nih_debug_assert_failure!("The plugin failed to reinitialize after restoring its state");
+
+        self.reinitialization_failed.store(true, Ordering::SeqCst);
+        let host = &self.host_callback;
+        unsafe { (host.request_restart)(&**host) };
+    }
+
     pub fn set_latency_samples(&self, samples: u32) {
         // Only make a callback if it's actually needed
         // XXX: For CLAP we could move this handling to the Plugin struct, but it may be worthwhile
         //      to keep doing it this way to stay consistent with VST3.
         let old_latency = self.current_latency.swap(samples, Ordering::SeqCst);
-        if old_latency != samples {
-            let task_posted = self.do_maybe_async(Task::LatencyChanged);
-            nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+        if old_latency == samples {
+            return;
         }
+
+        let status = self.do_maybe_async_status(Task::LatencyChanged);
+        nih_debug_assert_ne!(
+            status,
+            TaskDispatch::QueueFull,
+            "The task queue is full, dropping task..."
+        );
     }
 
     unsafe extern "C" fn init(plugin: *const clap_plugin) -> bool {
@@ -1482,16 +1706,34 @@ impl<P: ClapPlugin> Wrapper<P> {
             query_host_extension::<clap_host_latency>(&wrapper.host_callback, CLAP_EXT_LATENCY);
         *wrapper.host_params.borrow_mut() =
             query_host_extension::<clap_host_params>(&wrapper.host_callback, CLAP_EXT_PARAMS);
+        *wrapper.host_tail.borrow_mut() =
+            query_host_extension::<clap_host_tail>(&wrapper.host_callback, CLAP_EXT_TAIL);
         *wrapper.host_thread_check.borrow_mut() = query_host_extension::<clap_host_thread_check>(
             &wrapper.host_callback,
             CLAP_EXT_THREAD_CHECK,
         );
 
+        // Any messages that were logged before this point will also be written to the host's log
+        if let Some(host_log) =
+            query_host_extension::<clap_host_log>(&wrapper.host_callback, CLAP_EXT_LOG)
+        {
+            let sink = ClapHostLogSink::new(
+                ClapPtr::new(&*wrapper.host_callback as *const clap_host),
+                host_log,
+            );
+            *wrapper.host_log_sink.borrow_mut() = Some(install_host_log_sink(Arc::new(sink)));
+        }
+
         true
     }
 
     unsafe extern "C" fn destroy(plugin: *const clap_plugin) {
-        Arc::from_raw(plugin as *mut Self);
+        let wrapper = Arc::from_raw(plugin as *mut Self);
+
+        // The host won't call `on_main_thread()` anymore, so any tasks that are still queued are
+        // either executed or dropped here
+        ShutdownMode::for_plugin::<P>()
+            .finish_tasks(Some(&*wrapper), std::iter::from_fn(|| wrapper.tasks.pop()));
     }
 
     unsafe extern "C" fn activate(
@@ -1576,6 +1818,9 @@ impl<P: ClapPlugin> Wrapper<P> {
 
             // Also store this for later, so we can reinitialize the plugin after restoring state
             wrapper.current_buffer_config.store(Some(buffer_config));
+            wrapper
+                .reinitialization_failed
+                .store(false, Ordering::SeqCst);
 
             true
         } else {
@@ -1628,6 +1873,11 @@ impl<P: ClapPlugin> Wrapper<P> {
         check_null_ptr!(CLAP_PROCESS_ERROR, plugin, process);
         let wrapper = &*(plugin as *const Self);
 
+        // The host has already been asked to restart the plugin in this case
+        if wrapper.reinitialization_failed.load(Ordering::SeqCst) {
+            return CLAP_PROCESS_ERROR;
+        }
+
         // Panic on allocations if the `assert_process_allocs` feature has been enabled, and make
         // sure that FTZ is set up correctly
         process_wrapper(|| {
@@ -1668,7 +1918,7 @@ impl<P: ClapPlugin> Wrapper<P> {
             // split the buffer.
             let mut transport_info = process.transport;
 
-            let result = loop {
+            let mut result = loop {
                 if !process.in_events.is_null() {
                     let split_result = wrapper.handle_in_events_until(
                         &*process.in_events,
@@ -1805,26 +2055,15 @@ impl<P: ClapPlugin> Wrapper<P> {
                     } else {
                         auxiliary_input_idx as isize
                     };
-                    let host_input = process.audio_inputs.offset(host_input_idx);
-                    if host_input_idx >= process.audio_inputs_count as isize
-                            || process.audio_inputs.is_null()
-                            || (*host_input).data32.is_null()
-                            // Would only happen if the user configured zero channels for the
-                            // auxiliary buffers
-                            || storage.is_empty()
-                            || (*host_input).channel_count != buffer.channels() as u32
-                    {
-                        nih_debug_assert!(host_input_idx < process.audio_inputs_count as isize);
-                        nih_debug_assert!(!process.audio_inputs.is_null());
-                        nih_debug_assert!(!(*host_input).data32.is_null());
-                        nih_debug_assert!(!storage.is_empty());
-                        nih_debug_assert_eq!((*host_input).channel_count, buffer.channels() as u32);
-
-                        // If the host passes weird data then we need to be very sure that there are
-                        // no dangling references to previous data
-                        buffer.with_raw_vec(|slices| slices.fill_with(|| &mut []));
-                        continue;
-                    }
+                    // A sidechain input the host hasn't connected (or that it passed with the wrong
+                    // channel layout) is treated as silence, so the plugin never reads stale data
+                    let host_input_connected = host_input_idx < process.audio_inputs_count as isize
+                        && !process.audio_inputs.is_null()
+                        && !(*process.audio_inputs.offset(host_input_idx))
+                            .data32
+                            .is_null()
+                        && (*process.audio_inputs.offset(host_input_idx)).channel_count
+                            == buffer.channels() as u32;
 
                     // We'll always reuse the start of the buffer even of the current block is
                     // shorter for cache locality reasons
@@ -1834,10 +2073,15 @@ impl<P: ClapPlugin> Wrapper<P> {
                         // zeroes when sizing up
                         assert!(block_len <= channel_storage.capacity());
                         channel_storage.set_len(block_len);
-                        channel_storage.copy_from_slice(std::slice::from_raw_parts(
-                            (*(*host_input).data32.add(channel_idx)).add(block_start),
-                            block_len,
-                        ));
+                        if host_input_connected {
+                            let host_input = process.audio_inputs.offset(host_input_idx);
+                            channel_storage.copy_from_slice(std::slice::from_raw_parts(
+                                (*(*host_input).data32.add(channel_idx)).add(block_start),
+                                block_len,
+                            ));
+                        } else {
+                            channel_storage.fill(0.0);
+                        }
                     }
 
                     buffer.with_raw_vec(|slices| {
@@ -1947,20 +2191,27 @@ impl<P: ClapPlugin> Wrapper<P> {
                             transport.pos_seconds = Some(seconds);
                         }
                     }
-                    // TODO: CLAP does not mention whether this is behind a flag or not
-                    if P::SAMPLE_ACCURATE_AUTOMATION && block_start > 0 {
-                        transport.bar_start_pos_beats = match transport.bar_start_pos_beats() {
-                            Some(updated) => Some(updated),
-                            None => Some(context.bar_start as f64 / CLAP_BEATTIME_FACTOR as f64),
-                        };
-                        transport.bar_number = match transport.bar_number() {
-                            Some(updated) => Some(updated),
-                            None => Some(context.bar_number),
-                        };
-                    } else {
-                        transport.bar_start_pos_beats =
-                            Some(context.bar_start as f64 / CLAP_BEATTIME_FACTOR as f64);
-                        transport.bar_number = Some(context.bar_number);
+                    // CLAP does not explicitly mention whether this is behind a flag or not, but
+                    // the bar start is expressed in beat time so it can only be meaningful when the
+                    // host also provides the beats timeline. Otherwise these fields stay `None`
+                    // instead of reporting a bogus first bar.
+                    if context.flags & CLAP_TRANSPORT_HAS_BEATS_TIMELINE != 0 {
+                        if P::SAMPLE_ACCURATE_AUTOMATION && block_start > 0 {
+                            transport.bar_start_pos_beats = match transport.bar_start_pos_beats() {
+                                Some(updated) => Some(updated),
+                                None => {
+                                    Some(context.bar_start as f64 / CLAP_BEATTIME_FACTOR as f64)
+                                }
+                            };
+                            transport.bar_number = match transport.bar_number() {
+                                Some(updated) => Some(updated),
+                                None => Some(context.bar_number),
+                            };
+                        } else {
+                            transport.bar_start_pos_beats =
+                                Some(context.bar_start as f64 / CLAP_BEATTIME_FACTOR as f64);
+                            transport.bar_number = Some(context.bar_number);
+                        }
                     }
                     // TODO: They also aren't very clear about this, but presumably if the loop is
                     //       active and the corresponding song transport information is available then
@@ -1983,7 +2234,13 @@ impl<P: ClapPlugin> Wrapper<P> {
                     }
                 }
 
-                let result = if buffer_is_valid {
+                // When the plugin opted into bypass passthrough, the main input has already been
+                // copied to the main output so there's nothing left to do
+                let bypassed = bypass_passthrough_active::<P>(
+                    wrapper.bypass_param,
+                    &wrapper.current_bus_config.load(),
+                );
+                let result = if buffer_is_valid && !bypassed {
                     let mut plugin = wrapper.plugin.write();
                     // SAFETY: Shortening these borrows is safe as even if the plugin overwrites the
                     //         slices (which it cannot do without using unsafe code), then they
@@ -1992,9 +2249,18 @@ impl<P: ClapPlugin> Wrapper<P> {
                         inputs: &mut *(aux_input_buffers.as_mut_slice() as *mut [Buffer]),
                         outputs: &mut *(aux_output_buffers.as_mut_slice() as *mut [Buffer]),
                     };
-                    let mut context = wrapper.make_process_context(transport);
+                    let mut context =
+                        wrapper.make_process_context(transport, block_end - block_start);
                     let result = plugin.process(&mut output_buffer, &mut aux, &mut context);
-                    wrapper.last_process_status.store(result);
+
+                    // The host's tail extension is allowed to be called from the audio thread
+                    let old_result = wrapper.last_process_status.swap(result);
+                    if tail_samples(old_result) != tail_samples(result) {
+                        if let Some(host_tail) = &*wrapper.host_tail.borrow() {
+                            (host_tail.changed)(&*wrapper.host_callback);
+                        }
+                    }
+
                     result
                 } else {
                     ProcessStatus::Normal
@@ -2007,7 +2273,9 @@ impl<P: ClapPlugin> Wrapper<P> {
                         return CLAP_PROCESS_ERROR;
                     }
                     ProcessStatus::Normal => CLAP_PROCESS_CONTINUE_IF_NOT_QUIET,
-                    ProcessStatus::Tail(_) => CLAP_PROCESS_CONTINUE,
+                    // The host will query the tail's length through the tail extension, and it
+                    // may suspend the plugin once the tail has been rendered
+                    ProcessStatus::Tail(_) => CLAP_PROCESS_TAIL,
                     ProcessStatus::KeepAlive => CLAP_PROCESS_CONTINUE,
                 };
 
@@ -2051,14 +2319,19 @@ impl<P: ClapPlugin> Wrapper<P> {
                 //         this could lead to inconsistencies. It's the plugin's responsibility to
                 //         not perform any realtime-unsafe work when the initialize function is
                 //         called a second time if it supports runtime preset loading.
-                permit_alloc(|| {
+                let initialized = permit_alloc(|| {
                     plugin.initialize(
                         &bus_config,
                         &buffer_config,
                         &mut wrapper.make_init_context(),
                     )
                 });
-                plugin.reset();
+                if initialized {
+                    plugin.reset();
+                } else {
+                    wrapper.handle_reinitialization_failure();
+                    result = CLAP_PROCESS_ERROR;
+                }
 
                 // We'll pass the state object back to the GUI thread so deallocation can happen
                 // there without potentially blocking the audio thread
@@ -2098,6 +2371,11 @@ impl<P: ClapPlugin> Wrapper<P> {
             &wrapper.clap_plugin_note_ports as *const _ as *const c_void
         } else if id == CStr::from_ptr(CLAP_EXT_PARAMS) {
             &wrapper.clap_plugin_params as *const _ as *const c_void
+        } else if (id == CStr::from_ptr(CLAP_EXT_PRESET_LOAD)
+            || id == CStr::from_ptr(CLAP_EXT_PRESET_LOAD_COMPAT))
+            && !wrapper.factory_presets.is_empty()
+        {
+            &wrapper.clap_plugin_preset_load as *const _ as *const c_void
         } else if id == CStr::from_ptr(CLAP_EXT_STATE) {
             &wrapper.clap_plugin_state as *const _ as *const c_void
         } else if id == CStr::from_ptr(CLAP_EXT_TAIL) {
@@ -2114,9 +2392,7 @@ impl<P: ClapPlugin> Wrapper<P> {
 
         // [Self::do_maybe_async] posts a task to the queue and asks the host to call this function
         // on the main thread, so once that's done we can just handle all requests here
-        while let Some(task) = wrapper.tasks.pop() {
-            wrapper.execute(task);
-        }
+        wrapper.execute_batch(std::iter::from_fn(|| wrapper.tasks.pop()));
     }
 
     unsafe extern "C" fn ext_audio_ports_config_count(plugin: *const clap_plugin) -> u32 {
@@ -2181,8 +2457,8 @@ impl<P: ClapPlugin> Wrapper<P> {
                 } else {
                     0
                 } + bus_config.aux_output_busses.num_busses;
-                config.has_main_input = bus_config.num_output_channels > 0;
-                config.main_input_channel_count = bus_config.num_output_channels;
+                config.has_main_input = bus_config.num_input_channels > 0;
+                config.main_input_channel_count = bus_config.num_input_channels;
                 config.main_input_port_type = input_port_type;
                 config.has_main_output = bus_config.num_output_channels > 0;
                 config.main_output_channel_count = bus_config.num_output_channels;
@@ -2211,6 +2487,8 @@ impl<P: ClapPlugin> Wrapper<P> {
         // We use the vector indices for the config ID
         match wrapper.supported_bus_configs.get(config_id as usize) {
             Some(bus_config) => {
+                // The host may only select a config while the plugin is deactivated, so the plugin
+                // will be initialized with this config the next time it gets activated
                 wrapper.current_bus_config.store(*bus_config);
 
                 true
@@ -2461,25 +2739,22 @@ impl<P: ClapPlugin> Wrapper<P> {
         check_null_ptr!(false, plugin);
         let wrapper = &*(plugin as *const Self);
 
-        // On macOS scaling is done by the OS, and all window sizes are in logical pixels
-        if cfg!(target_os = "macos") {
-            nih_debug_assert_failure!("Ignoring host request to set explicit DPI scaling factor");
+        let old_scale = wrapper.editor_scaling_factor.load(Ordering::Relaxed);
+        if !set_editor_scale_factor(
+            wrapper.editor.as_ref().unwrap().as_ref(),
+            &wrapper.editor_scaling_factor,
+            scale as f32,
+        ) {
             return false;
         }
 
-        if wrapper
-            .editor
-            .as_ref()
-            .unwrap()
-            .set_scale_factor(scale as f32)
-        {
-            wrapper
-                .editor_scaling_factor
-                .store(scale as f32, std::sync::atomic::Ordering::Relaxed);
-            true
-        } else {
-            false
+        // The window's physical size changes with the scale, so the host needs to resize the window
+        // when this happens while the editor is open
+        if scale as f32 != old_scale && wrapper.editor_handle.read().is_some() {
+            wrapper.request_resize();
         }
+
+        true
     }
 
     unsafe extern "C" fn ext_gui_get_size(
@@ -2574,13 +2849,24 @@ impl<P: ClapPlugin> Wrapper<P> {
                     return false;
                 };
 
-                // This extension is only exposed when we have an editor
-                *editor_handle = Some(wrapper.editor.as_ref().unwrap().spawn(
-                    ParentWindowHandle { handle },
-                    wrapper.clone().make_gui_context(),
-                ));
+                let parent = ParentWindowHandle { handle };
+                if parent.is_valid() {
+                    // This extension is only exposed when we have an editor
+                    *editor_handle = wrapper
+                        .editor
+                        .as_ref()
+                        .unwrap()
+                        .spawn(parent, wrapper.clone().make_gui_context());
+                    if editor_handle.is_none() {
+                        nih_log!("The editor could not be opened");
+                    }
 
-                true
+                    editor_handle.is_some()
+                } else {
+                    nih_debug_assert_failure!("Host passed a null window handle");
+
+                    false
+                }
             } else {
                 nih_debug_assert_failure!(
                     "Host tried to attach editor while the editor is already attached"
@@ -2627,11 +2913,12 @@ impl<P: ClapPlugin> Wrapper<P> {
     }
 
     unsafe extern "C" fn ext_note_ports_count(_plugin: *const clap_plugin, is_input: bool) -> u32 {
-        match is_input {
-            true if P::MIDI_INPUT >= MidiConfig::Basic => 1,
-            false if P::MIDI_OUTPUT >= MidiConfig::Basic => 1,
-            _ => 0,
-        }
+        let midi_config = if is_input {
+            P::MIDI_INPUT
+        } else {
+            P::MIDI_OUTPUT
+        };
+        note_port_dialects(midi_config).is_some() as u32
     }
 
     unsafe extern "C" fn ext_note_ports_get(
@@ -2640,33 +2927,29 @@ impl<P: ClapPlugin> Wrapper<P> {
         is_input: bool,
         info: *mut clap_note_port_info,
     ) -> bool {
-        match (index, is_input) {
-            (0, true) if P::MIDI_INPUT >= MidiConfig::Basic => {
-                *info = std::mem::zeroed();
-
-                let info = &mut *info;
-                info.id = 0;
-                // TODO: Implement MPE (would just be a toggle for the plugin to expose it) and MIDI2
-                info.supported_dialects = CLAP_NOTE_DIALECT_CLAP;
-                if P::MIDI_INPUT >= MidiConfig::MidiCCs {
-                    info.supported_dialects |= CLAP_NOTE_DIALECT_MIDI;
-                }
-                info.preferred_dialect = CLAP_NOTE_DIALECT_CLAP;
-                strlcpy(&mut info.name, "Note Input");
+        check_null_ptr!(false, info);
 
-                true
-            }
-            (0, false) if P::MIDI_OUTPUT >= MidiConfig::Basic => {
+        let midi_config = if is_input {
+            P::MIDI_INPUT
+        } else {
+            P::MIDI_OUTPUT
+        };
+        match (index, note_port_dialects(midi_config)) {
+            (0, Some(supported_dialects)) => {
                 *info = std::mem::zeroed();
 
                 let info = &mut *info;
                 info.id = 0;
-                info.supported_dialects = CLAP_NOTE_DIALECT_CLAP;
-                if P::MIDI_OUTPUT >= MidiConfig::MidiCCs {
-                    info.supported_dialects |= CLAP_NOTE_DIALECT_MIDI;
-                }
+                info.supported_dialects = supported_dialects;
                 info.preferred_dialect = CLAP_NOTE_DIALECT_CLAP;
-                strlcpy(&mut info.name, "Note Output");
+                strlcpy(
+                    &mut info.name,
+                    if is_input {
+                        "Note Input"
+                    } else {
+                        "Note Output"
+                    },
+                );
 
                 true
             }
@@ -2698,10 +2981,6 @@ impl<P: ClapPlugin> Wrapper<P> {
         let param_ptr = &wrapper.param_by_hash[param_hash];
         let default_value = param_ptr.default_normalized_value();
         let step_count = param_ptr.step_count();
-        let flags = param_ptr.flags();
-        let automatable = !flags.contains(ParamFlags::NON_AUTOMATABLE);
-        let hidden = flags.contains(ParamFlags::HIDDEN);
-        let is_bypass = flags.contains(ParamFlags::BYPASS);
 
         *param_info = std::mem::zeroed();
 
@@ -2709,20 +2988,11 @@ impl<P: ClapPlugin> Wrapper<P> {
         //       hashmap lookup, but for now we'll stay consistent with the VST3 implementation.
         let param_info = &mut *param_info;
         param_info.id = *param_hash;
-        // TODO: Somehow expose per note/channel/port modulation
-        param_info.flags = 0;
-        if automatable && !hidden {
-            param_info.flags |= CLAP_PARAM_IS_AUTOMATABLE | CLAP_PARAM_IS_MODULATABLE;
-        }
-        if hidden {
-            param_info.flags |= CLAP_PARAM_IS_HIDDEN | CLAP_PARAM_IS_READONLY;
-        }
-        if is_bypass {
-            param_info.flags |= CLAP_PARAM_IS_BYPASS
-        }
-        if step_count.is_some() {
-            param_info.flags |= CLAP_PARAM_IS_STEPPED
-        }
+        param_info.flags = param_info_flags(
+            param_ptr.flags(),
+            step_count.is_some(),
+            param_ptr.poly_modulation_id().is_some(),
+        );
         param_info.cookie = ptr::null_mut();
         strlcpy(&mut param_info.name, param_ptr.name());
         strlcpy(&mut param_info.module, param_group);
@@ -2859,6 +3129,34 @@ impl<P: ClapPlugin> Wrapper<P> {
         true
     }
 
+    unsafe extern "C" fn ext_preset_load_from_location(
+        plugin: *const clap_plugin,
+        location_kind: u32,
+        _location: *const c_char,
+        load_key: *const c_char,
+    ) -> bool {
+        check_null_ptr!(false, plugin, load_key);
+        let wrapper = &*(plugin as *const Self);
+
+        // The preset discovery factory only declares a single location containing all factory
+        // presets, and it uses the preset's index as the load key
+        if location_kind != CLAP_PRESET_DISCOVERY_LOCATION_PLUGIN {
+            nih_debug_assert_failure!("Unknown preset location kind {}", location_kind);
+            return false;
+        }
+
+        let load_key = CStr::from_ptr(load_key);
+        let preset =
+            parse_preset_load_key(load_key).and_then(|idx| wrapper.factory_presets.get(idx));
+        match preset {
+            Some(preset) => wrapper.load_json_state(&preset.state),
+            None => {
+                nih_debug_assert_failure!("Unknown factory preset load key {:?}", load_key);
+                false
+            }
+        }
+    }
+
     unsafe extern "C" fn ext_state_save(
         plugin: *const clap_plugin,
         stream: *const clap_ostream,
@@ -2888,7 +3186,16 @@ impl<P: ClapPlugin> Wrapper<P> {
 
                 nih_debug_assert_eq!(num_length_bytes_written as usize, length_bytes.len());
                 nih_debug_assert_eq!(num_bytes_written as usize, serialized.len());
-                true
+
+                // Any additional data the plugin wants to stream to the host directly is written
+                // after the JSON state
+                match wrapper.params.save_stream(&mut ClapOutputStream(&*stream)) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        nih_debug_assert_failure!("Could not save streamed state: {}", err);
+                        false
+                    }
+                }
             }
             Err(err) => {
                 nih_debug_assert_failure!("Could not save state: {}", err);
@@ -2924,45 +3231,26 @@ impl<P: ClapPlugin> Wrapper<P> {
         nih_debug_assert_eq!(num_bytes_read as u64, length);
         read_buffer.set_len(length as usize);
 
-        let success = state::deserialize_json(
-            &read_buffer,
-            wrapper.params.clone(),
-            state::make_params_getter(&wrapper.param_by_hash, &wrapper.param_id_to_hash),
-            wrapper.current_buffer_config.load().as_ref(),
-        );
-        if !success {
+        // The JSON state is parsed first so nothing gets changed if it's invalid. The streamed
+        // data then needs to be loaded before the plugin gets reinitialized as part of applying the
+        // JSON state.
+        let state = match state::parse_json(&read_buffer, &*wrapper.params, P::migrate_state) {
+            Some(state) => state,
+            None => return false,
+        };
+        if let Err(err) = wrapper.params.load_stream(&mut ClapInputStream(&*stream)) {
+            nih_debug_assert_failure!("Could not load streamed state: {}", err);
             return false;
         }
 
-        // Reinitialize the plugin after loading state so it can respond to the new parameter values
-        wrapper.notify_param_values_changed();
-
-        let bus_config = wrapper.current_bus_config.load();
-        if let Some(buffer_config) = wrapper.current_buffer_config.load() {
-            let mut plugin = wrapper.plugin.write();
-            plugin.initialize(
-                &bus_config,
-                &buffer_config,
-                &mut wrapper.make_init_context(),
-            );
-            // TODO: This also goes for the VST3 version, but should we call reset here? Won't the
-            //       host always restart playback? Check this with a couple of hosts and remove the
-            //       duplicate reset if it's not needed.
-            process_wrapper(|| plugin.reset());
-        }
-
-        true
+        wrapper.load_state(&state)
     }
 
     unsafe extern "C" fn ext_tail_get(plugin: *const clap_plugin) -> u32 {
         check_null_ptr!(0, plugin);
         let wrapper = &*(plugin as *const Self);
 
-        match wrapper.last_process_status.load() {
-            ProcessStatus::Tail(samples) => samples,
-            ProcessStatus::KeepAlive => u32::MAX,
-            _ => 0,
-        }
+        tail_samples(wrapper.last_process_status.load())
     }
 }
 
@@ -2982,3 +3270,372 @@ unsafe fn query_host_extension<T>(
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clap_sys::version::CLAP_VERSION;
+
+    use super::*;
+    use crate::prelude::{
+        AuxiliaryBuffers, AuxiliaryIOConfig, BusConfig, ClapFeature, FloatParam, FloatRange,
+        GuiContext, Param, ParamPtr, ParamSetter, Plugin, ProcessContext,
+    };
+    use crate::wrapper::state::ParamValue;
+
+    /// A host that does not support any extensions.
+    fn test_host() -> clap_host {
+        unsafe extern "C" fn get_extension(
+            _host: *const clap_host,
+            _extension_id: *const c_char,
+        ) -> *const c_void {
+            ptr::null()
+        }
+        unsafe extern "C" fn request(_host: *const clap_host) {}
+
+        clap_host {
+            clap_version: CLAP_VERSION,
+            host_data: ptr::null_mut(),
+            name: c"Test Host".as_ptr(),
+            vendor: c"".as_ptr(),
+            url: c"".as_ptr(),
+            version: c"0.0.1".as_ptr(),
+            get_extension,
+            request_restart: request,
+            request_process: request,
+            request_callback: request,
+        }
+    }
+
+    /// A host that supports the tail extension, and that counts the number of times the plugin
+    /// called `clap_host_tail::changed()` in the `AtomicU32` pointed to by `host_data`.
+    fn tail_host(tail_changed_calls: &AtomicU32) -> clap_host {
+        unsafe extern "C" fn get_extension(
+            _host: *const clap_host,
+            extension_id: *const c_char,
+        ) -> *const c_void {
+            static HOST_TAIL: clap_host_tail = clap_host_tail { changed };
+
+            if CStr::from_ptr(extension_id) == CStr::from_ptr(CLAP_EXT_TAIL) {
+                &HOST_TAIL as *const clap_host_tail as *const c_void
+            } else {
+                ptr::null()
+            }
+        }
+        unsafe extern "C" fn changed(host: *const clap_host) {
+            let tail_changed_calls = &*((*host).host_data as *const AtomicU32);
+            tail_changed_calls.fetch_add(1, Ordering::SeqCst);
+        }
+
+        clap_host {
+            host_data: tail_changed_calls as *const AtomicU32 as *mut c_void,
+            get_extension,
+            ..test_host()
+        }
+    }
+
+    /// A host that supports the latency extension, and that counts how often the plugin has called
+    /// `clap_host_latency::changed()` in `latency_changed_calls`.
+    fn latency_host(latency_changed_calls: &AtomicU32) -> clap_host {
+        unsafe extern "C" fn get_extension(
+            _host: *const clap_host,
+            extension_id: *const c_char,
+        ) -> *const c_void {
+            static HOST_LATENCY: clap_host_latency = clap_host_latency { changed };
+
+            if CStr::from_ptr(extension_id) == CStr::from_ptr(CLAP_EXT_LATENCY) {
+                &HOST_LATENCY as *const clap_host_latency as *const c_void
+            } else {
+                ptr::null()
+            }
+        }
+        unsafe extern "C" fn changed(host: *const clap_host) {
+            let latency_changed_calls = &*((*host).host_data as *const AtomicU32);
+            latency_changed_calls.fetch_add(1, Ordering::SeqCst);
+        }
+
+        clap_host {
+            host_data: latency_changed_calls as *const AtomicU32 as *mut c_void,
+            get_extension,
+            ..test_host()
+        }
+    }
+
+    struct TestParams {
+        gain: FloatParam,
+        cutoff: FloatParam,
+        attack: FloatParam,
+    }
+
+    impl Default for TestParams {
+        fn default() -> Self {
+            let range = FloatRange::Linear { min: 0.0, max: 1.0 };
+
+            Self {
+                gain: FloatParam::new("Gain", 0.5, range),
+                cutoff: FloatParam::new("Cutoff", 0.5, range),
+                attack: FloatParam::new("Attack", 0.5, range),
+            }
+        }
+    }
+
+    unsafe impl Params for TestParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![
+                (String::from("gain"), self.gain.as_ptr(), String::new()),
+                (
+                    String::from("cutoff"),
+                    self.cutoff.as_ptr(),
+                    String::from("Filter"),
+                ),
+                (
+                    String::from("attack"),
+                    self.attack.as_ptr(),
+                    String::from("Filter/Envelope"),
+                ),
+            ]
+        }
+    }
+
+    struct TestPlugin {
+        params: Arc<TestParams>,
+        /// The status returned from `process()`.
+        process_status: ProcessStatus,
+    }
+
+    impl Default for TestPlugin {
+        fn default() -> Self {
+            Self {
+                params: Arc::default(),
+                process_status: ProcessStatus::Normal,
+            }
+        }
+    }
+
+    impl Plugin for TestPlugin {
+        const NAME: &'static str = "Test";
+        const VENDOR: &'static str = "";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.1";
+
+        const DEFAULT_NUM_INPUTS: u32 = 0;
+        const DEFAULT_NUM_OUTPUTS: u32 = 0;
+
+        const AUDIO_PORT_CONFIGS: &'static [BusConfig] =
+            &[bus_config(0, 0), bus_config(0, 2), bus_config(1, 2)];
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext,
+        ) -> ProcessStatus {
+            self.process_status
+        }
+    }
+
+    const fn bus_config(num_input_channels: u32, num_output_channels: u32) -> BusConfig {
+        BusConfig {
+            num_input_channels,
+            num_output_channels,
+            aux_input_busses: AuxiliaryIOConfig {
+                num_busses: 0,
+                num_channels: 0,
+            },
+            aux_output_busses: AuxiliaryIOConfig {
+                num_busses: 0,
+                num_channels: 0,
+            },
+        }
+    }
+
+    impl ClapPlugin for TestPlugin {
+        const CLAP_ID: &'static str = "com.nih-plug.test";
+        const CLAP_DESCRIPTION: &'static str = "";
+        const CLAP_FEATURES: &'static [ClapFeature] = &[];
+        const CLAP_MANUAL_URL: &'static str = "";
+        const CLAP_SUPPORT_URL: &'static str = "";
+    }
+
+    #[test]
+    fn distinct_plugin_instance_ids() {
+        let host = test_host();
+        let first = Wrapper::<TestPlugin>::new(&host);
+        let second = Wrapper::<TestPlugin>::new(&host);
+
+        let first_id = first.clone().make_gui_context().plugin_instance_id();
+        let second_id = second.clone().make_gui_context().plugin_instance_id();
+        assert_ne!(first_id, second_id);
+
+        // The ID stays the same for the instance's entire lifetime
+        assert_eq!(first.make_gui_context().plugin_instance_id(), first_id);
+    }
+
+    #[test]
+    fn param_groups_as_modules() {
+        let host = test_host();
+        let wrapper = Wrapper::<TestPlugin>::new(&host);
+        let plugin = &wrapper.clap_plugin as *const clap_plugin;
+
+        let modules: Vec<String> = unsafe {
+            assert_eq!(Wrapper::<TestPlugin>::ext_params_count(plugin), 3);
+            (0..3)
+                .map(|param_index| {
+                    let mut param_info: clap_param_info = mem::zeroed();
+                    assert!(Wrapper::<TestPlugin>::ext_params_get_info(
+                        plugin,
+                        param_index,
+                        &mut param_info
+                    ));
+
+                    CStr::from_ptr(param_info.module.as_ptr())
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        };
+        assert_eq!(modules, ["", "Filter", "Filter/Envelope"]);
+    }
+
+    #[test]
+    fn tail_status_reaches_tail_extension() {
+        let tail_changed_calls = AtomicU32::new(0);
+        let host = tail_host(&tail_changed_calls);
+        let wrapper = Wrapper::<TestPlugin>::new(&host);
+        let plugin = &wrapper.clap_plugin as *const clap_plugin;
+
+        unsafe {
+            assert!(Wrapper::<TestPlugin>::init(plugin));
+            assert!(Wrapper::<TestPlugin>::activate(plugin, 44100.0, 1, 512));
+            assert!(Wrapper::<TestPlugin>::start_processing(plugin));
+
+            let plugin_tail = Wrapper::<TestPlugin>::get_extension(plugin, CLAP_EXT_TAIL)
+                as *const clap_plugin_tail;
+            assert!(!plugin_tail.is_null());
+            let get_tail = (*plugin_tail).get;
+
+            let mut process: clap_process = mem::zeroed();
+            process.frames_count = 64;
+            process.steady_time = -1;
+            let mut run_process = |status: ProcessStatus| {
+                wrapper.plugin.write().process_status = status;
+                let result = Wrapper::<TestPlugin>::process(plugin, &process);
+                process.steady_time += 1;
+
+                result
+            };
+
+            assert_eq!(get_tail(plugin), 0);
+            assert_eq!(run_process(ProcessStatus::Tail(48000)), CLAP_PROCESS_TAIL);
+            assert_eq!(get_tail(plugin), 48000);
+            assert_eq!(tail_changed_calls.load(Ordering::SeqCst), 1);
+
+            // The host should only be notified when the tail length actually changes
+            run_process(ProcessStatus::Tail(48000));
+            assert_eq!(tail_changed_calls.load(Ordering::SeqCst), 1);
+
+            assert_eq!(run_process(ProcessStatus::KeepAlive), CLAP_PROCESS_CONTINUE);
+            assert_eq!(get_tail(plugin), u32::MAX);
+            assert_eq!(tail_changed_calls.load(Ordering::SeqCst), 2);
+
+            assert_eq!(run_process(ProcessStatus::Tail(0)), CLAP_PROCESS_TAIL);
+            assert_eq!(get_tail(plugin), 0);
+            assert_eq!(tail_changed_calls.load(Ordering::SeqCst), 3);
+
+            Wrapper::<TestPlugin>::stop_processing(plugin);
+            Wrapper::<TestPlugin>::deactivate(plugin);
+        }
+    }
+
+    #[test]
+    fn gui_context_state_round_trip() {
+        let host = test_host();
+        let wrapper = Wrapper::<TestPlugin>::new(&host);
+        let context = wrapper.clone().make_gui_context();
+        let setter = ParamSetter::new(context.as_ref());
+        let params = wrapper.plugin.read().params.clone();
+
+        // Parameter changes made through the GUI context are only applied once the host has
+        // received them, so this changes the value the same way the host would
+        unsafe { params.gain.as_ptr().set_normalized_value(0.75) };
+        assert_eq!(setter.get_parameter_by_id("gain"), Some(0.75));
+        let state = context.get_state();
+        assert!(matches!(state.params.get("gain"), Some(ParamValue::F32(value)) if *value == 0.75));
+
+        unsafe { params.gain.as_ptr().set_normalized_value(0.0) };
+        context.set_state(state);
+        assert_eq!(setter.get_parameter_by_id("gain"), Some(0.75));
+        assert_eq!(params.gain.unmodulated_plain_value(), 0.75);
+    }
+
+    #[test]
+    fn destroy_drains_queued_tasks() {
+        let latency_changed_calls = AtomicU32::new(0);
+        let host = latency_host(&latency_changed_calls);
+        let plugin = unsafe {
+            &(*Arc::into_raw(Wrapper::<TestPlugin>::new(&host))).clap_plugin as *const clap_plugin
+        };
+
+        unsafe {
+            assert!(Wrapper::<TestPlugin>::init(plugin));
+
+            // These would normally be executed in `on_main_thread()`, but the host never got
+            // around to calling that
+            let wrapper = &*(plugin as *const Wrapper<TestPlugin>);
+            assert!(wrapper.tasks.push(Task::LatencyChanged).is_ok());
+            assert!(wrapper.tasks.push(Task::LatencyChanged).is_ok());
+
+            Wrapper::<TestPlugin>::destroy(plugin);
+        }
+
+        // The two identical tasks are coalesced into one
+        assert_eq!(latency_changed_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn audio_ports_configs() {
+        let host = test_host();
+        let wrapper = Wrapper::<TestPlugin>::new(&host);
+        let plugin = &wrapper.clap_plugin as *const clap_plugin;
+
+        // `(input_port_count, has_main_input, main_input_channel_count, output_port_count,
+        // has_main_output, main_output_channel_count)` for every config
+        let configs: Vec<_> = unsafe {
+            let audio_ports_config =
+                Wrapper::<TestPlugin>::get_extension(plugin, CLAP_EXT_AUDIO_PORTS_CONFIG)
+                    as *const clap_plugin_audio_ports_config;
+            assert!(!audio_ports_config.is_null());
+            let audio_ports_config = &*audio_ports_config;
+
+            let num_configs = (audio_ports_config.count)(plugin);
+            (0..num_configs)
+                .map(|index| {
+                    let mut config: clap_audio_ports_config = mem::zeroed();
+                    assert!((audio_ports_config.get)(plugin, index, &mut config));
+                    assert_eq!(config.id, index);
+
+                    (
+                        config.input_port_count,
+                        config.has_main_input,
+                        config.main_input_channel_count,
+                        config.output_port_count,
+                        config.has_main_output,
+                        config.main_output_channel_count,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            configs,
+            [
+                (0, false, 0, 0, false, 0),
+                (0, false, 0, 1, true, 2),
+                (1, true, 1, 1, true, 2),
+            ]
+        );
+    }
+}
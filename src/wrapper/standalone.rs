@@ -16,9 +16,10 @@ mod wrapper;
 
 /// Open an NIH-plug plugin as a standalone application. If the plugin has an editor, this will open
 /// the editor and block until the editor is closed. Otherwise this will block until SIGINT is
-/// received. This is mainly useful for quickly testing plugin GUIs. In order to use this, you will
-/// first need to make your plugin's main struct `pub` and expose a `lib` artifact in addition to
-/// your plugin's `cdylib`:
+/// received, so plugins whose [`Plugin::editor()`] returns `None` run headless and only process
+/// audio and MIDI. This is mainly useful for quickly testing plugin GUIs. In order to use this, you
+/// will first need to make your plugin's main struct `pub` and expose a `lib` artifact in addition
+/// to your plugin's `cdylib`:
 ///
 /// ```toml
 /// # Cargo.toml
@@ -75,10 +76,16 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
                 nih_log!("Using the JACK backend");
                 run_wrapper::<P, _>(backend, config)
             }
-            Err(_) => {
-                nih_log!("Could not initialize JACK, falling back to the dummy audio backend");
-                run_wrapper::<P, _>(backend::Dummy::new(config.clone()), config)
-            }
+            Err(_) => match backend::Cpal::new::<P>(config.clone()) {
+                Ok(backend) => {
+                    nih_log!("Could not initialize JACK, using the CPAL backend");
+                    run_wrapper::<P, _>(backend, config)
+                }
+                Err(err) => {
+                    nih_log!("Could not initialize JACK or CPAL ({err:#}), falling back to the dummy audio backend");
+                    run_wrapper::<P, _>(backend::Dummy::new(config.clone()), config)
+                }
+            },
         },
         config::BackendType::Jack => match backend::Jack::new::<P>(config.clone()) {
             Ok(backend) => run_wrapper::<P, _>(backend, config),
@@ -87,6 +94,13 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
                 false
             }
         },
+        config::BackendType::Cpal => match backend::Cpal::new::<P>(config.clone()) {
+            Ok(backend) => run_wrapper::<P, _>(backend, config),
+            Err(err) => {
+                nih_error!("Could not initialize the CPAL backend: {:#}", err);
+                false
+            }
+        },
         config::BackendType::Dummmy => {
             run_wrapper::<P, _>(backend::Dummy::new(config.clone()), config)
         }
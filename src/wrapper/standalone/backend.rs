@@ -1,9 +1,11 @@
+pub use self::cpal::Cpal;
 pub use self::dummy::Dummy;
 pub use self::jack::Jack;
 pub use crate::buffer::Buffer;
 use crate::context::Transport;
 use crate::midi::NoteEvent;
 
+mod cpal;
 mod dummy;
 mod jack;
 
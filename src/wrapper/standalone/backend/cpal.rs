@@ -0,0 +1,470 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{
+    BufferSize, Device, Sample, SampleFormat, Stream, StreamConfig, SupportedBufferSize,
+    SupportedStreamConfigRange,
+};
+use crossbeam::channel;
+use crossbeam::queue::ArrayQueue;
+use midir::{MidiInput, MidiInputConnection};
+
+use super::super::config::WrapperConfig;
+use super::Backend;
+use crate::buffer::Buffer;
+use crate::context::Transport;
+use crate::midi::{MidiConfig, NoteEvent};
+use crate::plugin::Plugin;
+
+/// Uses CPAL for audio, and midir for MIDI input. This uses the platform's default audio API, so
+/// it works everywhere JACK isn't available.
+pub struct Cpal {
+    config: WrapperConfig,
+    /// The name used for the MIDI client.
+    client_name: String,
+    /// Whether the plugin wants to receive MIDI. If this is `false`, then the `--midi-input` option
+    /// is ignored.
+    midi_input_enabled: bool,
+
+    /// The input device, its configuration, and the sample format used for that configuration.
+    /// This is `None` if the plugin does not have any inputs, or if no input device is available.
+    input: Option<(Device, StreamConfig, SampleFormat)>,
+    output_device: Device,
+    output_config: StreamConfig,
+    output_sample_format: SampleFormat,
+}
+
+/// A simple message to tell the audio thread to shut down, since the actual processing happens in
+/// CPAL's callbacks.
+enum Task {
+    Shutdown,
+}
+
+impl Backend for Cpal {
+    fn run(
+        &mut self,
+        cb: impl FnMut(&mut Buffer, Transport, &[NoteEvent], &mut Vec<NoteEvent>) -> bool
+            + 'static
+            + Send,
+    ) {
+        // The input stream writes interleaved samples to this queue, and the output stream reads
+        // them back before calling the process callback. The queue can hold a couple periods worth
+        // of audio to account for the two streams not running in lockstep.
+        let input_queue = self.input.as_ref().map(|(_, input_config, _)| {
+            Arc::new(ArrayQueue::new(
+                self.config.period_size as usize * input_config.channels as usize * 4,
+            ))
+        });
+        let input_stream = match (&self.input, &input_queue) {
+            (Some((input_device, input_config, input_sample_format)), Some(input_queue)) => {
+                let input_stream = match input_sample_format {
+                    SampleFormat::I16 => {
+                        build_input_stream::<i16>(input_device, input_config, input_queue.clone())
+                    }
+                    SampleFormat::U16 => {
+                        build_input_stream::<u16>(input_device, input_config, input_queue.clone())
+                    }
+                    SampleFormat::F32 => {
+                        build_input_stream::<f32>(input_device, input_config, input_queue.clone())
+                    }
+                };
+
+                match input_stream {
+                    Ok(stream) => Some(stream),
+                    Err(err) => {
+                        nih_error!("Could not open the audio input stream, continuing without audio input: {err:#}");
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        // Incoming MIDI events get sent to the output stream's callback through this queue. midir
+        // does not give us sample accurate timings, so all events are placed at the start of the
+        // next block.
+        let midi_input_queue = Arc::new(ArrayQueue::new(2048));
+        let _midi_input_connection = match &self.config.midi_input {
+            Some(port_name) if self.midi_input_enabled => {
+                match connect_midi_input(&self.client_name, port_name, midi_input_queue.clone()) {
+                    Ok(connection) => Some(connection),
+                    Err(err) => {
+                        nih_error!("Could not connect to MIDI input '{port_name}': {err:#}");
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let (control_sender, control_receiver) = channel::bounded(32);
+        let output_stream = match self.output_sample_format {
+            SampleFormat::I16 => {
+                self.build_output_stream::<i16>(cb, input_queue, midi_input_queue, control_sender)
+            }
+            SampleFormat::U16 => {
+                self.build_output_stream::<u16>(cb, input_queue, midi_input_queue, control_sender)
+            }
+            SampleFormat::F32 => {
+                self.build_output_stream::<f32>(cb, input_queue, midi_input_queue, control_sender)
+            }
+        };
+        let output_stream = match output_stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                nih_error!("Could not open the audio output stream: {err:#}");
+                return;
+            }
+        };
+
+        if let Some(input_stream) = &input_stream {
+            if let Err(err) = input_stream.play() {
+                nih_error!("Could not start the audio input stream: {err}");
+            }
+        }
+        if let Err(err) = output_stream.play() {
+            nih_error!("Could not start the audio output stream: {err}");
+            return;
+        }
+
+        // The processing happens in the output stream's callback, so we need to block this thread
+        // until we get the request to shut down
+        #[allow(clippy::never_loop)]
+        loop {
+            match control_receiver.recv() {
+                Ok(Task::Shutdown) => break,
+                Err(err) => {
+                    nih_debug_assert_failure!("Error reading from channel: {}", err);
+                    break;
+                }
+            }
+        }
+
+        // Dropping the streams stops them
+        drop(output_stream);
+        drop(input_stream);
+    }
+}
+
+impl Cpal {
+    /// Initialize the CPAL backend using the system's default audio API. Returns an error if this
+    /// failed for whatever reason. The plugin generic argument is to get the name for the MIDI
+    /// client, and to know whether or not the standalone should receive MIDI.
+    pub fn new<P: Plugin>(config: WrapperConfig) -> Result<Self> {
+        let host = cpal::default_host();
+        if config.output_channels == 0 {
+            anyhow::bail!("The CPAL backend needs at least one output channel");
+        }
+
+        let output_device = match &config.output_device {
+            Some(name) => host
+                .output_devices()
+                .context("Could not list the audio output devices")?
+                .find(|device| device.name().map(|n| &n == name).unwrap_or(false))
+                .with_context(|| format!("Unknown audio output device '{name}'"))?,
+            None => host
+                .default_output_device()
+                .context("No default audio output device available")?,
+        };
+        let output_config_range = find_config_range(
+            output_device
+                .supported_output_configs()
+                .context("Could not query the audio output device's configurations")?,
+            &config,
+            config.output_channels,
+        )
+        .with_context(|| {
+            format!(
+                "The audio output device does not support {} channels at {} Hz",
+                config.output_channels, config.sample_rate
+            )
+        })?;
+        let (output_config, output_sample_format) = stream_config(output_config_range, &config);
+
+        // Not having an input device is not fatal, the plugin will simply receive silence
+        let input = if config.input_channels > 0 {
+            let input_device = match &config.input_device {
+                Some(name) => host
+                    .input_devices()
+                    .context("Could not list the audio input devices")?
+                    .find(|device| device.name().map(|n| &n == name).unwrap_or(false))
+                    .with_context(|| format!("Unknown audio input device '{name}'"))
+                    .map(Some)?,
+                None => host.default_input_device(),
+            };
+
+            input_device.and_then(|input_device| {
+                let input_config_range = input_device
+                    .supported_input_configs()
+                    .ok()
+                    .and_then(|configs| find_config_range(configs, &config, config.input_channels));
+                match input_config_range {
+                    Some(input_config_range) => {
+                        let (input_config, input_sample_format) =
+                            stream_config(input_config_range, &config);
+                        Some((input_device, input_config, input_sample_format))
+                    }
+                    None => {
+                        nih_error!(
+                            "The audio input device does not support {} channels at {} Hz, \
+                             continuing without audio input",
+                            config.input_channels,
+                            config.sample_rate
+                        );
+                        None
+                    }
+                }
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            client_name: P::NAME.to_string(),
+            midi_input_enabled: P::MIDI_INPUT >= MidiConfig::Basic,
+            config,
+
+            input,
+            output_device,
+            output_config,
+            output_sample_format,
+        })
+    }
+
+    /// Build the output stream. The plugin is processed from the output stream's callback, with
+    /// the device's buffers being split up into blocks of at most `period_size` samples.
+    fn build_output_stream<T: Sample>(
+        &self,
+        mut cb: impl FnMut(&mut Buffer, Transport, &[NoteEvent], &mut Vec<NoteEvent>) -> bool
+            + 'static
+            + Send,
+        input_queue: Option<Arc<ArrayQueue<f32>>>,
+        midi_input_queue: Arc<ArrayQueue<NoteEvent>>,
+        control_sender: channel::Sender<Task>,
+    ) -> Result<Stream> {
+        let period_size = self.config.period_size as usize;
+        let num_output_channels = self.output_config.channels as usize;
+        let num_input_channels = self
+            .input
+            .as_ref()
+            .map(|(_, input_config, _)| input_config.channels as usize)
+            .unwrap_or(0);
+
+        let mut channels = vec![vec![0.0f32; period_size]; num_output_channels];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.with_raw_vec(|output_slices| {
+                output_slices.resize_with(num_output_channels, || &mut []);
+            })
+        }
+
+        let mut input_events = Vec::with_capacity(2048);
+        let mut output_events = Vec::with_capacity(2048);
+
+        let config = self.config.clone();
+        let sample_rate = self.output_config.sample_rate.0 as f32;
+        let mut num_processed_samples = 0;
+        let mut shutting_down = false;
+        let data_callback = move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            if shutting_down {
+                data.fill(T::from(&0.0f32));
+                return;
+            }
+
+            for output_chunk in data.chunks_mut(period_size * num_output_channels) {
+                let num_frames = output_chunk.len() / num_output_channels;
+
+                // The buffer's slices need to point to the first `num_frames` samples of our
+                // channel buffers
+                unsafe {
+                    buffer.with_raw_vec(|output_slices| {
+                        for (output_slice, channel) in output_slices.iter_mut().zip(&mut channels) {
+                            // SAFETY: `channels` is only accessed through `buffer` after this
+                            *output_slice = &mut *(&mut channel[..num_frames] as *mut [f32]);
+                        }
+                    })
+                }
+
+                // Just like the other backends, the inputs are copied to the outputs before
+                // processing. Inputs without a corresponding output are discarded, and missing
+                // input samples are replaced with silence.
+                let output_slices = buffer.as_slice();
+                for output_slice in output_slices.iter_mut() {
+                    output_slice.fill(0.0);
+                }
+                if let Some(input_queue) = &input_queue {
+                    // The input stream only ever pushes whole frames, so as long as this also only
+                    // pops whole frames the channels can't get out of alignment. This is the only
+                    // consumer, so the queue can't shrink between checking its length and popping.
+                    for frame_idx in 0..num_frames {
+                        if input_queue.len() < num_input_channels {
+                            break;
+                        }
+
+                        for channel_idx in 0..num_input_channels {
+                            let sample = input_queue.pop().unwrap_or(0.0);
+                            if let Some(output_slice) = output_slices.get_mut(channel_idx) {
+                                output_slice[frame_idx] = sample;
+                            }
+                        }
+                    }
+                }
+
+                input_events.clear();
+                while let Some(event) = midi_input_queue.pop() {
+                    input_events.push(event);
+                }
+
+                let mut transport = Transport::new(sample_rate);
+                transport.pos_samples = Some(num_processed_samples);
+                transport.tempo = Some(config.tempo as f64);
+                transport.time_sig_numerator = Some(config.timesig_num as i32);
+                transport.time_sig_denominator = Some(config.timesig_denom as i32);
+                transport.playing = true;
+
+                // TODO: MIDI output is not yet supported with this backend
+                output_events.clear();
+                if !cb(&mut buffer, transport, &input_events, &mut output_events) {
+                    shutting_down = true;
+                    control_sender.send(Task::Shutdown).unwrap();
+                    output_chunk.fill(T::from(&0.0f32));
+                    return;
+                }
+
+                let output_slices = buffer.as_slice();
+                for (frame_idx, output_frame) in output_chunk
+                    .chunks_exact_mut(num_output_channels)
+                    .enumerate()
+                {
+                    for (output_sample, output_slice) in
+                        output_frame.iter_mut().zip(output_slices.iter())
+                    {
+                        *output_sample = T::from(&output_slice[frame_idx]);
+                    }
+                }
+
+                num_processed_samples += num_frames as i64;
+            }
+        };
+
+        self.output_device
+            .build_output_stream(&self.output_config, data_callback, |err| {
+                nih_error!("Error during audio playback: {err}")
+            })
+            .context("Could not build the output stream")
+    }
+}
+
+/// Build an input stream that writes the device's interleaved samples to `input_queue`. Samples are
+/// written one whole frame at a time.
+fn build_input_stream<T: Sample>(
+    input_device: &Device,
+    input_config: &StreamConfig,
+    input_queue: Arc<ArrayQueue<f32>>,
+) -> Result<Stream> {
+    let num_channels = input_config.channels as usize;
+
+    input_device
+        .build_input_stream(
+            input_config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                for frame in data.chunks_exact(num_channels) {
+                    // If the output stream can't keep up then we'll just drop the frames. This is
+                    // the only producer, so the queue can't fill up between these two steps.
+                    if input_queue.capacity() - input_queue.len() < num_channels {
+                        break;
+                    }
+
+                    for sample in frame {
+                        let _ = input_queue.push(sample.to_f32());
+                    }
+                }
+            },
+            |err| nih_error!("Error during audio recording: {err}"),
+        )
+        .context("Could not build the input stream")
+}
+
+/// Connect to the MIDI input port called `port_name`. Incoming MIDI messages are converted to
+/// [`NoteEvent`]s and written to `midi_input_queue`.
+fn connect_midi_input(
+    client_name: &str,
+    port_name: &str,
+    midi_input_queue: Arc<ArrayQueue<NoteEvent>>,
+) -> Result<MidiInputConnection<()>> {
+    let midi_input = MidiInput::new(client_name).context("Could not create a MIDI client")?;
+    let port = midi_input
+        .ports()
+        .into_iter()
+        .find(|port| {
+            midi_input
+                .port_name(port)
+                .map(|name| name == port_name)
+                .unwrap_or(false)
+        })
+        .context("Unknown MIDI input port")?;
+
+    midi_input
+        .connect(
+            &port,
+            "midi_input",
+            move |_timestamp, message, _| {
+                // Messages like program changes and channel pressure only have a single data
+                // byte, so those are padded with zeroes. SysEx messages are not supported.
+                if message.is_empty() || message.len() > 3 {
+                    return;
+                }
+                let mut midi_data = [0u8; 3];
+                midi_data[..message.len()].copy_from_slice(message);
+
+                if let Ok(event) = NoteEvent::from_midi(0, midi_data) {
+                    let push_successful = midi_input_queue.push(event).is_ok();
+                    nih_debug_assert!(push_successful, "The MIDI input queue is full");
+                }
+            },
+            (),
+        )
+        .map_err(|err| anyhow::anyhow!("{err}"))
+}
+
+/// Find a supported stream configuration range with `num_channels` channels that supports the
+/// configured sample rate. 32-bit floating point sample formats are preferred over integer ones.
+fn find_config_range(
+    config_ranges: impl Iterator<Item = SupportedStreamConfigRange>,
+    config: &WrapperConfig,
+    num_channels: u32,
+) -> Option<SupportedStreamConfigRange> {
+    let sample_rate = cpal::SampleRate(config.sample_rate as u32);
+
+    config_ranges
+        .filter(|range| {
+            range.channels() as u32 == num_channels
+                && range.min_sample_rate() <= sample_rate
+                && range.max_sample_rate() >= sample_rate
+        })
+        .max_by_key(|range| range.sample_format() == SampleFormat::F32)
+}
+
+/// Create a stream configuration from a supported configuration range. The configured period size
+/// is used as the buffer size if the device supports it, otherwise the device's default buffer size
+/// is used. The output stream takes care of splitting larger buffers into smaller blocks.
+fn stream_config(
+    config_range: SupportedStreamConfigRange,
+    config: &WrapperConfig,
+) -> (StreamConfig, SampleFormat) {
+    let supported_config =
+        config_range.with_sample_rate(cpal::SampleRate(config.sample_rate as u32));
+    let buffer_size = match supported_config.buffer_size() {
+        SupportedBufferSize::Range { min, max } if (*min..=*max).contains(&config.period_size) => {
+            BufferSize::Fixed(config.period_size)
+        }
+        _ => BufferSize::Default,
+    };
+
+    let sample_format = supported_config.sample_format();
+    let mut stream_config = supported_config.config();
+    stream_config.buffer_size = buffer_size;
+
+    (stream_config, sample_format)
+}
@@ -49,6 +49,26 @@ pub struct WrapperConfig {
     #[clap(value_parser, long)]
     pub connect_jack_midi_output: Option<String>,
 
+    /// The name of the audio input device to use. Uses the system's default input device if not
+    /// set.
+    ///
+    /// This option is only used with the CPAL backend.
+    #[clap(value_parser, long)]
+    pub input_device: Option<String>,
+
+    /// The name of the audio output device to use. Uses the system's default output device if not
+    /// set.
+    ///
+    /// This option is only used with the CPAL backend.
+    #[clap(value_parser, long)]
+    pub output_device: Option<String>,
+
+    /// If set, then the plugin will receive MIDI from the MIDI input port with this name.
+    ///
+    /// This option is only used with the CPAL backend.
+    #[clap(value_parser, long)]
+    pub midi_input: Option<String>,
+
     /// The editor's DPI scaling factor.
     ///
     /// This option is ignored on macOS.
@@ -74,10 +94,13 @@ pub struct WrapperConfig {
 pub enum BackendType {
     /// Automatically pick the backend depending on what's available.
     ///
-    /// This defaults to JACK if JACK is available, and falls back to the dummy backend if not.
+    /// This defaults to JACK if JACK is available, then tries CPAL, and falls back to the dummy
+    /// backend if neither can be used.
     Auto,
     /// Use JACK for audio and MIDI.
     Jack,
+    /// Use the platform's default audio API through CPAL, with MIDI input through midir.
+    Cpal,
     /// Does not playback or receive any audio or MIDI.
     Dummmy,
 }
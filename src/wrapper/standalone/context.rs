@@ -3,10 +3,13 @@ use std::sync::Arc;
 
 use super::backend::Backend;
 use super::wrapper::{GuiTask, Wrapper};
-use crate::context::{GuiContext, InitContext, PluginApi, ProcessContext, Transport};
+use crate::context::{
+    AutomationState, GuiContext, InitContext, PluginApi, ProcessContext, Transport,
+};
 use crate::midi::NoteEvent;
 use crate::param::internals::ParamPtr;
-use crate::plugin::Plugin;
+use crate::plugin::{Plugin, ProcessMode};
+use crate::wrapper::util::clamp_output_event_timing;
 
 /// A [`GuiContext`] implementation for the wrapper. This is passed to the plugin in
 /// [`Editor::spawn()`][crate::prelude::Editor::spawn()] so it can interact with the rest of the plugin and
@@ -39,6 +42,8 @@ pub(crate) struct WrapperProcessContext<'a, P: Plugin, B: Backend> {
     pub(super) input_events_idx: usize,
     pub(super) output_events: &'a mut Vec<NoteEvent>,
     pub(super) transport: Transport,
+    /// The number of samples in the current block. Output events are clamped to this length.
+    pub(super) block_len: usize,
 }
 
 impl<P: Plugin, B: Backend> GuiContext for WrapperGuiContext<P, B> {
@@ -46,6 +51,10 @@ impl<P: Plugin, B: Backend> GuiContext for WrapperGuiContext<P, B> {
         PluginApi::Standalone
     }
 
+    fn plugin_instance_id(&self) -> u64 {
+        self.wrapper.plugin_instance_id
+    }
+
     fn request_resize(&self) -> bool {
         let (unscaled_width, unscaled_height) = self.wrapper.editor.as_ref().unwrap().size();
 
@@ -63,6 +72,15 @@ impl<P: Plugin, B: Backend> GuiContext for WrapperGuiContext<P, B> {
         true
     }
 
+    fn scale_factor(&self) -> f32 {
+        self.wrapper.dpi_scale()
+    }
+
+    fn automation_state(&self) -> AutomationState {
+        // There is no host to record or play back automation
+        AutomationState::Unknown
+    }
+
     unsafe fn raw_begin_set_parameter(&self, _param: ParamPtr) {
         // Since there's no autmoation being recorded here, gestures don't mean anything
     }
@@ -73,6 +91,14 @@ impl<P: Plugin, B: Backend> GuiContext for WrapperGuiContext<P, B> {
 
     unsafe fn raw_end_set_parameter(&self, _param: ParamPtr) {}
 
+    fn raw_param_by_id(&self, id: &str) -> Option<ParamPtr> {
+        self.wrapper.param_by_id(id)
+    }
+
+    fn raw_begin_gesture(&self) {}
+
+    fn raw_end_gesture(&self) {}
+
     fn get_state(&self) -> crate::wrapper::state::PluginState {
         self.wrapper.get_state_object()
     }
@@ -88,7 +114,7 @@ impl<P: Plugin, B: Backend> InitContext for WrapperInitContext<'_, P, B> {
     }
 
     fn set_latency_samples(&self, _samples: u32) {
-        nih_debug_assert_failure!("TODO: WrapperInitContext::set_latency_samples()");
+        // There's no host to compensate for the plugin's latency, so this can safely be ignored
     }
 }
 
@@ -97,10 +123,19 @@ impl<P: Plugin, B: Backend> ProcessContext for WrapperProcessContext<'_, P, B> {
         PluginApi::Standalone
     }
 
+    fn plugin_instance_id(&self) -> u64 {
+        self.wrapper.plugin_instance_id
+    }
+
     fn transport(&self) -> &Transport {
         &self.transport
     }
 
+    fn process_mode(&self) -> ProcessMode {
+        // The standalone wrapper always processes audio in realtime
+        ProcessMode::Realtime
+    }
+
     fn next_event(&mut self) -> Option<NoteEvent> {
         // We'll pretend we're a queue, choo choo
         if self.input_events_idx < self.input_events.len() {
@@ -113,11 +148,18 @@ impl<P: Plugin, B: Backend> ProcessContext for WrapperProcessContext<'_, P, B> {
         }
     }
 
-    fn send_event(&mut self, event: NoteEvent) {
-        self.output_events.push(event);
+    fn send_event(&mut self, mut event: NoteEvent) {
+        clamp_output_event_timing(&mut event, self.block_len);
+
+        // The events are kept sorted by timing, with events at the same sample keeping the order
+        // they were sent in
+        let insert_idx = self
+            .output_events
+            .partition_point(|e| e.timing() <= event.timing());
+        self.output_events.insert(insert_idx, event);
     }
 
     fn set_latency_samples(&self, _samples: u32) {
-        nih_debug_assert_failure!("TODO: WrapperProcessContext::set_latency_samples()");
+        // There's no host to compensate for the plugin's latency, so this can safely be ignored
     }
 }
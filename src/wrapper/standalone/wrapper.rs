@@ -23,6 +23,10 @@ use crate::plugin::{
 };
 use crate::util::permit_alloc;
 use crate::wrapper::state::{self, PluginState};
+use crate::wrapper::util::{
+    assert_unique_param_ids, bus_config_supported, bypass_passthrough_active, find_bypass_param,
+    next_plugin_instance_id,
+};
 
 /// How many parameter changes we can store in our unprocessed parameter change queue. Storing more
 /// than this many parameters at a time will cause changes to get lost.
@@ -43,6 +47,9 @@ pub struct Wrapper<P: Plugin, B: Backend> {
     known_parameters: HashSet<ParamPtr>,
     /// A mapping from parameter string IDs to parameter pointers.
     param_map: HashMap<String, ParamPtr>,
+    /// The plugin's bypass parameter, if it has one. Used to implement
+    /// [`Plugin::BYPASS_PASSTHROUGH`].
+    bypass_param: Option<ParamPtr>,
     /// The plugin's editor, if it has one. This object does not do anything on its own, but we need
     /// to instantiate this in advance so we don't need to lock the entire [`Plugin`] object when
     /// creating an editor.
@@ -54,6 +61,9 @@ pub struct Wrapper<P: Plugin, B: Backend> {
     bus_config: BusConfig,
     buffer_config: BufferConfig,
 
+    /// This instance's ID, returned from `plugin_instance_id()` on the contexts.
+    pub plugin_instance_id: u64,
+
     /// Parameter changes that have been output by the GUI that have not yet been set in the plugin.
     /// This queue will be flushed at the end of every processing cycle, just like in the plugin
     /// versions.
@@ -82,8 +92,8 @@ pub enum WrapperError {
 
 struct WrapperWindowHandler {
     /// The editor handle for the plugin's open editor. The editor should clean itself up when it
-    /// gets dropped.
-    _editor_handle: Box<dyn Any>,
+    /// gets dropped. This is `None` if the editor could not be opened.
+    _editor_handle: Option<Box<dyn Any + Send + Sync>>,
 
     /// This is used to communicate with the wrapper from the audio thread and from within the
     /// baseview window handler on the GUI thread.
@@ -137,15 +147,8 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
         // For consistency's sake we'll include the same assertions as the other backends
         // TODO: Move these common checks to a function instead of repeating them in every wrapper
         let param_map = params.param_map();
+        assert_unique_param_ids(param_map.iter().map(|(id, _, _)| id.as_str()));
         if cfg!(debug_assertions) {
-            let param_ids: HashSet<_> = param_map.iter().map(|(id, _, _)| id.clone()).collect();
-            nih_debug_assert_eq!(
-                param_map.len(),
-                param_ids.len(),
-                "The plugin has duplicate parameter IDs, weird things may happen. \
-                 Consider using 6 character parameter IDs to avoid collissions.."
-            );
-
             let mut bypass_param_exists = false;
             for (_, ptr, _) in &param_map {
                 let flags = unsafe { ptr.flags() };
@@ -167,6 +170,7 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
             plugin: RwLock::new(plugin),
             params,
             known_parameters: param_map.iter().map(|(_, ptr, _)| *ptr).collect(),
+            bypass_param: find_bypass_param(param_map.iter().map(|(_, ptr, _)| ptr)),
             param_map: param_map
                 .into_iter()
                 .map(|(param_id, param_ptr, _)| (param_id, param_ptr))
@@ -192,13 +196,14 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
             unprocessed_param_changes: ArrayQueue::new(EVENT_QUEUE_CAPACITY),
             updated_state_sender,
             updated_state_receiver,
+            plugin_instance_id: next_plugin_instance_id(),
         });
 
         // Right now the IO configuration is fixed in the standalone target, so if the plugin cannot
         // work with this then we cannot initialize the plugin at all.
         {
             let mut plugin = wrapper.plugin.write();
-            if !plugin.accepts_bus_config(&wrapper.bus_config) {
+            if !bus_config_supported(&*plugin, &wrapper.bus_config) {
                 return Err(WrapperError::IncompatibleConfig);
             }
 
@@ -262,16 +267,17 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
                         gl_config: None,
                     },
                     move |window| {
-                        // TODO: This spawn function should be able to fail and return an error, but
-                        //       baseview does not support this yet. Once this is added, we should
-                        //       immediately close the parent window when this happens so the loop
-                        //       can exit.
+                        // TODO: If this fails we should immediately close the parent window so the
+                        //       loop can exit
                         let editor_handle = editor.spawn(
                             ParentWindowHandle {
                                 handle: window.raw_window_handle(),
                             },
                             context,
                         );
+                        if editor_handle.is_none() {
+                            nih_error!("The editor could not be opened");
+                        }
 
                         WrapperWindowHandler {
                             _editor_handle: editor_handle,
@@ -318,6 +324,11 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
         push_succesful
     }
 
+    /// Look up a parameter by its ID.
+    pub fn param_by_id(&self, id: &str) -> Option<ParamPtr> {
+        self.param_map.get(id).copied()
+    }
+
     /// The DPI scale factor for this standalone application
     pub fn dpi_scale(&self) -> f32 {
         // DPI scaling should be ignored on macOS since the OS already handles this
@@ -344,7 +355,10 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
     /// Update the plugin's internal state, called by the plugin itself from the GUI thread. To
     /// prevent corrupting data and changing parameters during processing the actual state is only
     /// updated at the end of the audio processing cycle.
-    pub fn set_state_object(&self, state: PluginState) {
+    pub fn set_state_object(&self, mut state: PluginState) {
+        // This needs to happen here so the audio thread does not need to allocate
+        state::migrate_state(&mut state, &*self.params, P::migrate_state);
+
         match self.updated_state_sender.send(state) {
             Ok(_) => {
                 // As mentioned above, the state object will be passed back to this thread
@@ -375,25 +389,35 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
                 }
 
                 let sample_rate = self.buffer_config.sample_rate;
-                if let ProcessStatus::Error(err) = self.plugin.write().process(
-                    buffer,
-                    // TODO: Provide extra inputs and outputs in the JACk backend
-                    &mut AuxiliaryBuffers {
-                        inputs: &mut [],
-                        outputs: &mut [],
-                    },
-                    &mut self.make_process_context(transport, input_events, output_events),
-                ) {
-                    nih_error!("The plugin returned an error while processing:");
-                    nih_error!("{}", err);
-
-                    let push_successful = gui_task_sender.send(GuiTask::Close).is_ok();
-                    nih_debug_assert!(
-                        push_successful,
-                        "Could not queue window close, the editor will remain open"
-                    );
+                let block_len = buffer.len();
+                // The backend has already copied the inputs to the buffer, so a passthrough bypass
+                // doesn't need to do anything else
+                if !bypass_passthrough_active::<P>(self.bypass_param, &self.bus_config) {
+                    if let ProcessStatus::Error(err) = self.plugin.write().process(
+                        buffer,
+                        // TODO: Provide extra inputs and outputs in the JACk backend
+                        &mut AuxiliaryBuffers {
+                            inputs: &mut [],
+                            outputs: &mut [],
+                        },
+                        &mut self.make_process_context(
+                            transport,
+                            block_len,
+                            input_events,
+                            output_events,
+                        ),
+                    ) {
+                        nih_error!("The plugin returned an error while processing:");
+                        nih_error!("{}", err);
+
+                        let push_successful = gui_task_sender.send(GuiTask::Close).is_ok();
+                        nih_debug_assert!(
+                            push_successful,
+                            "Could not queue window close, the editor will remain open"
+                        );
 
-                    return false;
+                        return false;
+                    }
                 }
 
                 // Any output note events are now in a vector that can be processed by the
@@ -477,6 +501,7 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
     fn make_process_context<'a>(
         &'a self,
         transport: Transport,
+        block_len: usize,
         input_events: &'a [NoteEvent],
         output_events: &'a mut Vec<NoteEvent>,
     ) -> WrapperProcessContext<'a, P, B> {
@@ -486,6 +511,7 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
             input_events_idx: 0,
             output_events,
             transport,
+            block_len,
         }
     }
 }
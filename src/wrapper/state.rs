@@ -3,6 +3,7 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::{self, Read, Write};
 use std::sync::Arc;
 
 use crate::param::internals::{ParamPtr, Params};
@@ -27,6 +28,10 @@ pub enum ParamValue {
 /// deserialized using serde.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginState {
+    /// The [`Params::state_version()`] of the plugin's parameters object when this state was
+    /// saved. This is 0 for states saved before state versions were introduced.
+    #[serde(default)]
+    pub version: u32,
     /// The plugin's parameter values. These are stored unnormalized. This mean sthe old values will
     /// be recalled when when the parameter's range gets increased. Doing so may still mess with
     /// parameter automation though, depending on how the host impelments that.
@@ -67,7 +72,9 @@ pub(crate) fn make_params_getter<'a>(
 }
 
 /// Serialize a plugin's state to a state object. This is separate from [`serialize_json()`] to
-/// allow passing the raw object directly to the plugin. The parameters are not pulled directly from
+/// allow passing the raw object directly to the plugin. This only reads from the `Params` object
+/// and never touches the plugin itself, so it can be called from the main thread while the audio
+/// thread is processing audio. The parameters are not pulled directly from
 /// `plugin_params` by default to avoid unnecessary allocations in the `.param_map()` method, as the
 /// plugin wrappers will already have a list of parameters handy. See [`make_params_iter()`].
 pub(crate) unsafe fn serialize_object<'a>(
@@ -109,7 +116,11 @@ pub(crate) unsafe fn serialize_object<'a>(
     // storing things like sample data.
     let fields = plugin_params.serialize_fields();
 
-    PluginState { params, fields }
+    PluginState {
+        version: plugin_params.state_version(),
+        params,
+        fields,
+    }
 }
 
 /// Serialize a plugin's state to a vector containing JSON data. This can (and should) be shared
@@ -122,6 +133,88 @@ pub(crate) unsafe fn serialize_json<'a>(
     serde_json::to_vec(&plugin_state)
 }
 
+/// Written in front of the plugin's state by [`write_state()`] when
+/// [`Params::save_stream()`] wrote additional data after the JSON state. This is followed by the
+/// JSON state's length as a little endian `u64`, the JSON state itself, and then the streamed data.
+/// States without streamed data are still stored as plain JSON so older versions of the plugin can
+/// still load them, and JSON can never start with a null byte.
+pub(crate) const STREAMED_STATE_MAGIC: [u8; 8] = *b"\0NIHSTRM";
+
+/// Write a plugin's serialized JSON state to `writer`, followed by any data the plugin streams
+/// through [`Params::save_stream()`]. This is used for plugin formats that can't tell how much
+/// data is left in a stream, so the two parts can be separated again with [`read_state()`].
+pub(crate) fn write_state(
+    writer: &mut impl Write,
+    json: &[u8],
+    plugin_params: &dyn Params,
+) -> io::Result<()> {
+    let mut framed_writer = FramedStateWriter {
+        json: Some(json),
+        writer: &mut *writer,
+    };
+    plugin_params.save_stream(&mut framed_writer)?;
+
+    // If the plugin didn't stream anything, then the JSON state hasn't been written yet
+    match framed_writer.json {
+        Some(json) => writer.write_all(json),
+        None => Ok(()),
+    }
+}
+
+/// Read a state written by [`write_state()`] from `reader`. Returns the JSON state, and whether the
+/// state contains streamed data. If it does, then the rest of `reader` contains the data that
+/// should be passed to [`Params::load_stream()`].
+pub(crate) fn read_state(reader: &mut impl Read) -> io::Result<(Vec<u8>, bool)> {
+    let mut json = Vec::new();
+    reader
+        .take(STREAMED_STATE_MAGIC.len() as u64)
+        .read_to_end(&mut json)?;
+    if json != STREAMED_STATE_MAGIC {
+        reader.read_to_end(&mut json)?;
+        return Ok((json, false));
+    }
+
+    let mut length_bytes = [0; 8];
+    reader.read_exact(&mut length_bytes)?;
+    let length = u64::from_le_bytes(length_bytes);
+
+    json.clear();
+    reader.take(length).read_to_end(&mut json)?;
+    if json.len() as u64 != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok((json, true))
+}
+
+/// Writes the framed JSON state from [`write_state()`] to `writer` right before the first data
+/// written by [`Params::save_stream()`].
+struct FramedStateWriter<'a, W: Write> {
+    /// The JSON state, or `None` if the framed JSON state has already been written.
+    json: Option<&'a [u8]>,
+    writer: W,
+}
+
+impl<W: Write> Write for FramedStateWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if let Some(json) = self.json.take() {
+            self.writer.write_all(&STREAMED_STATE_MAGIC)?;
+            self.writer.write_all(&(json.len() as u64).to_le_bytes())?;
+            self.writer.write_all(json)?;
+        }
+
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
 /// Deserialize a plugin's state from a [`PluginState`] object. This is used to allow the plugin to
 /// do its own internal preset management. Returns `false` and logs an error if the state could not
 /// be deserialized.
@@ -153,18 +246,29 @@ pub(crate) unsafe fn deserialize_object(
             (ParamPtr::BoolParam(p), ParamValue::Bool(v)) => (*p).set_plain_value(*v),
             // Enums are either serialized based on the active variant's index (which may not be the
             // same as the discriminator), or a custom set stable string ID. The latter allows the
-            // variants to be reordered.
+            // variants to be reordered. If the variant has since been removed, then the parameter
+            // is reset to its default value.
             (ParamPtr::EnumParam(p), ParamValue::I32(variant_idx)) => {
-                (*p).set_plain_value(*variant_idx)
+                if (0..(*p).len() as i32).contains(variant_idx) {
+                    (*p).set_plain_value(*variant_idx)
+                } else {
+                    nih_warn!(
+                        "Unknown variant index {} for enum parameter \"{}\", using the default value instead",
+                        variant_idx,
+                        param_id_str,
+                    );
+                    (*p).set_plain_value((*p).default_plain_value());
+                }
             }
             (ParamPtr::EnumParam(p), ParamValue::String(id)) => {
-                let deserialized_enum = (*p).set_from_id(id);
-                nih_debug_assert!(
-                    deserialized_enum,
-                    "Unknown ID {:?} for enum parameter \"{}\"",
-                    id,
-                    param_id_str,
-                );
+                if !(*p).set_from_id(id) {
+                    nih_warn!(
+                        "Unknown ID {:?} for enum parameter \"{}\", using the default value instead",
+                        id,
+                        param_id_str,
+                    );
+                    (*p).set_plain_value((*p).default_plain_value());
+                }
             }
             (param_ptr, param_value) => {
                 nih_debug_assert_failure!(
@@ -189,24 +293,466 @@ pub(crate) unsafe fn deserialize_object(
     true
 }
 
-/// Deserialize a plugin's state from a vector containing JSON data. This can (and should) be shared
-/// across plugin formats. Returns `false` and logs an error if the state could not be deserialized.
-///
-/// Make sure to reinitialize plugin after deserializing the state so it can react to the new
-/// parameter values. The smoothers have already been reset by this function.
-pub(crate) unsafe fn deserialize_json(
+/// If `state` was saved with a different [`Params::state_version()`] than the current one, then
+/// call `migrate_state` with the state's old version so the plugin can update it. The wrappers
+/// pass [`Plugin::migrate_state()`][crate::prelude::Plugin::migrate_state()] here. This allocates,
+/// so it should not be called from the audio thread.
+pub(crate) fn migrate_state(
+    state: &mut PluginState,
+    plugin_params: &dyn Params,
+    migrate_state: impl FnOnce(u32, &mut PluginState),
+) {
+    let current_version = plugin_params.state_version();
+    if state.version != current_version {
+        migrate_state(state.version, state);
+        state.version = current_version;
+    }
+}
+
+/// Parse a plugin's state from a vector containing JSON data. This can (and should) be shared
+/// across plugin formats. Older states are migrated using `migrate_state`, see [`migrate_state()`].
+/// Returns `None` and logs an error if the state could not be parsed. This does not touch the
+/// plugin's parameters yet, so the state can be parsed before anything else gets changed. Use
+/// [`deserialize_object()`] to apply the state afterwards.
+pub(crate) fn parse_json(
     state: &[u8],
-    plugin_params: Arc<dyn Params>,
-    params_getter: impl Fn(&str) -> Option<ParamPtr>,
-    current_buffer_config: Option<&BufferConfig>,
-) -> bool {
-    let state: PluginState = match serde_json::from_slice(state) {
+    plugin_params: &dyn Params,
+    migrate_state: impl FnOnce(u32, &mut PluginState),
+) -> Option<PluginState> {
+    let mut state: PluginState = match serde_json::from_slice(state) {
         Ok(s) => s,
         Err(err) => {
             nih_debug_assert_failure!("Error while deserializing state: {}", err);
-            return false;
+            return None;
         }
     };
+    self::migrate_state(&mut state, plugin_params, migrate_state);
+
+    Some(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, RwLock};
+
+    use super::*;
+    use crate::param::enums::{Enum, EnumParam};
+    use crate::param::internals::{deserialize_field, serialize_field, PersistentField};
+    use crate::param::range::{FloatRange, IntRange};
+    use crate::param::{BoolParam, FloatParam, IntParam};
+
+    #[derive(Enum, Debug, PartialEq)]
+    enum IdEnum {
+        #[id = "foo"]
+        Foo,
+        #[id = "bar"]
+        Bar,
+        #[id = "baz"]
+        Baz,
+    }
+
+    #[derive(Enum, Debug, PartialEq)]
+    enum IndexEnum {
+        Foo,
+        Bar,
+        Baz,
+    }
+
+    struct EnumParams {
+        with_ids: EnumParam<IdEnum>,
+        without_ids: EnumParam<IndexEnum>,
+    }
+
+    unsafe impl Params for EnumParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![
+                (
+                    String::from("with_ids"),
+                    self.with_ids.as_ptr(),
+                    String::new(),
+                ),
+                (
+                    String::from("without_ids"),
+                    self.without_ids.as_ptr(),
+                    String::new(),
+                ),
+            ]
+        }
+    }
+
+    struct AllParams {
+        float: FloatParam,
+        int: IntParam,
+        bool: BoolParam,
+        with_ids: EnumParam<IdEnum>,
+        without_ids: EnumParam<IndexEnum>,
+
+        wavetable: RwLock<Vec<f32>>,
+    }
+
+    impl Default for AllParams {
+        fn default() -> Self {
+            Self {
+                float: FloatParam::new(
+                    "Float",
+                    0.0,
+                    FloatRange::Linear {
+                        min: -1.0,
+                        max: 1.0,
+                    },
+                ),
+                int: IntParam::new("Int", 0, IntRange::Linear { min: -10, max: 10 }),
+                bool: BoolParam::new("Bool", false),
+                with_ids: EnumParam::new("With IDs", IdEnum::Foo),
+                without_ids: EnumParam::new("Without IDs", IndexEnum::Foo),
+
+                wavetable: RwLock::new(Vec::new()),
+            }
+        }
+    }
+
+    unsafe impl Params for AllParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![
+                (String::from("float"), self.float.as_ptr(), String::new()),
+                (String::from("int"), self.int.as_ptr(), String::new()),
+                (String::from("bool"), self.bool.as_ptr(), String::new()),
+                (
+                    String::from("with_ids"),
+                    self.with_ids.as_ptr(),
+                    String::new(),
+                ),
+                (
+                    String::from("without_ids"),
+                    self.without_ids.as_ptr(),
+                    String::new(),
+                ),
+            ]
+        }
+
+        fn serialize_fields(&self) -> HashMap<String, String> {
+            let mut serialized = HashMap::new();
+            serialized.insert(
+                String::from("wavetable"),
+                PersistentField::map(&self.wavetable, serialize_field).unwrap(),
+            );
+
+            serialized
+        }
+
+        fn deserialize_fields(&self, serialized: &HashMap<String, String>) {
+            if let Some(data) = serialized.get("wavetable") {
+                PersistentField::set(&self.wavetable, deserialize_field(data).unwrap());
+            }
+        }
+    }
 
-    deserialize_object(&state, plugin_params, params_getter, current_buffer_config)
+    /// Streams `data` to the host, and stores the data it loads in `loaded`.
+    #[derive(Default)]
+    struct StreamingParams {
+        data: Vec<u8>,
+        loaded: Mutex<Vec<u8>>,
+    }
+
+    unsafe impl Params for StreamingParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            Vec::new()
+        }
+
+        fn save_stream(&self, writer: &mut dyn Write) -> io::Result<()> {
+            writer.write_all(&self.data)
+        }
+
+        fn load_stream(&self, reader: &mut dyn Read) -> io::Result<()> {
+            reader
+                .read_to_end(&mut self.loaded.lock().unwrap())
+                .map(|_| ())
+        }
+    }
+
+    /// Parameters for the second version of a plugin where the `old_gain` parameter has been
+    /// renamed to `gain`.
+    struct VersionedParams {
+        gain: FloatParam,
+    }
+
+    unsafe impl Params for VersionedParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![(String::from("gain"), self.gain.as_ptr(), String::new())]
+        }
+
+        fn state_version(&self) -> u32 {
+            2
+        }
+    }
+
+    /// Look up parameters by their ID in a parameter map.
+    fn params_getter(
+        param_map: &[(String, ParamPtr, String)],
+    ) -> impl Fn(&str) -> Option<ParamPtr> + Copy + '_ {
+        |param_id| {
+            param_map
+                .iter()
+                .find(|(candidate, _, _)| candidate == param_id)
+                .map(|(_, param_ptr, _)| *param_ptr)
+        }
+    }
+
+    /// Deserialize `params` into a new parameters object, and then deserialize `later_params` on
+    /// top of that.
+    fn deserialize_twice(
+        params: &[(&str, ParamValue)],
+        later_params: &[(&str, ParamValue)],
+    ) -> Arc<EnumParams> {
+        let plugin_params = Arc::new(EnumParams {
+            with_ids: EnumParam::new("With IDs", IdEnum::Bar),
+            without_ids: EnumParam::new("Without IDs", IndexEnum::Bar),
+        });
+        let param_map = plugin_params.param_map();
+        let params_getter = params_getter(&param_map);
+
+        for params in [params, later_params] {
+            let state = PluginState {
+                version: 0,
+                params: params
+                    .iter()
+                    .map(|(param_id, value)| (param_id.to_string(), value.clone()))
+                    .collect(),
+                fields: HashMap::new(),
+            };
+            assert!(unsafe {
+                deserialize_object(&state, plugin_params.clone(), params_getter, None)
+            });
+        }
+
+        plugin_params
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let mut params = AllParams::default();
+        params.float.set_plain_value(0.123_456_79);
+        params.int.set_plain_value(-7);
+        params.bool.set_plain_value(true);
+        params.with_ids.set_plain_value(IdEnum::Baz);
+        params.without_ids.set_plain_value(IndexEnum::Bar);
+        *params.wavetable.write().unwrap() = vec![0.0, 0.5, -1.0, 1.0 / 3.0];
+        let params = Arc::new(params);
+
+        let param_map = params.param_map();
+        let serialized = unsafe {
+            serialize_json(
+                params.clone(),
+                param_map
+                    .iter()
+                    .map(|(param_id, param_ptr, _)| (param_id, *param_ptr)),
+            )
+        }
+        .unwrap();
+
+        let restored = Arc::new(AllParams::default());
+        let restored_param_map = restored.param_map();
+        let state = parse_json(&serialized, &*restored, |_, _| {
+            panic!("The state should not need to be migrated")
+        })
+        .unwrap();
+        assert!(unsafe {
+            deserialize_object(
+                &state,
+                restored.clone(),
+                params_getter(&restored_param_map),
+                None,
+            )
+        });
+
+        assert_eq!(restored.float.plain_value(), params.float.plain_value());
+        assert_eq!(restored.int.plain_value(), -7);
+        assert!(restored.bool.plain_value());
+        assert_eq!(restored.with_ids.value(), IdEnum::Baz);
+        assert_eq!(restored.without_ids.value(), IndexEnum::Bar);
+        assert_eq!(
+            *restored.wavetable.read().unwrap(),
+            [0.0, 0.5, -1.0, 1.0 / 3.0]
+        );
+    }
+
+    #[test]
+    fn serialize_while_processing() {
+        let params = Arc::new(AllParams::default());
+        *params.wavetable.write().unwrap() = vec![1.0, 2.0];
+        let param_map = params.param_map();
+
+        // Saving the state only needs a read lock, so a processing thread that is reading the
+        // wavetable should not block it
+        let (locked_sender, locked_receiver) = std::sync::mpsc::channel();
+        let (saved_sender, saved_receiver) = std::sync::mpsc::channel::<()>();
+        let processing_thread = {
+            let params = params.clone();
+            std::thread::spawn(move || {
+                let wavetable = params.wavetable.read().unwrap();
+                locked_sender.send(()).unwrap();
+                saved_receiver.recv().unwrap();
+                drop(wavetable);
+            })
+        };
+
+        locked_receiver.recv().unwrap();
+        let state = unsafe {
+            serialize_object(
+                params.clone(),
+                param_map
+                    .iter()
+                    .map(|(param_id, param_ptr, _)| (param_id, *param_ptr)),
+            )
+        };
+        saved_sender.send(()).unwrap();
+        processing_thread.join().unwrap();
+
+        assert_eq!(state.fields.get("wavetable").unwrap(), "[1.0,2.0]");
+    }
+
+    #[test]
+    fn hidden_params_are_restored() {
+        let mut params = AllParams {
+            float: FloatParam::new("Float", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }).hide(),
+            ..AllParams::default()
+        };
+        params.float.set_plain_value(0.25);
+        let params = Arc::new(params);
+
+        let param_map = params.param_map();
+        let state = unsafe {
+            serialize_object(
+                params.clone(),
+                param_map
+                    .iter()
+                    .map(|(param_id, param_ptr, _)| (param_id, *param_ptr)),
+            )
+        };
+        assert!(matches!(
+            state.params.get("float"),
+            Some(ParamValue::F32(value)) if *value == 0.25
+        ));
+
+        let restored = Arc::new(AllParams {
+            float: FloatParam::new("Float", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }).hide(),
+            ..AllParams::default()
+        });
+        let restored_param_map = restored.param_map();
+        assert!(unsafe {
+            deserialize_object(
+                &state,
+                restored.clone(),
+                params_getter(&restored_param_map),
+                None,
+            )
+        });
+        assert_eq!(restored.float.plain_value(), 0.25);
+    }
+
+    #[test]
+    fn old_state_is_migrated() {
+        let params = Arc::new(VersionedParams {
+            gain: FloatParam::new("Gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+        });
+        let param_map = params.param_map();
+
+        let mut migrated_from = None;
+        let old_state = br#"{"version":1,"params":{"old_gain":{"f32":0.5}},"fields":{}}"#;
+        let state = parse_json(old_state, &*params, |old_version, state| {
+            migrated_from = Some(old_version);
+            if let Some(value) = state.params.remove("old_gain") {
+                state.params.insert(String::from("gain"), value);
+            }
+        })
+        .unwrap();
+        assert_eq!(state.version, params.state_version());
+        assert!(unsafe {
+            deserialize_object(&state, params.clone(), params_getter(&param_map), None)
+        });
+        assert_eq!(migrated_from, Some(1));
+        assert_eq!(params.gain.plain_value(), 0.5);
+
+        // States saved before versioning was introduced don't have a version field at all
+        let mut state: PluginState =
+            serde_json::from_slice(br#"{"params":{},"fields":{}}"#).unwrap();
+        migrate_state(&mut state, &*params, |old_version, _| {
+            assert_eq!(old_version, 0)
+        });
+        assert_eq!(state.version, 2);
+    }
+
+    #[test]
+    fn enum_ids() {
+        let params = deserialize_twice(
+            &[("with_ids", ParamValue::String(String::from("baz")))],
+            &[("with_ids", ParamValue::String(String::from("foo")))],
+        );
+        assert_eq!(params.with_ids.value(), IdEnum::Foo);
+    }
+
+    #[test]
+    fn removed_enum_id_falls_back_to_default() {
+        let params = deserialize_twice(
+            &[("with_ids", ParamValue::String(String::from("baz")))],
+            &[("with_ids", ParamValue::String(String::from("removed")))],
+        );
+        assert_eq!(params.with_ids.value(), IdEnum::Bar);
+    }
+
+    #[test]
+    fn removed_enum_index_falls_back_to_default() {
+        let params = deserialize_twice(
+            &[("without_ids", ParamValue::I32(2))],
+            &[("without_ids", ParamValue::I32(3))],
+        );
+        assert_eq!(params.without_ids.value(), IndexEnum::Bar);
+        assert_eq!(params.without_ids.to_string(), "Bar");
+    }
+
+    #[test]
+    fn state_without_streamed_data() {
+        let params = StreamingParams::default();
+        let mut data = Vec::new();
+        write_state(&mut data, b"{}", &params).unwrap();
+        assert_eq!(data, b"{}");
+
+        let (json, has_streamed_data) = read_state(&mut data.as_slice()).unwrap();
+        assert_eq!(json, b"{}");
+        assert!(!has_streamed_data);
+    }
+
+    #[test]
+    fn state_with_streamed_data() {
+        let params = StreamingParams {
+            data: b"samples".to_vec(),
+            ..StreamingParams::default()
+        };
+        let mut data = Vec::new();
+        write_state(&mut data, b"{}", &params).unwrap();
+        assert!(data.starts_with(&STREAMED_STATE_MAGIC));
+
+        let mut reader = data.as_slice();
+        let (json, has_streamed_data) = read_state(&mut reader).unwrap();
+        assert_eq!(json, b"{}");
+        assert!(has_streamed_data);
+
+        let loaded_params = StreamingParams::default();
+        loaded_params.load_stream(&mut reader).unwrap();
+        assert_eq!(*loaded_params.loaded.lock().unwrap(), b"samples");
+    }
+
+    #[test]
+    fn truncated_streamed_state() {
+        let params = StreamingParams {
+            data: b"samples".to_vec(),
+            ..StreamingParams::default()
+        };
+        let mut data = Vec::new();
+        write_state(&mut data, b"{\"params\":{}}", &params).unwrap();
+
+        // Cut the state off in the middle of the JSON data
+        data.truncate(STREAMED_STATE_MAGIC.len() + 8 + 4);
+        assert!(read_state(&mut data.as_slice()).is_err());
+    }
 }
@@ -1,17 +1,36 @@
+use atomic_float::AtomicF32;
+use atomic_refcell::AtomicRefCell;
 use std::cmp;
+use std::collections::HashSet;
 use std::fs::File;
 use std::marker::PhantomData;
 use std::os::raw::c_char;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::midi::NoteEvent;
+use crate::param::internals::ParamPtr;
+use crate::param::ParamFlags;
+use crate::plugin::{BusConfig, Editor, Plugin, ProcessStatus};
+
+pub mod logger;
+
+use self::logger::NihLogger;
 
 /// The environment variable for controlling the logging behavior.
 const NIH_LOG_ENV: &str = "NIH_LOG";
+/// The environment variable for controlling the maximum log level.
+const NIH_LOG_LEVEL_ENV: &str = "NIH_LOG_LEVEL";
+
+/// The bits in the MXCSR register that control flush-to-zero (bit 15) and denormals-are-zero (bit
+/// 6) behavior on x86 CPUs with SSE.
+#[cfg(all(target_feature = "sse", not(feature = "allow_denormals")))]
+const X86_FTZ_DAZ_BITS: u32 = (1 << 15) | (1 << 6);
 
 /// The bit that controls flush-to-zero behavior for denormals in 32 and 64-bit floating point
 /// numbers on AArch64.
 ///
 /// <https://developer.arm.com/documentation/ddi0595/2021-06/AArch64-Registers/FPCR--Floating-point-Control-Register>
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", not(feature = "allow_denormals")))]
 const AARCH64_FTZ_BIT: u64 = 1 << 24;
 
 #[cfg(all(debug_assertions, feature = "assert_process_allocs"))]
@@ -32,6 +51,55 @@ pub fn hash_param_id(id: &str) -> u32 {
     hash
 }
 
+/// Make sure that none of a plugin's parameters share the same parameter ID. The `Params` derive
+/// macro already rejects duplicate IDs within a single struct, but parameters from nested
+/// parameter objects can still collide with other parameters. Duplicate IDs cause one of the
+/// parameters to silently be overwritten when the plugin's state is restored, so this is checked
+/// in release builds as well.
+///
+/// # Panics
+///
+/// Panics with a list of the duplicate IDs if any parameter ID occurs more than once.
+pub fn assert_unique_param_ids<'a>(param_ids: impl IntoIterator<Item = &'a str>) {
+    let mut seen_ids = HashSet::new();
+    let mut duplicate_ids: Vec<&str> = param_ids
+        .into_iter()
+        .filter(|id| !seen_ids.insert(*id))
+        .collect();
+    duplicate_ids.sort_unstable();
+    duplicate_ids.dedup();
+
+    assert!(
+        duplicate_ids.is_empty(),
+        "The plugin has duplicate parameter IDs: {duplicate_ids:?}. Every parameter needs a unique \
+         ID, consider using `#[nested(id_prefix = \"...\")]` for nested parameter objects."
+    );
+}
+
+/// Make sure that no two parameters share the same poly modulation ID, see
+/// [`FloatParam::with_poly_modulation_id()`][crate::prelude::FloatParam::with_poly_modulation_id()].
+/// The wrapper uses these IDs to route the host's per-voice modulation events, so parameters with
+/// duplicate IDs would silently receive each other's modulation.
+///
+/// # Panics
+///
+/// Panics with a list of the duplicate IDs if any poly modulation ID occurs more than once.
+pub fn assert_unique_poly_modulation_ids(poly_modulation_ids: impl IntoIterator<Item = u32>) {
+    let mut seen_ids = HashSet::new();
+    let mut duplicate_ids: Vec<u32> = poly_modulation_ids
+        .into_iter()
+        .filter(|id| !seen_ids.insert(*id))
+        .collect();
+    duplicate_ids.sort_unstable();
+    duplicate_ids.dedup();
+
+    assert!(
+        duplicate_ids.is_empty(),
+        "The plugin has duplicate poly modulation IDs: {duplicate_ids:?}. Every polyphonically \
+         modulated parameter needs a unique poly modulation ID."
+    );
+}
+
 /// The equivalent of the `strlcpy()` C function. Copy `src` to `dest` as a null-terminated
 /// C-string. If `dest` does not have enough capacity, add a null terminator at the end to prevent
 /// buffer overflows.
@@ -51,6 +119,226 @@ pub fn strlcpy(dest: &mut [c_char], src: &str) {
     dest[copy_len] = 0;
 }
 
+/// Find the plugin's bypass parameter, if it has one. If there are multiple bypass parameters then
+/// only the first one is used, just like the host would.
+pub fn find_bypass_param<'a>(params: impl IntoIterator<Item = &'a ParamPtr>) -> Option<ParamPtr> {
+    params
+        .into_iter()
+        .find(|ptr| unsafe { ptr.flags() }.contains(ParamFlags::BYPASS))
+        .copied()
+}
+
+/// Generate a new ID for a plugin instance. Every call returns a different ID.
+pub fn next_plugin_instance_id() -> u64 {
+    static NEXT_PLUGIN_INSTANCE_ID: AtomicU64 = AtomicU64::new(0);
+
+    NEXT_PLUGIN_INSTANCE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Whether the wrapper should skip the plugin's process function and pass the main input through
+/// to the main output instead. See [`Plugin::BYPASS_PASSTHROUGH`].
+pub fn bypass_passthrough_active<P: Plugin>(
+    bypass_param: Option<ParamPtr>,
+    bus_config: &BusConfig,
+) -> bool {
+    P::BYPASS_PASSTHROUGH
+        && bus_config.num_input_channels == bus_config.num_output_channels
+        && bypass_param.is_some_and(|ptr| unsafe { ptr.normalized_value() } >= 0.5)
+}
+
+/// All bus configurations the plugin supports, in the order they should be offered to the host. If
+/// the plugin declares [`Plugin::AUDIO_PORT_CONFIGS`] then exactly those configurations are used.
+/// Otherwise the sensible mono and stereo configurations are probed using
+/// [`bus_config_supported()`]. We don't do surround or anything beyond stereo right now.
+pub fn supported_bus_configs<P: Plugin>(plugin: &P) -> Vec<BusConfig> {
+    let default_bus_config = default_bus_config::<P>();
+    if !P::AUDIO_PORT_CONFIGS.is_empty() {
+        nih_debug_assert!(
+            P::AUDIO_PORT_CONFIGS.contains(&default_bus_config),
+            "The plugin's default bus config is not one of its 'AUDIO_PORT_CONFIGS'"
+        );
+
+        return P::AUDIO_PORT_CONFIGS.to_vec();
+    }
+
+    let mut supported_bus_configs = Vec::new();
+    for num_output_channels in [1, 2] {
+        for num_input_channels in [0, num_output_channels] {
+            #[allow(clippy::single_element_loop)]
+            for num_aux_channels in [num_output_channels] {
+                let bus_config = BusConfig {
+                    num_input_channels,
+                    num_output_channels,
+                    // We won't support a variable number of busses until that's required, so we'll
+                    // always use the number of auxiliary busses specified by the plugin
+                    aux_input_busses: P::DEFAULT_AUX_INPUTS
+                        .map(|mut aux| {
+                            aux.num_channels = num_aux_channels;
+                            aux
+                        })
+                        .unwrap_or_default(),
+                    aux_output_busses: P::DEFAULT_AUX_OUTPUTS
+                        .map(|mut aux| {
+                            aux.num_channels = num_aux_channels;
+                            aux
+                        })
+                        .unwrap_or_default(),
+                };
+                if bus_config_supported(plugin, &bus_config) {
+                    supported_bus_configs.push(bus_config);
+                }
+            }
+        }
+    }
+
+    // In the off chance that the default config specified by the plugin is not in the above list,
+    // we'll try that as well
+    if !supported_bus_configs.contains(&default_bus_config)
+        && bus_config_supported(plugin, &default_bus_config)
+    {
+        supported_bus_configs.push(default_bus_config);
+    }
+
+    supported_bus_configs
+}
+
+/// Whether the plugin supports a bus configuration proposed by the host. If the plugin declares
+/// [`Plugin::AUDIO_PORT_CONFIGS`] then the configuration needs to be one of those, and
+/// [`Plugin::accepts_bus_config()`] is used otherwise.
+pub fn bus_config_supported<P: Plugin>(plugin: &P, bus_config: &BusConfig) -> bool {
+    if P::AUDIO_PORT_CONFIGS.is_empty() {
+        plugin.accepts_bus_config(bus_config)
+    } else {
+        P::AUDIO_PORT_CONFIGS.contains(bus_config)
+    }
+}
+
+/// The bus configuration described by the plugin's `DEFAULT_*` constants.
+fn default_bus_config<P: Plugin>() -> BusConfig {
+    BusConfig {
+        num_input_channels: P::DEFAULT_NUM_INPUTS,
+        num_output_channels: P::DEFAULT_NUM_OUTPUTS,
+        aux_input_busses: P::DEFAULT_AUX_INPUTS.unwrap_or_default(),
+        aux_output_busses: P::DEFAULT_AUX_OUTPUTS.unwrap_or_default(),
+    }
+}
+
+/// Keeps track of the parameters that are changed during a gesture started with
+/// [`ParamSetter::begin_gesture()`][crate::prelude::ParamSetter::begin_gesture()]. While a gesture
+/// is active, every parameter gets a single begin event the first time it's touched, and the end
+/// events for all of those parameters are deferred until the gesture ends. This should only be
+/// used from the main thread.
+#[derive(Debug, Default)]
+pub struct GestureGroup {
+    /// The parameters touched during the current gesture, in the order they were first touched.
+    /// This is `None` when no gesture is active.
+    params: AtomicRefCell<Option<Vec<ParamPtr>>>,
+}
+
+impl GestureGroup {
+    /// Start a new gesture. Returns `false` and leaves the active gesture alone if there already
+    /// was one.
+    pub fn begin(&self) -> bool {
+        let mut params = self.params.borrow_mut();
+        if params.is_some() {
+            return false;
+        }
+
+        *params = Some(Vec::new());
+        true
+    }
+
+    /// Whether a gesture is currently active.
+    pub fn is_active(&self) -> bool {
+        self.params.borrow().is_some()
+    }
+
+    /// Whether the wrapper should tell the host it's going to start changing `param`. This is
+    /// always the case outside of gestures. During a gesture this is only true the first time a
+    /// parameter is touched.
+    pub fn begin_param(&self, param: ParamPtr) -> bool {
+        match &mut *self.params.borrow_mut() {
+            Some(params) if params.contains(&param) => false,
+            Some(params) => {
+                params.push(param);
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// End the current gesture, returning the parameters that need to receive an end event. Returns
+    /// `None` if there was no active gesture.
+    pub fn end(&self) -> Option<Vec<ParamPtr>> {
+        self.params.borrow_mut().take()
+    }
+}
+
+/// Make sure an event sent by the plugin through
+/// [`ProcessContext::send_event()`][crate::prelude::ProcessContext::send_event()] falls within the
+/// current block of `block_len` samples. Events with timings beyond the end of the block are moved
+/// to the block's last sample.
+pub fn clamp_output_event_timing(event: &mut NoteEvent, block_len: usize) {
+    let last_sample_idx = block_len.saturating_sub(1) as u32;
+    let timing = event.timing();
+    if timing > last_sample_idx {
+        nih_debug_assert_failure!(
+            "The plugin sent an event at sample {} in a block of {} samples, the event will be \
+             moved to the last sample",
+            timing,
+            block_len
+        );
+        event.subtract_timing(timing - last_sample_idx);
+    }
+}
+
+/// The tail length in samples the wrappers report to the host for a process status. `u32::MAX`
+/// means the tail is infinite, which matches the meaning of that value in both CLAP and VST3.
+pub fn tail_samples(status: ProcessStatus) -> u32 {
+    match status {
+        ProcessStatus::Tail(samples) => samples,
+        ProcessStatus::KeepAlive => u32::MAX,
+        ProcessStatus::Normal | ProcessStatus::Error(_) => 0,
+    }
+}
+
+/// The capacity for the wrapper's task queues, from [`Plugin::TASK_QUEUE_CAPACITY`]. A capacity of 0
+/// would either panic while the host creates the plugin instance or silently drop every task, so
+/// this fails to compile for plugins that set the capacity to 0.
+pub fn task_queue_capacity<P: Plugin>() -> usize {
+    const {
+        assert!(
+            P::TASK_QUEUE_CAPACITY > 0,
+            "TASK_QUEUE_CAPACITY needs to be at least 1"
+        )
+    };
+
+    P::TASK_QUEUE_CAPACITY
+}
+
+/// Pass a DPI scaling factor from the host on to the editor, and store it in `scaling_factor` if
+/// the editor accepted it. Returns whether the new factor is now in effect. Hosts can do this at any
+/// time, including while the editor is open, such as when the window is moved to a monitor with a
+/// different scale.
+pub fn set_editor_scale_factor(
+    editor: &dyn Editor,
+    scaling_factor: &AtomicF32,
+    factor: f32,
+) -> bool {
+    // On macOS scaling is done by the OS, and all window sizes are in logical pixels
+    if cfg!(target_os = "macos") {
+        nih_debug_assert_failure!("Ignoring host request to set explicit DPI scaling factor");
+        return false;
+    }
+
+    if editor.set_scale_factor(factor) {
+        scaling_factor.store(factor, Ordering::Relaxed);
+        true
+    } else {
+        false
+    }
+}
+
 /// Set up the logger so that the `nih_*!()` logging and assertion macros log output to a
 /// centralized location and panics also get written there. By default this logs to STDERR unless
 /// the user is running Windows and a debugger has been attached, in which case
@@ -63,6 +351,13 @@ pub fn strlcpy(dest: &mut [c_char], src: &str) {
 ///   `OutputDebugString()`.
 /// - A file path, in which case the output gets appended to the end of that file which will be
 ///   created if necessary.
+///
+/// When the user did not set `NIH_LOG` and the plugin is running in a host that provides a logging
+/// facility, then the log output is sent to the host instead. See
+/// [`install_host_log_sink()`][logger::install_host_log_sink()].
+///
+/// The `NIH_LOG_LEVEL` environment variable can be set to `error`, `warn`, `info`, `debug`,
+/// `trace`, or `off` to only log messages at or above that level. See [`log_level_filter()`].
 pub fn setup_logger() {
     // `win_dbg_logger` has no way to let us know that the logger has already been set up, so we'll
     // need to do it this way
@@ -76,26 +371,19 @@ pub fn setup_logger() {
 
     let nih_log_env = std::env::var(NIH_LOG_ENV);
     let nih_log_env_str = nih_log_env.as_deref().unwrap_or("");
-
-    #[cfg(target_os = "windows")]
-    if nih_log_env_str.eq_ignore_ascii_case("windbg") || win_dbg_logger::is_debugger_present() {
-        win_dbg_logger::init();
-        log_panics::init();
-        return;
-    }
-
-    // If opening the file fails, then we'll log to STDERR anyways, hence this closure
-    let log_level = if cfg!(debug_assertions) {
-        simplelog::LevelFilter::Trace
-    } else {
-        simplelog::LevelFilter::Info
+    let nih_log_level_env = std::env::var(NIH_LOG_LEVEL_ENV).ok();
+    let (log_level, invalid_log_level) = match log_level_filter(nih_log_level_env.as_deref()) {
+        Ok(log_level) => (log_level, false),
+        Err(default_log_level) => (default_log_level, true),
     };
+
     let logger_config = simplelog::ConfigBuilder::new()
         .set_thread_mode(simplelog::ThreadLogMode::Both)
         .set_location_level(simplelog::LevelFilter::Debug)
         .build();
-    let init_stderr_logger = || {
-        simplelog::TermLogger::init(
+    // If opening the file fails, then we'll log to STDERR anyways, hence this closure
+    let stderr_logger = || -> Box<dyn log::Log> {
+        simplelog::TermLogger::new(
             log_level,
             logger_config.clone(),
             simplelog::TerminalMode::Stderr,
@@ -103,34 +391,90 @@ pub fn setup_logger() {
         )
     };
 
-    // If the logger has already been set up outside of this function then that won't cause any
-    // problems, so we can ignore the results here
-    if nih_log_env_str.eq_ignore_ascii_case("stderr") || nih_log_env_str.is_empty() {
-        let _ = init_stderr_logger();
+    let mut file_error = None;
+    let fallback_logger: Box<dyn log::Log> = if let Some(logger) = windbg_logger(nih_log_env_str) {
+        logger
+    } else if nih_log_env_str.eq_ignore_ascii_case("stderr") || nih_log_env_str.is_empty() {
+        stderr_logger()
     } else {
         let file = File::options()
             .append(true)
             .create(true)
             .open(nih_log_env_str);
         match file {
-            Ok(file) => {
-                let _ = simplelog::WriteLogger::init(log_level, logger_config, file);
-            }
+            Ok(file) => simplelog::WriteLogger::new(log_level, logger_config.clone(), file),
             Err(err) => {
-                let _ = init_stderr_logger();
-                nih_debug_assert_failure!("Could not open '{}': {:?}", nih_log_env_str, err);
+                file_error = Some(err);
+                stderr_logger()
             }
         }
+    };
+
+    // Messages are only sent to the host if the user did not explicitly choose an output. If the
+    // logger has already been set up outside of this function then that won't cause any problems,
+    // so we can ignore the result here.
+    let logger = NihLogger::new(fallback_logger, log_level, nih_log_env_str.is_empty());
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(log_level);
+    }
+
+    if let Some(err) = file_error {
+        nih_debug_assert_failure!("Could not open '{}': {:?}", nih_log_env_str, err);
+    }
+    if invalid_log_level {
+        nih_warn!(
+            "Unknown {} value '{}', expected one of 'error', 'warn', 'info', 'debug', 'trace', or \
+             'off'",
+            NIH_LOG_LEVEL_ENV,
+            nih_log_level_env.as_deref().unwrap_or_default()
+        );
     }
 
     log_panics::init();
 }
 
+/// Create a logger that sends the log output to the Windows debugger using `OutputDebugString()`
+/// if the user asked for that or if a debugger is attached. This always returns `None` on other
+/// platforms.
+#[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
+fn windbg_logger(nih_log_env_str: &str) -> Option<Box<dyn log::Log>> {
+    #[cfg(target_os = "windows")]
+    if nih_log_env_str.eq_ignore_ascii_case("windbg") || win_dbg_logger::is_debugger_present() {
+        return Some(Box::new(&win_dbg_logger::DEBUGGER_LOGGER));
+    }
+
+    None
+}
+
+/// Determine the maximum log level from the value of the `NIH_LOG_LEVEL` environment variable. The
+/// level names are case insensitive. If the variable is not set, then everything is logged in debug
+/// builds, and only messages at the info level and above are logged in release builds. The `log`
+/// macros check this level before formatting their arguments, so filtered messages don't cost
+/// anything beyond a single comparison. Returns an `Err` containing that default level if the
+/// variable contains an invalid value.
+///
+/// The debug and trace levels are compiled out of release builds, so in those builds setting the
+/// level to `debug` or `trace` is the same as setting it to `info`.
+pub fn log_level_filter(
+    nih_log_level_env: Option<&str>,
+) -> Result<simplelog::LevelFilter, simplelog::LevelFilter> {
+    let default_level = if cfg!(debug_assertions) {
+        simplelog::LevelFilter::Trace
+    } else {
+        simplelog::LevelFilter::Info
+    };
+
+    match nih_log_level_env {
+        Some(level) if !level.is_empty() => level.parse().map_err(|_| default_level),
+        _ => Ok(default_level),
+    }
+}
+
 /// A wrapper around the entire process function, including the plugin wrapper parts. This sets up
-/// `assert_no_alloc` if needed, while also making sure that things like FTZ are set up correctly if
-/// the host has not already done so.
+/// `assert_no_alloc` if needed, while also making sure that things like FTZ and DAZ are set up
+/// correctly if the host has not already done so.
 pub fn process_wrapper<T, F: FnOnce() -> T>(f: F) -> T {
-    // Make sure FTZ is always enabled, even if the host doesn't do it for us
+    // Make sure FTZ and DAZ are always enabled, even if the host doesn't do it for us
     let _ftz_guard = ScopedFtz::enable();
 
     cfg_if::cfg_if! {
@@ -142,11 +486,13 @@ pub fn process_wrapper<T, F: FnOnce() -> T>(f: F) -> T {
     }
 }
 
-/// Enable the CPU's Flush To Zero flag while this object is in scope. If the flag was not already
-/// set, it will be restored to its old value when this gets dropped.
+/// Enable the CPU's Flush To Zero and Denormals Are Zero flags while this object is in scope. Any
+/// flags that were not already set will be cleared again when this gets dropped. This does nothing
+/// when the `allow_denormals` feature is enabled.
 struct ScopedFtz {
-    /// Whether FTZ should be disabled again, i.e. if FTZ was not enabled before.
-    should_disable_again: bool,
+    /// The flags that were set when this object was created, and that should be cleared again when
+    /// it gets dropped.
+    enabled_bits: u64,
     /// We can't directly implement !Send and !Sync, but this will do the same thing. This object
     /// affects the current thread's floating point registers, so it may only be dropped on the
     /// current thread.
@@ -156,59 +502,75 @@ struct ScopedFtz {
 impl ScopedFtz {
     fn enable() -> Self {
         cfg_if::cfg_if! {
-            if #[cfg(target_feature = "sse")] {
-                let mode = unsafe { std::arch::x86_64::_MM_GET_FLUSH_ZERO_MODE() };
-                let should_disable_again = mode != std::arch::x86_64::_MM_FLUSH_ZERO_ON;
-                if should_disable_again {
-                    unsafe { std::arch::x86_64::_MM_SET_FLUSH_ZERO_MODE(std::arch::x86_64::_MM_FLUSH_ZERO_ON) };
-                }
-
-                Self {
-                    should_disable_again,
-                    _send_sync_marker: PhantomData,
+            if #[cfg(feature = "allow_denormals")] {
+                let enabled_bits = 0;
+            } else if #[cfg(target_feature = "sse")] {
+                // The `_MM_SET_FLUSH_ZERO_MODE()` family of functions has been deprecated, so this
+                // modifies the MXCSR register directly instead
+                let mxcsr = unsafe { read_mxcsr() };
+                let enabled_bits = X86_FTZ_DAZ_BITS & !mxcsr;
+                if enabled_bits != 0 {
+                    unsafe { write_mxcsr(mxcsr | enabled_bits) };
                 }
+                let enabled_bits = enabled_bits as u64;
             } else if #[cfg(target_arch = "aarch64")] {
                 // There are no convient intrinsics to change the FTZ settings on AArch64, so this
-                // requires inline assembly:
+                // requires inline assembly. AArch64's FTZ flag also flushes denormal inputs to
+                // zero.
                 // https://developer.arm.com/documentation/ddi0595/2021-06/AArch64-Registers/FPCR--Floating-point-Control-Register
                 let mut fpcr: u64;
                 unsafe { std::arch::asm!("mrs {}, fpcr", out(reg) fpcr) };
 
-                let should_disable_again = fpcr & AARCH64_FTZ_BIT == 0;
-                if should_disable_again {
-                    unsafe { std::arch::asm!("msr fpcr, {}", in(reg) fpcr | AARCH64_FTZ_BIT) };
-                }
-
-                Self {
-                    should_disable_again,
-                    _send_sync_marker: PhantomData,
+                let enabled_bits = AARCH64_FTZ_BIT & !fpcr;
+                if enabled_bits != 0 {
+                    unsafe { std::arch::asm!("msr fpcr, {}", in(reg) fpcr | enabled_bits) };
                 }
             } else {
-                Self {
-                    should_disable_again: false,
-                    _send_sync_marker: PhantomData,
-                }
+                let enabled_bits = 0;
             }
         }
+
+        Self {
+            enabled_bits,
+            _send_sync_marker: PhantomData,
+        }
     }
 }
 
 impl Drop for ScopedFtz {
     fn drop(&mut self) {
-        if self.should_disable_again {
+        if self.enabled_bits != 0 {
             cfg_if::cfg_if! {
-                if #[cfg(target_feature = "sse")] {
-                    unsafe { std::arch::x86_64::_MM_SET_FLUSH_ZERO_MODE(std::arch::x86_64::_MM_FLUSH_ZERO_OFF) };
+                if #[cfg(feature = "allow_denormals")] {
+                    // Nothing was enabled, so this branch is never reached
+                } else if #[cfg(target_feature = "sse")] {
+                    let mxcsr = unsafe { read_mxcsr() };
+                    unsafe { write_mxcsr(mxcsr & !(self.enabled_bits as u32)) };
                 } else if #[cfg(target_arch = "aarch64")] {
                     let mut fpcr: u64;
                     unsafe { std::arch::asm!("mrs {}, fpcr", out(reg) fpcr) };
-                    unsafe { std::arch::asm!("msr fpcr, {}", in(reg) fpcr & !AARCH64_FTZ_BIT) };
+                    unsafe { std::arch::asm!("msr fpcr, {}", in(reg) fpcr & !self.enabled_bits) };
                 }
             };
         }
     }
 }
 
+/// Read the current thread's MXCSR register.
+#[cfg(all(target_feature = "sse", not(feature = "allow_denormals")))]
+unsafe fn read_mxcsr() -> u32 {
+    let mut mxcsr: u32 = 0;
+    std::arch::asm!("stmxcsr [{}]", in(reg) &mut mxcsr, options(nostack, preserves_flags));
+
+    mxcsr
+}
+
+/// Overwrite the current thread's MXCSR register.
+#[cfg(all(target_feature = "sse", not(feature = "allow_denormals")))]
+unsafe fn write_mxcsr(mxcsr: u32) {
+    std::arch::asm!("ldmxcsr [{}]", in(reg) &mxcsr, options(nostack, readonly, preserves_flags));
+}
+
 #[cfg(test)]
 mod miri {
     use std::ffi::CStr;
@@ -237,3 +599,291 @@ mod miri {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::range::FloatRange;
+    use crate::param::{FloatParam, Param};
+
+    #[test]
+    fn unique_param_ids() {
+        assert_unique_param_ids(["foo", "bar", "foo_1", "foo_2"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "[\"bar\", \"foo\"]")]
+    fn duplicate_param_ids() {
+        assert_unique_param_ids(["foo", "bar", "foo_1", "foo", "bar", "foo"]);
+    }
+
+    #[test]
+    fn unique_poly_modulation_ids() {
+        assert_unique_poly_modulation_ids([0, 1, 2]);
+        assert_unique_poly_modulation_ids([]);
+    }
+
+    #[test]
+    #[should_panic(expected = "[1, 5]")]
+    fn duplicate_poly_modulation_ids() {
+        assert_unique_poly_modulation_ids([5, 1, 2, 1, 5, 5]);
+    }
+
+    #[test]
+    fn distinct_plugin_instance_ids() {
+        assert_ne!(next_plugin_instance_id(), next_plugin_instance_id());
+    }
+
+    #[test]
+    fn gesture_group_deduplicates_params() {
+        let range = FloatRange::Linear { min: 0.0, max: 1.0 };
+        let first_param = FloatParam::new("First", 0.0, range);
+        let second_param = FloatParam::new("Second", 0.0, range);
+        let first = first_param.as_ptr();
+        let second = second_param.as_ptr();
+
+        let group = GestureGroup::default();
+        assert!(group.begin_param(first));
+        assert!(group.begin_param(first));
+        assert_eq!(group.end(), None);
+
+        assert!(group.begin());
+        assert!(!group.begin());
+        assert!(group.begin_param(second));
+        assert!(group.begin_param(first));
+        assert!(!group.begin_param(second));
+        assert_eq!(group.end(), Some(vec![second, first]));
+        assert!(!group.is_active());
+    }
+
+    #[test]
+    fn tail_samples_from_status() {
+        assert_eq!(tail_samples(ProcessStatus::Normal), 0);
+        assert_eq!(tail_samples(ProcessStatus::Tail(0)), 0);
+        assert_eq!(tail_samples(ProcessStatus::Tail(48000)), 48000);
+        assert_eq!(tail_samples(ProcessStatus::KeepAlive), u32::MAX);
+        assert_eq!(tail_samples(ProcessStatus::Error("oh no")), 0);
+    }
+
+    /// Only stores the scaling factor it receives, and rejects factors above 2.0.
+    #[derive(Default)]
+    struct ScalingEditor {
+        scaling_factor: AtomicF32,
+    }
+
+    impl Editor for ScalingEditor {
+        fn spawn(
+            &self,
+            _parent: crate::plugin::ParentWindowHandle,
+            _context: std::sync::Arc<dyn crate::context::GuiContext>,
+        ) -> Option<Box<dyn std::any::Any + Send + Sync>> {
+            Some(Box::new(()))
+        }
+
+        fn size(&self) -> (u32, u32) {
+            (100, 100)
+        }
+
+        fn set_scale_factor(&self, factor: f32) -> bool {
+            if factor > 2.0 {
+                return false;
+            }
+
+            self.scaling_factor.store(factor, Ordering::Relaxed);
+            true
+        }
+
+        fn param_values_changed(&self) {}
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn editor_scale_factor_changes() {
+        let editor = ScalingEditor::default();
+        let scaling_factor = AtomicF32::new(1.0);
+
+        assert!(set_editor_scale_factor(&editor, &scaling_factor, 1.5));
+        assert_eq!(editor.scaling_factor.load(Ordering::Relaxed), 1.5);
+        assert_eq!(scaling_factor.load(Ordering::Relaxed), 1.5);
+
+        // A rejected change should leave the old factor in place
+        assert!(!set_editor_scale_factor(&editor, &scaling_factor, 3.0));
+        assert_eq!(scaling_factor.load(Ordering::Relaxed), 1.5);
+
+        assert!(set_editor_scale_factor(&editor, &scaling_factor, 1.0));
+        assert_eq!(editor.scaling_factor.load(Ordering::Relaxed), 1.0);
+        assert_eq!(scaling_factor.load(Ordering::Relaxed), 1.0);
+    }
+
+    struct NoParams;
+
+    unsafe impl crate::param::internals::Params for NoParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            Vec::new()
+        }
+    }
+
+    /// The bus config negotiation behavior for a [`TestPlugin`].
+    trait TestBusConfigs: Send + Sync + 'static {
+        const AUDIO_PORT_CONFIGS: &'static [BusConfig] = &[];
+
+        /// Used for [`Plugin::accepts_bus_config()`]. Only accepts the default stereo config by
+        /// default.
+        fn accepts_bus_config(config: &BusConfig) -> bool {
+            config.num_input_channels == 2 && config.num_output_channels == 2
+        }
+    }
+
+    /// A stereo-only plugin that uses the default bus config negotiation.
+    struct Stereo;
+
+    impl TestBusConfigs for Stereo {}
+
+    /// A stereo plugin that can also be used as a mono plugin.
+    struct MonoStereo;
+
+    impl TestBusConfigs for MonoStereo {
+        fn accepts_bus_config(config: &BusConfig) -> bool {
+            config.num_input_channels == config.num_output_channels
+        }
+    }
+
+    /// A plugin that declares its supported configurations up front.
+    struct Surround;
+
+    impl TestBusConfigs for Surround {
+        const AUDIO_PORT_CONFIGS: &'static [BusConfig] =
+            &[bus_config(2, 2), bus_config(6, 6), bus_config(6, 2)];
+    }
+
+    struct TestPlugin<C: TestBusConfigs>(PhantomData<C>);
+
+    impl<C: TestBusConfigs> Default for TestPlugin<C> {
+        fn default() -> Self {
+            Self(PhantomData)
+        }
+    }
+
+    impl<C: TestBusConfigs> Plugin for TestPlugin<C> {
+        const NAME: &'static str = "Test";
+        const VENDOR: &'static str = "";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.1";
+
+        const AUDIO_PORT_CONFIGS: &'static [BusConfig] = C::AUDIO_PORT_CONFIGS;
+
+        fn params(&self) -> std::sync::Arc<dyn crate::param::internals::Params> {
+            std::sync::Arc::new(NoParams)
+        }
+
+        fn accepts_bus_config(&self, config: &BusConfig) -> bool {
+            C::accepts_bus_config(config)
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut crate::buffer::Buffer,
+            _aux: &mut crate::plugin::AuxiliaryBuffers,
+            _context: &mut impl crate::context::ProcessContext,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    const fn bus_config(num_input_channels: u32, num_output_channels: u32) -> BusConfig {
+        BusConfig {
+            num_input_channels,
+            num_output_channels,
+            aux_input_busses: crate::plugin::AuxiliaryIOConfig {
+                num_busses: 0,
+                num_channels: 0,
+            },
+            aux_output_busses: crate::plugin::AuxiliaryIOConfig {
+                num_busses: 0,
+                num_channels: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn probed_bus_configs() {
+        assert_eq!(
+            supported_bus_configs(&TestPlugin::<MonoStereo>::default()),
+            [bus_config(1, 1), bus_config(2, 2)]
+        );
+    }
+
+    #[test]
+    fn declared_bus_configs() {
+        // The declared configs don't need to pass `accepts_bus_config()`
+        assert_eq!(
+            supported_bus_configs(&TestPlugin::<Surround>::default()),
+            Surround::AUDIO_PORT_CONFIGS
+        );
+    }
+
+    #[test]
+    fn negotiate_bus_configs() {
+        let stereo = TestPlugin::<Stereo>::default();
+        assert!(bus_config_supported(&stereo, &bus_config(2, 2)));
+        assert!(!bus_config_supported(&stereo, &bus_config(1, 1)));
+        assert!(!bus_config_supported(&stereo, &bus_config(1, 2)));
+
+        let mono_stereo = TestPlugin::<MonoStereo>::default();
+        assert!(bus_config_supported(&mono_stereo, &bus_config(1, 1)));
+
+        let surround = TestPlugin::<Surround>::default();
+        assert!(bus_config_supported(&surround, &bus_config(6, 2)));
+        assert!(!bus_config_supported(&surround, &bus_config(1, 1)));
+    }
+
+    #[test]
+    fn log_level_filter_from_env() {
+        assert_eq!(
+            log_level_filter(Some("warn")),
+            Ok(simplelog::LevelFilter::Warn)
+        );
+        assert_eq!(
+            log_level_filter(Some("ERROR")),
+            Ok(simplelog::LevelFilter::Error)
+        );
+        assert_eq!(
+            log_level_filter(Some("off")),
+            Ok(simplelog::LevelFilter::Off)
+        );
+        assert_eq!(log_level_filter(Some("")), log_level_filter(None));
+
+        // Invalid values fall back to the default level, but the caller gets to log a warning
+        let default_level = log_level_filter(None).unwrap();
+        assert_eq!(log_level_filter(Some("loud")), Err(default_level));
+    }
+
+    #[test]
+    fn clamp_output_event_timing_in_block() {
+        let mut event = NoteEvent::NoteOn {
+            timing: 10,
+            voice_id: None,
+            channel: 0,
+            note: 60,
+            velocity: 1.0,
+        };
+        clamp_output_event_timing(&mut event, 64);
+
+        assert_eq!(event.timing(), 10);
+    }
+
+    #[test]
+    fn clamp_output_event_timing_past_block() {
+        let mut event = NoteEvent::NoteOff {
+            timing: 100,
+            voice_id: None,
+            channel: 0,
+            note: 60,
+            velocity: 0.0,
+        };
+        clamp_output_event_timing(&mut event, 64);
+
+        assert_eq!(event.timing(), 63);
+    }
+}
@@ -0,0 +1,196 @@
+//! The logger installed by [`setup_logger()`][super::setup_logger()]. Log messages are forwarded
+//! to the host's logging facility when a wrapper has installed a [`HostLogSink`], and they're
+//! written to STDERR, a file, or the Windows debugger otherwise.
+
+use parking_lot::{Mutex, RwLock};
+use std::sync::Arc;
+
+/// The maximum number of messages that are kept around until the first host log sink is
+/// installed. Messages beyond this limit are only written to the fallback logger.
+const EARLY_MESSAGES_CAPACITY: usize = 512;
+
+/// The installed host log sinks. Messages are sent to the most recently installed sink. There can
+/// be more than one sink when multiple plugin instances are loaded in the same process.
+static HOST_LOG_SINKS: RwLock<Vec<Arc<dyn HostLogSink>>> = RwLock::new(Vec::new());
+
+/// Messages logged before the first sink was installed. This is set to `None` once those messages
+/// have been flushed to a sink, so they're only ever sent to the host once.
+static EARLY_MESSAGES: Mutex<Option<Vec<(log::Level, String)>>> = Mutex::new(Some(Vec::new()));
+
+/// Something that writes log messages to the host's own log, so they show up in the DAW's console.
+/// The CLAP wrapper installs one of these during initialization if the host supports the log
+/// extension. VST3 does not have a comparable host interface, so VST3 plugins always use the
+/// fallback logger.
+pub trait HostLogSink: Send + Sync {
+    /// Write a single message to the host's log. This can be called from any thread, and it must
+    /// not log anything itself.
+    fn log(&self, level: log::Level, message: &str);
+}
+
+/// Removes the host log sink again when dropped. This needs to be dropped before the resources
+/// used by the sink are freed.
+#[must_use]
+pub struct HostLogSinkGuard {
+    sink: Arc<dyn HostLogSink>,
+}
+
+/// The `log` logger used by NIH-plug. See the module's docs.
+pub struct NihLogger {
+    /// The logger used when no host log sink has been installed.
+    fallback: Box<dyn log::Log>,
+    /// The maximum level that should be logged. `win_dbg_logger`'s logger does not do any
+    /// filtering on its own, so this is checked here instead.
+    level: log::LevelFilter,
+    /// Whether messages should be sent to a host log sink if one is installed. This is disabled
+    /// when the user explicitly chose where the output should go using the `NIH_LOG` environment
+    /// variable.
+    use_host_sink: bool,
+}
+
+/// Start sending log messages to `sink`. Any messages that were logged before the first sink was
+/// installed are immediately written to it. The sink stays active until the returned guard is
+/// dropped.
+pub fn install_host_log_sink(sink: Arc<dyn HostLogSink>) -> HostLogSinkGuard {
+    let mut sinks = HOST_LOG_SINKS.write();
+
+    // The write lock prevents new messages from being added while these are being flushed, so
+    // nothing can get lost or be logged out of order here
+    if let Some(early_messages) = EARLY_MESSAGES.lock().take() {
+        for (level, message) in early_messages {
+            sink.log(level, &message);
+        }
+    }
+
+    sinks.push(sink.clone());
+    HostLogSinkGuard { sink }
+}
+
+impl Drop for HostLogSinkGuard {
+    fn drop(&mut self) {
+        // `Arc::ptr_eq()` would also compare the vtable pointers, which is not what we want here
+        let sink_ptr = Arc::as_ptr(&self.sink) as *const ();
+        HOST_LOG_SINKS
+            .write()
+            .retain(|sink| Arc::as_ptr(sink) as *const () != sink_ptr);
+    }
+}
+
+impl NihLogger {
+    pub fn new(fallback: Box<dyn log::Log>, level: log::LevelFilter, use_host_sink: bool) -> Self {
+        Self {
+            fallback,
+            level,
+            use_host_sink,
+        }
+    }
+}
+
+impl log::Log for NihLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level && self.fallback.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if self.use_host_sink {
+            // The read lock is held while the sink is used so it cannot be removed in the meantime
+            let sinks = HOST_LOG_SINKS.read();
+            match sinks.last() {
+                Some(sink) => {
+                    sink.log(record.level(), &record.args().to_string());
+                    return;
+                }
+                None => {
+                    if let Some(early_messages) = &mut *EARLY_MESSAGES.lock() {
+                        if early_messages.len() < EARLY_MESSAGES_CAPACITY {
+                            early_messages.push((record.level(), record.args().to_string()));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.fallback.log(record);
+    }
+
+    fn flush(&self) {
+        self.fallback.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Log;
+
+    use super::*;
+
+    /// Discards everything.
+    struct NullLogger;
+
+    impl log::Log for NullLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, _record: &log::Record) {}
+
+        fn flush(&self) {}
+    }
+
+    /// Stores every message it receives.
+    #[derive(Default)]
+    struct RecordingSink {
+        messages: Mutex<Vec<(log::Level, String)>>,
+    }
+
+    impl HostLogSink for RecordingSink {
+        fn log(&self, level: log::Level, message: &str) {
+            self.messages.lock().push((level, message.to_owned()));
+        }
+    }
+
+    #[test]
+    fn early_messages_are_flushed_to_sink() {
+        let logger = NihLogger::new(Box::new(NullLogger), log::LevelFilter::Info, true);
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .args(format_args!("before the sink"))
+                .build(),
+        );
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Debug)
+                .args(format_args!("filtered out"))
+                .build(),
+        );
+
+        let sink = Arc::new(RecordingSink::default());
+        let guard = install_host_log_sink(sink.clone());
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Info)
+                .args(format_args!("after the sink"))
+                .build(),
+        );
+
+        drop(guard);
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Error)
+                .args(format_args!("after removing the sink"))
+                .build(),
+        );
+
+        assert_eq!(
+            *sink.messages.lock(),
+            [
+                (log::Level::Warn, String::from("before the sink")),
+                (log::Level::Info, String::from("after the sink")),
+            ]
+        );
+    }
+}
@@ -2,14 +2,17 @@ use atomic_refcell::AtomicRefMut;
 use std::collections::VecDeque;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use vst3_sys::vst::IComponentHandler;
+use vst3_sys::vst::{IComponentHandler, IComponentHandler2};
 
 use super::inner::{Task, WrapperInner};
-use crate::context::{GuiContext, InitContext, PluginApi, ProcessContext, Transport};
+use crate::context::{
+    AutomationState, GuiContext, InitContext, PluginApi, ProcessContext, Transport,
+};
 use crate::midi::NoteEvent;
 use crate::param::internals::ParamPtr;
-use crate::plugin::Vst3Plugin;
+use crate::plugin::{ProcessMode, Vst3Plugin};
 use crate::wrapper::state::PluginState;
+use crate::wrapper::util::clamp_output_event_timing;
 
 /// A [`GuiContext`] implementation for the wrapper. This is passed to the plugin in
 /// [`Editor::spawn()`][crate::prelude::Editor::spawn()] so it can interact with the rest of the plugin and
@@ -33,6 +36,8 @@ pub(crate) struct WrapperProcessContext<'a, P: Vst3Plugin> {
     pub(super) input_events_guard: AtomicRefMut<'a, VecDeque<NoteEvent>>,
     pub(super) output_events_guard: AtomicRefMut<'a, VecDeque<NoteEvent>>,
     pub(super) transport: Transport,
+    /// The number of samples in the current block. Output events are clamped to this length.
+    pub(super) block_len: usize,
 }
 
 impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
@@ -40,18 +45,43 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
         PluginApi::Vst3
     }
 
+    fn plugin_instance_id(&self) -> u64 {
+        self.inner.plugin_instance_id
+    }
+
     fn request_resize(&self) -> bool {
+        // The host's answer is only available when we can call `IPlugFrame::resizeView()` directly
+        if self.inner.is_main_thread() {
+            return match &*self.inner.plug_view.read() {
+                Some(plug_view) => unsafe { plug_view.request_resize() },
+                None => false,
+            };
+        }
+
         let task_posted = self.inner.do_maybe_async(Task::RequestResize);
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
 
-        // TODO: We don't handle resize request failures right now. In practice this should however
-        //       not happen.
-        true
+        task_posted
+    }
+
+    fn scale_factor(&self) -> f32 {
+        match &*self.inner.plug_view.read() {
+            Some(plug_view) => plug_view.scaling_factor(),
+            None => 1.0,
+        }
+    }
+
+    fn automation_state(&self) -> AutomationState {
+        self.inner.automation_state.load()
     }
 
     // All of these functions are supposed to be called from the main thread, so we'll put some
     // trust in the caller and assume that this is indeed the case
     unsafe fn raw_begin_set_parameter(&self, param: ParamPtr) {
+        if !self.inner.gesture_group.begin_param(param) {
+            return;
+        }
+
         match &*self.inner.component_handler.borrow() {
             Some(handler) => match self.inner.param_ptr_to_hash.get(&param) {
                 Some(hash) => {
@@ -64,6 +94,11 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
     }
 
     unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
+        // Parameters changed during a gesture don't need an explicit begin call
+        if self.inner.gesture_group.is_active() {
+            self.raw_begin_set_parameter(param);
+        }
+
         match &*self.inner.component_handler.borrow() {
             Some(handler) => match self.inner.param_ptr_to_hash.get(&param) {
                 Some(hash) => {
@@ -95,6 +130,11 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
     }
 
     unsafe fn raw_end_set_parameter(&self, param: ParamPtr) {
+        // During a gesture this is deferred until the gesture ends
+        if self.inner.gesture_group.is_active() {
+            return;
+        }
+
         match &*self.inner.component_handler.borrow() {
             Some(handler) => match self.inner.param_ptr_to_hash.get(&param) {
                 Some(hash) => {
@@ -106,6 +146,48 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
         }
     }
 
+    fn raw_param_by_id(&self, id: &str) -> Option<ParamPtr> {
+        let hash = self.inner.param_id_to_hash.get(id)?;
+        self.inner.param_by_hash.get(hash).copied()
+    }
+
+    fn raw_begin_gesture(&self) {
+        let started = self.inner.gesture_group.begin();
+        nih_debug_assert!(
+            started,
+            "begin_gesture() was called while a gesture was already active"
+        );
+
+        // Hosts that support `IComponentHandler2` will treat all edits until the group edit is
+        // finished as a single undo step
+        if started {
+            if let Some(handler) = &*self.inner.component_handler.borrow() {
+                if let Some(handler) = handler.cast::<dyn IComponentHandler2>() {
+                    unsafe { handler.start_group_edit() };
+                }
+            }
+        }
+    }
+
+    fn raw_end_gesture(&self) {
+        match self.inner.gesture_group.end() {
+            Some(params) => {
+                for param in params {
+                    unsafe { self.raw_end_set_parameter(param) };
+                }
+
+                if let Some(handler) = &*self.inner.component_handler.borrow() {
+                    if let Some(handler) = handler.cast::<dyn IComponentHandler2>() {
+                        unsafe { handler.finish_group_edit() };
+                    }
+                }
+            }
+            None => nih_debug_assert_failure!(
+                "end_gesture() was called without a matching begin_gesture()"
+            ),
+        }
+    }
+
     fn get_state(&self) -> PluginState {
         self.inner.get_state_object()
     }
@@ -130,16 +212,31 @@ impl<P: Vst3Plugin> ProcessContext for WrapperProcessContext<'_, P> {
         PluginApi::Vst3
     }
 
+    fn plugin_instance_id(&self) -> u64 {
+        self.inner.plugin_instance_id
+    }
+
     fn transport(&self) -> &Transport {
         &self.transport
     }
 
+    fn process_mode(&self) -> ProcessMode {
+        self.inner.current_process_mode.load()
+    }
+
     fn next_event(&mut self) -> Option<NoteEvent> {
         self.input_events_guard.pop_front()
     }
 
-    fn send_event(&mut self, event: NoteEvent) {
-        self.output_events_guard.push_back(event);
+    fn send_event(&mut self, mut event: NoteEvent) {
+        clamp_output_event_timing(&mut event, self.block_len);
+
+        // The events are kept sorted by timing, with events at the same sample keeping the order
+        // they were sent in
+        let insert_idx = self
+            .output_events_guard
+            .partition_point(|e| e.timing() <= event.timing());
+        self.output_events_guard.insert(insert_idx, event);
     }
 
     fn set_latency_samples(&self, samples: u32) {
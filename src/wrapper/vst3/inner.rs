@@ -2,7 +2,7 @@ use atomic_refcell::AtomicRefCell;
 use crossbeam::atomic::AtomicCell;
 use crossbeam::channel::{self, SendTimeoutError};
 use parking_lot::RwLock;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, VecDeque};
 use std::mem::MaybeUninit;
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
@@ -16,14 +16,19 @@ use super::param_units::ParamUnits;
 use super::util::{ObjectPtr, VstPtr, VST3_MIDI_PARAMS_END, VST3_MIDI_PARAMS_START};
 use super::view::WrapperView;
 use crate::buffer::Buffer;
-use crate::context::Transport;
-use crate::event_loop::{EventLoop, MainThreadExecutor, OsEventLoop};
+use crate::context::{AutomationState, Transport};
+use crate::event_loop::{
+    coalesce_tasks, EventLoop, MainThreadExecutor, OsEventLoop, ShutdownMode, TaskDispatch,
+};
 use crate::midi::{MidiConfig, NoteEvent};
 use crate::param::internals::{ParamPtr, Params};
 use crate::param::ParamFlags;
 use crate::plugin::{BufferConfig, BusConfig, Editor, ProcessMode, ProcessStatus, Vst3Plugin};
 use crate::wrapper::state::{self, PluginState};
-use crate::wrapper::util::{hash_param_id, process_wrapper};
+use crate::wrapper::util::{
+    assert_unique_param_ids, find_bypass_param, hash_param_id, next_plugin_instance_id,
+    process_wrapper, task_queue_capacity, GestureGroup,
+};
 
 /// The actual wrapper bits. We need this as an `Arc<T>` so we can safely use our event loop API.
 /// Since we can't combine that with VST3's interior reference counting this just has to be moved to
@@ -43,6 +48,9 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     /// The host's [`IComponentHandler`] instance, if passed through
     /// [`IEditController::set_component_handler`].
     pub component_handler: AtomicRefCell<Option<VstPtr<dyn IComponentHandler>>>,
+    /// The parameters touched during a multi-parameter gesture started from the editor's
+    /// [`GuiContext`][crate::prelude::GuiContext].
+    pub gesture_group: GestureGroup,
 
     /// Our own [`IPlugView`] instance. This is set while the editor is actually visible (which is
     /// different form the lifetime of [`WrapperView`][super::WrapperView] itself).
@@ -62,6 +70,10 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     /// Whether the plugin is currently processing audio. In other words, the last state
     /// `IAudioProcessor::setActive()` has been called with.
     pub is_processing: AtomicBool,
+    /// Set when the plugin could not be reinitialized after restoring its state. The plugin is in
+    /// an undefined state until the host reactivates it, so audio processing fails until then. See
+    /// [`handle_reinitialization_failure()`][Self::handle_reinitialization_failure()].
+    pub reinitialization_failed: AtomicBool,
     /// The current bus configuration, modified through `IAudioProcessor::setBusArrangements()`.
     pub current_bus_config: AtomicCell<BusConfig>,
     /// The current buffer configuration, containing the sample rate and the maximum block size.
@@ -69,6 +81,10 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     pub current_buffer_config: AtomicCell<Option<BufferConfig>>,
     /// The current audio processing mode. Set in `IAudioProcessor::setup_processing()`.
     pub current_process_mode: AtomicCell<ProcessMode>,
+    /// This instance's ID, returned from `plugin_instance_id()` on the contexts.
+    pub plugin_instance_id: u64,
+    /// The host's automation mode, set through `IAutomationState::set_automation_state()`.
+    pub automation_state: AtomicCell<AutomationState>,
     /// The last process status returned by the plugin. This is used for tail handling.
     pub last_process_status: AtomicCell<ProcessStatus>,
     /// The current latency in samples, as set by the plugin through the [`ProcessContext`].
@@ -143,12 +159,15 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     /// having to add a setter function to the parameter (or even worse, have it be completely
     /// untyped).
     pub param_ptr_to_hash: HashMap<ParamPtr, u32>,
+    /// The plugin's bypass parameter, if it has one. Used to implement
+    /// [`Plugin::BYPASS_PASSTHROUGH`][crate::prelude::Plugin::BYPASS_PASSTHROUGH].
+    pub bypass_param: Option<ParamPtr>,
 }
 
 /// Tasks that can be sent from the plugin to be executed on the main thread in a non-blocking
 /// realtime safe way (either a random thread or `IRunLoop` on Linux, the OS' message loop on
 /// Windows and macOS).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Task {
     /// Trigger a restart with the given restart flags. This is a bit set of the flags from
     /// [`vst3_sys::vst::RestartFlags`].
@@ -211,19 +230,12 @@ impl<P: Vst3Plugin> WrapperInner<P> {
                 (id, hash, ptr, group)
             })
             .collect();
-        if cfg!(debug_assertions) {
-            let param_map = params.param_map();
-            let param_ids: HashSet<_> = param_id_hashes_ptrs_groups
+        assert_unique_param_ids(
+            param_id_hashes_ptrs_groups
                 .iter()
-                .map(|(id, _, _, _)| id.clone())
-                .collect();
-            nih_debug_assert_eq!(
-                param_map.len(),
-                param_ids.len(),
-                "The plugin has duplicate parameter IDs, weird things may happen. \
-                 Consider using 6 character parameter IDs to avoid collissions.."
-            );
-
+                .map(|(id, _, _, _)| id.as_str()),
+        );
+        if cfg!(debug_assertions) {
             let mut bypass_param_exists = false;
             for (id, hash, ptr, _) in &param_id_hashes_ptrs_groups {
                 let flags = unsafe { ptr.flags() };
@@ -251,6 +263,8 @@ impl<P: Vst3Plugin> WrapperInner<P> {
             .iter()
             .map(|(_, hash, _, _)| *hash)
             .collect();
+        let bypass_param =
+            find_bypass_param(param_id_hashes_ptrs_groups.iter().map(|(_, _, ptr, _)| ptr));
         let param_by_hash = param_id_hashes_ptrs_groups
             .iter()
             .map(|(_, hash, ptr, _)| (*hash, *ptr))
@@ -259,8 +273,7 @@ impl<P: Vst3Plugin> WrapperInner<P> {
             param_id_hashes_ptrs_groups
                 .iter()
                 .map(|(_, hash, _, group_name)| (*hash, group_name.as_str())),
-        )
-        .expect("Inconsistent parameter groups");
+        );
         let param_id_to_hash = param_id_hashes_ptrs_groups
             .iter()
             .map(|(id, hash, _, _)| (id.clone(), *hash))
@@ -276,12 +289,14 @@ impl<P: Vst3Plugin> WrapperInner<P> {
             editor,
 
             component_handler: AtomicRefCell::new(None),
+            gesture_group: GestureGroup::default(),
 
             plug_view: RwLock::new(None),
 
             event_loop: AtomicRefCell::new(MaybeUninit::uninit()),
 
             is_processing: AtomicBool::new(false),
+            reinitialization_failed: AtomicBool::new(false),
             // Some hosts, like the current version of Bitwig and Ardour at the time of writing,
             // will try using the plugin's default not yet initialized bus arrangement. Because of
             // that, we'll always initialize this configuration even before the host requests a
@@ -294,6 +309,8 @@ impl<P: Vst3Plugin> WrapperInner<P> {
             }),
             current_buffer_config: AtomicCell::new(None),
             current_process_mode: AtomicCell::new(ProcessMode::Realtime),
+            plugin_instance_id: next_plugin_instance_id(),
+            automation_state: AtomicCell::new(AutomationState::Unknown),
             last_process_status: AtomicCell::new(ProcessStatus::Normal),
             current_latency: AtomicU32::new(0),
             output_buffer: AtomicRefCell::new(Buffer::default()),
@@ -312,14 +329,19 @@ impl<P: Vst3Plugin> WrapperInner<P> {
             param_units,
             param_id_to_hash,
             param_ptr_to_hash,
+            bypass_param,
         };
 
         // FIXME: Right now this is safe, but if we are going to have a singleton main thread queue
         //        serving multiple plugin instances, Arc can't be used because its reference count
         //        is separate from the internal COM-style reference count.
         let wrapper: Arc<WrapperInner<P>> = wrapper.into();
-        *wrapper.event_loop.borrow_mut() =
-            MaybeUninit::new(OsEventLoop::new_and_spawn(Arc::downgrade(&wrapper)));
+        *wrapper.event_loop.borrow_mut() = MaybeUninit::new(OsEventLoop::new_and_spawn(
+            Arc::downgrade(&wrapper),
+            P::NAME,
+            task_queue_capacity::<P>(),
+            ShutdownMode::for_plugin::<P>(),
+        ));
 
         wrapper
     }
@@ -332,12 +354,17 @@ impl<P: Vst3Plugin> WrapperInner<P> {
         WrapperInitContext { inner: self }
     }
 
-    pub fn make_process_context(&self, transport: Transport) -> WrapperProcessContext<'_, P> {
+    pub fn make_process_context(
+        &self,
+        transport: Transport,
+        block_len: usize,
+    ) -> WrapperProcessContext<'_, P> {
         WrapperProcessContext {
             inner: self,
             input_events_guard: self.input_events.borrow_mut(),
             output_events_guard: self.output_events.borrow_mut(),
             transport,
+            block_len,
         }
     }
 
@@ -348,27 +375,80 @@ impl<P: Vst3Plugin> WrapperInner<P> {
     /// If the task queue is full, then this will return false.
     #[must_use]
     pub fn do_maybe_async(&self, task: Task) -> bool {
+        matches!(
+            self.do_maybe_async_status(task),
+            TaskDispatch::ExecutedInline | TaskDispatch::Queued
+        )
+    }
+
+    /// The same as [`do_maybe_async()`][Self::do_maybe_async()], but this returns whether the task
+    /// was executed directly, whether it was queued, or why it was dropped. Tasks posted to the
+    /// host's `IRunLoop` count as queued.
+    #[must_use]
+    pub fn do_maybe_async_status(&self, task: Task) -> TaskDispatch {
         let event_loop = self.event_loop.borrow();
         let event_loop = unsafe { event_loop.assume_init_ref() };
         if event_loop.is_main_thread() {
             unsafe { self.execute(task) };
-            true
+            TaskDispatch::ExecutedInline
         } else {
             // If the editor is open, and the host exposes the `IRunLoop` interface, then we'll run
             // the task on the host's GUI thread using that interface. Otherwise we'll use the
             // regular eent loop. If the editor gets dropped while there's still outstanding work
             // left in the run loop task queue, then those tasks will be posted to the regular event
             // loop so no work is lost.
-            match &*self.plug_view.read() {
+            let task = match &*self.plug_view.read() {
                 Some(plug_view) => match plug_view.do_maybe_in_run_loop(task) {
-                    Ok(()) => true,
-                    Err(task) => event_loop.do_maybe_async(task),
+                    Ok(()) => return TaskDispatch::Queued,
+                    Err(task) => task,
                 },
-                None => event_loop.do_maybe_async(task),
+                None => task,
+            };
+
+            // Resizing the editor should not have to wait for any other tasks, and restart
+            // requests notify the host about parameter value and state changes. Those need to
+            // reach the host even while the event loop is paused because the plugin is deactivated,
+            // for instance after the host restored the plugin's state from another thread.
+            match task {
+                Task::RequestResize | Task::TriggerRestart(_) => {
+                    event_loop.do_maybe_async_high_priority(task)
+                }
             }
         }
     }
 
+    /// Whether this is called from the thread the wrapper was created on, which is the host's GUI
+    /// thread.
+    pub fn is_main_thread(&self) -> bool {
+        let event_loop = self.event_loop.borrow();
+        unsafe { event_loop.assume_init_ref() }.is_main_thread()
+    }
+
+    /// Park the event loop's worker thread. This is done while the plugin is deactivated and its
+    /// editor is closed. Tasks posted from the main thread are still executed directly, and the
+    /// tasks posted by [`do_maybe_async()`][Self::do_maybe_async()] from other threads use the
+    /// high priority queue, which is still processed while the worker is paused.
+    pub fn pause_event_loop(&self) {
+        let event_loop = self.event_loop.borrow();
+        unsafe { event_loop.assume_init_ref() }.pause();
+    }
+
+    /// Undo [`pause_event_loop()`][Self::pause_event_loop()]. Tasks queued in the meantime are
+    /// executed right away.
+    pub fn resume_event_loop(&self) {
+        let event_loop = self.event_loop.borrow();
+        unsafe { event_loop.assume_init_ref() }.resume();
+    }
+
+    /// Whether the host is allowed to change a parameter's value. Changes the host sends for
+    /// [`ParamFlags::READ_ONLY`] parameters should be ignored.
+    pub fn host_can_change_param(&self, hash: u32) -> bool {
+        match self.param_by_hash.get(&hash) {
+            Some(param_ptr) => !unsafe { param_ptr.flags() }.contains(ParamFlags::READ_ONLY),
+            None => true,
+        }
+    }
+
     /// If there's an editor open, let it know that parameter values have changed. This should be
     /// called whenever there's been a call or multiple calls to
     /// [`set_normalized_value_by_hash()[Self::set_normalized_value_by_hash()`].
@@ -424,6 +504,9 @@ impl<P: Vst3Plugin> WrapperInner<P> {
     /// prevent corrupting data and changing parameters during processing the actual state is only
     /// updated at the end of the audio processing cycle.
     pub fn set_state_object(&self, mut state: PluginState) {
+        // This needs to happen here so the audio thread does not need to allocate
+        state::migrate_state(&mut state, &*self.params, P::migrate_state);
+
         // Use a loop and timeouts to handle the super rare edge case when this function gets called
         // between a process call and the host disabling the plugin
         loop {
@@ -469,8 +552,12 @@ impl<P: Vst3Plugin> WrapperInner<P> {
                 let bus_config = self.current_bus_config.load();
                 if let Some(buffer_config) = self.current_buffer_config.load() {
                     let mut plugin = self.plugin.write();
-                    plugin.initialize(&bus_config, &buffer_config, &mut self.make_init_context());
-                    process_wrapper(|| plugin.reset());
+                    if plugin.initialize(&bus_config, &buffer_config, &mut self.make_init_context())
+                    {
+                        process_wrapper(|| plugin.reset());
+                    } else {
+                        self.handle_reinitialization_failure();
+                    }
                 }
 
                 break;
@@ -484,14 +571,31 @@ impl<P: Vst3Plugin> WrapperInner<P> {
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
     }
 
+    /// Called when [`Plugin::initialize()`][crate::prelude::Plugin::initialize()] returns `false`
+    /// while reinitializing the plugin after restoring its state. The plugin is marked as
+    /// inactive, and audio processing fails until the host reactivates the plugin through
+    /// `IComponent::setActive()`. That also reports the failure to the host if initialization
+    /// still fails. This can be called from any thread.
+    pub fn handle_reinitialization_failure(&self) {
+        nih_debug_assert_failure!("The plugin failed to reinitialize after restoring its state");
+
+        self.reinitialization_failed.store(true, Ordering::SeqCst);
+    }
+
     pub fn set_latency_samples(&self, samples: u32) {
         // Only trigger a restart if it's actually needed
         let old_latency = self.current_latency.swap(samples, Ordering::SeqCst);
-        if old_latency != samples {
-            let task_posted =
-                self.do_maybe_async(Task::TriggerRestart(RestartFlags::kLatencyChanged as i32));
-            nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+        if old_latency == samples {
+            return;
         }
+
+        let status =
+            self.do_maybe_async_status(Task::TriggerRestart(RestartFlags::kLatencyChanged as i32));
+        nih_debug_assert_ne!(
+            status,
+            TaskDispatch::QueueFull,
+            "The task queue is full, dropping task..."
+        );
     }
 }
 
@@ -518,4 +622,20 @@ impl<P: Vst3Plugin> MainThreadExecutor<Task> for WrapperInner<P> {
             },
         }
     }
+
+    unsafe fn execute_batch(&self, tasks: impl Iterator<Item = Task>) {
+        // The editor only needs to be resized once to its latest size, and multiple restart
+        // requests can be combined into a single request with all of the flags set
+        let mut restart_flags = 0;
+        for task in coalesce_tasks(tasks) {
+            match task {
+                Task::TriggerRestart(flags) => restart_flags |= flags,
+                Task::RequestResize => self.execute(Task::RequestResize),
+            }
+        }
+
+        if restart_flags != 0 {
+            self.execute(Task::TriggerRestart(restart_flags));
+        }
+    }
 }
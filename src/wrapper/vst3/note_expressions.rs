@@ -109,10 +109,12 @@ impl NoteExpressionController {
             .note_ids
             .iter()
             .find(|(note_id, _, _)| *note_id == event.note_id)?;
+        let voice_id = (event.note_id != -1).then_some(event.note_id);
 
         match event.type_id {
             VOLUME_EXPRESSION_ID => Some(NoteEvent::PolyVolume {
                 timing,
+                voice_id,
                 channel,
                 note,
                 // Because expression values in VST3 are always in the `[0, 1]` range, they added a
@@ -121,6 +123,7 @@ impl NoteExpressionController {
             }),
             PAN_EXPRESSION_ID => Some(NoteEvent::PolyPan {
                 timing,
+                voice_id,
                 channel,
                 note,
                 // Our panning expressions are symmetrical around 0
@@ -128,6 +131,7 @@ impl NoteExpressionController {
             }),
             TUNING_EXPRESSION_ID => Some(NoteEvent::PolyTuning {
                 timing,
+                voice_id,
                 channel,
                 note,
                 // This denormalized to the same [-120, 120] range used by CLAP and our expression
@@ -136,21 +140,24 @@ impl NoteExpressionController {
             }),
             VIBRATO_EXPRESSION_ID => Some(NoteEvent::PolyVibrato {
                 timing,
+                voice_id,
                 channel,
                 note,
                 vibrato: event.value as f32,
             }),
-            EXPRESSION_EXPRESSION_ID => Some(NoteEvent::PolyBrightness {
+            EXPRESSION_EXPRESSION_ID => Some(NoteEvent::PolyExpression {
                 timing,
+                voice_id,
                 channel,
                 note,
-                brightness: event.value as f32,
+                expression: event.value as f32,
             }),
-            BRIGHTNESS_EXPRESSION_ID => Some(NoteEvent::PolyExpression {
+            BRIGHTNESS_EXPRESSION_ID => Some(NoteEvent::PolyBrightness {
                 timing,
+                voice_id,
                 channel,
                 note,
-                expression: event.value as f32,
+                brightness: event.value as f32,
             }),
             _ => None,
         }
@@ -43,26 +43,27 @@ pub struct ParamUnit {
 impl ParamUnits {
     /// Construct a [`ParamUnits`] object from an iterator over pairs of `(param_hash, param_group)`
     /// where `param_hash` is the integer hash used to represent a parameter in the VST3 wrapper and
-    /// `param_group` is a slash delimited path.
-    ///
-    /// Returns an error if the iterator contains nested groups without a matching parent.
-    pub fn from_param_groups<'a, I>(groups: I) -> Result<Self, &'static str>
+    /// `param_group` is a slash delimited path. Units are also created for all of a group's parent
+    /// groups, even if those don't directly contain any parameters.
+    pub fn from_param_groups<'a, I>(groups: I) -> Self
     where
         I: Iterator<Item = (u32, &'a str)> + Clone,
     {
-        // First we'll build a unit for each unique parameter
-        let unique_group_names: HashSet<&str> = groups
-            .clone()
-            .into_iter()
-            .filter_map(|(_, group_name)| {
-                // The root should not be included here since that's a special case in VST3
-                if !group_name.is_empty() {
-                    Some(group_name)
-                } else {
-                    None
-                }
-            })
-            .collect();
+        // First we'll build a unit for each unique parameter group. A group like `Foo/Bar` may be
+        // the only group starting with `Foo`, so every prefix of a group's path also needs its own
+        // unit to act as a parent.
+        let mut unique_group_names: HashSet<&str> = HashSet::new();
+        for (_, group_name) in groups.clone() {
+            // The root should not be included here since that's a special case in VST3
+            if group_name.is_empty() {
+                continue;
+            }
+
+            unique_group_names.insert(group_name);
+            for (sep_pos, _) in group_name.match_indices('/') {
+                unique_group_names.insert(&group_name[..sep_pos]);
+            }
+        }
         let mut groups_units: Vec<(&str, ParamUnit)> = unique_group_names
             .into_iter()
             .map(|group_name| {
@@ -98,10 +99,7 @@ impl ParamUnits {
             // the root unit
             if let Some(sep_pos) = group_name.rfind('/') {
                 let parent_group_name = &group_name[..sep_pos];
-                let parent_unit_id = *vst3_unit_id_by_group_name
-                    .get(parent_group_name)
-                    .ok_or("Missing parent group")?;
-                unit.parent_id = parent_unit_id;
+                unit.parent_id = vst3_unit_id_by_group_name[parent_group_name];
             }
         }
 
@@ -116,10 +114,10 @@ impl ParamUnits {
             .collect();
         let units: Vec<ParamUnit> = groups_units.into_iter().map(|(_, unit)| unit).collect();
 
-        Ok(Self {
+        Self {
             units,
             unit_id_by_hash,
-        })
+        }
     }
 
     /// Get the number of units.
@@ -141,3 +139,45 @@ impl ParamUnits {
         self.unit_id_by_hash.get(&param_hash).copied()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_groups() {
+        let groups = [(1, ""), (2, "Foo"), (3, "Bar"), (4, "Foo")];
+        let units = ParamUnits::from_param_groups(groups.into_iter());
+
+        assert_eq!(units.len(), 2);
+        let (bar_id, bar) = units.info(0).unwrap();
+        let (foo_id, foo) = units.info(1).unwrap();
+        assert_eq!((bar.name.as_str(), bar.parent_id), ("Bar", kRootUnitId));
+        assert_eq!((foo.name.as_str(), foo.parent_id), ("Foo", kRootUnitId));
+
+        assert_eq!(units.get_vst3_unit_id(1), Some(kRootUnitId));
+        assert_eq!(units.get_vst3_unit_id(2), Some(foo_id));
+        assert_eq!(units.get_vst3_unit_id(3), Some(bar_id));
+        assert_eq!(units.get_vst3_unit_id(4), Some(foo_id));
+    }
+
+    #[test]
+    fn nested_groups_without_parameters() {
+        // There are no parameters directly in `Foo` or `Foo/Bar`, but they still need units
+        let groups = [(1, "Foo/Bar/Baz"), (2, "Foo/Qux")];
+        let units = ParamUnits::from_param_groups(groups.into_iter());
+
+        assert_eq!(units.len(), 4);
+        let (foo_id, foo) = units.info(0).unwrap();
+        let (bar_id, bar) = units.info(1).unwrap();
+        let (baz_id, baz) = units.info(2).unwrap();
+        let (qux_id, qux) = units.info(3).unwrap();
+        assert_eq!((foo.name.as_str(), foo.parent_id), ("Foo", kRootUnitId));
+        assert_eq!((bar.name.as_str(), bar.parent_id), ("Bar", foo_id));
+        assert_eq!((baz.name.as_str(), baz.parent_id), ("Baz", bar_id));
+        assert_eq!((qux.name.as_str(), qux.parent_id), ("Qux", foo_id));
+
+        assert_eq!(units.get_vst3_unit_id(1), Some(baz_id));
+        assert_eq!(units.get_vst3_unit_id(2), Some(qux_id));
+    }
+}
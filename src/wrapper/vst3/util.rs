@@ -1,10 +1,15 @@
 use std::cmp;
+use std::ffi::c_void;
+use std::io;
 use std::ops::Deref;
+use vst3_sys::base::IBStream;
 use vst3_sys::interfaces::IUnknown;
 use vst3_sys::vst::TChar;
 use vst3_sys::ComInterface;
 use widestring::U16CString;
 
+use crate::context::AutomationState;
+
 /// When `Plugin::MIDI_INPUT` is set to `MidiConfig::MidiCCs` or higher then we'll register 130*16
 /// additional parameters to handle MIDI CCs, channel pressure, and pitch bend, in that order.
 /// vst3-sys doesn't expose these constants.
@@ -108,6 +113,58 @@ impl<T: IUnknown> Drop for ObjectPtr<T> {
     }
 }
 
+/// Convert the `AutomationStates` flags passed to `IAutomationState::set_automation_state()`.
+pub fn automation_state_from_flags(flags: i32) -> AutomationState {
+    // These are `kReadState` and `kWriteState`
+    match (flags & (1 << 0) != 0, flags & (1 << 1) != 0) {
+        (false, false) => AutomationState::Off,
+        (true, false) => AutomationState::Read,
+        (false, true) => AutomationState::Write,
+        (true, true) => AutomationState::ReadWrite,
+    }
+}
+
+/// A [`Read`][io::Read] and [`Write`][io::Write] implementation for a host's `IBStream`, used to
+/// stream state data.
+pub struct VstStream<'a>(pub &'a vst3_sys::VstPtr<dyn IBStream>);
+
+impl io::Read for VstStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Some hosts will return `kResultFalse` when reading at the end of the stream, so we can
+        // only rely on the number of bytes read. Reading zero bytes means the end of the stream has
+        // been reached.
+        let mut num_bytes_read = 0;
+        let len = buf.len().min(i32::MAX as usize) as i32;
+        unsafe {
+            self.0
+                .read(buf.as_mut_ptr() as *mut c_void, len, &mut num_bytes_read)
+        };
+
+        Ok(num_bytes_read.max(0) as usize)
+    }
+}
+
+impl io::Write for VstStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut num_bytes_written = 0;
+        let len = buf.len().min(i32::MAX as usize) as i32;
+        let result = unsafe {
+            self.0
+                .write(buf.as_ptr() as *const c_void, len, &mut num_bytes_written)
+        };
+
+        if result == vst3_sys::base::kResultOk {
+            Ok(num_bytes_written.max(0) as usize)
+        } else {
+            Err(io::Error::other("The host could not write to the stream"))
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// SAFETY: Sharing these pointers across thread is s safe as they have internal atomic reference
 /// counting, so as long as a `VstPtr<T>` handle exists the object will stay alive.
 unsafe impl<T: ComInterface + ?Sized> Send for VstPtr<T> {}
@@ -122,6 +179,14 @@ mod miri {
 
     use super::*;
 
+    #[test]
+    fn automation_states() {
+        assert_eq!(automation_state_from_flags(0), AutomationState::Off);
+        assert_eq!(automation_state_from_flags(1), AutomationState::Read);
+        assert_eq!(automation_state_from_flags(2), AutomationState::Write);
+        assert_eq!(automation_state_from_flags(3), AutomationState::ReadWrite);
+    }
+
     #[test]
     fn u16strlcpy_normal() {
         let mut dest = [0; 256];
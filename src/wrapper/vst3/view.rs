@@ -14,6 +14,7 @@ use vst3_sys::VST3;
 use super::inner::{Task, WrapperInner};
 use super::util::{ObjectPtr, VstPtr};
 use crate::plugin::{Editor, ParentWindowHandle, Vst3Plugin};
+use crate::wrapper::util::{set_editor_scale_factor, task_queue_capacity};
 
 // Alias needed for the VST3 attribute macro
 use vst3_sys as vst3_com;
@@ -22,7 +23,7 @@ use vst3_sys as vst3_com;
 // NOTE: This should also be used on the BSDs, but vst3-sys exposes these interfaces only for Linux
 #[cfg(target_os = "linux")]
 use {
-    crate::event_loop::{EventLoop, MainThreadExecutor, TASK_QUEUE_CAPACITY},
+    crate::event_loop::{EventLoop, MainThreadExecutor},
     crossbeam::queue::ArrayQueue,
     libc,
     vst3_sys::gui::linux::{FileDescriptor, IEventHandler, IRunLoop},
@@ -145,11 +146,9 @@ impl<P: Vst3Plugin> WrapperView<P> {
                 let plug_view: SharedVstPtr<dyn IPlugView> =
                     mem::transmute(&self.__iplugviewvptr as *const *const _);
                 let result = plug_frame.resize_view(plug_view, &mut size);
-
-                debug_assert_eq!(
-                    result, kResultOk,
-                    "The host denied the resize, we currently don't handle this for VST3 plugins"
-                );
+                if result != kResultOk {
+                    nih_debug_assert_failure!("The host denied the resize request");
+                }
 
                 result == kResultOk
             }
@@ -157,6 +156,11 @@ impl<P: Vst3Plugin> WrapperView<P> {
         }
     }
 
+    /// The DPI scaling factor set by the host, or 1.0 if the host hasn't set one.
+    pub fn scaling_factor(&self) -> f32 {
+        self.scaling_factor.load(Ordering::Relaxed)
+    }
+
     /// If the host supports `IRunLoop`, then this will post the task to a task queue that will be
     /// run on the host's UI thread. If not, then this will return an `Err` value containing the
     /// task so it can be run elsewhere.
@@ -200,7 +204,7 @@ impl<P: Vst3Plugin> RunLoopEventHandler<P> {
             run_loop,
             socket_read_fd,
             socket_write_fd,
-            ArrayQueue::new(TASK_QUEUE_CAPACITY),
+            ArrayQueue::new(task_queue_capacity::<P>()),
         );
 
         // vst3-sys provides no way to convert to a SharedVstPtr, so, uh, yeah. These are pointers
@@ -314,11 +318,23 @@ impl<P: Vst3Plugin> IPlugView for WrapperView<P> {
                 }
             };
 
-            *editor_handle = Some(self.editor.spawn(
-                ParentWindowHandle { handle },
-                self.inner.clone().make_gui_context(),
-            ));
+            let parent = ParentWindowHandle { handle };
+            if !parent.is_valid() {
+                nih_debug_assert_failure!("Host passed a null window handle");
+                return kInvalidArgument;
+            }
+
+            *editor_handle = self
+                .editor
+                .spawn(parent, self.inner.clone().make_gui_context());
+            if editor_handle.is_none() {
+                nih_log!("The editor could not be opened");
+                return kResultFalse;
+            }
             *self.inner.plug_view.write() = Some(ObjectPtr::from(self));
+            // The event loop may have been paused while the plugin was deactivated, and the editor
+            // needs it to be able to post tasks from its own thread
+            self.inner.resume_event_loop();
 
             kResultOk
         } else {
@@ -464,18 +480,18 @@ impl<P: Vst3Plugin> IPlugViewContentScaleSupport for WrapperView<P> {
         //       on macOS and both the editor implementation and the wrappers would know about the
         //       correct scale.
 
-        // On macOS scaling is done by the OS, and all window sizes are in logical pixels
-        if cfg!(target_os = "macos") {
-            nih_debug_assert_failure!("Ignoring host request to set explicit DPI scaling factor");
+        let old_factor = self.scaling_factor.load(Ordering::Relaxed);
+        if !set_editor_scale_factor(self.editor.as_ref(), &self.scaling_factor, factor) {
             return kResultFalse;
         }
 
-        if self.editor.set_scale_factor(factor) {
-            self.scaling_factor.store(factor, Ordering::Relaxed);
-            kResultOk
-        } else {
-            kResultFalse
+        // The view's physical size changes with the scale, so the host needs to resize the window
+        // when this happens while the editor is open
+        if factor != old_factor {
+            self.request_resize();
         }
+
+        kResultOk
     }
 }
 
@@ -1,5 +1,6 @@
 use std::cmp;
-use std::ffi::c_void;
+use std::ffi::{c_void, CStr};
+use std::io;
 use std::mem::{self, MaybeUninit};
 use std::ptr;
 use std::sync::atomic::Ordering;
@@ -10,10 +11,10 @@ use vst3_sys::base::{IBStream, IPluginBase};
 use vst3_sys::utils::SharedVstPtr;
 use vst3_sys::vst::{
     kNoParamId, kNoParentUnitId, kNoProgramListId, kRootUnitId, Event, EventTypes, IAudioProcessor,
-    IComponent, IEditController, IEventList, IMidiMapping, INoteExpressionController,
-    IParamValueQueue, IParameterChanges, IProcessContextRequirements, IUnitInfo,
-    LegacyMidiCCOutEvent, NoteExpressionTypeInfo, NoteExpressionValueDescription, NoteOffEvent,
-    NoteOnEvent, ParameterFlags, PolyPressureEvent, ProgramListInfo, TChar, UnitInfo,
+    IAutomationState, IComponent, IEditController, IEventList, IMidiMapping,
+    INoteExpressionController, IParamValueQueue, IParameterChanges, IProcessContextRequirements,
+    IUnitInfo, LegacyMidiCCOutEvent, NoteExpressionTypeInfo, NoteExpressionValueDescription,
+    NoteOffEvent, NoteOnEvent, ParameterFlags, PolyPressureEvent, ProgramListInfo, TChar, UnitInfo,
 };
 use vst3_sys::VST3;
 use widestring::U16CStr;
@@ -33,10 +34,14 @@ use crate::plugin::{
 };
 use crate::util::permit_alloc;
 use crate::wrapper::state;
-use crate::wrapper::util::process_wrapper;
+use crate::wrapper::util::{
+    bus_config_supported, bypass_passthrough_active, process_wrapper, tail_samples,
+};
 use crate::wrapper::vst3::inner::ProcessEvent;
 use crate::wrapper::vst3::note_expressions::{self, NoteExpressionController};
-use crate::wrapper::vst3::util::{VST3_MIDI_CHANNELS, VST3_MIDI_PARAMS_END};
+use crate::wrapper::vst3::util::{
+    automation_state_from_flags, VstStream, VST3_MIDI_CHANNELS, VST3_MIDI_PARAMS_END,
+};
 
 // Alias needed for the VST3 attribute macro
 use vst3_sys as vst3_com;
@@ -48,7 +53,8 @@ use vst3_sys as vst3_com;
     IMidiMapping,
     INoteExpressionController,
     IProcessContextRequirements,
-    IUnitInfo
+    IUnitInfo,
+    IAutomationState
 ))]
 pub(crate) struct Wrapper<P: Vst3Plugin> {
     inner: Arc<WrapperInner<P>>,
@@ -395,6 +401,14 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
                     &buffer_config,
                     &mut self.inner.make_init_context(),
                 ) {
+                    // The event loop is only resumed once the plugin has been initialized, so a
+                    // failed initialization doesn't leave it running for an inactive plugin. Any
+                    // tasks the plugin posted while initializing are executed now.
+                    self.inner.resume_event_loop();
+                    self.inner
+                        .reinitialization_failed
+                        .store(false, Ordering::SeqCst);
+
                     // NOTE: We don't call `Plugin::reset()` here. The call is done in `set_process()`
                     //       instead. Otherwise we would call the function twice, and `set_process()` needs
                     //       to be called after this function before the plugin may process audio again.
@@ -461,6 +475,11 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
             (false, _) => {
                 self.inner.plugin.write().deactivate();
 
+                // The editor may still post resize requests from its own thread
+                if self.inner.plug_view.read().is_none() {
+                    self.inner.pause_event_loop();
+                }
+
                 kResultOk
             }
         }
@@ -471,39 +490,38 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
 
         let state = state.upgrade().unwrap();
 
-        // We need to know how large the state is before we can read it. The current position can be
-        // zero, but it can also be something else. Bitwig prepends the preset header in the stream,
-        // while some other hosts don't expose that to the plugin.
-        let mut current_pos = 0;
-        let mut eof_pos = 0;
-        if state.tell(&mut current_pos) != kResultOk
-            || state.seek(0, vst3_sys::base::kIBSeekEnd, &mut eof_pos) != kResultOk
-            || state.seek(current_pos, vst3_sys::base::kIBSeekSet, ptr::null_mut()) != kResultOk
-        {
-            nih_debug_assert_failure!("Could not get the stream length");
-            return kResultFalse;
-        }
-
-        let stream_byte_size = (eof_pos - current_pos) as i32;
-        let mut num_bytes_read = 0;
-        let mut read_buffer: Vec<u8> = Vec::with_capacity(stream_byte_size as usize);
-        state.read(
-            read_buffer.as_mut_ptr() as *mut c_void,
-            read_buffer.capacity() as i32,
-            &mut num_bytes_read,
-        );
-        read_buffer.set_len(num_bytes_read as usize);
+        // States containing data written by `Params::save_stream()` start with a magic header,
+        // followed by the JSON state's length. Other states only contain JSON data. Some hosts will
+        // return `kResultFalse` when reading zero bytes, so `VstStream` only looks at the number of
+        // bytes read.
+        let mut stream = VstStream(&state);
+        let (json, has_streamed_data) = match state::read_state(&mut stream) {
+            Ok(result) => result,
+            Err(err) => {
+                nih_debug_assert_failure!("Could not read the state: {}", err);
+                return kResultFalse;
+            }
+        };
 
-        // If the size is zero, some hosts will always return `kResultFalse` even if the read was
-        // 'successful', so we can't check the return value but we can check the number of bytes
-        // read.
-        if read_buffer.len() != stream_byte_size as usize {
-            nih_debug_assert_failure!("Unexpected stream length");
+        // The JSON state is parsed first so nothing gets changed if it's invalid. The streamed data
+        // then needs to be loaded before the plugin gets reinitialized below. States without
+        // streamed data are passed an empty reader.
+        let plugin_state = match state::parse_json(&json, &*self.inner.params, P::migrate_state) {
+            Some(plugin_state) => plugin_state,
+            None => return kResultFalse,
+        };
+        let load_result = if has_streamed_data {
+            self.inner.params.load_stream(&mut stream)
+        } else {
+            self.inner.params.load_stream(&mut io::empty())
+        };
+        if let Err(err) = load_result {
+            nih_debug_assert_failure!("Could not load streamed state: {}", err);
             return kResultFalse;
         }
 
-        let success = state::deserialize_json(
-            &read_buffer,
+        let success = state::deserialize_object(
+            &plugin_state,
             self.inner.params.clone(),
             state::make_params_getter(&self.inner.param_by_hash, &self.inner.param_id_to_hash),
             self.inner.current_buffer_config.load().as_ref(),
@@ -518,11 +536,15 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
         let bus_config = self.inner.current_bus_config.load();
         if let Some(buffer_config) = self.inner.current_buffer_config.load() {
             let mut plugin = self.inner.plugin.write();
-            plugin.initialize(
+            if !plugin.initialize(
                 &bus_config,
                 &buffer_config,
                 &mut self.inner.make_init_context(),
-            );
+            ) {
+                self.inner.handle_reinitialization_failure();
+                return kResultFalse;
+            }
+
             // TODO: This also goes for the CLAP version, but should we call reset here? Won't the
             //       host always restart playback? Check this with a couple of hosts and remove the
             //       duplicate reset if it's not needed.
@@ -543,15 +565,15 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
         );
         match serialized {
             Ok(serialized) => {
-                let mut num_bytes_written = 0;
-                let result = state.write(
-                    serialized.as_ptr() as *const c_void,
-                    serialized.len() as i32,
-                    &mut num_bytes_written,
-                );
+                // If the plugin streams additional data to the host, then the JSON state is
+                // prefixed with a header so it can be separated from the streamed data again
+                if let Err(err) =
+                    state::write_state(&mut VstStream(&state), &serialized, &*self.inner.params)
+                {
+                    nih_debug_assert_failure!("Could not save state: {}", err);
+                    return kResultFalse;
+                }
 
-                nih_debug_assert_eq!(result, kResultOk);
-                nih_debug_assert_eq!(num_bytes_written as usize, serialized.len());
                 kResultOk
             }
             Err(err) => {
@@ -633,8 +655,10 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
             let param_ptr = &self.inner.param_by_hash[param_hash];
             let default_value = param_ptr.default_normalized_value();
             let flags = param_ptr.flags();
-            let automatable = !flags.contains(ParamFlags::NON_AUTOMATABLE);
+            let automatable =
+                !flags.intersects(ParamFlags::NON_AUTOMATABLE | ParamFlags::READ_ONLY);
             let hidden = flags.contains(ParamFlags::HIDDEN);
+            let read_only = flags.contains(ParamFlags::READ_ONLY);
             let is_bypass = flags.contains(ParamFlags::BYPASS);
 
             info.id = *param_hash;
@@ -651,6 +675,9 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
             if hidden {
                 info.flags |= ParameterFlags::kIsReadOnly as i32 | (1 << 4); // kIsHidden
             }
+            if read_only {
+                info.flags |= ParameterFlags::kIsReadOnly as i32;
+            }
             if is_bypass {
                 info.flags |= ParameterFlags::kIsBypass as i32;
             }
@@ -738,6 +765,9 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
         if self.inner.is_processing.load(Ordering::SeqCst) {
             return kResultOk;
         }
+        if !self.inner.host_can_change_param(id) {
+            return kResultFalse;
+        }
 
         let sample_rate = self
             .inner
@@ -761,7 +791,12 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
         kResultOk
     }
 
-    unsafe fn create_view(&self, _name: vst3_sys::base::FIDString) -> *mut c_void {
+    unsafe fn create_view(&self, name: vst3_sys::base::FIDString) -> *mut c_void {
+        // `editor` is the only view type defined by the VST3 SDK, and hosts may probe for others
+        if !name.is_null() && CStr::from_ptr(name).to_bytes() != b"editor" {
+            return ptr::null_mut();
+        }
+
         // Without specialization this is the least redundant way to check if the plugin has an
         // editor. The default implementation returns a None here.
         match &self.inner.editor {
@@ -854,12 +889,7 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 num_channels: num_aux_output_channels,
             },
         };
-        if self
-            .inner
-            .plugin
-            .read()
-            .accepts_bus_config(&proposed_config)
-        {
+        if bus_config_supported(&*self.inner.plugin.read(), &proposed_config) {
             self.inner.current_bus_config.store(proposed_config);
 
             kResultOk
@@ -1005,6 +1035,11 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
     unsafe fn process(&self, data: *mut vst3_sys::vst::ProcessData) -> tresult {
         check_null_ptr!(data);
 
+        // The plugin first needs to be reactivated in this case
+        if self.inner.reinitialization_failed.load(Ordering::SeqCst) {
+            return kResultFalse;
+        }
+
         // Panic on allocations if the `assert_process_allocs` feature has been enabled, and make
         // sure that FTZ is set up correctly
         process_wrapper(|| {
@@ -1066,7 +1101,7 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                     {
                         let param_hash = param_change_queue.get_parameter_id();
                         let num_changes = param_change_queue.get_point_count();
-                        if num_changes <= 0 {
+                        if num_changes <= 0 || !self.inner.host_can_change_param(param_hash) {
                             continue;
                         }
 
@@ -1162,6 +1197,7 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                 timing,
                                 event: NoteEvent::NoteOn {
                                     timing,
+                                    voice_id: (event.note_id != -1).then_some(event.note_id),
                                     channel: event.channel as u8,
                                     note: event.pitch as u8,
                                     velocity: event.velocity,
@@ -1173,6 +1209,7 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                 timing,
                                 event: NoteEvent::NoteOff {
                                     timing,
+                                    voice_id: (event.note_id != -1).then_some(event.note_id),
                                     channel: event.channel as u8,
                                     note: event.pitch as u8,
                                     velocity: event.velocity,
@@ -1184,6 +1221,7 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                 timing,
                                 event: NoteEvent::PolyPressure {
                                     timing,
+                                    voice_id: (event.note_id != -1).then_some(event.note_id),
                                     channel: event.channel as u8,
                                     note: event.pitch as u8,
                                     pressure: event.pressure,
@@ -1221,7 +1259,7 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
             let mut block_start = 0usize;
             let mut block_end;
             let mut event_start_idx = 0;
-            let result = loop {
+            let mut result = loop {
                 // In sample-accurate automation mode we'll handle all parameter changes from the
                 // sorted process event array until we run into for the current sample, and then
                 // process the block between the current sample and the sample containing the next
@@ -1357,26 +1395,13 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                     } else {
                         auxiliary_input_idx as isize
                     };
-                    let host_input = data.inputs.offset(host_input_idx);
-                    if host_input_idx >= data.num_inputs as isize
-                             || data.inputs.is_null()
-                             || (*host_input).buffers.is_null()
-                             // Would only happen if the user configured zero channels for the
-                             // auxiliary buffers
-                             || storage.is_empty()
-                             || (*host_input).num_channels != buffer.channels() as i32
-                    {
-                        nih_debug_assert!(host_input_idx < data.num_inputs as isize);
-                        nih_debug_assert!(!data.inputs.is_null());
-                        nih_debug_assert!(!(*host_input).buffers.is_null());
-                        nih_debug_assert!(!storage.is_empty());
-                        nih_debug_assert_eq!((*host_input).num_channels, buffer.channels() as i32);
-
-                        // If the host passes weird data then we need to be very sure that there are
-                        // no dangling references to previous data
-                        buffer.with_raw_vec(|slices| slices.fill_with(|| &mut []));
-                        continue;
-                    }
+                    // A sidechain input the host hasn't connected (or that it passed with the wrong
+                    // channel layout) is treated as silence, so the plugin never reads stale data
+                    let host_input_connected = host_input_idx < data.num_inputs as isize
+                        && !data.inputs.is_null()
+                        && !(*data.inputs.offset(host_input_idx)).buffers.is_null()
+                        && (*data.inputs.offset(host_input_idx)).num_channels
+                            == buffer.channels() as i32;
 
                     // We'll always reuse the start of the buffer even of the current block is
                     // shorter for cache locality reasons
@@ -1386,11 +1411,16 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                         // zeroes when sizing up
                         assert!(block_len <= channel_storage.capacity());
                         channel_storage.set_len(block_len);
-                        channel_storage.copy_from_slice(std::slice::from_raw_parts(
-                            (*(*host_input).buffers.add(channel_idx)).add(block_start)
-                                as *const f32,
-                            block_len,
-                        ));
+                        if host_input_connected {
+                            let host_input = data.inputs.offset(host_input_idx);
+                            channel_storage.copy_from_slice(std::slice::from_raw_parts(
+                                (*(*host_input).buffers.add(channel_idx)).add(block_start)
+                                    as *const f32,
+                                block_len,
+                            ));
+                        } else {
+                            channel_storage.fill(0.0);
+                        }
                     }
 
                     buffer.with_raw_vec(|slices| {
@@ -1503,7 +1533,13 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                     }
                 }
 
-                let result = if buffer_is_valid {
+                // When the plugin opted into bypass passthrough, the main input has already been
+                // copied to the main output so there's nothing left to do
+                let bypassed = bypass_passthrough_active::<P>(
+                    self.inner.bypass_param,
+                    &self.inner.current_bus_config.load(),
+                );
+                let result = if buffer_is_valid && !bypassed {
                     let mut plugin = self.inner.plugin.write();
                     // SAFETY: Shortening these borrows is safe as even if the plugin overwrites the
                     //         slices (which it cannot do without using unsafe code), then they
@@ -1512,7 +1548,9 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                         inputs: &mut *(aux_input_buffers.as_mut_slice() as *mut [Buffer]),
                         outputs: &mut *(aux_output_buffers.as_mut_slice() as *mut [Buffer]),
                     };
-                    let mut context = self.inner.make_process_context(transport);
+                    let mut context = self
+                        .inner
+                        .make_process_context(transport, block_end - block_start);
                     let result = plugin.process(&mut output_buffer, &mut aux, &mut context);
                     self.inner.last_process_status.store(result);
                     result
@@ -1534,6 +1572,7 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                         match event {
                             NoteEvent::NoteOn {
                                 timing: _,
+                                voice_id,
                                 channel,
                                 note,
                                 velocity,
@@ -1545,13 +1584,16 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                     tuning: 0.0,
                                     velocity,
                                     length: 0, // What?
-                                    // We'll use this for our note IDs, that way we don't have
-                                    // to do anything complicated here
-                                    note_id: ((channel as i32) << 8) | note as i32,
+                                    // If the plugin didn't provide a voice ID, then we'll use
+                                    // this for our note IDs, that way we don't have to do
+                                    // anything complicated here
+                                    note_id: voice_id
+                                        .unwrap_or(((channel as i32) << 8) | note as i32),
                                 };
                             }
                             NoteEvent::NoteOff {
                                 timing: _,
+                                voice_id,
                                 channel,
                                 note,
                                 velocity,
@@ -1561,12 +1603,14 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                     channel: channel as i16,
                                     pitch: note as i16,
                                     velocity,
-                                    note_id: ((channel as i32) << 8) | note as i32,
+                                    note_id: voice_id
+                                        .unwrap_or(((channel as i32) << 8) | note as i32),
                                     tuning: 0.0,
                                 };
                             }
                             NoteEvent::PolyPressure {
                                 timing: _,
+                                voice_id,
                                 channel,
                                 note,
                                 pressure,
@@ -1575,20 +1619,49 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                 vst3_event.event.poly_pressure = PolyPressureEvent {
                                     channel: channel as i16,
                                     pitch: note as i16,
-                                    note_id: ((channel as i32) << 8) | note as i32,
+                                    note_id: voice_id
+                                        .unwrap_or(((channel as i32) << 8) | note as i32),
                                     pressure,
                                 };
                             }
-                            event @ (NoteEvent::PolyVolume { channel, note, .. }
-                            | NoteEvent::PolyPan { channel, note, .. }
-                            | NoteEvent::PolyTuning { channel, note, .. }
-                            | NoteEvent::PolyVibrato { channel, note, .. }
-                            | NoteEvent::PolyExpression { channel, note, .. }
-                            | NoteEvent::PolyBrightness { channel, note, .. })
-                                if P::MIDI_OUTPUT >= MidiConfig::Basic =>
-                            {
+                            event @ (NoteEvent::PolyVolume {
+                                voice_id,
+                                channel,
+                                note,
+                                ..
+                            }
+                            | NoteEvent::PolyPan {
+                                voice_id,
+                                channel,
+                                note,
+                                ..
+                            }
+                            | NoteEvent::PolyTuning {
+                                voice_id,
+                                channel,
+                                note,
+                                ..
+                            }
+                            | NoteEvent::PolyVibrato {
+                                voice_id,
+                                channel,
+                                note,
+                                ..
+                            }
+                            | NoteEvent::PolyExpression {
+                                voice_id,
+                                channel,
+                                note,
+                                ..
+                            }
+                            | NoteEvent::PolyBrightness {
+                                voice_id,
+                                channel,
+                                note,
+                                ..
+                            }) if P::MIDI_OUTPUT >= MidiConfig::Basic => {
                                 match NoteExpressionController::translate_event_reverse(
-                                    ((channel as i32) << 8) | note as i32,
+                                    voice_id.unwrap_or(((channel as i32) << 8) | note as i32),
                                     &event,
                                 ) {
                                     Some(translated_event) => {
@@ -1645,6 +1718,21 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                     value2: 0,
                                 };
                             }
+                            NoteEvent::MidiProgramChange {
+                                timing: _,
+                                channel,
+                                program,
+                            } if P::MIDI_OUTPUT >= MidiConfig::MidiCCs => {
+                                vst3_event.type_ = EventTypes::kLegacyMIDICCOutEvent as u16;
+                                vst3_event.event.legacy_midi_cc_out = LegacyMidiCCOutEvent {
+                                    control_number: 130, // kCtrlProgramChange
+                                    channel: channel as i8,
+                                    value: program as i8,
+                                    value2: 0,
+                                };
+                            }
+                            // VST3 doesn't have an equivalent for this event
+                            NoteEvent::VoiceTerminated { .. } => continue,
                             _ => {
                                 nih_debug_assert_failure!(
                                     "Invalid output event for the current MIDI_OUTPUT setting"
@@ -1704,14 +1792,19 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 //         this could lead to inconsistencies. It's the plugin's responsibility to
                 //         not perform any realtime-unsafe work when the initialize function is
                 //         called a second time if it supports runtime preset loading.
-                permit_alloc(|| {
+                let initialized = permit_alloc(|| {
                     plugin.initialize(
                         &bus_config,
                         &buffer_config,
                         &mut self.inner.make_init_context(),
                     )
                 });
-                plugin.reset();
+                if initialized {
+                    plugin.reset();
+                } else {
+                    self.inner.handle_reinitialization_failure();
+                    result = kResultFalse;
+                }
 
                 // We'll pass the state object back to the GUI thread so deallocation can happen
                 // there without potentially blocking the audio thread
@@ -1729,11 +1822,8 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
 
     unsafe fn get_tail_samples(&self) -> u32 {
         // https://github.com/steinbergmedia/vst3_pluginterfaces/blob/2ad397ade5b51007860bedb3b01b8afd2c5f6fba/vst/ivstaudioprocessor.h#L145-L159
-        match self.inner.last_process_status.load() {
-            ProcessStatus::Tail(samples) => samples,
-            ProcessStatus::KeepAlive => u32::MAX, // kInfiniteTail
-            _ => 0,                               // kNoTail
-        }
+        // `u32::MAX` is `kInfiniteTail`, and 0 is `kNoTail`
+        tail_samples(self.inner.last_process_status.load())
     }
 }
 
@@ -1838,6 +1928,16 @@ impl<P: Vst3Plugin> INoteExpressionController for Wrapper<P> {
     }
 }
 
+impl<P: Vst3Plugin> IAutomationState for Wrapper<P> {
+    unsafe fn set_automation_state(&self, state: i32) -> tresult {
+        self.inner
+            .automation_state
+            .store(automation_state_from_flags(state));
+
+        kResultOk
+    }
+}
+
 impl<P: Vst3Plugin> IProcessContextRequirements for Wrapper<P> {
     unsafe fn get_process_context_requirements(&self) -> u32 {
         IProcessContextRequirementsFlags::kNeedProjectTimeMusic
@@ -1950,3 +2050,181 @@ impl<P: Vst3Plugin> IUnitInfo for Wrapper<P> {
         kInvalidArgument
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{AutomationState, ParamSetter, ProcessContext};
+    use crate::param::internals::{ParamPtr, Params};
+    use crate::plugin::Plugin;
+
+    struct NoParams;
+
+    unsafe impl Params for NoParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            Vec::new()
+        }
+    }
+
+    struct TestPlugin {
+        /// The status returned from `process()`.
+        process_status: ProcessStatus,
+    }
+
+    impl Default for TestPlugin {
+        fn default() -> Self {
+            Self {
+                process_status: ProcessStatus::Normal,
+            }
+        }
+    }
+
+    impl Plugin for TestPlugin {
+        const NAME: &'static str = "Test";
+        const VENDOR: &'static str = "";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.1";
+
+        const DEFAULT_NUM_INPUTS: u32 = 0;
+        const DEFAULT_NUM_OUTPUTS: u32 = 0;
+
+        fn params(&self) -> Arc<dyn Params> {
+            Arc::new(NoParams)
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext,
+        ) -> ProcessStatus {
+            self.process_status
+        }
+    }
+
+    impl Vst3Plugin for TestPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugTestPlugn";
+        const VST3_CATEGORIES: &'static str = "Fx";
+    }
+
+    /// A plugin that only supports a stereo input and a stereo output.
+    #[derive(Default)]
+    struct StereoPlugin;
+
+    impl Plugin for StereoPlugin {
+        const NAME: &'static str = "Stereo Test";
+        const VENDOR: &'static str = "";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.1";
+
+        const DEFAULT_NUM_INPUTS: u32 = 2;
+        const DEFAULT_NUM_OUTPUTS: u32 = 2;
+
+        fn params(&self) -> Arc<dyn Params> {
+            Arc::new(NoParams)
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for StereoPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugStereoTst";
+        const VST3_CATEGORIES: &'static str = "Fx";
+    }
+
+    #[test]
+    fn automation_state() {
+        let wrapper = Wrapper::<TestPlugin>::new();
+        let context = wrapper.inner.clone().make_gui_context();
+        let setter = ParamSetter::new(context.as_ref());
+        assert_eq!(setter.automation_state(), AutomationState::Unknown);
+
+        // These are `kReadState` and `kWriteState`
+        unsafe { wrapper.set_automation_state(1 | 2) };
+        assert_eq!(setter.automation_state(), AutomationState::ReadWrite);
+        unsafe { wrapper.set_automation_state(0) };
+        assert_eq!(setter.automation_state(), AutomationState::Off);
+    }
+
+    #[test]
+    fn tail_status_reaches_get_tail_samples() {
+        let wrapper = Wrapper::<TestPlugin>::new();
+
+        unsafe {
+            let setup = vst3_sys::vst::ProcessSetup {
+                process_mode: ProcessModes::kRealtime as i32,
+                symbolic_sample_size: vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32,
+                max_samples_per_block: 512,
+                sample_rate: 44100.0,
+            };
+            assert_eq!(wrapper.setup_processing(&setup), kResultOk);
+            assert_eq!(wrapper.set_active(1), kResultOk);
+            assert_eq!(wrapper.set_processing(1), kResultOk);
+
+            // The host doesn't pass any buffers, events, or parameter changes
+            let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+            data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            data.num_samples = 64;
+            let mut run_process = |status: ProcessStatus| {
+                wrapper.inner.plugin.write().process_status = status;
+                wrapper.process(&mut data)
+            };
+
+            assert_eq!(wrapper.get_tail_samples(), 0);
+            assert_eq!(run_process(ProcessStatus::Tail(48000)), kResultOk);
+            assert_eq!(wrapper.get_tail_samples(), 48000);
+            // This is `kInfiniteTail`
+            assert_eq!(run_process(ProcessStatus::KeepAlive), kResultOk);
+            assert_eq!(wrapper.get_tail_samples(), u32::MAX);
+            // And this is `kNoTail`
+            assert_eq!(run_process(ProcessStatus::Tail(0)), kResultOk);
+            assert_eq!(wrapper.get_tail_samples(), 0);
+
+            // Deactivating the plugin resets the tail
+            assert_eq!(run_process(ProcessStatus::Tail(48000)), kResultOk);
+            assert_eq!(wrapper.set_processing(0), kResultOk);
+            assert_eq!(wrapper.get_tail_samples(), 0);
+            assert_eq!(wrapper.set_active(0), kResultOk);
+        }
+    }
+
+    #[test]
+    fn reject_mono_bus_arrangements() {
+        let wrapper = Wrapper::<StereoPlugin>::new();
+        let stereo_config = wrapper.inner.current_bus_config.load();
+
+        unsafe {
+            let mut inputs = [vst3_sys::vst::kMono];
+            let mut outputs = [vst3_sys::vst::kMono];
+            assert_eq!(
+                wrapper.set_bus_arrangements(inputs.as_mut_ptr(), 1, outputs.as_mut_ptr(), 1),
+                kResultFalse
+            );
+            assert_eq!(wrapper.inner.current_bus_config.load(), stereo_config);
+
+            // A mono input with a stereo output isn't supported either
+            let mut outputs = [vst3_sys::vst::kStereo];
+            assert_eq!(
+                wrapper.set_bus_arrangements(inputs.as_mut_ptr(), 1, outputs.as_mut_ptr(), 1),
+                kResultFalse
+            );
+            assert_eq!(wrapper.inner.current_bus_config.load(), stereo_config);
+
+            let mut inputs = [vst3_sys::vst::kStereo];
+            assert_eq!(
+                wrapper.set_bus_arrangements(inputs.as_mut_ptr(), 1, outputs.as_mut_ptr(), 1),
+                kResultOk
+            );
+            assert_eq!(wrapper.inner.current_bus_config.load(), stereo_config);
+        }
+    }
+}